@@ -0,0 +1,252 @@
+//! Throughput of the engine on a generated corpus, split into parsing
+//! records, applying records already parsed, and both together. The
+//! corpus is also sorted by client, and processed from end to end with
+//! and without `group_clients`, to measure what grouping saves on a
+//! client-sorted feed.
+//!
+//! Run with `cargo bench`. `cargo bench -- --save-baseline main` saves
+//! the results as the baseline `main`, and a later
+//! `cargo bench -- --bench-baseline main` compares against it and fails
+//! if any path got slower by more than `--bench-threshold`, 5% by
+//! default. A trailing name only runs the benchmarks containing it,
+//! such as `cargo bench -- apply`.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use criterion::{black_box, Criterion, Throughput};
+use payment_engine::generate;
+use payment_engine::state::CurrentState;
+use payment_engine::transaction::Transaction;
+
+/// The number of records in the corpus.
+const RECORDS: u32 = 1_000_000;
+
+/// The number of clients the corpus is spread over.
+const CLIENTS: u16 = 1_000;
+
+/// The name the benchmarks are grouped under.
+const GROUP: &str = "throughput";
+
+/// The benchmarks, by name.
+const BENCHES: [&str; 5] = ["parse", "apply", "end_to_end", "sorted", "grouped"];
+
+/// The options after `--`, as `cargo bench` passes them.
+#[derive(Debug, Default)]
+struct Options {
+    /// Measure rather than run every path once. `cargo bench` asks
+    /// for this, and `cargo test` does not.
+    bench: bool,
+    save_baseline: Option<String>,
+    compare_baseline: Option<String>,
+    /// How much slower than the baseline a path may get, as a fraction.
+    threshold: f64,
+    filter: Option<String>,
+}
+
+impl Options {
+    fn from_args() -> Result<Self, String> {
+        let mut options = Options {
+            threshold: 0.05,
+            ..Options::default()
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("`{}` needs a value", arg))
+            };
+            match arg.as_str() {
+                "--bench" => options.bench = true,
+                "--save-baseline" => options.save_baseline = Some(value()?),
+                "--bench-baseline" => options.compare_baseline = Some(value()?),
+                "--bench-threshold" => {
+                    let threshold = value()?;
+                    options.threshold = threshold
+                        .parse()
+                        .map_err(|_| format!("invalid threshold `{}`", threshold))?;
+                }
+                _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+                _ => options.filter = Some(arg),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Whether the benchmark `name` is run.
+    fn runs(&self, name: &str) -> bool {
+        self.filter
+            .as_deref()
+            .is_none_or(|filter| name.contains(filter))
+    }
+}
+
+/// Parses every record of `input`, the way the engine reads its input
+/// when no options change how records are read.
+fn parse(input: &[u8]) -> Vec<Transaction> {
+    csv::ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(input)
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+/// Applies `records` to a new state. Rejections are part of the corpus,
+/// so they are ignored.
+fn apply(records: &[Transaction]) -> CurrentState {
+    let mut state = CurrentState::default();
+    for tx in records {
+        let _ = state.add(tx);
+    }
+    state
+}
+
+/// Parses and applies every record of `input`, as `process` does,
+/// grouping consecutive records of a client if `grouped`.
+fn end_to_end(input: &[u8], grouped: bool) -> CurrentState {
+    let mut state = CurrentState::builder()
+        .group_clients(grouped)
+        .build()
+        .unwrap();
+    state.process_from_csv_with(input, |_, _| Ok(())).unwrap();
+    state
+}
+
+/// `input` with its records sorted by client, keeping the order of
+/// each client's records.
+fn sorted_by_client(input: &[u8]) -> Vec<u8> {
+    let text = std::str::from_utf8(input).unwrap();
+    let mut lines = text.lines();
+    let header = lines.next().unwrap();
+    let mut records: Vec<&str> = lines.collect();
+    records.sort_by_key(|record| {
+        let client = record.split(',').nth(1).unwrap();
+        client.trim().parse::<u16>().unwrap()
+    });
+    let mut sorted = String::with_capacity(text.len());
+    for line in std::iter::once(header).chain(records) {
+        sorted.push_str(line);
+        sorted.push('\n');
+    }
+    sorted.into_bytes()
+}
+
+/// Where criterion keeps its results.
+fn output_directory() -> PathBuf {
+    let target = std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("target"));
+    target.join("criterion")
+}
+
+/// The mean time of one run of the benchmark `name`, in nanoseconds, as
+/// saved under `baseline`.
+fn mean(directory: &Path, name: &str, baseline: &str) -> Result<f64, String> {
+    let path = directory
+        .join(GROUP)
+        .join(name)
+        .join(baseline)
+        .join("estimates.json");
+    let estimates: serde_json::Value = std::fs::read(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|bytes| serde_json::from_slice(&bytes).map_err(|err| err.to_string()))
+        .map_err(|err| format!("cannot read `{}`: {}", path.display(), err))?;
+    estimates["mean"]["point_estimate"]
+        .as_f64()
+        .ok_or_else(|| format!("no mean in `{}`", path.display()))
+}
+
+fn main() -> ExitCode {
+    let options = match Options::from_args() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut input = Vec::new();
+    generate::generate(&mut input, RECORDS, CLIENTS, 0).unwrap();
+    let records = parse(&input);
+    let sorted = sorted_by_client(&input);
+
+    if !options.bench {
+        black_box(apply(&records));
+        black_box(end_to_end(&input, false));
+        let ungrouped = end_to_end(&sorted, false);
+        let grouped = end_to_end(&sorted, true);
+        assert_eq!(ungrouped.state_hash(), grouped.state_hash());
+        return ExitCode::SUCCESS;
+    }
+
+    let directory = output_directory();
+    let mut criterion = Criterion::default()
+        .output_directory(&directory)
+        .sample_size(10)
+        .warm_up_time(Duration::from_secs(1))
+        .measurement_time(Duration::from_secs(20));
+    if let Some(baseline) = &options.save_baseline {
+        criterion = criterion.save_baseline(baseline.clone());
+    }
+    if let Some(baseline) = &options.compare_baseline {
+        criterion = criterion.retain_baseline(baseline.clone(), true);
+    }
+    let mut group = criterion.benchmark_group(GROUP);
+    group.throughput(Throughput::Elements(u64::from(RECORDS)));
+    if options.runs("parse") {
+        group.bench_function("parse", |b| b.iter(|| parse(black_box(&input))));
+    }
+    if options.runs("apply") {
+        group.bench_function("apply", |b| b.iter(|| apply(black_box(&records))));
+    }
+    if options.runs("end_to_end") {
+        group.bench_function("end_to_end", |b| {
+            b.iter(|| end_to_end(black_box(&input), false))
+        });
+    }
+    if options.runs("sorted") {
+        group.bench_function("sorted", |b| {
+            b.iter(|| end_to_end(black_box(&sorted), false))
+        });
+    }
+    if options.runs("grouped") {
+        group.bench_function("grouped", |b| {
+            b.iter(|| end_to_end(black_box(&sorted), true))
+        });
+    }
+    group.finish();
+
+    let baseline = match &options.compare_baseline {
+        Some(baseline) => baseline,
+        None => return ExitCode::SUCCESS,
+    };
+    let mut regressed = false;
+    for name in BENCHES.into_iter().filter(|name| options.runs(name)) {
+        let change = match (
+            mean(&directory, name, baseline),
+            mean(&directory, name, "new"),
+        ) {
+            (Ok(before), Ok(after)) => after / before - 1.0,
+            (Err(err), _) | (_, Err(err)) => {
+                eprintln!("Error: {}", err);
+                return ExitCode::FAILURE;
+            }
+        };
+        if change > options.threshold {
+            eprintln!(
+                "Regression: `{}` is {:.1}% slower than baseline `{}`, more than {:.1}% allowed",
+                name,
+                change * 100.0,
+                baseline,
+                options.threshold * 100.0
+            );
+            regressed = true;
+        }
+    }
+    match regressed {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS,
+    }
+}
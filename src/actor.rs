@@ -0,0 +1,490 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::errors::{self, ConfigError, InvariantError};
+use crate::parallel::{self, Rejection};
+use crate::state::{ConflictPolicy, CurrentState, CurrentStateBuilder, TakenId};
+use crate::summary::InputSummary;
+use crate::transaction::{Transaction, TransactionType};
+
+/// How many records may wait in an actor's mailbox before the router
+/// waits for it to catch up.
+const MAILBOX_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Where client actors run.
+pub enum Executor {
+    #[default]
+    /// Each actor runs on its own thread.
+    Threads,
+    #[cfg(feature = "tokio")]
+    /// Each actor runs as a task on a Tokio runtime, with one worker
+    /// thread per actor.
+    Tokio,
+}
+
+impl FromStr for Executor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "threads" => Ok(Executor::Threads),
+            #[cfg(feature = "tokio")]
+            "tokio" => Ok(Executor::Tokio),
+            #[cfg(not(feature = "tokio"))]
+            "tokio" => Err("the `tokio` executor requires the `tokio` feature".to_owned()),
+            _ => Err(format!("unknown executor `{}`", s)),
+        }
+    }
+}
+
+/// The client a record is routed by.
+fn client_of(state: &CurrentState, tx: &Transaction) -> u16 {
+    // Unknown accounts are rejected by whichever actor receives them.
+    match &tx.account {
+        Some(account) => state.counterparties().client(account).unwrap_or_default(),
+        None => tx.client,
+    }
+}
+
+/// The actor that records for a client are routed to. A client is
+/// always served by the same actor, so its records keep their order.
+fn actor_for(state: &CurrentState, tx: &Transaction, actors: usize) -> usize {
+    client_of(state, tx) as usize % actors
+}
+
+/// Processes a CSV stream with `actors` actors, each owning the state
+/// of the clients routed to it, so records for different clients are
+/// applied concurrently while those for one client are applied in
+/// order. Rejected records are passed to `on_reject` on the calling
+/// thread. The actors' states are merged once the input is exhausted.
+///
+/// The records of each client are applied in the order they were
+/// read, as they would be by a single state. This is checked as they
+/// are applied: each record is numbered as it is routed, and an actor
+/// given a client's records out of order stops processing with an
+/// invariant violation rather than applying them. Sequence numbers
+/// given by the input are checked by each actor as usual.
+///
+/// Transaction IDs are unique across actors, as they are in a single
+/// state. The router remembers which actor each deposit, withdrawal
+/// and adjustment ID was last sent to, and when a record reusing the
+/// ID goes to another actor, first asks that one whether it accepted
+/// it. If it did, the receiving actor is told the ID is taken, so it
+/// rejects the record just as a single state would. Audit entries and
+/// events are ordered by actor rather than by input.
+pub fn process_csv_actors(
+    builder: &CurrentStateBuilder,
+    reader: impl std::io::Read,
+    actors: NonZeroUsize,
+    executor: Executor,
+    mut on_reject: impl FnMut(&Transaction, &errors::Error) -> Result<(), errors::Error>,
+) -> Result<CurrentState, errors::Error> {
+    run_actors(builder, reader, actors, executor, |tx, err| {
+        on_reject(&tx, &err)
+    })
+}
+
+/// Processes independent input files one after another, each with
+/// `process_csv_actors`, and merges their states in the order of
+/// `paths`. The summary of each input is returned alongside.
+///
+/// Transaction IDs shared between files are handled as
+/// `process_files_parallel` handles them: under `keep-first` and
+/// `keep-last`, the rejected records of every file are held back until
+/// the last one is processed.
+pub fn process_files_actors(
+    builder: &CurrentStateBuilder,
+    paths: &[PathBuf],
+    actors: NonZeroUsize,
+    executor: Executor,
+    on_reject: impl Fn(&Path, &Transaction, &errors::Error) -> Result<(), errors::Error>,
+) -> Result<(CurrentState, Vec<InputSummary>), errors::Error> {
+    if builder.conflict_policy() != ConflictPolicy::Fail {
+        let process = |builder: CurrentStateBuilder, path: &Path| {
+            let mut rejects = Vec::new();
+            let state = run_actors(&builder, File::open(path)?, actors, executor, |tx, err| {
+                rejects.push((tx, err));
+                Ok(())
+            })?;
+            Ok((state, rejects))
+        };
+        return parallel::process_files_in_turn(builder, paths, process, on_reject);
+    }
+    let mut merged = builder.clone().build()?;
+    let mut summaries = Vec::with_capacity(paths.len());
+    for path in paths {
+        let state = process_csv_actors(builder, File::open(path)?, actors, executor, |tx, err| {
+            on_reject(path, tx, err)
+        })?;
+        summaries.push(InputSummary {
+            path: path.clone(),
+            summary: state.summary().clone(),
+        });
+        merged.merge(state)?;
+    }
+    Ok((merged, summaries))
+}
+
+/// Runs `process_csv_actors`, passing rejected records to `on_reject`
+/// by value.
+fn run_actors(
+    builder: &CurrentStateBuilder,
+    reader: impl std::io::Read,
+    actors: NonZeroUsize,
+    executor: Executor,
+    mut on_reject: impl FnMut(Transaction, errors::Error) -> Result<(), errors::Error>,
+) -> Result<CurrentState, errors::Error> {
+    let mut merged = builder.clone().build()?;
+    // Each actor would append to the same archive independently.
+    if builder.archives() {
+        return Err(ConfigError::ArchiveWithActors.into());
+    }
+    let states = (0..actors.get())
+        .map(|_| builder.clone().build())
+        .collect::<Result<Vec<_>, _>>()?;
+    let (reject_tx, rejects) = mpsc::channel();
+    let route = |send: &dyn Fn(usize, Letter)| {
+        let mut position = 0;
+        // The actor each ID was last sent to, which is the only one
+        // that may have accepted it.
+        let mut routed: HashMap<u32, usize> = HashMap::new();
+        merged.read_csv(reader, |tx| {
+            for (tx, err) in rejects.try_iter() {
+                forward(&mut on_reject, tx, err)?;
+            }
+            position += 1;
+            let actor = actor_for(&merged, &tx, actors.get());
+            let regular = matches!(
+                tx.r#type(),
+                TransactionType::Deposit
+                    | TransactionType::Withdrawal
+                    | TransactionType::Adjustment
+            );
+            match routed.get(&tx.id) {
+                Some(&holder) if holder != actor => match ask(send, holder, tx.id) {
+                    Some(taken) => send(actor, Letter::Taken(tx.id, taken)),
+                    None if regular => {
+                        routed.insert(tx.id, actor);
+                    }
+                    None => {}
+                },
+                Some(_) => {}
+                None if regular => {
+                    routed.insert(tx.id, actor);
+                }
+                None => {}
+            }
+            send(actor, Letter::Record(position, Box::new(tx)));
+            Ok(())
+        })
+    };
+    let (result, states) = match executor {
+        Executor::Threads => run_threads(states, reject_tx, route),
+        #[cfg(feature = "tokio")]
+        Executor::Tokio => run_tokio(states, reject_tx, route)?,
+    };
+    for (tx, err) in rejects.try_iter() {
+        forward(&mut on_reject, tx, err)?;
+    }
+    result?;
+    for state in states {
+        merged.merge(state)?;
+    }
+    Ok(merged)
+}
+
+/// Passes a rejection from an actor to `on_reject`, or stops
+/// processing if the actor found a broken invariant.
+fn forward(
+    on_reject: &mut impl FnMut(Transaction, errors::Error) -> Result<(), errors::Error>,
+    tx: Transaction,
+    err: errors::Error,
+) -> Result<(), errors::Error> {
+    match err {
+        errors::Error::Invariant(_) => Err(err),
+        err => on_reject(tx, err),
+    }
+}
+
+/// Asks `actor` how it accepted the transaction with ID `id`, if it
+/// did. Its mailbox is read in order, so the answer takes into account
+/// every record routed to it before.
+fn ask(send: &dyn Fn(usize, Letter), actor: usize, id: u32) -> Option<TakenId> {
+    let (reply, answer) = mpsc::channel();
+    send(actor, Letter::Ask(id, reply));
+    // A closed mailbox means the actor panicked, which resurfaces when
+    // it is joined.
+    answer.recv().ok().flatten()
+}
+
+#[derive(Debug)]
+/// What the router sends an actor.
+enum Letter {
+    /// A record and its position in the input, counting from one.
+    Record(u64, Box<Transaction>),
+    /// Asks how the transaction with this ID was accepted, if it was.
+    Ask(u32, mpsc::Sender<Option<TakenId>>),
+    /// A transaction ID accepted by another actor.
+    Taken(u32, TakenId),
+}
+
+#[derive(Debug)]
+/// The state of the clients routed to one actor.
+struct Actor {
+    state: CurrentState,
+    /// The position of the last record applied for each client.
+    positions: HashMap<u16, u64>,
+}
+
+impl Actor {
+    fn new(state: CurrentState) -> Self {
+        Actor {
+            state,
+            positions: HashMap::new(),
+        }
+    }
+
+    /// Feeds a mailbox into the state.
+    fn serve(
+        &mut self,
+        mailbox: impl IntoIterator<Item = Letter>,
+        rejects: &mpsc::Sender<Rejection>,
+    ) {
+        for letter in mailbox {
+            let (position, tx) = match letter {
+                Letter::Record(position, tx) => (position, *tx),
+                Letter::Ask(id, reply) => {
+                    let _ = reply.send(self.state.accepted_id(id));
+                    continue;
+                }
+                Letter::Taken(id, taken) => {
+                    self.state.take_id(id, taken);
+                    continue;
+                }
+            };
+            let client = client_of(&self.state, &tx);
+            let result = match self.positions.get(&client) {
+                Some(&after) if after >= position => Err(InvariantError::OutOfOrder {
+                    client,
+                    position,
+                    after,
+                    recent: vec![tx.clone()],
+                }
+                .into()),
+                _ => {
+                    self.positions.insert(client, position);
+                    self.state.add(&tx)
+                }
+            };
+            if let Err(err) = result {
+                // The router only stops listening once every actor is done.
+                let _ = rejects.send((tx, err));
+            }
+        }
+    }
+}
+
+/// Runs each actor on its own thread while `route` feeds them.
+fn run_threads(
+    states: Vec<CurrentState>,
+    rejects: mpsc::Sender<Rejection>,
+    route: impl FnOnce(&dyn Fn(usize, Letter)) -> Result<(), errors::Error>,
+) -> (Result<(), errors::Error>, Vec<CurrentState>) {
+    thread::scope(|scope| {
+        let (mailboxes, handles): (Vec<_>, Vec<_>) = states
+            .into_iter()
+            .map(|state| {
+                let (mailbox, inbox) = mpsc::sync_channel(MAILBOX_CAPACITY);
+                let rejects = rejects.clone();
+                let handle = scope.spawn(move || {
+                    let mut actor = Actor::new(state);
+                    actor.serve(inbox, &rejects);
+                    actor.state
+                });
+                (mailbox, handle)
+            })
+            .unzip();
+        // A closed mailbox means the actor panicked, which resurfaces
+        // when it is joined.
+        let result = route(&|actor, letter| {
+            let _ = mailboxes[actor].send(letter);
+        });
+        drop(mailboxes);
+        let states = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+        (result, states)
+    })
+}
+
+#[cfg(feature = "tokio")]
+/// Runs each actor as a task on a Tokio runtime while `route` feeds them.
+fn run_tokio(
+    states: Vec<CurrentState>,
+    rejects: mpsc::Sender<Rejection>,
+    route: impl FnOnce(&dyn Fn(usize, Letter)) -> Result<(), errors::Error>,
+) -> Result<(Result<(), errors::Error>, Vec<CurrentState>), errors::Error> {
+    use tokio::sync::mpsc as tokio_mpsc;
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(states.len())
+        .build()?;
+    let (mailboxes, handles): (Vec<_>, Vec<_>) = states
+        .into_iter()
+        .map(|state| {
+            let (mailbox, mut inbox) = tokio_mpsc::channel(MAILBOX_CAPACITY);
+            let rejects = rejects.clone();
+            let handle = runtime.spawn(async move {
+                let mut actor = Actor::new(state);
+                while let Some(letter) = inbox.recv().await {
+                    actor.serve([letter], &rejects);
+                }
+                actor.state
+            });
+            (mailbox, handle)
+        })
+        .unzip();
+    let result = route(&|actor, letter| {
+        let _ = mailboxes[actor].blocking_send(letter);
+    });
+    drop(mailboxes);
+    let states = handles
+        .into_iter()
+        .map(|handle| runtime.block_on(handle).unwrap())
+        .collect();
+    Ok((result, states))
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::errors::RejectionCode;
+    use crate::fixture::Scenario;
+    use crate::generate::SplitMix64;
+    use crate::state::CsvClient;
+
+    /// The number of clients in the corpus.
+    const CLIENTS: u16 = 24;
+
+    /// Records for `CLIENTS` clients, each with its own sequence
+    /// numbers. About one record in ten reuses a number its client has
+    /// already given, so it arrives out of order; the rest skip ahead.
+    fn corpus() -> Vec<u8> {
+        let mut rng = SplitMix64(176);
+        let mut scenario = Scenario::new();
+        let mut last = [0u64; CLIENTS as usize + 1];
+        let mut deposits: Vec<Vec<u32>> = vec![Vec::new(); CLIENTS as usize + 1];
+        for id in 1..=4000 {
+            let client = rng.below(u64::from(CLIENTS)) as u16 + 1;
+            let seq = match rng.below(10) {
+                0 => last[client as usize].saturating_sub(rng.below(3)),
+                _ => {
+                    last[client as usize] += 1 + rng.below(3);
+                    last[client as usize]
+                }
+            };
+            let amount = Decimal::new(rng.below(10_000) as i64 + 1, 2);
+            let earlier = &deposits[client as usize];
+            let disputed = match earlier.is_empty() {
+                true => None,
+                false => Some(earlier[rng.below(earlier.len() as u64) as usize]),
+            };
+            let builder = Transaction::builder();
+            let tx = match (rng.below(10), disputed) {
+                (0, Some(tx)) => builder.dispute(client, tx).seq(seq).build(),
+                (1, Some(tx)) => builder.resolve(client, tx).seq(seq).build(),
+                (2..=4, _) => builder.withdrawal(client, amount).id(id).seq(seq).build(),
+                _ => {
+                    deposits[client as usize].push(id);
+                    builder.deposit(client, amount).id(id).seq(seq).build()
+                }
+            };
+            scenario.push(tx);
+        }
+        let mut input = Vec::new();
+        scenario.write_csv(&mut input).unwrap();
+        input
+    }
+
+    /// The balances of every client, by ID.
+    fn balances(state: &CurrentState) -> Vec<CsvClient> {
+        let mut clients: Vec<_> = state.clients().collect();
+        clients.sort_by_key(|client| client.client);
+        clients
+    }
+
+    /// The client, ID, sequence number and rejection code of each
+    /// rejected record, sorted, as actors report them out of input order.
+    fn rejected(rejects: &[(Transaction, Option<RejectionCode>)]) -> Vec<(u16, u32, u64, &str)> {
+        let mut rejected: Vec<_> = rejects
+            .iter()
+            .map(|(tx, code)| {
+                let seq = tx.seq.unwrap_or_default();
+                (
+                    tx.client,
+                    tx.id,
+                    seq,
+                    code.map_or("", RejectionCode::as_str),
+                )
+            })
+            .collect();
+        rejected.sort_unstable();
+        rejected
+    }
+
+    /// Checks that `executor` gives the same balances and rejections,
+    /// out of order ones included, as a single state, for several
+    /// numbers of actors.
+    fn matches_serial(executor: Executor) {
+        let input = corpus();
+        let mut serial = CurrentState::default();
+        let expected: Vec<_> = serial
+            .process_from_csv_collected(&input[..])
+            .unwrap()
+            .into_iter()
+            .map(|(tx, err)| (tx, err.rejection_code()))
+            .collect();
+        let expected_rejected = rejected(&expected);
+        assert!(expected_rejected
+            .iter()
+            .any(|&(.., code)| code == RejectionCode::OutOfOrder.as_str()));
+
+        for actors in [1, 2, 3, 7, 16] {
+            let mut rejects = Vec::new();
+            let state = process_csv_actors(
+                &CurrentState::builder(),
+                &input[..],
+                NonZeroUsize::new(actors).unwrap(),
+                executor,
+                |tx, err| {
+                    rejects.push((tx.clone(), err.rejection_code()));
+                    Ok(())
+                },
+            )
+            .unwrap();
+            assert_eq!(balances(&state), balances(&serial), "{} actors", actors);
+            assert_eq!(state.state_hash(), serial.state_hash(), "{} actors", actors);
+            assert_eq!(rejected(&rejects), expected_rejected, "{} actors", actors);
+        }
+    }
+
+    #[test]
+    fn threads_keep_each_clients_order() {
+        matches_serial(Executor::Threads);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn tokio_tasks_keep_each_clients_order() {
+        matches_serial(Executor::Tokio);
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt::Debug;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::transaction::DisputeInitiator;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+/// What an adjudicator decided about a dispute.
+pub enum Ruling {
+    /// Leave the dispute open, to be resolved or charged back by a
+    /// later record.
+    #[default]
+    Pending,
+    /// Resolve the dispute, releasing the held funds.
+    Resolve,
+    /// Charge the dispute back.
+    Chargeback,
+}
+
+impl Ruling {
+    /// The name of the ruling, as written in the audit log.
+    pub fn name(self) -> &'static str {
+        match self {
+            Ruling::Pending => "pending",
+            Ruling::Resolve => "resolve",
+            Ruling::Chargeback => "chargeback",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+/// A dispute just opened, as put to an adjudicator.
+pub struct DisputeCase {
+    pub client: u16,
+    pub tx: u32,
+    /// The amount of the disputed transaction, now held.
+    pub amount: Decimal,
+    pub initiator: Option<DisputeInitiator>,
+    pub timestamp: Option<u64>,
+    /// The sender's own ID for the dispute record.
+    pub external_ref: Option<String>,
+    /// Why the dispute was opened, if the record says.
+    pub reason_code: Option<String>,
+}
+
+/// Consulted whenever a dispute is opened, so external case management
+/// can settle it straight away.
+///
+/// Adjudicators are called from the thread applying records, and the
+/// record waits for the ruling. Cases that take longer to decide should
+/// be ruled `Pending` and settled asynchronously, by submitting a
+/// resolve or chargeback record once decided, as through `serve`.
+pub trait Adjudicator: Debug + Send + Sync {
+    /// Rules on `case`.
+    fn adjudicate(&self, case: &DisputeCase) -> Ruling;
+}
+
+#[cfg(feature = "http")]
+pub use http::HttpAdjudicator;
+
+#[cfg(feature = "http")]
+mod http {
+    use std::time::Duration;
+
+    use serde::Deserialize;
+
+    use super::{Adjudicator, DisputeCase, Ruling};
+    use crate::http::Endpoint;
+
+    /// How long to wait for the service before leaving the dispute open.
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[derive(Debug, Deserialize)]
+    /// The body of a reply from the service.
+    struct Reply {
+        ruling: Ruling,
+    }
+
+    #[derive(Debug, Clone)]
+    /// POSTs each case as JSON to a case-management service, which
+    /// replies with a JSON object such as `{"ruling": "resolve"}`.
+    ///
+    /// The service is spoken to in plain HTTP, so it should be local,
+    /// or behind a local proxy that handles TLS. Disputes are left
+    /// pending if the service cannot be reached or replies with
+    /// anything else.
+    pub struct HttpAdjudicator {
+        endpoint: Endpoint,
+    }
+
+    impl HttpAdjudicator {
+        /// Posts cases to `url`, such as `http://localhost:8080/cases`.
+        pub fn new(url: &str) -> Result<Self, String> {
+            Ok(HttpAdjudicator {
+                endpoint: Endpoint::new(url)?,
+            })
+        }
+
+        /// Sends one case and reads the ruling.
+        fn post(&self, case: &DisputeCase) -> std::io::Result<Ruling> {
+            let body = serde_json::to_vec(case)?;
+            let reply = self
+                .endpoint
+                .post_json(self.endpoint.path(), &body, TIMEOUT)?;
+            let reply: Reply = serde_json::from_str(&reply)?;
+            Ok(reply.ruling)
+        }
+    }
+
+    impl Adjudicator for HttpAdjudicator {
+        fn adjudicate(&self, case: &DisputeCase) -> Ruling {
+            self.post(case).unwrap_or_else(|err| {
+                eprintln!(
+                    "Warning: leaving dispute of transaction {} open: {}",
+                    case.tx, err
+                );
+                Ruling::Pending
+            })
+        }
+    }
+}
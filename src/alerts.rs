@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::errors::{ConfigError, Error};
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// The balances at which a client is alerted on, so treasury hears of
+/// accounts running dry or holding too much before it matters.
+pub struct BalanceThresholds {
+    /// Alerts when the available funds fall below this floor.
+    pub available_below: Option<Decimal>,
+    /// Alerts when the total funds rise above this ceiling.
+    pub total_above: Option<Decimal>,
+}
+
+impl BalanceThresholds {
+    /// These thresholds, with those they leave unset taken from
+    /// `fallback`.
+    fn or(self, fallback: BalanceThresholds) -> Self {
+        BalanceThresholds {
+            available_below: self.available_below.or(fallback.available_below),
+            total_above: self.total_above.or(fallback.total_above),
+        }
+    }
+
+    /// Whether `available` is below the floor.
+    pub(crate) fn below_floor(&self, available: Decimal) -> bool {
+        self.available_below.is_some_and(|floor| available < floor)
+    }
+
+    /// Whether `total` is above the ceiling.
+    pub(crate) fn above_ceiling(&self, total: Decimal) -> bool {
+        self.total_above.is_some_and(|ceiling| total > ceiling)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+/// A row of a file of per-client thresholds. Used for deserialization.
+struct CsvThresholds {
+    client: u16,
+    #[serde(default)]
+    available_below: Option<Decimal>,
+    #[serde(default)]
+    total_above: Option<Decimal>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+/// The balance thresholds of every client: global thresholds, and
+/// thresholds of individual clients that override them.
+///
+/// A client crossing a threshold raises the `low_balance` or
+/// `high_balance` flag, records a changelog event and notifies the
+/// sink, if any. The flag is cleared once the client is back within
+/// its thresholds, and crossing again alerts again.
+pub struct BalanceAlerts {
+    global: BalanceThresholds,
+    clients: HashMap<u16, BalanceThresholds>,
+}
+
+impl BalanceAlerts {
+    /// Alerts every client on `global`.
+    pub fn new(global: BalanceThresholds) -> Self {
+        BalanceAlerts {
+            global,
+            clients: HashMap::new(),
+        }
+    }
+
+    /// Reads the thresholds of individual clients from a CSV file with
+    /// `client`, `available_below` and `total_above` columns, on top of
+    /// `global`. Empty cells keep the global threshold. Each client may
+    /// only appear once.
+    pub fn from_csv(global: BalanceThresholds, reader: impl std::io::Read) -> Result<Self, Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut alerts = BalanceAlerts::new(global);
+        for row in rdr.deserialize() {
+            let row: CsvThresholds = row?;
+            let thresholds = BalanceThresholds {
+                available_below: row.available_below,
+                total_above: row.total_above,
+            };
+            if alerts.clients.insert(row.client, thresholds).is_some() {
+                return Err(ConfigError::DuplicateThresholds(row.client).into());
+            }
+        }
+        Ok(alerts)
+    }
+
+    /// Sets the thresholds of `client`, over the global ones.
+    pub fn set(&mut self, client: u16, thresholds: BalanceThresholds) {
+        self.clients.insert(client, thresholds);
+    }
+
+    /// The thresholds `client` is alerted on.
+    pub fn thresholds(&self, client: u16) -> BalanceThresholds {
+        match self.clients.get(&client) {
+            Some(thresholds) => thresholds.or(self.global),
+            None => self.global,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::transaction::Transaction;
+
+#[derive(Debug)]
+/// An append-only file of transactions moved out of memory.
+/// Only the byte offset of each archived transaction is kept in
+/// memory, so lookups need a read from disk but no scan.
+pub struct Archive {
+    path: PathBuf,
+    /// Opened on the first append, so an unused archive creates no file.
+    file: Option<BufWriter<File>>,
+    /// The offset the next record will be written at.
+    end: u64,
+    /// The offset of the latest copy of each transaction archived
+    /// through this instance.
+    index: HashMap<u32, u64>,
+}
+
+impl Archive {
+    /// Creates an archive backed by the file at `path`, which is
+    /// appended to if it already exists. Transactions archived by
+    /// earlier runs are not indexed.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Archive {
+            path: path.into(),
+            file: None,
+            end: 0,
+            index: HashMap::new(),
+        }
+    }
+
+    /// The file backing the archive.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the transaction with ID `id` is in the archive.
+    pub fn contains(&self, id: u32) -> bool {
+        self.index.contains_key(&id)
+    }
+
+    /// Appends a transaction to the archive.
+    pub fn append(&mut self, tx: &Transaction) -> Result<(), csv::Error> {
+        let write_headers = self.file.is_none() && self.open()? == 0;
+        let mut bytes = Vec::new();
+        {
+            let mut wtr = csv::WriterBuilder::new()
+                .has_headers(write_headers)
+                .from_writer(&mut bytes);
+            wtr.serialize(tx)?;
+            wtr.flush()?;
+        }
+        let record_start = if write_headers {
+            // Headers are a single line, ended by the default terminator.
+            bytes.iter().position(|&b| b == b'\n').unwrap() + 1
+        } else {
+            0
+        };
+        // `open` has just run if the file was not yet open.
+        self.file.as_mut().unwrap().write_all(&bytes)?;
+        self.index.insert(tx.id, self.end + record_start as u64);
+        self.end += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Opens the file for appending, returning its current length.
+    fn open(&mut self) -> Result<u64, csv::Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.end = file.metadata()?.len();
+        self.file = Some(BufWriter::new(file));
+        Ok(self.end)
+    }
+
+    /// Writes out any buffered transactions.
+    pub fn flush(&mut self) -> Result<(), csv::Error> {
+        if let Some(file) = &mut self.file {
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Reads the latest archived copy of the transaction with ID `id`.
+    pub fn find(&mut self, id: u32) -> Result<Option<Transaction>, csv::Error> {
+        let offset = match self.index.get(&id) {
+            Some(&offset) => offset,
+            None => return Ok(None),
+        };
+        self.flush()?;
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(File::open(&self.path)?);
+        let headers = rdr.headers()?.clone();
+        let mut position = csv::Position::new();
+        position.set_byte(offset);
+        rdr.seek(position)?;
+        let mut record = csv::StringRecord::new();
+        rdr.read_record(&mut record)?;
+        Ok(Some(record.deserialize(Some(&headers))?))
+    }
+
+    /// Forgets the transaction with ID `id`, once it is back in memory.
+    pub fn remove(&mut self, id: u32) {
+        self.index.remove(&id);
+    }
+
+    /// Takes over the index of another archive of the same file.
+    /// Both archives are expected to be flushed, as they are after
+    /// [`CurrentState::compact`](crate::state::CurrentState::compact).
+    pub fn absorb(&mut self, other: Archive) {
+        self.index.extend(other.index);
+        // The other archive may have appended since this one opened.
+        self.file = None;
+    }
+}
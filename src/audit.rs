@@ -0,0 +1,80 @@
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::errors::RejectionCode;
+use crate::transaction::DisputeInitiator;
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+/// The kinds of events recorded in the audit log.
+pub enum AuditEvent {
+    /// A manual ledger correction was applied.
+    Adjustment,
+    /// A client reached the dispute limit for an initiator.
+    DisputeLimitReached,
+    /// A transaction was held for review.
+    Quarantined,
+    /// A held transaction was approved and processed.
+    QuarantineApproved,
+    /// A held transaction was denied and discarded.
+    QuarantineDenied,
+    /// A fee was charged for a chargeback.
+    ChargebackFee,
+    /// A client's or merchant's chargeback rate exceeded the threshold.
+    ChargebackRateExceeded,
+    /// A client's or merchant's chargeback rate fell back to the
+    /// release rate.
+    ChargebackRateRecovered,
+    /// An adjudicator ruled on a dispute as it was opened.
+    DisputeAdjudicated,
+    /// A dispute of a transaction already under dispute updated the
+    /// open dispute's references and reason code, which is given as
+    /// the `reason`.
+    DisputeUpdated,
+    /// A client was merged into another, which received its funds.
+    /// The entry is for the client merged away, with the total moved,
+    /// and `tx` zero.
+    ClientMerged,
+    /// A transaction, an open dispute or a held transaction was moved
+    /// to the client its own was merged into.
+    TransactionReassigned,
+    /// A share of a deposit held in reserve was released to the
+    /// client's available funds. The entry is for the deposit.
+    ReserveReleased,
+    /// A record made the engine panic, and was rejected. The panic
+    /// message is given as the `reason`.
+    InternalError,
+}
+
+#[derive(Debug, Serialize, Clone)]
+/// One entry in the audit log.
+pub struct AuditEntry {
+    pub event: AuditEvent,
+    pub client: u16,
+    pub tx: u32,
+    pub amount: Option<Decimal>,
+    pub reference: Option<String>,
+    /// Why the event was rejected, if it was.
+    pub rejection: Option<RejectionCode>,
+    /// Who opened the dispute, for dispute events.
+    pub initiator: Option<DisputeInitiator>,
+    /// Why the transaction was held, for quarantine events.
+    pub reason: Option<String>,
+    /// The sender's own ID for the record behind the event.
+    pub external_ref: Option<String>,
+}
+
+/// Writes audit entries as CSV.
+pub fn write_csv<'a>(
+    writer: impl std::io::Write,
+    entries: impl IntoIterator<Item = &'a AuditEntry>,
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    entries
+        .into_iter()
+        .try_for_each(|entry| wtr.serialize(entry))?;
+    wtr.flush()?;
+    Ok(())
+}
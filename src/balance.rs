@@ -0,0 +1,101 @@
+use rust_decimal::Decimal;
+
+#[cfg(feature = "fixed-point")]
+/// The number of decimal places balances are kept to with the
+/// `fixed-point` feature, so one minor unit is `10^-PLACES`.
+pub(crate) const PLACES: u32 = 8;
+
+#[cfg(feature = "fixed-point")]
+/// The largest number of minor units a balance may hold either side of
+/// zero, so every balance still converts to a `Decimal` exactly.
+const MAX_MINOR_UNITS: u128 = (1 << 96) - 1;
+
+#[cfg(not(feature = "fixed-point"))]
+type Repr = Decimal;
+
+#[cfg(feature = "fixed-point")]
+/// A whole number of minor units.
+type Repr = i128;
+
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+/// A client's available, held or fee balance.
+///
+/// By default this is a `Decimal`. With the `fixed-point` feature it is
+/// a whole number of minor units of `10^-PLACES` in an `i128`, which
+/// adds and compares without the scale handling of a `Decimal`.
+/// Amounts are converted as they are applied, and balances as they are
+/// reported, so nothing outside the client state sees the difference,
+/// except that amounts with more than `PLACES` decimal places cannot
+/// be applied.
+pub(crate) struct Balance(Repr);
+
+#[cfg(not(feature = "fixed-point"))]
+impl Balance {
+    /// `amount` as a balance. Every amount fits.
+    pub(crate) fn from_decimal(amount: Decimal) -> Option<Self> {
+        Some(Balance(amount))
+    }
+
+    /// The balance as a `Decimal`.
+    pub(crate) fn to_decimal(self) -> Decimal {
+        self.0
+    }
+
+    pub(crate) fn checked_add(self, other: Balance) -> Option<Self> {
+        self.0.checked_add(other.0).map(Balance)
+    }
+
+    pub(crate) fn checked_sub(self, other: Balance) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Balance)
+    }
+
+    pub(crate) fn is_zero(self) -> bool {
+        self.0.is_zero()
+    }
+
+    #[cfg(test)]
+    /// The largest amount a balance holds.
+    pub(crate) fn max_amount() -> Decimal {
+        Decimal::MAX
+    }
+}
+
+#[cfg(feature = "fixed-point")]
+impl Balance {
+    /// `minor` minor units, if that is within range.
+    fn bounded(minor: i128) -> Option<Self> {
+        (minor.unsigned_abs() <= MAX_MINOR_UNITS).then_some(Balance(minor))
+    }
+
+    /// `amount` as a balance, or `None` if it has more than `PLACES`
+    /// decimal places, or is too large.
+    pub(crate) fn from_decimal(amount: Decimal) -> Option<Self> {
+        let amount = amount.normalize();
+        let places = PLACES.checked_sub(amount.scale())?;
+        Self::bounded(amount.mantissa().checked_mul(10i128.pow(places))?)
+    }
+
+    /// The balance as a `Decimal`, without trailing zeroes.
+    pub(crate) fn to_decimal(self) -> Decimal {
+        // Balances are bounded to what a `Decimal` holds.
+        Decimal::from_i128_with_scale(self.0, PLACES).normalize()
+    }
+
+    pub(crate) fn checked_add(self, other: Balance) -> Option<Self> {
+        Self::bounded(self.0 + other.0)
+    }
+
+    pub(crate) fn checked_sub(self, other: Balance) -> Option<Self> {
+        Self::bounded(self.0 - other.0)
+    }
+
+    pub(crate) fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    #[cfg(test)]
+    /// The largest amount a balance holds.
+    pub(crate) fn max_amount() -> Decimal {
+        Balance(MAX_MINOR_UNITS as i128).to_decimal()
+    }
+}
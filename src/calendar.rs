@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::errors::Error;
+
+/// The number of seconds in a day.
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+/// A day in UTC, written `YYYY-MM-DD`.
+pub struct Date {
+    /// Days since the Unix epoch.
+    days: u64,
+}
+
+impl Date {
+    /// The day `timestamp`, in seconds since the Unix epoch, falls on.
+    pub fn from_timestamp(timestamp: u64) -> Self {
+        Date {
+            days: timestamp / SECONDS_PER_DAY,
+        }
+    }
+
+    /// The date of `year`, `month` and `day`, if it exists and is not
+    /// before the Unix epoch.
+    pub fn from_ymd(year: u64, month: u64, day: u64) -> Option<Self> {
+        if year < 1970 || !(1..=12).contains(&month) || day == 0 || day > days_in(year, month) {
+            return None;
+        }
+        // Howard Hinnant's `days_from_civil`, with years starting on
+        // 1 March, so leap days end the year.
+        let year = year - u64::from(month <= 2);
+        let era = year / 400;
+        let year_of_era = year % 400;
+        let shifted_month = (month + 9) % 12;
+        let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        Some(Date {
+            days: era * 146_097 + day_of_era - 719_468,
+        })
+    }
+
+    /// The year, month and day, by Howard Hinnant's `civil_from_days`.
+    pub fn ymd(self) -> (u64, u64, u64) {
+        // Shift the epoch to 0000-03-01, so leap days end the year.
+        let days = self.days + 719_468;
+        let era = days / 146_097;
+        let day_of_era = days % 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = match shifted_month < 10 {
+            true => shifted_month + 3,
+            false => shifted_month - 9,
+        };
+        let year = year_of_era + era * 400 + u64::from(month <= 2);
+        (year, month, day)
+    }
+
+    /// Whether the day is a Saturday or a Sunday.
+    pub fn is_weekend(self) -> bool {
+        // The Unix epoch was a Thursday.
+        (self.days + 3) % 7 >= 5
+    }
+
+    /// The day after.
+    fn next(self) -> Self {
+        Date {
+            days: self.days + 1,
+        }
+    }
+}
+
+/// The number of days in `month` of `year`.
+fn days_in(year: u64, month: u64) -> u64 {
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = self.ymd();
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+impl FromStr for Date {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected a date as `YYYY-MM-DD`, got `{}`", s);
+        let mut parts = s.splitn(3, '-').map(|part| part.parse::<u64>().ok());
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(Some(year)), Some(Some(month)), Some(Some(day))) => {
+                Date::from_ymd(year, month, day).ok_or_else(invalid)
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Serialize for Date {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Date {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+/// A row of a holiday set. Used for deserialization.
+struct CsvHoliday {
+    date: Date,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+/// The days funds move on: every weekday but the holidays loaded, in
+/// UTC. Settlement dates and chargeback deadlines are counted in these
+/// business days.
+pub struct Calendar {
+    holidays: BTreeMap<Date, Option<String>>,
+}
+
+impl Calendar {
+    /// Adds the holidays in a CSV file with a `date` column, as
+    /// `YYYY-MM-DD`, and an optional `name` column. Several sets may be
+    /// loaded, such as those of each market funds move through; days in
+    /// more than one set keep the first name given.
+    pub fn load_csv(&mut self, reader: impl std::io::Read) -> Result<(), Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        for row in rdr.deserialize() {
+            let row: CsvHoliday = row?;
+            self.holidays.entry(row.date).or_insert(row.name);
+        }
+        Ok(())
+    }
+
+    /// Adds `date` as a holiday.
+    pub fn add_holiday(&mut self, date: Date, name: Option<String>) {
+        self.holidays.insert(date, name);
+    }
+
+    /// The name of the holiday on `date`, if it is one.
+    pub fn holiday(&self, date: Date) -> Option<&str> {
+        self.holidays.get(&date)?.as_deref()
+    }
+
+    /// Whether funds move on `date`.
+    pub fn is_business_day(&self, date: Date) -> bool {
+        !date.is_weekend() && !self.holidays.contains_key(&date)
+    }
+
+    /// The business day `days` business days after `date`, T+`days`.
+    /// A weekend or holiday counts as the next business day, so T+0 is
+    /// the first business day on or after `date`.
+    pub fn add_business_days(&self, date: Date, days: u32) -> Date {
+        let mut date = date;
+        while !self.is_business_day(date) {
+            date = date.next();
+        }
+        for _ in 0..days {
+            date = date.next();
+            while !self.is_business_day(date) {
+                date = date.next();
+            }
+        }
+        date
+    }
+}
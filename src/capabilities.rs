@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::errors::RejectionCode;
+use crate::settlement::DEFAULT_SETTLEMENT_DAYS;
+use crate::state::CurrentState;
+
+/// Every Cargo feature of the engine, with whether this build has it.
+const FEATURES: [(&str, bool); 11] = [
+    ("cli", cfg!(feature = "cli")),
+    ("wasm", cfg!(feature = "wasm")),
+    ("datafusion", cfg!(feature = "datafusion")),
+    ("tokio", cfg!(feature = "tokio")),
+    ("smtp", cfg!(feature = "smtp")),
+    ("http", cfg!(feature = "http")),
+    ("warehouse", cfg!(feature = "warehouse")),
+    ("clickhouse", cfg!(feature = "clickhouse")),
+    ("encryption", cfg!(feature = "encryption")),
+    ("fixed-point", cfg!(feature = "fixed-point")),
+    ("chaos", cfg!(feature = "chaos")),
+];
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+/// What this build of the engine supports, so orchestration tooling
+/// can check that a deployed binary can run a configuration before
+/// running it.
+pub struct Capabilities {
+    /// The version of the engine.
+    pub version: &'static str,
+    /// The Cargo features the engine was built with.
+    pub features: Vec<&'static str>,
+    /// The formats input records can be read in.
+    pub input_formats: Vec<&'static str>,
+    /// The ways `serve` can take requests and share its records.
+    pub server_modes: Vec<&'static str>,
+    /// Where state is kept: in memory, and the files that can take
+    /// some of it out of memory.
+    pub backends: Vec<&'static str>,
+    /// How balances are kept: as `decimal`s, or as `fixed-point` minor
+    /// units.
+    pub balances: &'static str,
+    /// Every code a record can be rejected with.
+    pub rejection_codes: Vec<RejectionCode>,
+    /// The policies applied unless configured otherwise, keyed by the
+    /// name of their command-line flag.
+    pub defaults: BTreeMap<&'static str, String>,
+}
+
+/// What this build of the engine supports.
+pub fn capabilities() -> Capabilities {
+    let mut server_modes = vec!["stdio"];
+    if cfg!(unix) {
+        server_modes.push("ipc");
+    }
+    server_modes.extend(["http", "leader", "follower", "healthz"]);
+    let mut defaults = CurrentState::builder().describe_policies();
+    defaults.insert("settlement-days", DEFAULT_SETTLEMENT_DAYS.to_string());
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        features: FEATURES
+            .into_iter()
+            .filter_map(|(feature, enabled)| enabled.then_some(feature))
+            .collect(),
+        input_formats: vec!["csv", "jsonl"],
+        server_modes,
+        backends: vec!["memory", "archive", "seen-ids"],
+        balances: match cfg!(feature = "fixed-point") {
+            true => "fixed-point",
+            false => "decimal",
+        },
+        rejection_codes: RejectionCode::ALL.to_vec(),
+        defaults,
+    }
+}
@@ -0,0 +1,228 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{self, CaseError};
+use crate::state::CurrentState;
+use crate::transaction::DisputeInitiator;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+/// Where a dispute case stands. `open`, `resolved` and `charged_back`
+/// follow the records that open and close the dispute, and the others
+/// are set by case handlers in between.
+pub enum CaseStatus {
+    Open,
+    Investigating,
+    AwaitingEvidence,
+    Escalated,
+    Resolved,
+    ChargedBack,
+}
+
+impl CaseStatus {
+    /// Whether only records, rather than case handlers, set the status,
+    /// as it says what happened to the funds.
+    pub fn is_set_by_records(self) -> bool {
+        matches!(
+            self,
+            CaseStatus::Open | CaseStatus::Resolved | CaseStatus::ChargedBack
+        )
+    }
+
+    /// The name of the status, as it is serialized.
+    pub fn name(self) -> &'static str {
+        match self {
+            CaseStatus::Open => "open",
+            CaseStatus::Investigating => "investigating",
+            CaseStatus::AwaitingEvidence => "awaiting_evidence",
+            CaseStatus::Escalated => "escalated",
+            CaseStatus::Resolved => "resolved",
+            CaseStatus::ChargedBack => "charged_back",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+/// A change of the status of a case.
+pub struct StatusChange {
+    pub status: CaseStatus,
+    /// The record timestamp it happened at, if timestamps are present.
+    pub at: Option<u64>,
+    /// What the case handler noted with it, if anything.
+    pub note: Option<String>,
+}
+
+impl std::fmt::Display for StatusChange {
+    /// Writes the status, and when it was set if known, such as
+    /// `investigating@1700003600`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.at {
+            Some(at) => write!(f, "{}@{}", self.status.name(), at),
+            None => f.write_str(self.status.name()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+/// What is known about the dispute of one transaction beyond the funds
+/// it holds, so it can be worked as a case: who opened it and when, why,
+/// the evidence gathered, and every status it has been through.
+///
+/// A case is kept for every transaction ever disputed, and continues if
+/// the transaction is disputed again.
+pub struct Case {
+    pub tx: u32,
+    /// When the dispute was first opened, if timestamps are present.
+    pub opened: Option<u64>,
+    pub initiator: Option<DisputeInitiator>,
+    /// Why the transaction is disputed, in the case handler's words.
+    pub reason: Option<String>,
+    /// References to the evidence gathered, such as document IDs, in
+    /// the order they were added.
+    pub evidence: Vec<String>,
+    /// Every status of the case, oldest first, starting with `open`.
+    pub history: Vec<StatusChange>,
+}
+
+impl Case {
+    /// Opens the case of the dispute of `tx`.
+    pub(crate) fn open(tx: u32, at: Option<u64>, initiator: Option<DisputeInitiator>) -> Self {
+        Case {
+            tx,
+            opened: at,
+            initiator,
+            reason: None,
+            evidence: Vec::new(),
+            history: vec![StatusChange {
+                status: CaseStatus::Open,
+                at,
+                note: None,
+            }],
+        }
+    }
+
+    /// The current status of the case.
+    pub fn status(&self) -> CaseStatus {
+        // Cases are opened with a status, and never lose one.
+        self.history.last().unwrap().status
+    }
+
+    /// Moves the case to `status`, set by a record at `at`.
+    pub(crate) fn record_status(&mut self, status: CaseStatus, at: Option<u64>) {
+        self.history.push(StatusChange {
+            status,
+            at,
+            note: None,
+        });
+    }
+
+    /// Applies a case handler's `update`, made at `at`. A new status
+    /// goes in the history by when it was set.
+    pub fn apply(&mut self, update: &CaseUpdate, at: Option<u64>) -> Result<(), CaseError> {
+        if let Some(status) = update.status.filter(|status| status.is_set_by_records()) {
+            return Err(CaseError::StatusSetByRecords(status));
+        }
+        if update.note.is_some() && update.status.is_none() {
+            return Err(CaseError::NoteWithoutStatus);
+        }
+        if let Some(reason) = &update.reason {
+            self.reason = Some(reason.clone());
+        }
+        self.evidence.extend(update.evidence.iter().cloned());
+        if let Some(status) = update.status {
+            // Journaled updates are replayed once the records are, so
+            // they go in by when they were made, after any made then.
+            let position = self
+                .history
+                .iter()
+                .rposition(|change| change.at <= at)
+                .map_or(0, |i| i + 1);
+            let change = StatusChange {
+                status,
+                at,
+                note: update.note.clone(),
+            };
+            self.history.insert(position, change);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+/// A case handler's change to a case: any of a new status, optionally
+/// with a note, a reason, and evidence to add.
+pub struct CaseUpdate {
+    #[serde(default)]
+    pub status: Option<CaseStatus>,
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub evidence: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+/// A line of a case journal: an update to the case of `tx`, made at
+/// `at`, so replaying it gives the same history.
+pub struct JournalEntry {
+    pub tx: u32,
+    pub at: Option<u64>,
+    #[serde(flatten)]
+    pub update: CaseUpdate,
+}
+
+/// Reads a case journal, one JSON entry per line. Blank lines are
+/// skipped.
+pub fn read_journal(reader: impl std::io::Read) -> Result<Vec<JournalEntry>, errors::Error> {
+    let mut entries = Vec::new();
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(serde_json::from_str(&line)?);
+    }
+    Ok(entries)
+}
+
+/// Applies the updates of a case journal to `state`, as they were
+/// made. Updates that no longer apply, such as to a transaction the
+/// records given no longer dispute, are reported on `stderr` and
+/// skipped.
+pub fn replay(state: &mut CurrentState, entries: &[JournalEntry]) {
+    for entry in entries {
+        if let Err(err) = state.update_case(entry.tx, &entry.update, entry.at) {
+            eprintln!("Warning: case journal: {}", err);
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Case handlers' updates kept on disk, as the state is rebuilt from
+/// the records on every run and the updates are not records. Each
+/// entry is appended and synced before the update is acknowledged.
+pub struct CaseJournal {
+    file: File,
+}
+
+impl CaseJournal {
+    /// Opens the journal at `path` for appending, creating it if it
+    /// does not exist.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(CaseJournal { file })
+    }
+
+    /// Appends `entry` to the journal.
+    pub fn append(&mut self, entry: &JournalEntry) -> Result<(), errors::Error> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
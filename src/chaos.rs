@@ -0,0 +1,177 @@
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use rust_decimal::Decimal;
+
+use crate::generate::SplitMix64;
+use crate::transaction::Transaction;
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+/// The chance of each fault, from zero to one, so CI soak jobs can test
+/// how the engine and what drives it cope with bad feeds and disks.
+pub struct ChaosConfig {
+    /// The chance a record read is dropped.
+    pub drop: f64,
+    /// The chance a record read is processed twice.
+    pub duplicate: f64,
+    /// The chance reading goes on only after `delay_for`.
+    pub delay: f64,
+    /// How long a delayed record is held up.
+    pub delay_for: Duration,
+    /// The chance a record read has its client, ID or amount replaced
+    /// with one that still parses, such as an amount with its decimal
+    /// point moved.
+    pub corrupt: f64,
+    /// The chance a read or write of the archive or the seen set fails
+    /// with an I/O error.
+    pub backend: f64,
+    /// The seed faults are drawn with; the same seed and input give the
+    /// same faults.
+    pub seed: u64,
+}
+
+impl FromStr for ChaosConfig {
+    type Err = String;
+
+    /// Parses comma-separated `name=value` pairs, such as
+    /// `drop=0.01,duplicate=0.01,delay=0.001,delay-ms=50,corrupt=0.01,backend=0.001,seed=7`,
+    /// of which any may be left out.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = ChaosConfig::default();
+        for pair in s.split(',').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected `name=value`, got `{}`", pair))?;
+            let invalid = || format!("invalid value `{}` for `{}`", value, name);
+            let chance = || match value.parse::<f64>() {
+                Ok(chance) if (0.0..=1.0).contains(&chance) => Ok(chance),
+                _ => Err(invalid()),
+            };
+            match name {
+                "drop" => config.drop = chance()?,
+                "duplicate" => config.duplicate = chance()?,
+                "delay" => config.delay = chance()?,
+                "delay-ms" => {
+                    config.delay_for = Duration::from_millis(value.parse().map_err(|_| invalid())?)
+                }
+                "corrupt" => config.corrupt = chance()?,
+                "backend" => config.backend = chance()?,
+                "seed" => config.seed = value.parse().map_err(|_| invalid())?,
+                _ => return Err(format!("unknown fault `{}`", name)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug)]
+/// Injects faults into the records a state reads and the backends it
+/// keeps on disk, as a `ChaosConfig` says. Every fault injected is
+/// reported on `stderr`, so a failure can be traced back to it.
+///
+/// Faults are drawn from one generator shared by every state built
+/// from the same builder, so a parallel run does not draw them in the
+/// same order twice.
+pub struct Chaos {
+    config: ChaosConfig,
+    rng: Mutex<SplitMix64>,
+}
+
+impl Chaos {
+    pub fn new(config: ChaosConfig) -> Self {
+        Chaos {
+            config,
+            rng: Mutex::new(SplitMix64(config.seed)),
+        }
+    }
+
+    /// Whether a fault with this chance happens.
+    fn happens(&self, chance: f64) -> bool {
+        // Nothing panics while the generator is locked.
+        let draw = self.rng.lock().unwrap().next();
+        (draw as f64) < chance * u64::MAX as f64
+    }
+
+    /// A draw below `bound`, which must not be zero.
+    fn below(&self, bound: u64) -> u64 {
+        self.rng.lock().unwrap().below(bound)
+    }
+
+    /// The records to process in place of `tx`: none if it is dropped,
+    /// two if it is duplicated, and otherwise it, possibly corrupted
+    /// and after a delay.
+    pub(crate) fn perturb(&self, tx: Transaction) -> Vec<Transaction> {
+        if self.happens(self.config.drop) {
+            eprintln!("Chaos: dropped transaction ID `{}`", tx.id);
+            return Vec::new();
+        }
+        if self.happens(self.config.delay) {
+            eprintln!(
+                "Chaos: delayed transaction ID `{}` by {:?}",
+                tx.id, self.config.delay_for
+            );
+            thread::sleep(self.config.delay_for);
+        }
+        let tx = match self.happens(self.config.corrupt) {
+            true => self.corrupt(tx),
+            false => tx,
+        };
+        if self.happens(self.config.duplicate) {
+            eprintln!("Chaos: duplicated transaction ID `{}`", tx.id);
+            return vec![tx.clone(), tx];
+        }
+        vec![tx]
+    }
+
+    /// `tx` with its client, ID or amount replaced.
+    fn corrupt(&self, mut tx: Transaction) -> Transaction {
+        let id = tx.id;
+        match (self.below(3), tx.amount()) {
+            (0, _) => {
+                tx.client = self.below(u64::from(u16::MAX) + 1) as u16;
+                eprintln!(
+                    "Chaos: corrupted the client of transaction ID `{}` to `{}`",
+                    id, tx.client
+                );
+            }
+            (1, Some(amount)) => {
+                // A misplaced decimal point, by up to three places.
+                let places = self.below(3) as u32 + 1;
+                let factor = Decimal::from(10u32.pow(places));
+                let corrupted = match self.below(2) {
+                    0 => amount.checked_mul(factor).unwrap_or(amount),
+                    _ => amount / factor,
+                }
+                .normalize();
+                eprintln!(
+                    "Chaos: corrupted the amount of transaction ID `{}` to `{}`",
+                    id, corrupted
+                );
+                tx = tx.with_amount(corrupted);
+            }
+            _ => {
+                tx.id = self.below(u64::from(u32::MAX) + 1) as u32;
+                eprintln!(
+                    "Chaos: corrupted the ID of transaction ID `{}` to `{}`",
+                    id, tx.id
+                );
+            }
+        }
+        tx
+    }
+
+    /// Fails `operation` on a backend with an I/O error, if a backend
+    /// fault happens.
+    pub(crate) fn backend(&self, operation: &str) -> std::io::Result<()> {
+        if !self.happens(self.config.backend) {
+            return Ok(());
+        }
+        eprintln!("Chaos: failed {}", operation);
+        Err(std::io::Error::other(format!(
+            "injected failure of {}",
+            operation
+        )))
+    }
+}
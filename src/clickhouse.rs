@@ -0,0 +1,195 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::errors;
+use crate::events::{Event, EventSink};
+use crate::http::Endpoint;
+
+/// How long to wait for ClickHouse at each step of a request.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait before the first retry of a failed insert. Each
+/// retry after that waits twice as long as the one before.
+const FIRST_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The columns of the events table, in the order of `Event`'s fields.
+const COLUMNS: &str = "seq UInt64, kind LowCardinality(String), client UInt16, tx UInt32, \
+    available Nullable(Decimal(38, 8)), held Nullable(Decimal(38, 8)), \
+    total Nullable(Decimal(38, 8)), external_ref Nullable(String)";
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// Where and how events are inserted.
+pub struct ClickHouseConfig {
+    /// The table events are inserted into, created if it does not exist.
+    pub table: String,
+    /// The engine of the table, with its clauses, if it is created.
+    pub engine: String,
+    /// The most events sent in one insert.
+    pub batch_rows: usize,
+    /// The longest an event waits for its batch to fill before the
+    /// batch is sent anyway.
+    pub flush_interval: Duration,
+    /// How many times a failed insert is retried before its events are
+    /// dropped.
+    pub retries: u32,
+}
+
+impl Default for ClickHouseConfig {
+    fn default() -> Self {
+        ClickHouseConfig {
+            table: "events".to_owned(),
+            engine: "MergeTree ORDER BY (client, seq)".to_owned(),
+            batch_rows: 1000,
+            flush_interval: Duration::from_secs(1),
+            retries: 5,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Streams changelog events into a ClickHouse table through its HTTP
+/// interface, as `JSONEachRow` inserts.
+///
+/// Events are queued and inserted from a thread of their own, in
+/// batches of up to `batch_rows`, at most `flush_interval` after the
+/// first event of a batch arrives. A failed insert is retried with
+/// backoff, and its events are dropped with a warning if every retry
+/// fails, so an unreachable server cannot stall processing. Dropping
+/// the sink sends what is queued and waits for it.
+///
+/// Requests are plain HTTP, so the URL should be a local server or a
+/// proxy that adds TLS. Credentials can go in the URL's query, such as
+/// `http://localhost:8123/?user=engine&password=...`.
+pub struct ClickHouseSink {
+    /// Taken when the sink is dropped, so the thread sees the end.
+    events: Option<Sender<Event>>,
+    inserter: Option<JoinHandle<()>>,
+}
+
+impl ClickHouseSink {
+    /// Creates the table described by `config` on the server at `url`
+    /// if it does not exist, and starts inserting events into it.
+    pub fn start(url: &str, config: ClickHouseConfig) -> Result<Self, errors::Error> {
+        let endpoint = Endpoint::new(url).map_err(std::io::Error::other)?;
+        let create = format!(
+            "CREATE TABLE IF NOT EXISTS {} ({}) ENGINE = {}",
+            config.table, COLUMNS, config.engine
+        );
+        endpoint.post_json(&query_path(&endpoint, &create), &[], TIMEOUT)?;
+        let (events, received) = mpsc::channel();
+        let inserter = thread::spawn(move || insert_batches(&endpoint, &config, &received));
+        Ok(ClickHouseSink {
+            events: Some(events),
+            inserter: Some(inserter),
+        })
+    }
+}
+
+impl EventSink for ClickHouseSink {
+    fn publish(&self, events: Vec<Event>) {
+        if let Some(sender) = &self.events {
+            // The thread only ends once the sink is dropped.
+            events
+                .into_iter()
+                .for_each(|event| sender.send(event).unwrap());
+        }
+    }
+}
+
+impl Drop for ClickHouseSink {
+    fn drop(&mut self) {
+        self.events.take();
+        if let Some(inserter) = self.inserter.take() {
+            let _ = inserter.join();
+        }
+    }
+}
+
+/// Inserts the events received in batches until the sender is dropped,
+/// then inserts what is left.
+fn insert_batches(endpoint: &Endpoint, config: &ClickHouseConfig, received: &Receiver<Event>) {
+    let path = query_path(
+        endpoint,
+        &format!("INSERT INTO {} FORMAT JSONEachRow", config.table),
+    );
+    let mut batch = Vec::new();
+    let mut deadline: Option<Instant> = None;
+    loop {
+        let event = match deadline {
+            Some(deadline) => {
+                received.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => received.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let ended = match event {
+            Ok(event) => {
+                deadline.get_or_insert_with(|| Instant::now() + config.flush_interval);
+                batch.push(event);
+                if batch.len() < config.batch_rows {
+                    continue;
+                }
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+        if !batch.is_empty() {
+            insert(endpoint, &path, config.retries, &batch);
+            batch.clear();
+        }
+        deadline = None;
+        if ended {
+            return;
+        }
+    }
+}
+
+/// Inserts `batch` through `path`, retrying up to `retries` times.
+fn insert(endpoint: &Endpoint, path: &str, retries: u32, batch: &[Event]) {
+    let mut body = Vec::new();
+    for event in batch {
+        // Events are plain data, which always serializes.
+        serde_json::to_writer(&mut body, event).expect("events serialize to JSON");
+        body.push(b'\n');
+    }
+    let mut backoff = FIRST_BACKOFF;
+    for attempt in 0..=retries {
+        match endpoint.post_json(path, &body, TIMEOUT) {
+            Ok(_) => return,
+            Err(err) if attempt == retries => eprintln!(
+                "Warning: dropping {} events, as ClickHouse could not insert them: {}",
+                batch.len(),
+                err
+            ),
+            Err(_) => {
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+        }
+    }
+}
+
+/// The path of `endpoint` running `query`, after any parameters the
+/// URL already has.
+fn query_path(endpoint: &Endpoint, query: &str) -> String {
+    let path = endpoint.path();
+    let separator = match path.contains('?') {
+        true => '&',
+        false => '?',
+    };
+    format!("{}{}query={}", path, separator, percent_encode(query))
+}
+
+/// `s` with every byte other than letters, digits and `-_.~` written as
+/// `%XX`, for use in a URL query.
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
@@ -0,0 +1,277 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use clap::{Arg, Command};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// A shell that completion scripts can be generated for.
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    /// Parses `bash`, `zsh` or `fish`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(format!("invalid shell `{}`", s)),
+        }
+    }
+}
+
+/// Writes a script completing the subcommands and flags of `command`
+/// in `shell`, and file names wherever an input or a value goes.
+///
+/// Completion stops at the first level of subcommands, which is all
+/// the engine has. With no subcommand, file names are completed too,
+/// as a bare input file is processed.
+pub fn generate(shell: Shell, command: &mut Command, out: &mut impl Write) -> std::io::Result<()> {
+    // Adds the `help` subcommand and the `--help` and `--version` flags.
+    command.build();
+    match shell {
+        Shell::Bash => bash(command, out),
+        Shell::Zsh => zsh(command, out),
+        Shell::Fish => fish(command, out),
+    }
+}
+
+/// The subcommands of `command` shown in its help.
+fn subcommands<'a, 'help>(command: &'a Command<'help>) -> impl Iterator<Item = &'a Command<'help>> {
+    command.get_subcommands().filter(|sub| !sub.is_hide_set())
+}
+
+/// The flags of `command` shown in its help.
+fn flags<'a, 'help>(command: &'a Command<'help>) -> impl Iterator<Item = &'a Arg<'help>> {
+    command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+}
+
+/// The first sentence of the help of an item, on one line.
+fn summary(help: Option<&str>) -> String {
+    let help = help.unwrap_or_default();
+    // Abbreviations such as `e.g.` do not end a sentence.
+    let end = help
+        .match_indices(". ")
+        .map(|(i, _)| i)
+        .find(|&i| !help[..i].ends_with("e.g") && !help[..i].ends_with("i.e"))
+        .unwrap_or(help.len());
+    help[..end]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches('.')
+        .to_owned()
+}
+
+/// The name of `command` as it may appear in a shell function name.
+fn function_name(command: &Command) -> String {
+    command.get_name().replace('-', "_")
+}
+
+fn bash(command: &Command, out: &mut impl Write) -> std::io::Result<()> {
+    let name = command.get_name();
+    let function = function_name(command);
+    let words = |command: &Command| {
+        let mut words = Vec::new();
+        for arg in flags(command) {
+            if let Some(short) = arg.get_short() {
+                words.push(format!("-{}", short));
+            }
+            if let Some(long) = arg.get_long() {
+                words.push(format!("--{}", long));
+            }
+        }
+        words.join(" ")
+    };
+    let names: Vec<&str> = subcommands(command).map(Command::get_name).collect();
+    writeln!(out, "_{}() {{", function)?;
+    writeln!(
+        out,
+        "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\" sub=\"\" word"
+    )?;
+    writeln!(out, "    COMPREPLY=()")?;
+    writeln!(
+        out,
+        "    for word in \"${{COMP_WORDS[@]:1:COMP_CWORD-1}}\"; do"
+    )?;
+    writeln!(out, "        case \"$word\" in")?;
+    writeln!(
+        out,
+        "            {}) sub=\"$word\"; break ;;",
+        names.join("|")
+    )?;
+    writeln!(out, "        esac")?;
+    writeln!(out, "    done")?;
+    writeln!(out, "    local flags")?;
+    writeln!(out, "    case \"$sub\" in")?;
+    for sub in subcommands(command) {
+        writeln!(
+            out,
+            "        {}) flags=\"{}\" ;;",
+            sub.get_name(),
+            words(sub)
+        )?;
+    }
+    writeln!(out, "        *) flags=\"{}\" ;;", words(command))?;
+    writeln!(out, "    esac")?;
+    writeln!(out, "    if [[ \"$cur\" == -* ]]; then")?;
+    writeln!(
+        out,
+        "        COMPREPLY=($(compgen -W \"$flags\" -- \"$cur\"))"
+    )?;
+    writeln!(out, "    elif [[ -z \"$sub\" ]]; then")?;
+    writeln!(
+        out,
+        "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+        names.join(" ")
+    )?;
+    writeln!(out, "    fi")?;
+    writeln!(out, "}}")?;
+    writeln!(out, "complete -o default -F _{} {}", function, name)
+}
+
+/// `s` quoted for zsh's `_arguments` and `_describe`, between single
+/// quotes.
+fn zsh_escape(s: &str) -> String {
+    s.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+/// The `_arguments` specs of the flags of `command`.
+fn zsh_specs(command: &Command) -> Vec<String> {
+    let mut specs = Vec::new();
+    for arg in flags(command) {
+        let help = zsh_escape(&summary(arg.get_help()));
+        let repeat = match arg.is_multiple_occurrences_set() {
+            true => "*",
+            false => "",
+        };
+        let value = match arg.is_takes_value_set() {
+            true => format!(":{}:_files", arg.get_id()),
+            false => String::new(),
+        };
+        let names = [
+            arg.get_short().map(|short| format!("-{}", short)),
+            arg.get_long().map(|long| format!("--{}", long)),
+        ];
+        for name in names.into_iter().flatten() {
+            specs.push(format!("'{}{}[{}]{}'", repeat, name, help, value));
+        }
+    }
+    specs
+}
+
+fn zsh(command: &Command, out: &mut impl Write) -> std::io::Result<()> {
+    let function = function_name(command);
+    writeln!(out, "#compdef {}", command.get_name())?;
+    writeln!(out)?;
+    writeln!(out, "_{}_commands() {{", function)?;
+    writeln!(out, "    local commands; commands=(")?;
+    for sub in subcommands(command) {
+        let about = zsh_escape(&summary(sub.get_about()));
+        writeln!(out, "        '{}:{}'", sub.get_name(), about)?;
+    }
+    writeln!(out, "    )")?;
+    writeln!(out, "    _describe -t commands 'command' commands")?;
+    writeln!(out, "    _files")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "_{}() {{", function)?;
+    writeln!(out, "    local line state")?;
+    writeln!(out, "    _arguments -C \\")?;
+    for spec in zsh_specs(command) {
+        writeln!(out, "        {} \\", spec)?;
+    }
+    writeln!(out, "        '1: :_{}_commands' \\", function)?;
+    writeln!(out, "        '*:: :->args'")?;
+    writeln!(out, "    case $state in")?;
+    writeln!(out, "        args)")?;
+    writeln!(out, "            case $line[1] in")?;
+    for sub in subcommands(command) {
+        writeln!(out, "                {})", sub.get_name())?;
+        writeln!(out, "                    _arguments \\")?;
+        let mut specs = zsh_specs(sub);
+        if sub.get_arguments().any(Arg::is_positional) {
+            specs.push("'*:file:_files'".to_owned());
+        }
+        for (i, spec) in specs.iter().enumerate() {
+            let end = if i + 1 < specs.len() { " \\" } else { "" };
+            writeln!(out, "                        {}{}", spec, end)?;
+        }
+        writeln!(out, "                    ;;")?;
+    }
+    writeln!(out, "                *) _files ;;")?;
+    writeln!(out, "            esac")?;
+    writeln!(out, "            ;;")?;
+    writeln!(out, "    esac")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "_{} \"$@\"", function)
+}
+
+/// `s` quoted for fish, between single quotes.
+fn fish_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+/// The `complete` options for the flag `arg`.
+fn fish_flag(arg: &Arg) -> String {
+    let mut options = String::new();
+    if let Some(short) = arg.get_short() {
+        options += &format!(" -s {}", short);
+    }
+    if let Some(long) = arg.get_long() {
+        options += &format!(" -l {}", long);
+    }
+    if arg.is_takes_value_set() {
+        options += " -r -F";
+    }
+    options + &format!(" -d '{}'", fish_escape(&summary(arg.get_help())))
+}
+
+fn fish(command: &Command, out: &mut impl Write) -> std::io::Result<()> {
+    let name = command.get_name();
+    for arg in flags(command) {
+        writeln!(
+            out,
+            "complete -c {} -n '__fish_use_subcommand'{}",
+            name,
+            fish_flag(arg)
+        )?;
+    }
+    for sub in subcommands(command) {
+        writeln!(
+            out,
+            "complete -c {} -n '__fish_use_subcommand' -a {} -d '{}'",
+            name,
+            sub.get_name(),
+            fish_escape(&summary(sub.get_about()))
+        )?;
+    }
+    for sub in subcommands(command) {
+        let condition = format!("__fish_seen_subcommand_from {}", sub.get_name());
+        for arg in flags(sub) {
+            writeln!(
+                out,
+                "complete -c {} -n '{}'{}",
+                name,
+                condition,
+                fish_flag(arg)
+            )?;
+        }
+        // Only subcommands that take inputs complete file names.
+        if !sub.get_arguments().any(Arg::is_positional) {
+            writeln!(out, "complete -c {} -n '{}' -f", name, condition)?;
+        }
+    }
+    Ok(())
+}
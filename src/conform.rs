@@ -0,0 +1,240 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::diff::{self, Balances, ClientDiff};
+use crate::errors::{self, RejectionCode};
+use crate::state::{CsvClient, CurrentStateBuilder};
+use crate::transaction::Transaction;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+/// A test vector: records in the order they are applied, and the final
+/// client states and rejections they must produce. Vectors are JSON
+/// files, so they can be shared with other implementations of the same
+/// rules and run against each.
+pub struct TestVector {
+    #[serde(default)]
+    pub description: Option<String>,
+    /// The records, in the fields of the input format.
+    pub input: Vec<serde_json::Value>,
+    /// The state of every client at the end. Flags and risk scores are
+    /// not compared, as other implementations need not keep them.
+    #[serde(default)]
+    pub clients: Vec<CsvClient>,
+    /// Every record that is rejected, and why.
+    #[serde(default)]
+    pub rejects: Vec<ExpectedReject>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+/// A record a vector expects to be rejected.
+pub struct ExpectedReject {
+    /// The number of the record in the input, counting from one.
+    pub record: u64,
+    pub reason: RejectionCode,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// How the engine departed from a vector.
+pub enum Failure {
+    /// A record could not be read at all.
+    Unreadable { record: u64, message: String },
+    /// A client ended in a different state, or should or should not
+    /// exist. `left` is the expected state and `right` the actual one.
+    Client(ClientDiff),
+    /// A record was rejected for another reason, or rejected or
+    /// applied when it should not have been.
+    Reject {
+        record: u64,
+        expected: Option<RejectionCode>,
+        actual: Option<RejectionCode>,
+    },
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = |client: &Option<CsvClient>| match client {
+            Some(client) => format!(
+                "available {}, held {}, total {}, locked {}",
+                client.available, client.held, client.total, client.locked
+            ),
+            None => "no client".to_owned(),
+        };
+        let outcome = |reason: &Option<RejectionCode>| match reason {
+            Some(reason) => format!("rejected as `{}`", reason),
+            None => "applied".to_owned(),
+        };
+        match self {
+            Failure::Unreadable { record, message } => {
+                write!(f, "record {} could not be read: {}", record, message)
+            }
+            Failure::Client(diff) => write!(
+                f,
+                "client {}: expected {}, got {}",
+                diff.client,
+                state(&diff.left),
+                state(&diff.right)
+            ),
+            Failure::Reject {
+                record,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "record {}: expected to be {}, was {}",
+                record,
+                outcome(expected),
+                outcome(actual)
+            ),
+        }
+    }
+}
+
+/// Runs one vector against a state built by `builder`, returning how
+/// the engine departed from it, if at all. Only errors that abort
+/// processing, such as broken invariants, are returned as errors.
+pub fn run(
+    builder: &CurrentStateBuilder,
+    vector: &TestVector,
+) -> Result<Vec<Failure>, errors::Error> {
+    let mut state = builder.clone().build()?;
+    let mut failures = Vec::new();
+    let mut rejects = BTreeMap::new();
+    for (record, value) in (1..).zip(&vector.input) {
+        let result = Transaction::from_json(value.clone()).and_then(|tx| state.add(&tx));
+        if let Err(err) = result {
+            match err.rejection_code() {
+                Some(reason) => {
+                    rejects.insert(record, reason);
+                }
+                None if matches!(err, errors::Error::Json(_)) => {
+                    failures.push(Failure::Unreadable {
+                        record,
+                        message: err.to_string(),
+                    });
+                }
+                None => return Err(err),
+            }
+        }
+    }
+
+    let expected: Balances = vector
+        .clients
+        .iter()
+        .map(|client| (client.client, *client))
+        .collect();
+    let actual: Balances = state
+        .clients()
+        .map(|client| (client.client, client))
+        .collect();
+    failures.extend(
+        diff::delta_balances(&expected, &actual)
+            .into_iter()
+            .map(Failure::Client),
+    );
+
+    let expected: BTreeMap<u64, RejectionCode> = vector
+        .rejects
+        .iter()
+        .map(|reject| (reject.record, reject.reason))
+        .collect();
+    let records: BTreeSet<u64> = expected.keys().chain(rejects.keys()).copied().collect();
+    for record in records {
+        let (expected, actual) = (
+            expected.get(&record).copied(),
+            rejects.get(&record).copied(),
+        );
+        if expected != actual {
+            failures.push(Failure::Reject {
+                record,
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(failures)
+}
+
+#[derive(Debug, Clone)]
+/// The outcome of one vector file.
+pub struct VectorResult {
+    pub path: PathBuf,
+    /// Why the file could not be run, if it could not.
+    pub error: Option<String>,
+    pub failures: Vec<Failure>,
+}
+
+impl VectorResult {
+    /// Whether the engine conformed to the vector.
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.failures.is_empty()
+    }
+}
+
+/// Runs every `.json` vector directly in `dir`, in order of file name.
+/// A file that cannot be read or run fails on its own, without
+/// stopping the others.
+pub fn run_dir(
+    builder: &CurrentStateBuilder,
+    dir: &Path,
+) -> Result<Vec<VectorResult>, errors::Error> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file()
+            && path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+        {
+            paths.push(path);
+        }
+    }
+    paths.sort_unstable();
+    Ok(paths
+        .into_iter()
+        .map(|path| {
+            let result = File::open(&path)
+                .map_err(errors::Error::from)
+                .and_then(|file| Ok(serde_json::from_reader(std::io::BufReader::new(file))?))
+                .and_then(|vector: TestVector| run(builder, &vector));
+            match result {
+                Ok(failures) => VectorResult {
+                    path,
+                    error: None,
+                    failures,
+                },
+                Err(err) => VectorResult {
+                    path,
+                    error: Some(err.to_string()),
+                    failures: Vec::new(),
+                },
+            }
+        })
+        .collect())
+}
+
+/// Writes a report of `results`: one line per vector, each failure of
+/// a vector indented below it, and a count at the end.
+pub fn write_report(
+    mut writer: impl std::io::Write,
+    results: &[VectorResult],
+) -> std::io::Result<()> {
+    for result in results {
+        let verdict = match result.passed() {
+            true => "PASS",
+            false => "FAIL",
+        };
+        writeln!(writer, "{} {}", verdict, result.path.display())?;
+        if let Some(error) = &result.error {
+            writeln!(writer, "  {}", error)?;
+        }
+        for failure in &result.failures {
+            writeln!(writer, "  {}", failure)?;
+        }
+    }
+    let failed = results.iter().filter(|result| !result.passed()).count();
+    writeln!(writer, "{} vectors, {} failed", results.len(), failed)
+}
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::errors::{ConfigError, Error};
+
+#[derive(Debug, Deserialize)]
+/// A row of a counterparty mapping file.
+struct CsvCounterparty {
+    account: String,
+    client: u16,
+}
+
+#[derive(Debug, Default, Clone)]
+/// Maps external account identifiers, such as IBANs or bank account
+/// numbers, to internal client IDs, so bank feeds naming accounts
+/// can be processed directly.
+pub struct CounterpartyMap {
+    clients: HashMap<String, u16>,
+}
+
+/// Normalizes an account identifier, so `GB33 bukb 2020...` and
+/// `GB33BUKB2020...` name the same account.
+pub(crate) fn normalize(account: &str) -> String {
+    account
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+impl CounterpartyMap {
+    /// Reads a CSV file with `account` and `client` columns.
+    /// Each account may only appear once.
+    pub fn from_csv(reader: impl std::io::Read) -> Result<Self, Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut map = CounterpartyMap::default();
+        for row in rdr.deserialize() {
+            let row: CsvCounterparty = row?;
+            if map.insert(&row.account, row.client).is_some() {
+                return Err(ConfigError::DuplicateAccount(row.account).into());
+            }
+        }
+        Ok(map)
+    }
+
+    /// Maps `account` to `client`, returning the client it was
+    /// previously mapped to, if any.
+    pub fn insert(&mut self, account: &str, client: u16) -> Option<u16> {
+        self.clients.insert(normalize(account), client)
+    }
+
+    /// Removes the mapping for `account`, returning its client, if any.
+    pub fn remove(&mut self, account: &str) -> Option<u16> {
+        self.clients.remove(&normalize(account))
+    }
+
+    /// The client `account` is mapped to, if any.
+    pub fn client(&self, account: &str) -> Option<u16> {
+        self.clients.get(&normalize(account)).copied()
+    }
+
+    /// Maps every account of client `from` to client `to` instead.
+    pub(crate) fn reassign(&mut self, from: u16, to: u16) {
+        self.clients
+            .values_mut()
+            .filter(|client| **client == from)
+            .for_each(|client| *client = to);
+    }
+
+    /// The number of mapped accounts.
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Whether no accounts are mapped.
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+}
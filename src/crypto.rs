@@ -0,0 +1,101 @@
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+
+use crate::errors::CryptoError;
+
+/// The bytes every encrypted file starts with, naming the format so
+/// a future one can be told apart.
+const MAGIC: &[u8] = b"PEAES1";
+
+/// The length of an AES-GCM nonce.
+const NONCE_LEN: usize = 12;
+
+/// Supplies the key files are encrypted with, such as from the
+/// environment or a key management service.
+pub trait KeyProvider: Debug {
+    /// The 256-bit key.
+    fn key(&self) -> Result<[u8; 32], CryptoError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Where a key is read from, as named on the command line. Keys are
+/// written as 64 hexadecimal digits.
+pub enum KeySource {
+    /// `env:<variable>`.
+    Env(String),
+    /// `file:<path>`.
+    File(PathBuf),
+}
+
+impl KeyProvider for KeySource {
+    fn key(&self) -> Result<[u8; 32], CryptoError> {
+        let hex = match self {
+            KeySource::Env(name) => {
+                std::env::var(name).map_err(|_| CryptoError::MissingKey(name.clone()))?
+            }
+            KeySource::File(path) => std::fs::read_to_string(path)
+                .map_err(|_| CryptoError::MissingKey(path.display().to_string()))?,
+        };
+        parse_key(hex.trim())
+    }
+}
+
+impl FromStr for KeySource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("env", name)) => Ok(KeySource::Env(name.to_owned())),
+            Some(("file", path)) => Ok(KeySource::File(path.into())),
+            _ => Err(format!(
+                "expected `env:<variable>` or `file:<path>`, got `{}`",
+                s
+            )),
+        }
+    }
+}
+
+/// Parses a key written as 64 hexadecimal digits.
+fn parse_key(hex: &str) -> Result<[u8; 32], CryptoError> {
+    let mut key = [0; 32];
+    if hex.len() != 2 * key.len() || !hex.is_ascii() {
+        return Err(CryptoError::InvalidKey);
+    }
+    for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let digits = std::str::from_utf8(digits).map_err(|_| CryptoError::InvalidKey)?;
+        *byte = u8::from_str_radix(digits, 16).map_err(|_| CryptoError::InvalidKey)?;
+    }
+    Ok(key)
+}
+
+/// Encrypts `plaintext` with AES-256-GCM under a fresh random nonce.
+/// The result carries the format marker and nonce, so only the key is
+/// needed to decrypt it.
+pub fn encrypt(key: &impl KeyProvider, plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let key = key.key()?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| CryptoError::Encryption)?;
+    Ok([MAGIC, nonce.as_slice(), &ciphertext].concat())
+}
+
+/// Decrypts data written by `encrypt`, failing if it was not
+/// encrypted, was encrypted under another key, or was altered.
+pub fn decrypt(key: &impl KeyProvider, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let rest = data
+        .strip_prefix(MAGIC)
+        .filter(|rest| rest.len() >= NONCE_LEN)
+        .ok_or(CryptoError::NotEncrypted)?;
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let key = key.key()?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| CryptoError::Decryption)
+}
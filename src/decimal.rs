@@ -0,0 +1,111 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::errors::SchemaError;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// How amounts in the input are written.
+pub enum DecimalStyle {
+    /// `1,234.56`: commas group thousands, a point starts the fraction.
+    Us,
+    /// `1.234,56`: points group thousands, a comma starts the fraction.
+    Eu,
+}
+
+impl DecimalStyle {
+    /// The thousands separator and the decimal separator.
+    fn separators(self) -> (char, char) {
+        match self {
+            DecimalStyle::Us => (',', '.'),
+            DecimalStyle::Eu => ('.', ','),
+        }
+    }
+
+    /// Rewrites an amount in this style into the plain form `1234.56`,
+    /// or returns `None` if it is not written in this style. Thousands
+    /// separators are optional, but must group exactly three digits.
+    pub fn normalize(self, amount: &str) -> Option<String> {
+        let (thousands, decimal) = self.separators();
+        let (sign, unsigned) = match amount.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", amount.strip_prefix('+').unwrap_or(amount)),
+        };
+        let (integer, fraction) = match unsigned.split_once(decimal) {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (unsigned, None),
+        };
+        let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        let mut groups = integer.split(thousands);
+        let first = groups.next().unwrap_or_default();
+        let grouped = integer.contains(thousands);
+        if !is_digits(first) || (grouped && first.len() > 3) {
+            return None;
+        }
+        let mut normalized = format!("{}{}", sign, first);
+        for group in groups {
+            if group.len() != 3 || !is_digits(group) {
+                return None;
+            }
+            normalized.push_str(group);
+        }
+        if let Some(fraction) = fraction {
+            if !is_digits(fraction) {
+                return None;
+            }
+            normalized.push('.');
+            normalized.push_str(fraction);
+        }
+        Some(normalized)
+    }
+
+    /// Rewrites the amount in field `column` of `record` into the plain
+    /// form. Empty amounts are left as they are.
+    pub fn normalize_record(
+        self,
+        record: &csv::StringRecord,
+        column: usize,
+    ) -> Result<csv::StringRecord, SchemaError> {
+        let amount = match record.get(column) {
+            Some(amount) if !amount.is_empty() => amount,
+            _ => return Ok(record.clone()),
+        };
+        let normalized =
+            self.normalize(amount)
+                .ok_or_else(|| SchemaError::DecimalStyleMismatch {
+                    row: record.position().map_or(0, csv::Position::line),
+                    amount: amount.to_owned(),
+                    style: self,
+                })?;
+        let mut rewritten: csv::StringRecord = record
+            .iter()
+            .enumerate()
+            .map(|(index, field)| if index == column { &normalized } else { field })
+            .collect();
+        rewritten.set_position(record.position().cloned());
+        Ok(rewritten)
+    }
+}
+
+impl fmt::Display for DecimalStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DecimalStyle::Us => "us",
+            DecimalStyle::Eu => "eu",
+        })
+    }
+}
+
+impl FromStr for DecimalStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "us" => Ok(DecimalStyle::Us),
+            "eu" => Ok(DecimalStyle::Eu),
+            _ => Err(format!(
+                "unknown decimal style `{}`, expected `us` or `eu`",
+                s
+            )),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::state::CsvClient;
+
+/// Final client states read back from an output file, keyed by client ID.
+pub type Balances = BTreeMap<u16, CsvClient>;
+
+/// Reads final client states as written by `CurrentState::into_csv`.
+pub fn read_balances(reader: impl std::io::Read) -> Result<Balances, csv::Error> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    rdr.deserialize::<CsvClient>()
+        .map(|client| client.map(|client| (client.client, client)))
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// A client whose state differs between two sets of balances.
+/// A side is `None` if the client is missing from it.
+pub struct ClientDiff {
+    pub client: u16,
+    pub left: Option<CsvClient>,
+    pub right: Option<CsvClient>,
+}
+
+/// Lists the clients whose states differ, in order of client ID.
+pub fn diff_balances(left: &Balances, right: &Balances) -> Vec<ClientDiff> {
+    let ids: BTreeSet<u16> = left.keys().chain(right.keys()).copied().collect();
+    ids.into_iter()
+        .filter_map(|client| {
+            let left = left.get(&client).copied();
+            let right = right.get(&client).copied();
+            (left != right).then_some(ClientDiff {
+                client,
+                left,
+                right,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+/// One side of a `ClientDiff`, used for serialization.
+struct CsvDiffSide {
+    client: u16,
+    side: &'static str,
+    available: Option<Decimal>,
+    held: Option<Decimal>,
+    total: Option<Decimal>,
+    locked: Option<bool>,
+}
+
+impl CsvDiffSide {
+    fn new(client: u16, side: &'static str, state: Option<CsvClient>) -> Self {
+        CsvDiffSide {
+            client,
+            side,
+            available: state.map(|s| s.available),
+            held: state.map(|s| s.held),
+            total: state.map(|s| s.total),
+            locked: state.map(|s| s.locked),
+        }
+    }
+}
+
+/// Writes differences as CSV, one row per side of each difference.
+pub fn write_diffs(writer: impl std::io::Write, diffs: &[ClientDiff]) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    diffs.iter().try_for_each(|diff| {
+        wtr.serialize(CsvDiffSide::new(diff.client, "left", diff.left))?;
+        wtr.serialize(CsvDiffSide::new(diff.client, "right", diff.right))
+    })?;
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Whether the balances or lock status of a client differ, leaving out
+/// flags and scores, which downstream ledgers do not track.
+fn balances_differ(old: Option<&CsvClient>, new: Option<&CsvClient>) -> bool {
+    match (old, new) {
+        (Some(old), Some(new)) => {
+            (old.available, old.held, old.total, old.fees, old.locked)
+                != (new.available, new.held, new.total, new.fees, new.locked)
+        }
+        (old, new) => old.is_some() != new.is_some(),
+    }
+}
+
+/// Lists the clients whose balances or lock status changed from `old`
+/// to `new`, in order of client ID.
+pub fn delta_balances(old: &Balances, new: &Balances) -> Vec<ClientDiff> {
+    let ids: BTreeSet<u16> = old.keys().chain(new.keys()).copied().collect();
+    ids.into_iter()
+        .filter(|client| balances_differ(old.get(client), new.get(client)))
+        .map(|client| ClientDiff {
+            client,
+            left: old.get(&client).copied(),
+            right: new.get(&client).copied(),
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+/// One row of a delta, with the old and new values of a client.
+struct CsvDelta {
+    client: u16,
+    /// `added`, `changed` or `removed`.
+    change: &'static str,
+    old_available: Option<Decimal>,
+    old_held: Option<Decimal>,
+    old_total: Option<Decimal>,
+    old_locked: Option<bool>,
+    available: Option<Decimal>,
+    held: Option<Decimal>,
+    total: Option<Decimal>,
+    locked: Option<bool>,
+}
+
+impl CsvDelta {
+    fn new(diff: &ClientDiff) -> Self {
+        let (old, new) = (diff.left, diff.right);
+        CsvDelta {
+            client: diff.client,
+            change: match (old, new) {
+                (None, _) => "added",
+                (_, None) => "removed",
+                _ => "changed",
+            },
+            old_available: old.map(|s| s.available),
+            old_held: old.map(|s| s.held),
+            old_total: old.map(|s| s.total),
+            old_locked: old.map(|s| s.locked),
+            available: new.map(|s| s.available),
+            held: new.map(|s| s.held),
+            total: new.map(|s| s.total),
+            locked: new.map(|s| s.locked),
+        }
+    }
+}
+
+/// Writes a delta as CSV, one row per changed client, with the old
+/// values on the left, left empty for added clients, and the new values
+/// on the right, left empty for removed ones.
+pub fn write_delta(writer: impl std::io::Write, diffs: &[ClientDiff]) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    if diffs.is_empty() {
+        wtr.write_record([
+            "client",
+            "change",
+            "old_available",
+            "old_held",
+            "old_total",
+            "old_locked",
+            "available",
+            "held",
+            "total",
+            "locked",
+        ])?;
+    }
+    diffs
+        .iter()
+        .try_for_each(|diff| wtr.serialize(CsvDelta::new(diff)))?;
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+/// An order-independent digest over a set of entries.
+/// Each entry contributes its own SHA-256 digest, summed modulo
+/// 2^128, so entries can be added and removed incrementally and
+/// the result does not depend on hash map iteration order.
+pub struct StateHash(u128);
+
+impl StateHash {
+    /// Digests a single entry.
+    fn entry(entry: &[u8]) -> u128 {
+        let digest = Sha256::digest(entry);
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&digest[..16]);
+        u128::from_be_bytes(bytes)
+    }
+
+    /// Adds an entry to the set.
+    pub(crate) fn insert(&mut self, entry: &[u8]) {
+        self.0 = self.0.wrapping_add(Self::entry(entry));
+    }
+
+    /// Removes a previously added entry from the set.
+    pub(crate) fn remove(&mut self, entry: &[u8]) {
+        self.0 = self.0.wrapping_sub(Self::entry(entry));
+    }
+}
+
+impl fmt::Display for StateHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
@@ -0,0 +1,155 @@
+use rust_decimal::Decimal;
+use serde::{Serialize, Serializer};
+
+use crate::calendar::{Calendar, Date};
+use crate::cases::{Case, CaseStatus, StatusChange};
+use crate::lots::TracedWithdrawal;
+use crate::transaction::DisputeInitiator;
+
+/// The number of seconds in the year a float rate is quoted for.
+const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+/// How a dispute ended, if it has.
+pub enum DisputeOutcome {
+    Open,
+    Resolved,
+    ChargedBack,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+/// Funds held for one dispute, and what holding them cost.
+/// Used for serialization.
+pub struct HeldFunds {
+    pub client: u16,
+    pub tx: u32,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub amount: Decimal,
+    pub outcome: DisputeOutcome,
+    /// The reason code of the record that closed the dispute, or of the
+    /// dispute if it is open or the record gave none.
+    pub reason_code: Option<String>,
+    /// When the dispute was opened, if timestamps are present.
+    pub opened: Option<u64>,
+    /// When the dispute was resolved or charged back.
+    pub closed: Option<u64>,
+    /// How long the funds were held, up to the latest timestamp seen
+    /// for disputes that are still open.
+    pub held_seconds: Option<u64>,
+    /// The cost of holding the funds at the configured annual rate.
+    #[serde(with = "rust_decimal::serde::str_option")]
+    pub float_cost: Option<Decimal>,
+    /// The business day the dispute must be resolved or charged back
+    /// by, if a deadline is configured.
+    pub deadline: Option<Date>,
+    /// The withdrawals that spent the funds of the disputed deposit,
+    /// oldest first, if funds are traced. Written as `tx:amount` pairs
+    /// separated by `;`.
+    #[serde(serialize_with = "serialize_joined")]
+    pub traced_withdrawals: Vec<TracedWithdrawal>,
+    /// Who opened the dispute, if the record says.
+    pub initiator: Option<DisputeInitiator>,
+    /// Where the dispute's case stands.
+    pub status: Option<CaseStatus>,
+    /// Why the transaction is disputed, as the case handler put it.
+    pub reason: Option<String>,
+    /// The references to the evidence gathered for the case, separated
+    /// by `;`.
+    #[serde(serialize_with = "serialize_joined")]
+    pub evidence: Vec<String>,
+    /// Every status of the case, oldest first, as `status@timestamp`
+    /// or just `status` without timestamps, separated by `;`.
+    #[serde(serialize_with = "serialize_joined")]
+    pub status_history: Vec<StatusChange>,
+}
+
+/// Writes `items` as one field, so the report stays flat CSV.
+fn serialize_joined<S: Serializer, T: std::fmt::Display>(
+    items: &[T],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let items: Vec<String> = items.iter().map(ToString::to_string).collect();
+    serializer.serialize_str(&items.join(";"))
+}
+
+impl HeldFunds {
+    /// Records funds held from `opened` until `closed`.
+    pub(crate) fn new(
+        client: u16,
+        tx: u32,
+        amount: Decimal,
+        outcome: DisputeOutcome,
+        opened: Option<u64>,
+        closed: Option<u64>,
+    ) -> Self {
+        HeldFunds {
+            client,
+            tx,
+            amount,
+            outcome,
+            reason_code: None,
+            opened,
+            closed,
+            held_seconds: None,
+            float_cost: None,
+            deadline: None,
+            traced_withdrawals: Vec::new(),
+            initiator: None,
+            status: None,
+            reason: None,
+            evidence: Vec::new(),
+            status_history: Vec::new(),
+        }
+    }
+
+    /// Prices the funds held until `until`, at an annual `rate`.
+    pub(crate) fn priced(mut self, until: Option<u64>, rate: Option<Decimal>) -> Self {
+        self.held_seconds = self
+            .opened
+            .zip(self.closed.or(until))
+            .map(|(opened, until)| until.saturating_sub(opened));
+        self.float_cost = self.held_seconds.zip(rate).map(|(seconds, rate)| {
+            (self.amount * rate * Decimal::from(seconds) / Decimal::from(SECONDS_PER_YEAR))
+                .round_dp(4)
+        });
+        self
+    }
+
+    /// Dates the deadline `days` business days of `calendar` after the
+    /// dispute was opened.
+    pub(crate) fn due(mut self, calendar: &Calendar, days: Option<u32>) -> Self {
+        self.deadline = self
+            .opened
+            .zip(days)
+            .map(|(opened, days)| calendar.add_business_days(Date::from_timestamp(opened), days));
+        self
+    }
+
+    /// Adds what `case`, the case of the dispute, says about it.
+    pub(crate) fn with_case(mut self, case: Option<&Case>) -> Self {
+        if let Some(case) = case {
+            self.initiator = case.initiator;
+            self.status = Some(case.status());
+            self.reason.clone_from(&case.reason);
+            self.evidence.clone_from(&case.evidence);
+            self.status_history.clone_from(&case.history);
+        }
+        self
+    }
+}
+
+/// Writes held funds as CSV.
+pub fn write_csv<'a>(
+    writer: impl std::io::Write,
+    entries: impl IntoIterator<Item = &'a HeldFunds>,
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    entries
+        .into_iter()
+        .try_for_each(|entry| wtr.serialize(entry))?;
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{ConfigError, Error};
+use crate::transaction::Transaction;
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone)]
+/// Fields looked up for a record. Only the fields a record lacks are
+/// filled in; fields the record gives are kept.
+pub struct Enrichment {
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub merchant: Option<String>,
+}
+
+impl Enrichment {
+    /// Fills in the fields `tx` lacks.
+    pub(crate) fn fill(&self, tx: &mut Transaction) {
+        tx.timestamp = tx.timestamp.or(self.timestamp);
+        if tx.currency.is_none() {
+            tx.currency.clone_from(&self.currency);
+        }
+        if tx.category.is_none() {
+            tx.category.clone_from(&self.category);
+        }
+        if tx.merchant.is_none() {
+            tx.merchant.clone_from(&self.merchant);
+        }
+    }
+}
+
+/// Looks up fields that raw feeds lack, such as the currency, category
+/// or merchant of a record, from an external source, so policies that
+/// need them can run on those feeds.
+///
+/// Records are enriched before middleware and the engine's checks see
+/// them. Records read from a CSV stream are looked up in batches of
+/// `batch_size`, and the results are cached by key, so each key is
+/// looked up once.
+pub trait Enricher: Debug + Send + Sync {
+    /// The key `tx` is looked up by, if it is looked up at all.
+    fn key(&self, tx: &Transaction) -> Option<String>;
+
+    /// Looks up `keys`. Keys left out of the result are unknown, and
+    /// are not looked up again.
+    fn lookup(&self, keys: &[String]) -> Result<HashMap<String, Enrichment>, Error>;
+
+    /// The most keys looked up at once.
+    fn batch_size(&self) -> usize {
+        100
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// The field of a record it is looked up by.
+pub enum LookupKey {
+    /// The client, for records naming one rather than an account.
+    #[default]
+    Client,
+    Account,
+    ExternalRef,
+}
+
+impl LookupKey {
+    /// The key of `tx`, if it has the field.
+    pub fn of(self, tx: &Transaction) -> Option<String> {
+        match self {
+            LookupKey::Client => tx.account.is_none().then(|| tx.client.to_string()),
+            LookupKey::Account => tx.account.clone(),
+            LookupKey::ExternalRef => tx.external_ref.clone(),
+        }
+    }
+}
+
+impl FromStr for LookupKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(LookupKey::Client),
+            "account" => Ok(LookupKey::Account),
+            "external_ref" => Ok(LookupKey::ExternalRef),
+            _ => Err(format!(
+                "expected `client`, `account` or `external_ref`, got `{}`",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+/// A row of an enrichment file. Used for deserialization.
+struct CsvEnrichment {
+    key: String,
+    #[serde(default)]
+    timestamp: Option<u64>,
+    #[serde(default)]
+    currency: Option<String>,
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default)]
+    merchant: Option<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+/// Looks records up in a table read from a file once, such as a nightly
+/// export of merchant data.
+pub struct FileEnricher {
+    by: LookupKey,
+    table: HashMap<String, Enrichment>,
+}
+
+impl FileEnricher {
+    /// Reads a CSV file with a `key` column, holding the field `by` of
+    /// the records to enrich, and any of `timestamp`, `currency`,
+    /// `category` and `merchant`. Each key may only appear once.
+    pub fn from_csv(by: LookupKey, reader: impl std::io::Read) -> Result<Self, Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut table = HashMap::new();
+        for row in rdr.deserialize() {
+            let row: CsvEnrichment = row?;
+            let enrichment = Enrichment {
+                timestamp: row.timestamp,
+                currency: row.currency,
+                category: row.category,
+                merchant: row.merchant,
+            };
+            if table.insert(row.key.clone(), enrichment).is_some() {
+                return Err(ConfigError::DuplicateEnrichmentKey(row.key).into());
+            }
+        }
+        Ok(FileEnricher { by, table })
+    }
+}
+
+impl Enricher for FileEnricher {
+    fn key(&self, tx: &Transaction) -> Option<String> {
+        self.by.of(tx)
+    }
+
+    fn lookup(&self, keys: &[String]) -> Result<HashMap<String, Enrichment>, Error> {
+        Ok(keys
+            .iter()
+            .filter_map(|key| Some((key.clone(), self.table.get(key)?.clone())))
+            .collect())
+    }
+
+    /// The table is in memory, so there is nothing to save by batching.
+    fn batch_size(&self) -> usize {
+        1
+    }
+}
+
+#[cfg(feature = "http")]
+pub use http::HttpEnricher;
+
+#[cfg(feature = "http")]
+mod http {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::{Enricher, Enrichment, LookupKey};
+    use crate::errors::Error;
+    use crate::http::Endpoint;
+    use crate::transaction::Transaction;
+
+    /// How long to wait for the service at each step of a lookup.
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[derive(Debug, Clone)]
+    /// POSTs each batch of keys to a lookup service as
+    /// `{"keys": [...]}`, which replies with a JSON object of the fields
+    /// of each key it knows, such as `{"17": {"currency": "EUR"}}`.
+    ///
+    /// The service is spoken to in plain HTTP, so it should be local, or
+    /// behind a local proxy that handles TLS, such as a sidecar in front
+    /// of a Redis cache. Records are left as they are if the service
+    /// cannot be reached.
+    pub struct HttpEnricher {
+        endpoint: Endpoint,
+        by: LookupKey,
+        batch_size: usize,
+    }
+
+    impl HttpEnricher {
+        /// Looks up the field `by` of records at `url`, such as
+        /// `http://localhost:8080/lookup`, up to `batch_size` at once.
+        pub fn new(url: &str, by: LookupKey, batch_size: usize) -> Result<Self, String> {
+            Ok(HttpEnricher {
+                endpoint: Endpoint::new(url)?,
+                by,
+                batch_size: batch_size.max(1),
+            })
+        }
+    }
+
+    impl Enricher for HttpEnricher {
+        fn key(&self, tx: &Transaction) -> Option<String> {
+            self.by.of(tx)
+        }
+
+        fn lookup(&self, keys: &[String]) -> Result<HashMap<String, Enrichment>, Error> {
+            let body = serde_json::to_vec(&json!({ "keys": keys }))?;
+            let reply = self
+                .endpoint
+                .post_json(self.endpoint.path(), &body, TIMEOUT)?;
+            Ok(serde_json::from_str(&reply)?)
+        }
+
+        fn batch_size(&self) -> usize {
+            self.batch_size
+        }
+    }
+}
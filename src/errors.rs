@@ -1,5 +1,11 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use thiserror::Error;
 
+use crate::cases::CaseStatus;
+use crate::decimal::DecimalStyle;
+use crate::transaction::{DisputeInitiator, Transaction};
+
 #[derive(Debug, Error)]
 pub enum TransactionError {
     #[error("transation with ID `{0}` already exists")]
@@ -18,6 +24,64 @@ pub enum TransactionError {
     MissingAmount(u32),
     #[error("superfluous amount for transaction ID `{0}`")]
     SuperfluousAmount(u32),
+    #[error("transation with ID `{0}` exceeded the maximum allowed amount")]
+    AmountAboveLimit(u32),
+    #[error("missing timestamp for transaction ID `{0}`")]
+    MissingTimestamp(u32),
+    #[error("dispute window for transaction ID `{0}` has elapsed")]
+    DisputeWindowElapsed(u32),
+    #[error("transation with ID `{0}` had a zero amount")]
+    ZeroAmount(u32),
+    #[error("missing operator reference for adjustment with ID `{0}`")]
+    MissingReference(u32),
+    #[error("adjustment with ID `{0}` was rejected because adjustments are disabled")]
+    AdjustmentsDisabled(u32),
+    #[error("transation with ID `{0}` cannot be disputed")]
+    NotDisputable(u32),
+    #[error("superfluous initiator for transaction ID `{0}`")]
+    SuperfluousInitiator(u32),
+    #[error("missing client or account for transaction ID `{0}`")]
+    MissingClient(u32),
+    #[error("transation with ID `{0}` named both a client and an account")]
+    SuperfluousAccount(u32),
+    #[error("account for transaction ID `{0}` is not mapped to a client")]
+    UnknownAccount(u32),
+    #[error("transation with ID `{0}` is not quarantined")]
+    NotQuarantined(u32),
+    #[error("transation with ID `{0}` has been disputed again too many times")]
+    ReDisputeLimitReached(u32),
+    #[error("transation with ID `{0}` has unknown type `{1}`")]
+    UnknownType(u32, String),
+    #[error("transation with ID `{0}` uses an ID reserved for the engine")]
+    ReservedId(u32),
+    #[error("no engine transaction ID was left for transaction ID `{0}`")]
+    IdsExhausted(u32),
+    #[error("transation with ID `{0}` was refused by middleware: {1}")]
+    RefusedByMiddleware(u32, String),
+    #[error("transation with ID `{0}` gave unknown reason code `{1}`")]
+    UnknownReasonCode(u32, String),
+    #[error("superfluous reason code for transaction ID `{0}`")]
+    SuperfluousReasonCode(u32),
+    #[error("transation with ID `{0}` would overflow a balance or running total")]
+    ArithmeticOverflow(u32),
+    #[error(
+        "transation with ID `{0}` has sequence number `{1}`, but its client is already at `{2}`"
+    )]
+    OutOfOrder(u32, u64, u64),
+    #[error(
+        "the amount of transaction ID `{0}` has more decimal places than balances are kept to"
+    )]
+    UnrepresentableAmount(u32),
+    #[error("transation with ID `{0}` names a client or account on the denylist")]
+    Screened(u32),
+    #[error("transation with ID `{0}` made the engine fail: {1}")]
+    Internal(u32, String),
+    #[error("transation with ID `{0}` would add a client beyond the client limit")]
+    ClientLimitReached(u32),
+    #[error("dispute for transaction ID `{0}` would exceed the open dispute limit")]
+    OpenDisputeLimitReached(u32),
+    #[error("transation with ID `{0}` would take the state beyond its memory limit")]
+    MemoryLimitReached(u32),
 }
 
 #[derive(Debug, Error)]
@@ -26,6 +90,562 @@ pub enum ClientError {
     Locked(u32),
     #[error("client for transaction ID `{0}` had insufficient funds")]
     InsufficientFunds(u32),
+    #[error("client for transaction ID `{0}` is the suspense account")]
+    SuspenseAccount(u32),
+    #[error("withdrawal with ID `{0}` would dip into the buffer kept for open disputes")]
+    DisputeHoldBuffer(u32),
+    #[error("client for transaction ID `{0}` has been merged into another client")]
+    Merged(u32),
+    #[error("client frozen for transaction ID `{0}`")]
+    Frozen(u32),
+    #[error("transation with ID `{0}` would take its client past its hard daily limit")]
+    DailyLimitExceeded(u32),
+    #[error("deposit with ID `{0}` would take its client past its hard balance limit")]
+    BalanceLimitExceeded(u32),
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+/// A stable code for every reason a record can be rejected, so
+/// integrators can branch on codes rather than error messages.
+/// Once assigned, the numeric and string forms never change.
+pub enum RejectionCode {
+    AlreadyExists,
+    NonexistentTransaction,
+    AmountNotPositive,
+    ClientMismatch,
+    NonexistentDispute,
+    DisputeAlreadyExists,
+    MissingAmount,
+    SuperfluousAmount,
+    AmountAboveLimit,
+    MissingTimestamp,
+    DisputeWindowElapsed,
+    ZeroAmount,
+    MissingReference,
+    AdjustmentsDisabled,
+    NotDisputable,
+    SuperfluousInitiator,
+    MissingClient,
+    SuperfluousAccount,
+    UnknownAccount,
+    NotQuarantined,
+    ReDisputeLimitReached,
+    UnknownType,
+    ReservedId,
+    IdsExhausted,
+    RefusedByMiddleware,
+    UnknownReasonCode,
+    SuperfluousReasonCode,
+    ArithmeticOverflow,
+    OutOfOrder,
+    UnrepresentableAmount,
+    Screened,
+    InternalError,
+    ClientLimitReached,
+    OpenDisputeLimitReached,
+    MemoryLimitReached,
+    Locked,
+    InsufficientFunds,
+    SuspenseAccount,
+    DisputeHoldBuffer,
+    Merged,
+    Frozen,
+    DailyLimitExceeded,
+    BalanceLimitExceeded,
+}
+
+impl RejectionCode {
+    /// Every code, in declaration order.
+    pub const ALL: [RejectionCode; 43] = [
+        RejectionCode::AlreadyExists,
+        RejectionCode::NonexistentTransaction,
+        RejectionCode::AmountNotPositive,
+        RejectionCode::ClientMismatch,
+        RejectionCode::NonexistentDispute,
+        RejectionCode::DisputeAlreadyExists,
+        RejectionCode::MissingAmount,
+        RejectionCode::SuperfluousAmount,
+        RejectionCode::AmountAboveLimit,
+        RejectionCode::MissingTimestamp,
+        RejectionCode::DisputeWindowElapsed,
+        RejectionCode::ZeroAmount,
+        RejectionCode::MissingReference,
+        RejectionCode::AdjustmentsDisabled,
+        RejectionCode::NotDisputable,
+        RejectionCode::SuperfluousInitiator,
+        RejectionCode::MissingClient,
+        RejectionCode::SuperfluousAccount,
+        RejectionCode::UnknownAccount,
+        RejectionCode::NotQuarantined,
+        RejectionCode::ReDisputeLimitReached,
+        RejectionCode::UnknownType,
+        RejectionCode::ReservedId,
+        RejectionCode::IdsExhausted,
+        RejectionCode::RefusedByMiddleware,
+        RejectionCode::UnknownReasonCode,
+        RejectionCode::SuperfluousReasonCode,
+        RejectionCode::ArithmeticOverflow,
+        RejectionCode::OutOfOrder,
+        RejectionCode::UnrepresentableAmount,
+        RejectionCode::Screened,
+        RejectionCode::InternalError,
+        RejectionCode::ClientLimitReached,
+        RejectionCode::OpenDisputeLimitReached,
+        RejectionCode::MemoryLimitReached,
+        RejectionCode::Locked,
+        RejectionCode::InsufficientFunds,
+        RejectionCode::SuspenseAccount,
+        RejectionCode::DisputeHoldBuffer,
+        RejectionCode::Merged,
+        RejectionCode::Frozen,
+        RejectionCode::DailyLimitExceeded,
+        RejectionCode::BalanceLimitExceeded,
+    ];
+
+    /// The numeric form of the code.
+    /// Transaction errors are in the 100s, client errors in the 200s.
+    pub fn code(self) -> u16 {
+        match self {
+            RejectionCode::AlreadyExists => 100,
+            RejectionCode::NonexistentTransaction => 101,
+            RejectionCode::AmountNotPositive => 102,
+            RejectionCode::ClientMismatch => 103,
+            RejectionCode::NonexistentDispute => 104,
+            RejectionCode::DisputeAlreadyExists => 105,
+            RejectionCode::MissingAmount => 106,
+            RejectionCode::SuperfluousAmount => 107,
+            RejectionCode::AmountAboveLimit => 108,
+            RejectionCode::MissingTimestamp => 109,
+            RejectionCode::DisputeWindowElapsed => 110,
+            RejectionCode::ZeroAmount => 111,
+            RejectionCode::MissingReference => 112,
+            RejectionCode::AdjustmentsDisabled => 113,
+            RejectionCode::NotDisputable => 114,
+            RejectionCode::SuperfluousInitiator => 115,
+            RejectionCode::MissingClient => 116,
+            RejectionCode::SuperfluousAccount => 117,
+            RejectionCode::UnknownAccount => 118,
+            RejectionCode::NotQuarantined => 119,
+            RejectionCode::ReDisputeLimitReached => 120,
+            RejectionCode::UnknownType => 121,
+            RejectionCode::ReservedId => 122,
+            RejectionCode::IdsExhausted => 123,
+            RejectionCode::RefusedByMiddleware => 124,
+            RejectionCode::UnknownReasonCode => 125,
+            RejectionCode::SuperfluousReasonCode => 126,
+            RejectionCode::ArithmeticOverflow => 127,
+            RejectionCode::OutOfOrder => 128,
+            RejectionCode::UnrepresentableAmount => 129,
+            RejectionCode::Screened => 130,
+            RejectionCode::InternalError => 131,
+            RejectionCode::ClientLimitReached => 132,
+            RejectionCode::OpenDisputeLimitReached => 133,
+            RejectionCode::MemoryLimitReached => 134,
+            RejectionCode::Locked => 200,
+            RejectionCode::InsufficientFunds => 201,
+            RejectionCode::SuspenseAccount => 202,
+            RejectionCode::DisputeHoldBuffer => 203,
+            RejectionCode::Merged => 204,
+            RejectionCode::Frozen => 205,
+            RejectionCode::DailyLimitExceeded => 206,
+            RejectionCode::BalanceLimitExceeded => 207,
+        }
+    }
+
+    /// The string form of the code.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RejectionCode::AlreadyExists => "already_exists",
+            RejectionCode::NonexistentTransaction => "nonexistent_transaction",
+            RejectionCode::AmountNotPositive => "amount_not_positive",
+            RejectionCode::ClientMismatch => "client_mismatch",
+            RejectionCode::NonexistentDispute => "nonexistent_dispute",
+            RejectionCode::DisputeAlreadyExists => "dispute_already_exists",
+            RejectionCode::MissingAmount => "missing_amount",
+            RejectionCode::SuperfluousAmount => "superfluous_amount",
+            RejectionCode::AmountAboveLimit => "amount_above_limit",
+            RejectionCode::MissingTimestamp => "missing_timestamp",
+            RejectionCode::DisputeWindowElapsed => "dispute_window_elapsed",
+            RejectionCode::ZeroAmount => "zero_amount",
+            RejectionCode::MissingReference => "missing_reference",
+            RejectionCode::AdjustmentsDisabled => "adjustments_disabled",
+            RejectionCode::NotDisputable => "not_disputable",
+            RejectionCode::SuperfluousInitiator => "superfluous_initiator",
+            RejectionCode::MissingClient => "missing_client",
+            RejectionCode::SuperfluousAccount => "superfluous_account",
+            RejectionCode::UnknownAccount => "unknown_account",
+            RejectionCode::NotQuarantined => "not_quarantined",
+            RejectionCode::ReDisputeLimitReached => "redispute_limit_reached",
+            RejectionCode::UnknownType => "unknown_type",
+            RejectionCode::ReservedId => "reserved_id",
+            RejectionCode::IdsExhausted => "ids_exhausted",
+            RejectionCode::RefusedByMiddleware => "refused_by_middleware",
+            RejectionCode::UnknownReasonCode => "unknown_reason_code",
+            RejectionCode::SuperfluousReasonCode => "superfluous_reason_code",
+            RejectionCode::ArithmeticOverflow => "arithmetic_overflow",
+            RejectionCode::OutOfOrder => "out_of_order",
+            RejectionCode::UnrepresentableAmount => "unrepresentable_amount",
+            RejectionCode::Screened => "screened",
+            RejectionCode::InternalError => "internal_error",
+            RejectionCode::ClientLimitReached => "client_limit_reached",
+            RejectionCode::OpenDisputeLimitReached => "open_dispute_limit_reached",
+            RejectionCode::MemoryLimitReached => "memory_limit_reached",
+            RejectionCode::Locked => "locked",
+            RejectionCode::InsufficientFunds => "insufficient_funds",
+            RejectionCode::SuspenseAccount => "suspense_account",
+            RejectionCode::DisputeHoldBuffer => "dispute_hold_buffer",
+            RejectionCode::Merged => "merged",
+            RejectionCode::Frozen => "frozen",
+            RejectionCode::DailyLimitExceeded => "daily_limit_exceeded",
+            RejectionCode::BalanceLimitExceeded => "balance_limit_exceeded",
+        }
+    }
+}
+
+impl std::fmt::Display for RejectionCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for RejectionCode {
+    type Err = String;
+
+    /// Parses the string form of a code, such as `insufficient_funds`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RejectionCode::ALL
+            .into_iter()
+            .find(|code| code.as_str() == s)
+            .ok_or_else(|| format!("unknown rejection code `{}`", s))
+    }
+}
+
+impl Serialize for RejectionCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for RejectionCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl From<&TransactionError> for RejectionCode {
+    fn from(err: &TransactionError) -> Self {
+        match err {
+            TransactionError::AlreadyExists(_) => RejectionCode::AlreadyExists,
+            TransactionError::NonexistentTransaction(_) => RejectionCode::NonexistentTransaction,
+            TransactionError::AmountNotPositive(_) => RejectionCode::AmountNotPositive,
+            TransactionError::ClientMismatch(_) => RejectionCode::ClientMismatch,
+            TransactionError::NoxexistentDispute(_) => RejectionCode::NonexistentDispute,
+            TransactionError::DisputeAlreadyExists(_) => RejectionCode::DisputeAlreadyExists,
+            TransactionError::MissingAmount(_) => RejectionCode::MissingAmount,
+            TransactionError::SuperfluousAmount(_) => RejectionCode::SuperfluousAmount,
+            TransactionError::AmountAboveLimit(_) => RejectionCode::AmountAboveLimit,
+            TransactionError::MissingTimestamp(_) => RejectionCode::MissingTimestamp,
+            TransactionError::DisputeWindowElapsed(_) => RejectionCode::DisputeWindowElapsed,
+            TransactionError::ZeroAmount(_) => RejectionCode::ZeroAmount,
+            TransactionError::MissingReference(_) => RejectionCode::MissingReference,
+            TransactionError::AdjustmentsDisabled(_) => RejectionCode::AdjustmentsDisabled,
+            TransactionError::NotDisputable(_) => RejectionCode::NotDisputable,
+            TransactionError::SuperfluousInitiator(_) => RejectionCode::SuperfluousInitiator,
+            TransactionError::MissingClient(_) => RejectionCode::MissingClient,
+            TransactionError::SuperfluousAccount(_) => RejectionCode::SuperfluousAccount,
+            TransactionError::UnknownAccount(_) => RejectionCode::UnknownAccount,
+            TransactionError::NotQuarantined(_) => RejectionCode::NotQuarantined,
+            TransactionError::ReDisputeLimitReached(_) => RejectionCode::ReDisputeLimitReached,
+            TransactionError::UnknownType(..) => RejectionCode::UnknownType,
+            TransactionError::ReservedId(_) => RejectionCode::ReservedId,
+            TransactionError::IdsExhausted(_) => RejectionCode::IdsExhausted,
+            TransactionError::RefusedByMiddleware(..) => RejectionCode::RefusedByMiddleware,
+            TransactionError::UnknownReasonCode(..) => RejectionCode::UnknownReasonCode,
+            TransactionError::SuperfluousReasonCode(_) => RejectionCode::SuperfluousReasonCode,
+            TransactionError::ArithmeticOverflow(_) => RejectionCode::ArithmeticOverflow,
+            TransactionError::OutOfOrder(..) => RejectionCode::OutOfOrder,
+            TransactionError::UnrepresentableAmount(_) => RejectionCode::UnrepresentableAmount,
+            TransactionError::Screened(_) => RejectionCode::Screened,
+            TransactionError::Internal(..) => RejectionCode::InternalError,
+            TransactionError::ClientLimitReached(_) => RejectionCode::ClientLimitReached,
+            TransactionError::OpenDisputeLimitReached(_) => RejectionCode::OpenDisputeLimitReached,
+            TransactionError::MemoryLimitReached(_) => RejectionCode::MemoryLimitReached,
+        }
+    }
+}
+
+impl From<&ClientError> for RejectionCode {
+    fn from(err: &ClientError) -> Self {
+        match err {
+            ClientError::Locked(_) => RejectionCode::Locked,
+            ClientError::InsufficientFunds(_) => RejectionCode::InsufficientFunds,
+            ClientError::SuspenseAccount(_) => RejectionCode::SuspenseAccount,
+            ClientError::DisputeHoldBuffer(_) => RejectionCode::DisputeHoldBuffer,
+            ClientError::Merged(_) => RejectionCode::Merged,
+            ClientError::Frozen(_) => RejectionCode::Frozen,
+            ClientError::DailyLimitExceeded(_) => RejectionCode::DailyLimitExceeded,
+            ClientError::BalanceLimitExceeded(_) => RejectionCode::BalanceLimitExceeded,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("maximum amount `{0}` must be positive")]
+    LimitNotPositive(Decimal),
+    #[error("overdraft limit `{0}` must not be negative")]
+    NegativeOverdraft(Decimal),
+    #[error("dispute hold buffer `{0}` must not be negative")]
+    NegativeDisputeHoldBuffer(Decimal),
+    #[error("cannot round to `{0}` decimal places, at most 28 are supported")]
+    InvalidRounding(u32),
+    #[error("cannot round to `{0}` decimal places, balances are kept to {1}")]
+    RoundingBeyondBalances(u32, u32),
+    #[error("overdraft limit `{0}` cannot be held in a balance")]
+    UnrepresentableOverdraft(Decimal),
+    #[error("a dispute window requires timestamps to be enabled")]
+    DisputeWindowWithoutTimestamps,
+    #[error("the state hash interval must be at least one record")]
+    ZeroHashInterval,
+    #[error("dispute limit for `{0:?}` initiators must be at least one dispute")]
+    ZeroDisputeLimit(DisputeInitiator),
+    #[error("a dispute limit window requires timestamps to be enabled")]
+    DisputeLimitWindowWithoutTimestamps,
+    #[error("archiving requires a minimum age in records or seconds")]
+    ArchiveWithoutAge,
+    #[error("archiving by age in seconds requires timestamps to be enabled")]
+    ArchiveAgeWithoutTimestamps,
+    #[error("spilling to disk when the memory limit is reached requires an archive")]
+    SpillWithoutArchive,
+    #[error("archiving is not supported when processing multiple inputs")]
+    ArchiveWithMultipleInputs,
+    #[error("archiving is not supported when processing with actors")]
+    ArchiveWithActors,
+    #[error("account `{0}` is mapped to more than one client")]
+    DuplicateAccount(String),
+    #[error("denylist row {0} names neither a client nor an account")]
+    EmptyDenylistEntry(u64),
+    #[error("reason code `{0}` is listed more than once")]
+    DuplicateReasonCode(String),
+    #[error("balance thresholds for client `{0}` are given more than once")]
+    DuplicateThresholds(u16),
+    #[error("account limits for client `{0}` are given more than once")]
+    DuplicateLimits(u16),
+    #[error("soft limit `{0}` must not be above hard limit `{1}`")]
+    SoftLimitAboveHard(Decimal, Decimal),
+    #[error("a daily limit requires timestamps to be enabled")]
+    DailyLimitWithoutTimestamps,
+    #[error("dispute hold rate `{0}` must be above zero and at most one")]
+    InvalidHoldRate(Decimal),
+    #[error("hold rate row {0} must name either a client or a currency")]
+    AmbiguousHoldRate(u64),
+    #[error("the hold rate of client `{0}` is given more than once")]
+    DuplicateClientHoldRate(u16),
+    #[error("the hold rate of currency `{0}` is given more than once")]
+    DuplicateCurrencyHoldRate(String),
+    #[error("reserve rate `{0}` must be above zero and at most one")]
+    InvalidReserveRate(Decimal),
+    #[error("the reserve of client `{0}` is given more than once")]
+    DuplicateReserve(u16),
+    #[error("reserves require timestamps to be enabled")]
+    ReservesWithoutTimestamps,
+    #[error("freeze row {0} gives no reason")]
+    MissingFreezeReason(u64),
+    #[error("client `{0}` is frozen more than once")]
+    DuplicateFreeze(u16),
+    #[error("enrichment key `{0}` is listed more than once")]
+    DuplicateEnrichmentKey(String),
+    #[error("client `{0}` is placed under more than one merchant")]
+    DuplicateClientPlacement(u16),
+    #[error("merchant `{0}` is placed under more than one program")]
+    ConflictingProgram(String),
+    #[error("a velocity limit must allow at least one transaction")]
+    ZeroVelocityLimit,
+    #[error("a velocity limit requires timestamps to be enabled")]
+    VelocityWithoutTimestamps,
+    #[error("chargeback fee `{0}` must be positive")]
+    FeeNotPositive(Decimal),
+    #[error("a fee account requires a chargeback fee")]
+    FeeAccountWithoutFee,
+    #[error("dormancy by age in days requires timestamps to be enabled")]
+    DormancyAgeWithoutTimestamps,
+    #[error("float rate `{0}` must not be negative")]
+    NegativeFloatRate(Decimal),
+    #[error("a float rate requires timestamps to be enabled")]
+    FloatRateWithoutTimestamps,
+    #[error("a chargeback deadline requires timestamps to be enabled")]
+    DeadlineWithoutTimestamps,
+    #[error("a point in time requires timestamps to be enabled")]
+    AsOfTimestampWithoutTimestamps,
+    #[error("chargeback rate threshold `{0}` must be above zero and at most one")]
+    InvalidChargebackThreshold(Decimal),
+    #[error("chargeback release rate `{0}` must not be negative or above the threshold")]
+    InvalidChargebackRelease(Decimal),
+    #[error("a chargeback monitor window must be at least one transaction")]
+    ZeroChargebackWindow,
+    #[error("transaction type `{0}` is built in and cannot be registered")]
+    BuiltInTransactionType(String),
+    #[error("transaction type `{0}` is registered more than once")]
+    DuplicateTransactionType(String),
+}
+
+#[derive(Debug, Error)]
+pub enum CaseError {
+    #[error("transaction ID `{0}` has never been disputed")]
+    NoCase(u32),
+    #[error("status `{}` is only set by records", .0.name())]
+    StatusSetByRecords(CaseStatus),
+    #[error("a note can only be given with a status")]
+    NoteWithoutStatus,
+}
+
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    #[error("column {column} of the header, `{name}`, is not a known column")]
+    UnknownColumn { column: usize, name: String },
+    #[error("column {column} of the header, `{name}`, should be written `{expected}`")]
+    MixedCase {
+        column: usize,
+        name: String,
+        expected: String,
+    },
+    #[error("column {column} of the header, `{name}`, repeats an earlier column")]
+    DuplicateColumn { column: usize, name: String },
+    #[error("the header is missing the required column `{0}`")]
+    MissingColumn(&'static str),
+    #[error("row {row} has {actual} fields, but the header has {expected}")]
+    FieldCount {
+        row: u64,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("row {row}: amount `{amount}` is not written in the `{style}` decimal style")]
+    DecimalStyleMismatch {
+        row: u64,
+        amount: String,
+        style: DecimalStyle,
+    },
+    #[error("row {row}, column {column} (`{name}`): {message}")]
+    InvalidField {
+        row: u64,
+        column: u64,
+        name: String,
+        message: String,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum MergeError {
+    #[error("transation with ID `{0}` appears in more than one input")]
+    DuplicateTransaction(u32),
+    #[error("the balances of client `{0}` in different inputs overflow when added")]
+    BalanceOverflow(u16),
+    #[error("the statistics of different inputs overflow when added")]
+    SummaryOverflow,
+    #[error("the statistics of client `{0}` in different inputs overflow when added")]
+    ClientSummaryOverflow(u16),
+    #[error("the sequence numbers of client `{0}` do not increase from one input to the next")]
+    OutOfOrder(u16),
+}
+
+#[derive(Debug, Error)]
+pub enum ClientMergeError {
+    #[error("client `{0}` cannot be merged into itself")]
+    SameClient(u16),
+    #[error("client `{0}` does not exist")]
+    UnknownClient(u16),
+    #[error("client `{0}` has already been merged into client `{1}`")]
+    AlreadyMerged(u16, u16),
+    #[error("client `{0}` is locked, and merges of locked clients must be forced")]
+    Locked(u16),
+    #[error("client `{0}` is the suspense or fee account")]
+    ReservedAccount(u16),
+    #[error("merging client `{0}` into client `{1}` would overflow its balances or statistics")]
+    BalanceOverflow(u16, u16),
+}
+
+#[derive(Debug, Error)]
+pub enum ManifestError {
+    #[error("output has {actual} rows, but the manifest records {expected}")]
+    RowCountMismatch { expected: u64, actual: u64 },
+    #[error("output totals sum to `{actual}`, but the manifest records `{expected}`")]
+    TotalMismatch { expected: Decimal, actual: Decimal },
+    #[error("output digest does not match the manifest")]
+    PayloadDigestMismatch,
+    #[error("digest of input `{0}` does not match the manifest")]
+    InputDigestMismatch(String),
+}
+
+#[derive(Debug, Error)]
+pub enum CryptoError {
+    #[error("no key was found in `{0}`")]
+    MissingKey(String),
+    #[error("keys must be 64 hexadecimal digits")]
+    InvalidKey,
+    #[error("encryption failed")]
+    Encryption,
+    #[error("the file is not encrypted in a known format")]
+    NotEncrypted,
+    #[error("decryption failed: wrong key, or the file was altered")]
+    Decryption,
+}
+
+#[derive(Debug, Error)]
+pub enum InvariantError {
+    #[error("client `{client}` holds `{held}`, which is negative")]
+    NegativeHeld {
+        client: u16,
+        held: Decimal,
+        recent: Vec<Transaction>,
+    },
+    #[error("client `{client}` holds `{held}`, but its open disputes total `{disputed}`")]
+    HeldMismatch {
+        client: u16,
+        held: Decimal,
+        disputed: Decimal,
+        recent: Vec<Transaction>,
+    },
+    #[error("record `{position}` of client `{client}` was applied after record `{after}`")]
+    OutOfOrder {
+        client: u16,
+        position: u64,
+        after: u64,
+        recent: Vec<Transaction>,
+    },
+    #[error("transaction ID `{tx}` made the engine fail: {message}")]
+    Panicked {
+        tx: u32,
+        message: String,
+        recent: Vec<Transaction>,
+    },
+}
+
+impl InvariantError {
+    /// The most recent records up to the violation, oldest first.
+    pub fn recent(&self) -> &[Transaction] {
+        match self {
+            InvariantError::NegativeHeld { recent, .. }
+            | InvariantError::HeldMismatch { recent, .. }
+            | InvariantError::OutOfOrder { recent, .. }
+            | InvariantError::Panicked { recent, .. } => recent,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum FormatError {
+    #[error("input is gzip-compressed, which cannot be read yet; decompress it first, such as with `gunzip -c`")]
+    Gzip,
+    #[error(
+        "input is a Parquet file, which cannot be read yet; export it as CSV or JSON Lines first"
+    )]
+    Parquet,
+    #[error("input is not text, so it is neither CSV nor JSON Lines")]
+    Unrecognized,
+    #[error("line {0} is not a valid record: {1}")]
+    InvalidRecord(u64, serde_json::Error),
 }
 
 #[derive(Debug, Error)]
@@ -34,8 +654,63 @@ pub enum Error {
     Transaction(#[from] TransactionError),
     #[error("client error: {0}")]
     Client(#[from] ClientError),
+    #[error("configuration error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("schema error: {0}")]
+    Schema(#[from] SchemaError),
+    #[error("input format error: {0}")]
+    Format(#[from] FormatError),
+    #[error("merge error: {0}")]
+    Merge(#[from] MergeError),
+    #[error("client merge error: {0}")]
+    ClientMerge(#[from] ClientMergeError),
+    #[error("invariant violated: {0}")]
+    Invariant(#[from] InvariantError),
+    #[error("dispute case error: {0}")]
+    Case(#[from] CaseError),
+    #[error("manifest error: {0}")]
+    Manifest(#[from] ManifestError),
+    #[error("encryption error: {0}")]
+    Crypto(#[from] CryptoError),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
     #[error("csv error: {0}")]
     Csv(#[from] csv::Error),
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
+    /// Another process has the state file open.
+    #[error("state file {} is in use by another process", .0.display())]
+    StateBackendBusy(std::path::PathBuf),
+    /// A thread parsing records in parallel panicked or stopped.
+    #[error("a parsing thread failed: {0}")]
+    ParserFailed(String),
+    /// A server answered a JSON-RPC request with an error.
+    #[error("request failed with code {0}: {1}")]
+    Rpc(i32, String),
+}
+
+impl Error {
+    /// The rejection code for errors caused by a single record,
+    /// or `None` for errors that abort processing.
+    pub fn rejection_code(&self) -> Option<RejectionCode> {
+        match self {
+            Error::Transaction(err) => Some(err.into()),
+            Error::Client(err) => Some(err.into()),
+            Error::Config(_)
+            | Error::Schema(_)
+            | Error::Format(_)
+            | Error::Merge(_)
+            | Error::ClientMerge(_)
+            | Error::Invariant(_)
+            | Error::Case(_)
+            | Error::Manifest(_)
+            | Error::Crypto(_)
+            | Error::Json(_)
+            | Error::Csv(_)
+            | Error::Io(_)
+            | Error::StateBackendBusy(_)
+            | Error::ParserFailed(_)
+            | Error::Rpc(..) => None,
+        }
+    }
 }
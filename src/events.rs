@@ -0,0 +1,201 @@
+use std::fmt::Debug;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::output::AtomicFile;
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+/// The kinds of changes recorded in the changelog.
+pub enum EventKind {
+    /// A client's available or held funds changed.
+    BalanceChanged,
+    /// A client was locked.
+    AccountLocked,
+    /// A transaction was disputed.
+    DisputeOpened,
+    /// A dispute was resolved in the client's favour.
+    DisputeResolved,
+    /// A dispute ended in a chargeback.
+    DisputeChargedBack,
+    /// A client was merged into another, and no longer exists. The
+    /// other client's new balances follow as a separate event.
+    ClientMerged,
+    /// A client's available funds fell below its floor.
+    BalanceBelowFloor,
+    /// A client's total funds rose above its ceiling.
+    BalanceAboveCeiling,
+    /// A deposit or withdrawal was above its client's soft limit.
+    TransactionAboveSoftLimit,
+    /// A client's deposits and withdrawals of the day rose above its
+    /// soft limit.
+    DailyVolumeAboveSoftLimit,
+    /// A client's total funds rose above its soft limit.
+    BalanceAboveSoftLimit,
+}
+
+#[derive(Debug, Serialize, Clone)]
+/// One change to the state, in processing order.
+pub struct Event {
+    /// The number of the record that caused the change. A record may
+    /// cause several events, which share its number.
+    pub seq: u64,
+    pub kind: EventKind,
+    pub client: u16,
+    pub tx: u32,
+    /// The balances after the change, for balance events.
+    #[serde(with = "rust_decimal::serde::str_option")]
+    pub available: Option<Decimal>,
+    #[serde(with = "rust_decimal::serde::str_option")]
+    pub held: Option<Decimal>,
+    #[serde(with = "rust_decimal::serde::str_option")]
+    pub total: Option<Decimal>,
+    /// The sender's own ID for the record that caused the change.
+    pub external_ref: Option<String>,
+}
+
+/// Receives changelog events as records are applied, such as to stream
+/// them to an analytics store.
+///
+/// Events are published from the threads applying records, so sinks
+/// should queue them rather than send them there and then. A sink
+/// cannot reject the record behind an event, so sinks report their own
+/// failures.
+pub trait EventSink: Debug + Send + Sync {
+    /// Takes the events caused by one record, in processing order.
+    fn publish(&self, events: Vec<Event>);
+}
+
+#[derive(Debug)]
+/// Writes events to a file as they are published, as CSV if its path
+/// ends in `.csv` and as JSON lines otherwise, so they are not kept in
+/// memory. The file only appears at its path once `finish` is called.
+///
+/// With several actors, events are written in the order the actors
+/// publish them.
+pub struct FileSink {
+    /// The file, or the first failure to write to it, until finished.
+    writer: Mutex<Option<Result<EventWriter, crate::errors::Error>>>,
+}
+
+#[derive(Debug)]
+/// A file events are written to.
+enum EventWriter {
+    Csv(Box<csv::Writer<AtomicFile>>),
+    Jsonl(AtomicFile),
+}
+
+impl FileSink {
+    /// Starts writing events to a file that will replace `path`.
+    pub fn create(path: &Path) -> Result<Self, crate::errors::Error> {
+        let file = AtomicFile::create(path)?;
+        let writer = if path.extension().is_some_and(|ext| ext == "csv") {
+            EventWriter::Csv(Box::new(
+                csv::WriterBuilder::new()
+                    .has_headers(true)
+                    .from_writer(file),
+            ))
+        } else {
+            EventWriter::Jsonl(file)
+        };
+        Ok(FileSink {
+            writer: Mutex::new(Some(Ok(writer))),
+        })
+    }
+
+    /// Moves the file to its path, or returns the first failure to
+    /// write an event to it. Events published afterwards are dropped.
+    pub fn finish(&self) -> Result<(), crate::errors::Error> {
+        match self.writer.lock().unwrap().take() {
+            Some(Ok(writer)) => writer.commit(),
+            Some(Err(err)) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+impl EventSink for FileSink {
+    fn publish(&self, events: Vec<Event>) {
+        let mut writer = self.writer.lock().unwrap();
+        if let Some(Ok(file)) = &mut *writer {
+            if let Err(err) = file.write(&events) {
+                *writer = Some(Err(err));
+            }
+        }
+    }
+}
+
+impl EventWriter {
+    /// Appends `events` to the file.
+    fn write(&mut self, events: &[Event]) -> Result<(), crate::errors::Error> {
+        match self {
+            EventWriter::Csv(wtr) => events.iter().try_for_each(|event| wtr.serialize(event))?,
+            EventWriter::Jsonl(file) => {
+                for event in events {
+                    serde_json::to_writer(&mut *file, event)?;
+                    file.write_all(b"\n")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes the file and moves it to its path.
+    fn commit(self) -> Result<(), crate::errors::Error> {
+        let file = match self {
+            EventWriter::Csv(wtr) => wtr
+                .into_inner()
+                .map_err(|err| std::io::Error::new(err.error().kind(), err.to_string()))?,
+            EventWriter::Jsonl(file) => file,
+        };
+        file.commit()?;
+        Ok(())
+    }
+}
+
+/// Writes events as CSV if `path` ends in `.csv`, and as JSON lines otherwise.
+pub fn write_file<'a>(
+    path: &Path,
+    events: impl IntoIterator<Item = &'a Event>,
+) -> Result<(), crate::errors::Error> {
+    let mut file = AtomicFile::create(path)?;
+    if path.extension().is_some_and(|ext| ext == "csv") {
+        write_csv(&mut file, events)?;
+    } else {
+        write_jsonl(&mut file, events)?;
+    }
+    file.commit()?;
+    Ok(())
+}
+
+/// Writes events as CSV.
+pub fn write_csv<'a>(
+    writer: impl std::io::Write,
+    events: impl IntoIterator<Item = &'a Event>,
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    events
+        .into_iter()
+        .try_for_each(|event| wtr.serialize(event))?;
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes events as JSON, one per line.
+pub fn write_jsonl<'a>(
+    mut writer: impl std::io::Write,
+    events: impl IntoIterator<Item = &'a Event>,
+) -> Result<(), crate::errors::Error> {
+    for event in events {
+        serde_json::to_writer(&mut writer, event)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
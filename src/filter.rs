@@ -0,0 +1,65 @@
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
+use crate::transaction::Transaction;
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+/// Selects the records to process from an input, so an investigation
+/// can rerun only the slice of a large input it is about.
+///
+/// A record is selected only if it matches every criterion set, and
+/// the default filter selects every record. Records left out are
+/// skipped as if they were not in the input, so a dispute of a
+/// transaction left out is rejected as `nonexistent_transaction`.
+pub struct TxFilter {
+    clients: Option<HashSet<u16>>,
+    ids: Option<RangeInclusive<u32>>,
+    types: Option<HashSet<String>>,
+    timestamps: Option<RangeInclusive<u64>>,
+}
+
+impl TxFilter {
+    /// Only selects records for these clients. Records naming an
+    /// account are matched by the client it is mapped to.
+    pub fn clients(mut self, clients: impl IntoIterator<Item = u16>) -> Self {
+        self.clients = Some(clients.into_iter().collect());
+        self
+    }
+
+    /// Only selects records whose transaction ID is in `ids`.
+    pub fn ids(mut self, ids: RangeInclusive<u32>) -> Self {
+        self.ids = Some(ids);
+        self
+    }
+
+    /// Only selects records of these types, by the names used in the
+    /// input, such as `deposit` or the name of a custom type.
+    pub fn types(mut self, types: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.types = Some(types.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Only selects records timestamped within `timestamps`, in
+    /// seconds since the Unix epoch. Records without a timestamp are
+    /// left out.
+    pub fn timestamps(mut self, timestamps: RangeInclusive<u64>) -> Self {
+        self.timestamps = Some(timestamps);
+        self
+    }
+
+    /// Whether `tx`, belonging to `client`, is selected.
+    pub(crate) fn selects(&self, tx: &Transaction, client: Option<u16>) -> bool {
+        self.clients
+            .as_ref()
+            .is_none_or(|clients| client.is_some_and(|client| clients.contains(&client)))
+            && self.ids.as_ref().is_none_or(|ids| ids.contains(&tx.id))
+            && self
+                .types
+                .as_ref()
+                .is_none_or(|types| types.contains(tx.type_name()))
+            && self.timestamps.as_ref().is_none_or(|timestamps| {
+                tx.timestamp
+                    .is_some_and(|timestamp| timestamps.contains(&timestamp))
+            })
+    }
+}
@@ -0,0 +1,291 @@
+use std::marker::PhantomData;
+
+use rust_decimal::Decimal;
+
+use crate::errors;
+use crate::state::CurrentState;
+use crate::transaction::{DisputeInitiator, Transaction, TransactionKind};
+
+#[derive(Debug, Clone, Copy)]
+/// A builder state: the type of the record is not chosen yet.
+pub struct NeedsType;
+
+#[derive(Debug, Clone, Copy)]
+/// A builder state: the record moves funds, and needs its own ID.
+pub struct NeedsId;
+
+#[derive(Debug, Clone, Copy)]
+/// A builder state: the record is complete.
+pub struct Ready;
+
+#[derive(Debug, Clone)]
+/// Builds well-formed records for tests and examples.
+///
+/// Which fields may be set depends on the type chosen, so the rules
+/// on amounts are checked at compile time: deposits, withdrawals and
+/// adjustments take an amount and need an ID of their own, records of
+/// custom types need an ID and may have an amount, while disputes,
+/// resolves and chargebacks take the ID of the transaction they refer
+/// to and have no way to set an amount. Whether amounts are
+/// positive is still only checked by the engine.
+pub struct TransactionBuilder<S> {
+    tx: Transaction,
+    state: PhantomData<S>,
+}
+
+impl Default for TransactionBuilder<NeedsType> {
+    fn default() -> Self {
+        TransactionBuilder {
+            tx: Transaction {
+                kind: TransactionKind::Dispute,
+                client: 0,
+                id: 0,
+                timestamp: None,
+                reference: None,
+                initiator: None,
+                account: None,
+                external_ref: None,
+                reason_code: None,
+                currency: None,
+                category: None,
+                merchant: None,
+                seq: None,
+            },
+            state: PhantomData,
+        }
+    }
+}
+
+impl<S> TransactionBuilder<S> {
+    /// Moves to another state, keeping the fields set so far.
+    fn into_state<T>(self) -> TransactionBuilder<T> {
+        TransactionBuilder {
+            tx: self.tx,
+            state: PhantomData,
+        }
+    }
+
+    /// Sets the fields that decide the state.
+    fn typed<T>(mut self, kind: TransactionKind, client: u16) -> TransactionBuilder<T> {
+        self.tx.kind = kind;
+        self.tx.client = client;
+        self.into_state()
+    }
+
+    /// Sets the timestamp, in seconds since the Unix epoch.
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.tx.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Sets the sender's sequence number for the record.
+    pub fn seq(mut self, seq: u64) -> Self {
+        self.tx.seq = Some(seq);
+        self
+    }
+
+    /// Sets the sender's own ID for the record.
+    pub fn external_ref(mut self, external_ref: impl Into<String>) -> Self {
+        self.tx.external_ref = Some(external_ref.into());
+        self
+    }
+}
+
+impl TransactionBuilder<NeedsType> {
+    /// A deposit of `amount` to `client`.
+    pub fn deposit(self, client: u16, amount: Decimal) -> TransactionBuilder<NeedsId> {
+        self.typed(TransactionKind::Deposit { amount }, client)
+    }
+
+    /// A withdrawal of `amount` from `client`.
+    pub fn withdrawal(self, client: u16, amount: Decimal) -> TransactionBuilder<NeedsId> {
+        self.typed(TransactionKind::Withdrawal { amount }, client)
+    }
+
+    /// An adjustment of `client` by the signed `amount`, with the
+    /// operator `reference` adjustments require.
+    pub fn adjustment(
+        mut self,
+        client: u16,
+        amount: Decimal,
+        reference: impl Into<String>,
+    ) -> TransactionBuilder<NeedsId> {
+        self.tx.reference = Some(reference.into());
+        self.typed(TransactionKind::Adjustment { amount }, client)
+    }
+
+    /// A record of the custom type `tag` for `client`, with `amount`
+    /// if the type takes one.
+    pub fn custom(
+        self,
+        client: u16,
+        tag: impl Into<String>,
+        amount: Option<Decimal>,
+    ) -> TransactionBuilder<NeedsId> {
+        let tag = tag.into();
+        self.typed(TransactionKind::Custom { tag, amount }, client)
+    }
+
+    /// A dispute by `client` of the transaction with ID `tx`.
+    pub fn dispute(self, client: u16, tx: u32) -> TransactionBuilder<Ready> {
+        self.referring(TransactionKind::Dispute, client, tx)
+    }
+
+    /// A dispute by `client` of the transaction with ID `tx`, opened
+    /// by `initiator`.
+    pub fn dispute_by(
+        mut self,
+        client: u16,
+        tx: u32,
+        initiator: DisputeInitiator,
+    ) -> TransactionBuilder<Ready> {
+        self.tx.initiator = Some(initiator);
+        self.referring(TransactionKind::Dispute, client, tx)
+    }
+
+    /// A resolve of the dispute of the transaction with ID `tx`.
+    pub fn resolve(self, client: u16, tx: u32) -> TransactionBuilder<Ready> {
+        self.referring(TransactionKind::Resolve, client, tx)
+    }
+
+    /// A chargeback of the dispute of the transaction with ID `tx`.
+    pub fn chargeback(self, client: u16, tx: u32) -> TransactionBuilder<Ready> {
+        self.referring(TransactionKind::Chargeback, client, tx)
+    }
+
+    /// A dispute by `client` of the transaction with ID `tx`, for the
+    /// reason `code`.
+    pub fn dispute_for(
+        self,
+        client: u16,
+        tx: u32,
+        code: impl Into<String>,
+    ) -> TransactionBuilder<Ready> {
+        let mut builder = self.referring(TransactionKind::Dispute, client, tx);
+        builder.tx.reason_code = Some(code.into());
+        builder
+    }
+
+    /// A record referring to the transaction with ID `tx`.
+    fn referring(
+        mut self,
+        kind: TransactionKind,
+        client: u16,
+        tx: u32,
+    ) -> TransactionBuilder<Ready> {
+        self.tx.id = tx;
+        self.typed(kind, client)
+    }
+}
+
+impl TransactionBuilder<NeedsId> {
+    /// Sets the ID of the new transaction.
+    pub fn id(mut self, id: u32) -> TransactionBuilder<Ready> {
+        self.tx.id = id;
+        self.into_state()
+    }
+}
+
+impl TransactionBuilder<Ready> {
+    pub fn build(self) -> Transaction {
+        self.tx
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A sequence of records, with IDs for new transactions handed out in
+/// order from one, for writing small test vectors.
+pub struct Scenario {
+    records: Vec<Transaction>,
+    /// The ID given to the next new transaction.
+    next_id: u32,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Scenario {
+            records: Vec::new(),
+            next_id: 1,
+        }
+    }
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a record that moves funds under the next ID, returning it.
+    fn add_new(&mut self, builder: TransactionBuilder<NeedsId>) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.records.push(builder.id(id).build());
+        id
+    }
+
+    /// Adds a deposit, returning its ID.
+    pub fn deposit(&mut self, client: u16, amount: Decimal) -> u32 {
+        self.add_new(Transaction::builder().deposit(client, amount))
+    }
+
+    /// Adds a withdrawal, returning its ID.
+    pub fn withdrawal(&mut self, client: u16, amount: Decimal) -> u32 {
+        self.add_new(Transaction::builder().withdrawal(client, amount))
+    }
+
+    /// Adds an adjustment, returning its ID.
+    pub fn adjustment(&mut self, client: u16, amount: Decimal, reference: &str) -> u32 {
+        self.add_new(Transaction::builder().adjustment(client, amount, reference))
+    }
+
+    /// Adds a record of the custom type `tag`, returning its ID.
+    pub fn custom(&mut self, client: u16, tag: &str, amount: Option<Decimal>) -> u32 {
+        self.add_new(Transaction::builder().custom(client, tag, amount))
+    }
+
+    /// Adds a dispute of the transaction with ID `tx`.
+    pub fn dispute(&mut self, client: u16, tx: u32) -> &mut Self {
+        self.push(Transaction::builder().dispute(client, tx).build())
+    }
+
+    /// Adds a resolve of the dispute of the transaction with ID `tx`.
+    pub fn resolve(&mut self, client: u16, tx: u32) -> &mut Self {
+        self.push(Transaction::builder().resolve(client, tx).build())
+    }
+
+    /// Adds a chargeback of the dispute of the transaction with ID `tx`.
+    pub fn chargeback(&mut self, client: u16, tx: u32) -> &mut Self {
+        self.push(Transaction::builder().chargeback(client, tx).build())
+    }
+
+    /// Adds any record, such as one built with more fields set. IDs
+    /// handed out later skip the record's ID if it is higher.
+    pub fn push(&mut self, tx: Transaction) -> &mut Self {
+        self.next_id = self.next_id.max(tx.id.saturating_add(1));
+        self.records.push(tx);
+        self
+    }
+
+    /// The records so far, in order.
+    pub fn records(&self) -> &[Transaction] {
+        &self.records
+    }
+
+    /// Applies the records to `state` in order, returning the outcome
+    /// of each.
+    pub fn run(&self, state: &mut CurrentState) -> Vec<Result<(), errors::Error>> {
+        self.records.iter().map(|tx| state.add(tx)).collect()
+    }
+
+    /// Writes the records as an input CSV file.
+    pub fn write_csv(&self, writer: impl std::io::Write) -> Result<(), csv::Error> {
+        let mut wtr = csv::WriterBuilder::new()
+            .has_headers(true)
+            .from_writer(writer);
+        self.records
+            .iter()
+            .try_for_each(|tx| wtr.serialize(tx.to_input()))?;
+        wtr.flush()?;
+        Ok(())
+    }
+}
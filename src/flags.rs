@@ -0,0 +1,111 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+/// Flags raised on a client for follow-up, shown in the
+/// `flags` output column as names separated by `;`.
+pub struct ClientFlags(u8);
+
+impl ClientFlags {
+    /// The account needs a manual review.
+    pub const REVIEW: ClientFlags = ClientFlags(1 << 0);
+
+    /// The client, or its merchant, has too high a chargeback rate.
+    pub const CHARGEBACKS: ClientFlags = ClientFlags(1 << 1);
+
+    /// The client's available funds are below its floor.
+    pub const LOW_BALANCE: ClientFlags = ClientFlags(1 << 2);
+
+    /// The client's total funds are above its ceiling.
+    pub const HIGH_BALANCE: ClientFlags = ClientFlags(1 << 3);
+
+    /// A record for the client, or an account mapped to it, was blocked
+    /// by the denylist.
+    pub const SCREENED: ClientFlags = ClientFlags(1 << 4);
+
+    /// The client has no funds and never had a deposit or withdrawal
+    /// applied. Only raised in the output, with
+    /// `NeverActivePolicy::Flag`.
+    pub const NEVER_ACTIVE: ClientFlags = ClientFlags(1 << 5);
+
+    /// The client's last deposit or withdrawal took it past a soft
+    /// account limit.
+    pub const SOFT_LIMIT: ClientFlags = ClientFlags(1 << 6);
+
+    /// Every flag along with its name in the output.
+    const NAMES: [(ClientFlags, &'static str); 7] = [
+        (ClientFlags::REVIEW, "review"),
+        (ClientFlags::CHARGEBACKS, "chargebacks"),
+        (ClientFlags::LOW_BALANCE, "low_balance"),
+        (ClientFlags::HIGH_BALANCE, "high_balance"),
+        (ClientFlags::SCREENED, "screened"),
+        (ClientFlags::NEVER_ACTIVE, "never_active"),
+        (ClientFlags::SOFT_LIMIT, "soft_limit"),
+    ];
+
+    /// Whether every flag in `other` is raised.
+    pub fn contains(self, other: ClientFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Raises every flag in `other`.
+    pub fn insert(&mut self, other: ClientFlags) {
+        self.0 |= other.0;
+    }
+
+    /// Clears every flag in `other`.
+    pub fn remove(&mut self, other: ClientFlags) {
+        self.0 &= !other.0;
+    }
+
+    /// Whether no flags are raised.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl fmt::Display for ClientFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = ClientFlags::NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| name);
+        if let Some(name) = names.next() {
+            f.write_str(name)?;
+        }
+        names.try_for_each(|name| write!(f, ";{}", name))
+    }
+}
+
+impl FromStr for ClientFlags {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(';').filter(|name| !name.is_empty()).try_fold(
+            ClientFlags::default(),
+            |mut flags, name| {
+                let (flag, _) = ClientFlags::NAMES
+                    .iter()
+                    .find(|(_, known)| *known == name)
+                    .ok_or_else(|| format!("unknown client flag `{}`", name))?;
+                flags.insert(*flag);
+                Ok(flags)
+            },
+        )
+    }
+}
+
+impl Serialize for ClientFlags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ClientFlags {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
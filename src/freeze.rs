@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{ConfigError, Error};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+/// An administrative freeze of a client's account, such as a compliance
+/// hold while a case is reviewed.
+///
+/// Unlike the lock a chargeback leaves, a freeze is temporary: it lasts
+/// until it is lifted, or until the records reach `expires`, if given.
+pub struct Freeze {
+    /// Why the account is frozen, shown in the output.
+    pub reason: String,
+    /// The timestamp from which the freeze no longer applies, in the
+    /// units of record timestamps.
+    #[serde(default)]
+    pub expires: Option<u64>,
+}
+
+impl Freeze {
+    /// Whether the freeze still applies when the latest timestamp seen
+    /// is `now`. A freeze with an expiry applies until a record at or
+    /// after it is seen.
+    pub fn applies(&self, now: Option<u64>) -> bool {
+        match (self.expires, now) {
+            (Some(expires), Some(now)) => now < expires,
+            _ => true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+/// A row of a file of freezes. Used for deserialization.
+struct CsvFreeze {
+    client: u16,
+    reason: String,
+    #[serde(default)]
+    expires: Option<u64>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+/// The frozen clients, each with the freeze placed on it.
+pub struct Freezes {
+    clients: HashMap<u16, Freeze>,
+}
+
+impl Freezes {
+    /// Reads a CSV file with `client`, `reason` and `expires` columns,
+    /// of which `expires` may be left out or empty. Each client may
+    /// only appear once, and every freeze needs a reason.
+    pub fn from_csv(reader: impl std::io::Read) -> Result<Self, Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut freezes = Freezes::default();
+        for (row, record) in (1..).zip(rdr.deserialize()) {
+            let record: CsvFreeze = record?;
+            if record.reason.is_empty() {
+                return Err(ConfigError::MissingFreezeReason(row).into());
+            }
+            let freeze = Freeze {
+                reason: record.reason,
+                expires: record.expires,
+            };
+            if freezes.freeze(record.client, freeze).is_some() {
+                return Err(ConfigError::DuplicateFreeze(record.client).into());
+            }
+        }
+        Ok(freezes)
+    }
+
+    /// Freezes `client`, returning the freeze it replaces, if any.
+    pub fn freeze(&mut self, client: u16, freeze: Freeze) -> Option<Freeze> {
+        self.clients.insert(client, freeze)
+    }
+
+    /// Lifts the freeze on `client`, returning it, if there was one.
+    pub fn unfreeze(&mut self, client: u16) -> Option<Freeze> {
+        self.clients.remove(&client)
+    }
+
+    /// The freeze on `client` that still applies at `now`, if any.
+    pub fn get(&self, client: u16, now: Option<u64>) -> Option<&Freeze> {
+        self.clients
+            .get(&client)
+            .filter(|freeze| freeze.applies(now))
+    }
+
+    /// Moves the freeze on `src`, if any, to `dst`, unless `dst` has
+    /// one of its own.
+    pub(crate) fn merge_clients(&mut self, src: u16, dst: u16) {
+        if let Some(freeze) = self.clients.remove(&src) {
+            self.clients.entry(dst).or_insert(freeze);
+        }
+    }
+}
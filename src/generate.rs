@@ -0,0 +1,96 @@
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::transaction::TransactionType;
+
+#[derive(Debug, Serialize)]
+/// One generated input record, in the input CSV format.
+struct GeneratedRecord {
+    r#type: TransactionType,
+    client: u16,
+    tx: u32,
+    amount: Option<Decimal>,
+}
+
+#[derive(Debug)]
+/// A small deterministic pseudo-random generator (SplitMix64),
+/// so that the same seed always produces the same file.
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A value in `0..bound`; `bound` must not be zero.
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+/// Writes `records` pseudo-random transactions for `clients` clients
+/// as input CSV. Deposits and withdrawals dominate; disputes refer to
+/// earlier deposits, and resolves and chargebacks to open disputes.
+pub fn generate(
+    writer: impl std::io::Write,
+    records: u32,
+    clients: u16,
+    seed: u64,
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    let mut rng = SplitMix64(seed);
+    let clients = u64::from(clients.max(1));
+    let mut deposits: Vec<(u16, u32)> = Vec::new();
+    let mut disputes: Vec<(u16, u32)> = Vec::new();
+
+    for tx in 1..=records {
+        let roll = rng.below(100);
+        let record = if roll < 10 && !deposits.is_empty() {
+            let (client, id) = deposits.swap_remove(rng.below(deposits.len() as u64) as usize);
+            disputes.push((client, id));
+            GeneratedRecord {
+                r#type: TransactionType::Dispute,
+                client,
+                tx: id,
+                amount: None,
+            }
+        } else if roll < 15 && !disputes.is_empty() {
+            let (client, id) = disputes.swap_remove(rng.below(disputes.len() as u64) as usize);
+            let r#type = if roll < 14 {
+                TransactionType::Resolve
+            } else {
+                TransactionType::Chargeback
+            };
+            GeneratedRecord {
+                r#type,
+                client,
+                tx: id,
+                amount: None,
+            }
+        } else {
+            let client = rng.below(clients) as u16 + 1;
+            let amount = Decimal::new(rng.below(1_000_000) as i64 + 1, 4);
+            let r#type = if roll < 70 {
+                deposits.push((client, tx));
+                TransactionType::Deposit
+            } else {
+                TransactionType::Withdrawal
+            };
+            GeneratedRecord {
+                r#type,
+                client,
+                tx,
+                amount: Some(amount),
+            }
+        };
+        wtr.serialize(record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use std::collections::{BTreeMap, HashMap};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{ConfigError, Error};
+use crate::state::CurrentState;
+
+#[derive(Debug, Deserialize)]
+/// A row of a hierarchy mapping file.
+struct CsvPlacement {
+    client: u16,
+    merchant: String,
+    program: String,
+}
+
+#[derive(Debug, Default, Clone)]
+/// Places clients under merchants, and merchants under programs, so
+/// balances and disputes can be rolled up for program managers.
+pub struct Hierarchy {
+    merchants: HashMap<u16, String>,
+    programs: HashMap<String, String>,
+}
+
+impl Hierarchy {
+    /// Reads a CSV file with `client`, `merchant` and `program`
+    /// columns. Each client may only appear once, and each merchant
+    /// may only belong to one program.
+    pub fn from_csv(reader: impl std::io::Read) -> Result<Self, Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut hierarchy = Hierarchy::default();
+        for row in rdr.deserialize() {
+            let row: CsvPlacement = row?;
+            hierarchy.insert(row.client, row.merchant, row.program)?;
+        }
+        Ok(hierarchy)
+    }
+
+    /// Places `client` under `merchant`, and `merchant` under `program`.
+    pub fn insert(
+        &mut self,
+        client: u16,
+        merchant: String,
+        program: String,
+    ) -> Result<(), ConfigError> {
+        if self.merchants.contains_key(&client) {
+            return Err(ConfigError::DuplicateClientPlacement(client));
+        }
+        match self.programs.get(&merchant) {
+            Some(existing) if *existing != program => {
+                return Err(ConfigError::ConflictingProgram(merchant));
+            }
+            Some(_) => {}
+            None => {
+                self.programs.insert(merchant.clone(), program);
+            }
+        }
+        self.merchants.insert(client, merchant);
+        Ok(())
+    }
+
+    /// The merchant `client` is placed under, if any.
+    pub fn merchant(&self, client: u16) -> Option<&str> {
+        self.merchants.get(&client).map(String::as_str)
+    }
+
+    /// The program `merchant` belongs to, if any.
+    pub fn program(&self, merchant: &str) -> Option<&str> {
+        self.programs.get(merchant).map(String::as_str)
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+/// A level of the hierarchy above clients.
+pub enum Level {
+    Merchant,
+    Program,
+}
+
+#[derive(Debug, Serialize, Clone)]
+/// The clients under one merchant or program, added up. Used for
+/// serialization.
+pub struct Rollup {
+    pub level: Level,
+    /// The merchant or program, empty for clients not in the hierarchy.
+    pub name: String,
+    /// The program a merchant belongs to, empty for programs.
+    pub parent: String,
+    pub clients: u64,
+    pub locked: u64,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    /// Disputes opened and not yet resolved or charged back.
+    pub open_disputes: u64,
+    pub chargebacks: u64,
+    /// Deposits and withdrawals, which chargebacks are a share of.
+    pub transactions: u64,
+    /// The share of deposits and withdrawals charged back.
+    pub chargeback_rate: Decimal,
+}
+
+impl Rollup {
+    fn new(level: Level, name: &str, parent: &str) -> Self {
+        Rollup {
+            level,
+            name: name.to_owned(),
+            parent: parent.to_owned(),
+            clients: 0,
+            locked: 0,
+            available: Decimal::default(),
+            held: Decimal::default(),
+            total: Decimal::default(),
+            open_disputes: 0,
+            chargebacks: 0,
+            transactions: 0,
+            chargeback_rate: Decimal::default(),
+        }
+    }
+}
+
+/// Rolls up every client in `state` by merchant and by program,
+/// merchants first, each ordered by name.
+pub fn rollup(state: &CurrentState, hierarchy: &Hierarchy) -> Vec<Rollup> {
+    let stats = state.stats();
+    let mut rollups = BTreeMap::new();
+    for client in state.clients() {
+        let merchant = hierarchy.merchant(client.client).unwrap_or_default();
+        let program = hierarchy.program(merchant).unwrap_or_default();
+        let summary = stats.client(client.client).cloned().unwrap_or_default();
+        for (level, name, parent) in [
+            (Level::Merchant, merchant, program),
+            (Level::Program, program, ""),
+        ] {
+            let rollup = rollups
+                .entry((level, name))
+                .or_insert_with(|| Rollup::new(level, name, parent));
+            rollup.clients += 1;
+            rollup.locked += u64::from(client.locked);
+            rollup.available += client.available;
+            rollup.held += client.held;
+            rollup.total += client.total;
+            rollup.open_disputes += summary
+                .disputes
+                .saturating_sub(summary.resolves + summary.chargebacks);
+            rollup.chargebacks += summary.chargebacks;
+            rollup.transactions += summary.deposits + summary.withdrawals;
+        }
+    }
+    rollups
+        .into_values()
+        .map(|mut rollup| {
+            if rollup.transactions > 0 {
+                rollup.chargeback_rate = (Decimal::from(rollup.chargebacks)
+                    / Decimal::from(rollup.transactions))
+                .round_dp(4);
+            }
+            rollup
+        })
+        .collect()
+}
+
+/// Writes rollups as CSV.
+pub fn write_csv<'a>(
+    writer: impl std::io::Write,
+    rollups: impl IntoIterator<Item = &'a Rollup>,
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    rollups
+        .into_iter()
+        .try_for_each(|rollup| wtr.serialize(rollup))?;
+    wtr.flush()?;
+    Ok(())
+}
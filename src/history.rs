@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::search::MAX_PAGE_SIZE;
+use crate::transaction::Transaction;
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+/// A record applied to a client, as listed in its history.
+pub struct HistoryEntry {
+    /// The number of the record among those the state processed, which
+    /// only grows, so it orders a client's history and is its cursor.
+    pub seq: u64,
+    /// The type of the record, including disputes, resolves and
+    /// chargebacks, so a dispute's lifecycle reads in order.
+    #[serde(rename = "type")]
+    pub r#type: String,
+    pub tx: u32,
+    #[serde(with = "rust_decimal::serde::str_option")]
+    pub amount: Option<Decimal>,
+    pub timestamp: Option<u64>,
+    pub external_ref: Option<String>,
+    pub reason_code: Option<String>,
+}
+
+impl HistoryEntry {
+    /// The entry for `tx`, applied as record number `seq`.
+    pub(crate) fn new(seq: u64, tx: &Transaction) -> Self {
+        HistoryEntry {
+            seq,
+            r#type: tx.type_name().to_owned(),
+            tx: tx.id,
+            amount: tx.amount(),
+            timestamp: tx.timestamp,
+            external_ref: tx.external_ref.clone(),
+            reason_code: tx.reason_code.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone, Copy)]
+/// A request for a page of a client's history, as the parameters of
+/// the `list_transactions` method or the query of
+/// `GET /clients/{id}/transactions`.
+pub struct HistoryQuery {
+    /// The cursor of the previous page, if this is not the first.
+    #[serde(default)]
+    pub cursor: Option<u64>,
+    /// The most entries in the page, from one up to `MAX_PAGE_SIZE`.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl HistoryQuery {
+    /// Parses a URL query, such as `cursor=120&limit=50`, as
+    /// `ClientQuery::from_url_query` does.
+    pub fn from_url_query(query: &str) -> Result<Self, String> {
+        fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("invalid value `{}` for `{}`", value, name))
+        }
+
+        let mut parsed = HistoryQuery::default();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match name {
+                "cursor" => parsed.cursor = Some(parse(name, value)?),
+                "limit" => parsed.limit = Some(parse(name, value)?),
+                _ => return Err(format!("unknown parameter `{}`", name)),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+/// One page of a client's history, oldest first.
+pub struct HistoryPage {
+    pub transactions: Vec<HistoryEntry>,
+    /// The cursor to pass for the next page, if there are more entries.
+    /// Entries are only ever added after the last one, so the `seq` of
+    /// the last entry seen stays a valid cursor, for picking up the
+    /// records applied since.
+    pub next_cursor: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone)]
+/// Every record applied to each client, so integrators can sync a
+/// client's history page by page instead of rereading audit files.
+pub(crate) struct ClientHistory {
+    entries: HashMap<u16, Vec<HistoryEntry>>,
+}
+
+impl ClientHistory {
+    /// Adds `entry` to the end of the history of `client`.
+    pub(crate) fn record(&mut self, client: u16, entry: HistoryEntry) {
+        self.entries.entry(client).or_default().push(entry);
+    }
+
+    /// The page of the history of `client` after `cursor`, with at most
+    /// `limit` entries. Pages are found by binary search, so a page
+    /// takes time logarithmic in the length of the history.
+    pub(crate) fn page(&self, client: u16, cursor: Option<u64>, limit: usize) -> HistoryPage {
+        let entries = self.entries.get(&client).map_or(&[][..], Vec::as_slice);
+        let start = cursor.map_or(0, |cursor| {
+            entries.partition_point(|entry| entry.seq <= cursor)
+        });
+        let entries = &entries[start..];
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        let page = &entries[..limit.min(entries.len())];
+        HistoryPage {
+            transactions: page.to_vec(),
+            next_cursor: match entries.len() > limit {
+                true => page.last().map(|entry| entry.seq),
+                false => None,
+            },
+        }
+    }
+
+    /// Moves the history of `src` into that of `dst`, in record order.
+    /// A cursor into the history of `dst` from before the merge may
+    /// skip entries of `src` older than it.
+    pub(crate) fn merge_clients(&mut self, src: u16, dst: u16) {
+        if let Some(moved) = self.entries.remove(&src) {
+            let entries = self.entries.entry(dst).or_default();
+            entries.extend(moved);
+            entries.sort_by_key(|entry| entry.seq);
+        }
+    }
+
+    /// Absorbs the histories kept by `other`, which processed other
+    /// clients.
+    pub(crate) fn merge(&mut self, other: ClientHistory) {
+        for (client, moved) in other.entries {
+            let entries = self.entries.entry(client).or_default();
+            entries.extend(moved);
+            entries.sort_by_key(|entry| entry.seq);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::errors::{ConfigError, Error};
+
+#[derive(Debug, Deserialize)]
+/// A row of a file of hold rates. Used for deserialization.
+struct CsvHoldRate {
+    #[serde(default)]
+    client: Option<u16>,
+    #[serde(default)]
+    currency: Option<String>,
+    rate: Decimal,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// The share of a disputed transaction's amount that is held while the
+/// dispute is open, as some schemes only require part of it to be: a
+/// rate for every dispute, and rates for individual clients and
+/// currencies that override it.
+///
+/// The rest of the amount stays available. A resolve releases what was
+/// held, and a chargeback removes what was held from the held funds and
+/// the rest from the available funds, which may take them below zero,
+/// so the client loses the whole amount either way.
+pub struct HoldRates {
+    default: Decimal,
+    clients: HashMap<u16, Decimal>,
+    currencies: HashMap<String, Decimal>,
+}
+
+impl Default for HoldRates {
+    /// Holds the whole amount of every dispute.
+    fn default() -> Self {
+        HoldRates {
+            default: Decimal::ONE,
+            clients: HashMap::new(),
+            currencies: HashMap::new(),
+        }
+    }
+}
+
+impl HoldRates {
+    /// Holds `default`, such as `0.8` for 80%, of the amount of every
+    /// dispute.
+    pub fn new(default: Decimal) -> Result<Self, Error> {
+        Ok(HoldRates {
+            default: checked(default)?,
+            ..HoldRates::default()
+        })
+    }
+
+    /// Reads the rates of individual clients and currencies from a CSV
+    /// file with `client`, `currency` and `rate` columns, on top of
+    /// `default`. Each row gives either a client or a currency, and
+    /// each client and currency may only appear once.
+    pub fn from_csv(default: Decimal, reader: impl std::io::Read) -> Result<Self, Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut rates = HoldRates::new(default)?;
+        for (row, record) in (1..).zip(rdr.deserialize()) {
+            let record: CsvHoldRate = record?;
+            match (record.client, record.currency) {
+                (Some(client), None) => rates.set_client(client, record.rate)?,
+                (None, Some(currency)) => rates.set_currency(currency, record.rate)?,
+                _ => return Err(ConfigError::AmbiguousHoldRate(row).into()),
+            }
+        }
+        Ok(rates)
+    }
+
+    /// Holds `rate` of the amount of the disputes of `client`, whatever
+    /// their currency.
+    pub fn set_client(&mut self, client: u16, rate: Decimal) -> Result<(), Error> {
+        if self.clients.insert(client, checked(rate)?).is_some() {
+            return Err(ConfigError::DuplicateClientHoldRate(client).into());
+        }
+        Ok(())
+    }
+
+    /// Holds `rate` of the amount of disputes of transactions in
+    /// `currency`, for clients without a rate of their own.
+    pub fn set_currency(&mut self, currency: String, rate: Decimal) -> Result<(), Error> {
+        let rate = checked(rate)?;
+        if self.currencies.contains_key(&currency) {
+            return Err(ConfigError::DuplicateCurrencyHoldRate(currency).into());
+        }
+        self.currencies.insert(currency, rate);
+        Ok(())
+    }
+
+    /// The share held of a dispute by `client` of a transaction in
+    /// `currency`: the client's rate, then the currency's, then the
+    /// default.
+    pub fn rate(&self, client: u16, currency: Option<&str>) -> Decimal {
+        self.clients
+            .get(&client)
+            .or_else(|| self.currencies.get(currency?))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// `rate`, if it is above zero and at most one.
+fn checked(rate: Decimal) -> Result<Decimal, Error> {
+    match rate > Decimal::ZERO && rate <= Decimal::ONE {
+        true => Ok(rate),
+        false => Err(ConfigError::InvalidHoldRate(rate).into()),
+    }
+}
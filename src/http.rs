@@ -0,0 +1,78 @@
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+/// A service spoken to in plain HTTP, so it should be local, or behind a
+/// local proxy that handles TLS and authentication.
+pub(crate) struct Endpoint {
+    host: String,
+    path: String,
+}
+
+impl Endpoint {
+    /// Parses a URL such as `http://localhost:8080/cases`.
+    pub(crate) fn new(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("expected an `http://` URL, got `{}`", url))?;
+        let (host, path) = match rest.find('/') {
+            Some(start) => rest.split_at(start),
+            None => (rest, "/"),
+        };
+        let host = match host.contains(':') {
+            true => host.to_owned(),
+            false => format!("{}:80", host),
+        };
+        Ok(Endpoint {
+            host,
+            path: path.to_owned(),
+        })
+    }
+
+    /// The path of the URL.
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// POSTs `body` as JSON to `path` on the host, and returns the body
+    /// of a `200` reply, waiting at most `timeout` for each step.
+    pub(crate) fn post_json(
+        &self,
+        path: &str,
+        body: &[u8],
+        timeout: Duration,
+    ) -> std::io::Result<String> {
+        let addr = self
+            .host
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| std::io::Error::other(format!("cannot resolve `{}`", self.host)))?;
+        let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        // HTTP/1.0, so the reply is neither chunked nor kept alive.
+        write!(
+            stream,
+            "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+            path,
+            self.host,
+            body.len()
+        )?;
+        stream.write_all(body)?;
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply)?;
+        let reply = String::from_utf8_lossy(&reply);
+        let (head, body) = reply
+            .split_once("\r\n\r\n")
+            .ok_or_else(|| std::io::Error::other("malformed reply"))?;
+        let status = head.lines().next().unwrap_or_default();
+        if status.split_whitespace().nth(1) != Some("200") {
+            return Err(std::io::Error::other(format!(
+                "service replied `{}`",
+                status
+            )));
+        }
+        Ok(body.to_owned())
+    }
+}
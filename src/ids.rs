@@ -0,0 +1,181 @@
+use std::fmt::Debug;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Issues transaction IDs for entries the engine creates itself, such
+/// as chargeback fees, from a range partners may not use.
+///
+/// Allocators are shared between every state built from the same
+/// builder, so they take `&self` and must be safe to call from
+/// several threads at once.
+pub trait TxIdAllocator: Debug + Send + Sync {
+    /// Issues a fresh ID, or `None` once the range is used up.
+    fn allocate(&self) -> Option<u32>;
+
+    /// Takes back `id`, issued by `allocate` for an entry that was then
+    /// rolled back, so it can be issued again. An ID that cannot be
+    /// taken back, such as once a later one was issued, stays unused.
+    fn release(&self, _id: u32) {}
+
+    /// The IDs this allocator may issue. Partner records with an ID
+    /// in this range are rejected.
+    fn reserved(&self) -> RangeInclusive<u32>;
+}
+
+#[derive(Debug)]
+/// Issues IDs in order, from a starting ID up to `u32::MAX`.
+pub struct Sequential {
+    start: u32,
+    // Wider than an ID, so it cannot wrap around into issued IDs.
+    next: AtomicU64,
+}
+
+impl Sequential {
+    /// Issues IDs from `start` upwards.
+    pub fn starting_at(start: u32) -> Self {
+        Sequential {
+            start,
+            next: AtomicU64::new(start.into()),
+        }
+    }
+}
+
+impl TxIdAllocator for Sequential {
+    fn allocate(&self) -> Option<u32> {
+        u32::try_from(self.next.fetch_add(1, Ordering::Relaxed)).ok()
+    }
+
+    fn release(&self, id: u32) {
+        let id = u64::from(id);
+        let _ = self
+            .next
+            .compare_exchange(id + 1, id, Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    fn reserved(&self) -> RangeInclusive<u32> {
+        self.start..=u32::MAX
+    }
+}
+
+#[derive(Debug)]
+/// Issues IDs whose top byte names the engine instance, like
+/// Snowflake IDs, so instances never issue the same ID as each other.
+/// Each instance can issue 2^24 IDs.
+pub struct Snowflake {
+    node: u8,
+    sequence: AtomicU64,
+}
+
+impl Snowflake {
+    /// The number of bits the node is shifted by.
+    const NODE_SHIFT: u32 = 24;
+
+    /// Issues IDs for the instance `node`.
+    pub fn new(node: u8) -> Self {
+        Snowflake {
+            node,
+            sequence: AtomicU64::new(0),
+        }
+    }
+}
+
+impl TxIdAllocator for Snowflake {
+    fn allocate(&self) -> Option<u32> {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        (sequence >> Self::NODE_SHIFT == 0)
+            .then(|| u32::from(self.node) << Self::NODE_SHIFT | sequence as u32)
+    }
+
+    fn release(&self, id: u32) {
+        let sequence = u64::from(id & ((1 << Self::NODE_SHIFT) - 1));
+        let _ = self.sequence.compare_exchange(
+            sequence + 1,
+            sequence,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+    }
+
+    fn reserved(&self) -> RangeInclusive<u32> {
+        let base = u32::from(self.node) << Self::NODE_SHIFT;
+        base..=base | ((1 << Self::NODE_SHIFT) - 1)
+    }
+}
+
+/// Issues IDs by calling a function, such as one drawing from an
+/// external sequence, within a declared range.
+pub struct Callback<F> {
+    allocate: F,
+    reserved: RangeInclusive<u32>,
+}
+
+impl<F: Fn() -> Option<u32>> Callback<F> {
+    /// Issues IDs from `allocate`, which must only return IDs in
+    /// `reserved`.
+    pub fn new(reserved: RangeInclusive<u32>, allocate: F) -> Self {
+        Callback { allocate, reserved }
+    }
+}
+
+impl<F> Debug for Callback<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Callback")
+            .field("reserved", &self.reserved)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F: Fn() -> Option<u32> + Send + Sync> TxIdAllocator for Callback<F> {
+    fn allocate(&self) -> Option<u32> {
+        (self.allocate)().filter(|id| self.reserved.contains(id))
+    }
+
+    fn reserved(&self) -> RangeInclusive<u32> {
+        self.reserved.clone()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A built-in allocator, as named on the command line.
+pub enum AllocatorSpec {
+    /// `sequential:<start>`.
+    Sequential(u32),
+    /// `snowflake:<node>`.
+    Snowflake(u8),
+}
+
+impl AllocatorSpec {
+    /// Creates the allocator.
+    pub fn build(self) -> Arc<dyn TxIdAllocator> {
+        match self {
+            AllocatorSpec::Sequential(start) => Arc::new(Sequential::starting_at(start)),
+            AllocatorSpec::Snowflake(node) => Arc::new(Snowflake::new(node)),
+        }
+    }
+}
+
+impl FromStr for AllocatorSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = s.split_once(':').ok_or_else(|| {
+            format!(
+                "expected `sequential:<start>` or `snowflake:<node>`, got `{}`",
+                s
+            )
+        })?;
+        match kind {
+            "sequential" => value
+                .parse()
+                .map(AllocatorSpec::Sequential)
+                .map_err(|err| format!("invalid starting ID `{}`: {}", value, err)),
+            "snowflake" => value
+                .parse()
+                .map(AllocatorSpec::Snowflake)
+                .map_err(|err| format!("invalid node `{}`: {}", value, err)),
+            _ => Err(format!("unknown allocator `{}`", kind)),
+        }
+    }
+}
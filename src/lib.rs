@@ -0,0 +1,110 @@
+pub mod actor;
+pub mod adjudicate;
+pub mod alerts;
+pub mod archive;
+pub mod audit;
+mod balance;
+pub mod calendar;
+pub mod capabilities;
+pub mod cases;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+#[cfg(feature = "clickhouse")]
+pub mod clickhouse;
+#[cfg(feature = "cli")]
+pub mod completions;
+pub mod conform;
+pub mod counterparty;
+#[cfg(feature = "encryption")]
+pub mod crypto;
+pub mod decimal;
+pub mod diff;
+pub mod digest;
+pub mod disputes;
+pub mod enrich;
+pub mod errors;
+pub mod events;
+pub mod filter;
+pub mod fixture;
+pub mod flags;
+pub mod freeze;
+pub mod generate;
+pub mod hierarchy;
+pub mod history;
+pub mod holds;
+#[cfg(feature = "http")]
+mod http;
+pub mod ids;
+pub mod limits;
+pub mod lint;
+pub mod lots;
+pub mod manifest;
+pub mod middleware;
+pub mod minimize;
+pub mod monitor;
+pub mod notify;
+pub mod output;
+pub mod parallel;
+mod parse;
+pub mod partition;
+pub mod progress;
+pub mod quarantine;
+pub mod reasons;
+pub mod reconcile;
+pub mod registry;
+pub mod rejects;
+pub mod replicate;
+pub mod reserve;
+pub mod risk;
+pub mod rpc;
+pub mod schema;
+pub mod screen;
+pub mod search;
+pub mod seen;
+pub mod session;
+pub mod settlement;
+pub mod shared;
+pub mod sniff;
+pub mod state;
+pub mod statement;
+pub mod summary;
+pub mod supervise;
+#[cfg(feature = "datafusion")]
+pub mod tables;
+pub mod transaction;
+#[cfg(feature = "warehouse")]
+pub mod warehouse;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+#[derive(Debug)]
+/// The output of `process_csv_string`.
+pub struct ProcessedCsv {
+    /// The final client states, as the binary writes them.
+    pub clients: String,
+    /// The rejected records, as the binary writes them to `--rejects`.
+    pub rejects: String,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+/// Processes a CSV document held in memory and returns the final
+/// client states and the rejected records, each as CSV. Only errors
+/// that abort processing are returned as errors.
+pub fn process_csv_string(input: &str) -> Result<ProcessedCsv, String> {
+    let mut program_state = state::CurrentState::default();
+    let mut rejects = rejects::RejectsWriter::new(Vec::new());
+    program_state
+        .process_from_csv_with(input.as_bytes(), |tx, err| Ok(rejects.write(tx, err)?))
+        .map_err(|err| err.to_string())?;
+    let rejects = rejects.into_inner().map_err(|err| err.to_string())?;
+    let mut clients = Vec::new();
+    program_state
+        .into_csv(&mut clients)
+        .map_err(|err| err.to_string())?;
+    Ok(ProcessedCsv {
+        clients: String::from_utf8(clients).map_err(|err| err.to_string())?,
+        rejects: String::from_utf8(rejects).map_err(|err| err.to_string())?,
+    })
+}
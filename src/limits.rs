@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::errors::{ConfigError, Error};
+
+/// How long a day of volume lasts, in seconds of record timestamps.
+const DAY: u64 = 86_400;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// What an account limit caps.
+pub enum LimitKind {
+    /// The amount of a single deposit or withdrawal.
+    Transaction,
+    /// The amounts deposited and withdrawn by a client in a day, by
+    /// record timestamps, from midnight UTC.
+    Daily,
+    /// The total funds of a client after a deposit.
+    Balance,
+}
+
+impl FromStr for LimitKind {
+    type Err = String;
+
+    /// Parses `transaction`, `daily` or `balance`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "transaction" => Ok(LimitKind::Transaction),
+            "daily" => Ok(LimitKind::Daily),
+            "balance" => Ok(LimitKind::Balance),
+            _ => Err(format!("invalid limit kind `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// The two levels of one limit. Going past the soft limit is allowed,
+/// but flags the client and notifies the sink, giving it a grace
+/// margin before the hard limit, past which records are rejected.
+pub struct LimitLevels {
+    pub soft: Option<Decimal>,
+    pub hard: Option<Decimal>,
+}
+
+impl LimitLevels {
+    /// These levels, with those they leave unset taken from `fallback`.
+    fn or(self, fallback: LimitLevels) -> Self {
+        LimitLevels {
+            soft: self.soft.or(fallback.soft),
+            hard: self.hard.or(fallback.hard),
+        }
+    }
+
+    /// Whether `value` is past the soft limit.
+    pub(crate) fn past_soft(&self, value: Decimal) -> bool {
+        self.soft.is_some_and(|soft| value > soft)
+    }
+
+    /// Whether `value` is past the hard limit.
+    pub(crate) fn past_hard(&self, value: Decimal) -> bool {
+        self.hard.is_some_and(|hard| value > hard)
+    }
+
+    /// Checks that the levels are positive, and that the soft limit
+    /// comes before the hard one.
+    fn check(self) -> Result<Self, Error> {
+        for level in [self.soft, self.hard].into_iter().flatten() {
+            if level <= Decimal::ZERO {
+                return Err(ConfigError::LimitNotPositive(level).into());
+            }
+        }
+        if let (Some(soft), Some(hard)) = (self.soft, self.hard) {
+            if soft > hard {
+                return Err(ConfigError::SoftLimitAboveHard(soft, hard).into());
+            }
+        }
+        Ok(self)
+    }
+}
+
+impl FromStr for LimitLevels {
+    type Err = String;
+
+    /// Parses `soft/hard`, either of which may be left empty, such as
+    /// `1000/5000` or `/5000`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (soft, hard) = s
+            .split_once('/')
+            .ok_or_else(|| format!("expected `soft/hard`, found `{}`", s))?;
+        let level = |level: &str| match level {
+            "" => Ok(None),
+            _ => level
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("invalid limit `{}`", level)),
+        };
+        Ok(LimitLevels {
+            soft: level(soft)?,
+            hard: level(hard)?,
+        })
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// The levels of every kind of limit on one client.
+pub struct LimitSet {
+    pub transaction: LimitLevels,
+    pub daily: LimitLevels,
+    pub balance: LimitLevels,
+}
+
+impl LimitSet {
+    /// The levels of the limit of `kind`.
+    pub fn get(&self, kind: LimitKind) -> LimitLevels {
+        match kind {
+            LimitKind::Transaction => self.transaction,
+            LimitKind::Daily => self.daily,
+            LimitKind::Balance => self.balance,
+        }
+    }
+
+    /// Sets the levels of the limit of `kind`.
+    pub fn set(&mut self, kind: LimitKind, levels: LimitLevels) {
+        match kind {
+            LimitKind::Transaction => self.transaction = levels,
+            LimitKind::Daily => self.daily = levels,
+            LimitKind::Balance => self.balance = levels,
+        }
+    }
+
+    /// These limits, with the levels they leave unset taken from
+    /// `fallback`.
+    fn or(self, fallback: LimitSet) -> Self {
+        LimitSet {
+            transaction: self.transaction.or(fallback.transaction),
+            daily: self.daily.or(fallback.daily),
+            balance: self.balance.or(fallback.balance),
+        }
+    }
+
+    /// Checks the levels of every kind.
+    fn check(self) -> Result<Self, Error> {
+        Ok(LimitSet {
+            transaction: self.transaction.check()?,
+            daily: self.daily.check()?,
+            balance: self.balance.check()?,
+        })
+    }
+
+    /// Whether any daily limit is set.
+    fn has_daily(&self) -> bool {
+        self.daily != LimitLevels::default()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+/// A row of a file of per-client limits. Used for deserialization.
+struct CsvLimits {
+    client: u16,
+    #[serde(default)]
+    transaction_soft: Option<Decimal>,
+    #[serde(default)]
+    transaction_hard: Option<Decimal>,
+    #[serde(default)]
+    daily_soft: Option<Decimal>,
+    #[serde(default)]
+    daily_hard: Option<Decimal>,
+    #[serde(default)]
+    balance_soft: Option<Decimal>,
+    #[serde(default)]
+    balance_hard: Option<Decimal>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+/// The account limits of every client: global limits, and limits of
+/// individual clients that override them.
+///
+/// A deposit or withdrawal past a hard limit is rejected. One past a
+/// soft limit is applied, but raises the `soft_limit` flag, records a
+/// changelog event and notifies the sink, if any. The flag stands for
+/// the client's last deposit or withdrawal taking it past a soft limit,
+/// and is cleared by the next one that does not.
+pub struct AccountLimits {
+    global: LimitSet,
+    clients: HashMap<u16, LimitSet>,
+}
+
+impl AccountLimits {
+    /// Limits every client to `global`.
+    pub fn new(global: LimitSet) -> Result<Self, Error> {
+        Ok(AccountLimits {
+            global: global.check()?,
+            clients: HashMap::new(),
+        })
+    }
+
+    /// Reads the limits of individual clients from a CSV file with a
+    /// `client` column and `soft` and `hard` columns for each kind,
+    /// such as `daily_soft`, on top of `global`. Empty cells keep the
+    /// global limit. Each client may only appear once.
+    pub fn from_csv(global: LimitSet, reader: impl std::io::Read) -> Result<Self, Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut limits = AccountLimits::new(global)?;
+        for row in rdr.deserialize() {
+            let row: CsvLimits = row?;
+            if limits.clients.contains_key(&row.client) {
+                return Err(ConfigError::DuplicateLimits(row.client).into());
+            }
+            let set = LimitSet {
+                transaction: LimitLevels {
+                    soft: row.transaction_soft,
+                    hard: row.transaction_hard,
+                },
+                daily: LimitLevels {
+                    soft: row.daily_soft,
+                    hard: row.daily_hard,
+                },
+                balance: LimitLevels {
+                    soft: row.balance_soft,
+                    hard: row.balance_hard,
+                },
+            };
+            limits.set(row.client, set)?;
+        }
+        Ok(limits)
+    }
+
+    /// Sets the limits of `client`, over the global ones. The soft
+    /// limit must still come before the hard one once they are.
+    pub fn set(&mut self, client: u16, limits: LimitSet) -> Result<(), Error> {
+        limits.or(self.global).check()?;
+        self.clients.insert(client, limits);
+        Ok(())
+    }
+
+    /// The limits of `client`.
+    pub fn limits(&self, client: u16) -> LimitSet {
+        match self.clients.get(&client) {
+            Some(limits) => limits.or(self.global),
+            None => self.global,
+        }
+    }
+
+    /// Whether any client has a daily limit, which needs timestamps.
+    pub(crate) fn has_daily(&self) -> bool {
+        self.global.has_daily() || self.clients.values().any(LimitSet::has_daily)
+    }
+}
+
+/// The day `timestamp` falls on, counted from the epoch.
+pub(crate) fn day(timestamp: u64) -> u64 {
+    timestamp / DAY
+}
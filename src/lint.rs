@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::errors;
+use crate::schema;
+use crate::transaction::{Transaction, TransactionType};
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+/// The checks a file can fail.
+pub enum LintCheck {
+    /// The header does not match the input format.
+    Schema,
+    /// A record cannot be read as a transaction.
+    InvalidRecord,
+    /// A deposit, withdrawal or adjustment reuses an earlier ID.
+    DuplicateTransaction,
+    /// A dispute, resolve or chargeback names an ID that no earlier
+    /// record in the file introduced.
+    UnknownTransaction,
+    /// A deposit or withdrawal has a negative amount.
+    NegativeAmount,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+/// One problem found in a file. Used for serialization.
+pub struct Finding {
+    /// The line of the file, counting the header as line one.
+    pub row: u64,
+    pub tx: Option<u32>,
+    pub check: LintCheck,
+    pub message: String,
+}
+
+/// Checks a CSV input file on its own, without any state: the header,
+/// whether every record can be read, IDs reused within the file,
+/// disputes of IDs not introduced earlier in the file, and negative
+/// amounts. Every problem is listed, in file order.
+pub fn lint(reader: impl std::io::Read) -> Result<Vec<Finding>, csv::Error> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let headers = rdr.headers()?.clone();
+    let mut findings = Vec::new();
+    if let Err(err) = schema::check_headers(&headers) {
+        findings.push(Finding {
+            row: 1,
+            tx: None,
+            check: LintCheck::Schema,
+            message: err.to_string(),
+        });
+    }
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let (type_column, tx_column, amount_column) = (column("type"), column("tx"), column("amount"));
+
+    // The line each deposit, withdrawal or adjustment ID was introduced on.
+    let mut introduced: HashMap<u32, u64> = HashMap::new();
+    let mut record = csv::StringRecord::new();
+    loop {
+        match rdr.read_record(&mut record) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => match err.kind() {
+                csv::ErrorKind::UnequalLengths { pos: Some(pos), .. } => {
+                    findings.push(Finding {
+                        row: pos.line(),
+                        tx: None,
+                        check: LintCheck::InvalidRecord,
+                        message: describe(err, &headers),
+                    });
+                    continue;
+                }
+                _ => return Err(err),
+            },
+        }
+        let row = record.position().map_or(0, csv::Position::line);
+        let field = |column: Option<usize>| column.and_then(|column| record.get(column));
+        let tx = field(tx_column).and_then(|id| id.parse().ok());
+        let r#type = field(type_column).and_then(|name| name.parse::<TransactionType>().ok());
+        let amount = field(amount_column).and_then(|amount| amount.parse::<Decimal>().ok());
+        let mut finding = |check, message: String| {
+            findings.push(Finding {
+                row,
+                tx,
+                check,
+                message,
+            })
+        };
+
+        // Adjustments are signed, so only other amounts must be positive.
+        if amount.is_some_and(|amount| amount.is_sign_negative())
+            && r#type != Some(TransactionType::Adjustment)
+        {
+            finding(
+                LintCheck::NegativeAmount,
+                format!("amount `{}` is negative", amount.unwrap()),
+            );
+            continue;
+        }
+        let parsed: Transaction = match record.deserialize(Some(&headers)) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                finding(LintCheck::InvalidRecord, describe(err, &headers));
+                continue;
+            }
+        };
+        match parsed.r#type() {
+            TransactionType::Deposit
+            | TransactionType::Withdrawal
+            | TransactionType::Adjustment => {
+                if let Some(&first) = introduced.get(&parsed.id) {
+                    finding(
+                        LintCheck::DuplicateTransaction,
+                        format!(
+                            "transaction ID `{}` was already used on row {}",
+                            parsed.id, first
+                        ),
+                    );
+                } else {
+                    introduced.insert(parsed.id, row);
+                }
+            }
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                if !introduced.contains_key(&parsed.id) {
+                    finding(
+                        LintCheck::UnknownTransaction,
+                        format!(
+                            "transaction ID `{}` is not introduced earlier in the file",
+                            parsed.id
+                        ),
+                    );
+                }
+            }
+            TransactionType::Custom => {}
+        }
+    }
+    Ok(findings)
+}
+
+/// Describes why a record could not be read, without the position,
+/// which is reported separately.
+fn describe(err: csv::Error, headers: &csv::StringRecord) -> String {
+    match schema::diagnose(err, headers) {
+        errors::Error::Schema(err) => err.to_string(),
+        errors::Error::Csv(err) => match err.kind() {
+            csv::ErrorKind::Deserialize { err, .. } => err.kind().to_string(),
+            _ => err.to_string(),
+        },
+        err => err.to_string(),
+    }
+}
+
+/// Writes findings as CSV.
+pub fn write_csv(writer: impl std::io::Write, findings: &[Finding]) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    findings
+        .iter()
+        .try_for_each(|finding| wtr.serialize(finding))?;
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use rust_decimal::Decimal;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// Funds of a deposit spent by a withdrawal, written `tx:amount`.
+pub struct TracedWithdrawal {
+    /// The ID of the withdrawal.
+    pub tx: u32,
+    pub amount: Decimal,
+}
+
+impl fmt::Display for TracedWithdrawal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.tx, self.amount.normalize())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// What is left of one deposit.
+struct Lot {
+    /// The ID of the deposit.
+    tx: u32,
+    remaining: Decimal,
+    /// Whether the deposit is disputed, so its funds cannot be spent.
+    held: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+/// Which withdrawals spent the funds of each deposit, so the funds of a
+/// disputed deposit that are already gone can be traced.
+///
+/// The deposits of each client are lots, spent first in, first out, by
+/// its withdrawals. Lots of disputed deposits are skipped until the
+/// dispute is resolved, and dropped if it is charged back. Only
+/// deposits and withdrawals are traced: funds credited by adjustments
+/// or custom types are in no lot, and withdrawals of more than
+/// the lots left are only traced as far as they go.
+pub(crate) struct FundsTracer {
+    /// The deposits of each client with funds left, oldest first.
+    lots: HashMap<u16, VecDeque<Lot>>,
+    /// The withdrawals that spent the funds of each deposit, in the
+    /// order they were made.
+    spent: HashMap<u32, Vec<TracedWithdrawal>>,
+}
+
+impl FundsTracer {
+    /// Adds a deposit of `amount` by `client`.
+    pub(crate) fn deposit(&mut self, client: u16, tx: u32, amount: Decimal) {
+        self.lots.entry(client).or_default().push_back(Lot {
+            tx,
+            remaining: amount,
+            held: false,
+        });
+    }
+
+    /// Spends `amount` of the oldest deposits of `client` that are not
+    /// disputed for the withdrawal `tx`.
+    pub(crate) fn withdraw(&mut self, client: u16, tx: u32, amount: Decimal) {
+        let Some(lots) = self.lots.get_mut(&client) else {
+            return;
+        };
+        let mut left = amount;
+        for lot in lots.iter_mut().filter(|lot| !lot.held) {
+            if left.is_zero() {
+                break;
+            }
+            let spent = left.min(lot.remaining);
+            lot.remaining -= spent;
+            left -= spent;
+            self.spent
+                .entry(lot.tx)
+                .or_default()
+                .push(TracedWithdrawal { tx, amount: spent });
+        }
+        lots.retain(|lot| !lot.remaining.is_zero());
+    }
+
+    /// Marks the deposit `tx` of `client` as disputed, or as no longer
+    /// disputed, if it has funds left.
+    pub(crate) fn hold(&mut self, client: u16, tx: u32, held: bool) {
+        if let Some(lot) = self.lot(client, tx) {
+            lot.held = held;
+        }
+    }
+
+    /// Drops what is left of the deposit `tx` of `client`, once it is
+    /// charged back.
+    pub(crate) fn charge_back(&mut self, client: u16, tx: u32) {
+        if let Some(lots) = self.lots.get_mut(&client) {
+            lots.retain(|lot| lot.tx != tx);
+        }
+    }
+
+    /// The withdrawals that spent the funds of the deposit `tx`.
+    pub(crate) fn spent(&self, tx: u32) -> &[TracedWithdrawal] {
+        self.spent.get(&tx).map_or(&[], Vec::as_slice)
+    }
+
+    /// Moves the deposits of `src` to `dst`, after those of `dst`, as
+    /// the funds of `src` now belong to `dst`.
+    pub(crate) fn merge_clients(&mut self, src: u16, dst: u16) {
+        if let Some(lots) = self.lots.remove(&src) {
+            self.lots.entry(dst).or_default().extend(lots);
+        }
+    }
+
+    /// Absorbs the deposits and withdrawals traced by `other`, which
+    /// processed other clients.
+    pub(crate) fn merge(&mut self, other: FundsTracer) {
+        for (client, lots) in other.lots {
+            self.lots.entry(client).or_default().extend(lots);
+        }
+        for (tx, spent) in other.spent {
+            self.spent.entry(tx).or_default().extend(spent);
+        }
+    }
+
+    /// The lot of the deposit `tx` of `client`, if it has funds left.
+    fn lot(&mut self, client: u16, tx: u32) -> Option<&mut Lot> {
+        self.lots
+            .get_mut(&client)?
+            .iter_mut()
+            .find(|lot| lot.tx == tx)
+    }
+}
@@ -1,24 +1,2058 @@
-mod errors;
-mod state;
-mod transaction;
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::Write,
+    net::TcpListener,
+    num::{NonZeroU16, NonZeroUsize},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime},
+};
 
-use std::{fs::File, path::PathBuf};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use payment_engine::actor::{self, Executor};
+#[cfg(feature = "http")]
+use payment_engine::adjudicate::HttpAdjudicator;
+use payment_engine::alerts::{BalanceAlerts, BalanceThresholds};
+use payment_engine::calendar::Calendar;
+#[cfg(feature = "chaos")]
+use payment_engine::chaos::{Chaos, ChaosConfig};
+#[cfg(feature = "clickhouse")]
+use payment_engine::clickhouse::{ClickHouseConfig, ClickHouseSink};
+use payment_engine::completions::{self, Shell};
+use payment_engine::conform::{self, VectorResult};
+use payment_engine::counterparty::CounterpartyMap;
+#[cfg(feature = "encryption")]
+use payment_engine::crypto::{self, KeySource};
+use payment_engine::decimal::DecimalStyle;
+#[cfg(feature = "http")]
+use payment_engine::enrich::HttpEnricher;
+use payment_engine::enrich::{FileEnricher, LookupKey};
+use payment_engine::filter::TxFilter;
+use payment_engine::freeze::Freezes;
+use payment_engine::hierarchy::{self, Hierarchy};
+use payment_engine::holds::HoldRates;
+use payment_engine::ids::AllocatorSpec;
+use payment_engine::limits::{AccountLimits, LimitKind, LimitLevels, LimitSet};
+use payment_engine::manifest::{self, DigestingWriter, Manifest, Provenance};
+use payment_engine::monitor::{ChargebackMonitor, MonitorAction};
+use payment_engine::output::AtomicFile;
+use payment_engine::partition::{self, Partitioning};
+use payment_engine::progress::{Progress, ProgressObserver, ProgressTracker};
+use payment_engine::quarantine::{self, VelocityLimit};
+use payment_engine::reasons::ReasonCodes;
+use payment_engine::replicate::{self, Leader};
+use payment_engine::reserve::Reserves;
+use payment_engine::screen::Screener;
+use payment_engine::seen::SeenSet;
+use payment_engine::session::Session;
+use payment_engine::sniff::InputFormat;
+use payment_engine::state::ClientMerge;
+use payment_engine::statement::{Statement, StatementFormat};
+use payment_engine::summary::SummaryReport;
+use payment_engine::supervise::{self, RestartPolicy, Supervisor};
+use payment_engine::transaction::{DisputeInitiator, Transaction};
+#[cfg(feature = "warehouse")]
+use payment_engine::warehouse::{
+    self, BigQuerySink, RestSink, WarehouseApi, WarehouseSink, WarehouseTables,
+};
+use payment_engine::{
+    audit, capabilities, cases, diff, disputes, errors, events, generate, lint, minimize, parallel,
+    reconcile,
+    rejects::{RejectsWriter, Retries, RetryWriter},
+    risk, rpc, settlement, state,
+};
+use rust_decimal::Decimal;
 
-use clap::Parser;
+/// How often `serve` checks the denylist file for changes.
+const DENYLIST_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 /// The command-line arguments to the program
-struct Args {
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+/// The subcommands of the program.
+/// A bare input file is treated as `process`.
+enum Command {
+    /// Process CSV files and print the final client states.
+    Process(OutputArgs),
+    /// Process CSV files again every time they change.
+    Watch(WatchArgs),
+    /// Process CSV files and print the final state of one client.
+    Query(QueryArgs),
+    /// Generate a pseudo-random input CSV file.
+    Generate(GenerateArgs),
+    /// Compare two files of final client states.
+    Diff(DiffArgs),
+    /// Check a CSV file on its own, without processing it, and list
+    /// every problem found.
+    Lint(LintArgs),
+    /// Process a CSV file and compare the result against an expected
+    /// file of client states, with where each differing client diverged.
+    Reconcile(ReconcileArgs),
+    /// Run every JSON test vector in a directory and report the ones
+    /// the engine does not conform to.
+    Conform(ConformArgs),
+    /// Reduce an input CSV file that fails to the fewest records that
+    /// still fail the same way, using the engine to check each step.
+    Minimize(MinimizeArgs),
+    /// Check an output file and its inputs against a manifest.
+    VerifyManifest(VerifyManifestArgs),
+    /// List the features, formats and server modes this build supports,
+    /// and the policies applied unless configured otherwise.
+    Capabilities(CapabilitiesArgs),
+    /// Print a script completing subcommands and flags in a shell.
+    Completions(CompletionsArgs),
+    #[cfg(feature = "encryption")]
+    /// Decrypt an encrypted audit log to standard output.
+    Decrypt(DecryptArgs),
+    /// Read records as JSON lines from `stdin`, and acknowledge each on
+    /// `stdout` with its outcome and the client's balances.
+    Stream(StreamArgs),
+    /// Serve JSON-RPC 2.0 requests, one per line, to submit records and
+    /// query balances. Listens on a Unix socket with `--ipc`, and
+    /// otherwise on `stdin` and `stdout`.
+    Serve(ServeArgs),
+    /// Make an administrative change to the accounts of a running
+    /// server, and print the result.
+    Admin(AdminArgs),
+    /// Process CSV files and print each client's risk score and the
+    /// activity behind it, riskiest first.
+    RiskReport(ProcessArgs),
+    /// Process CSV files and print balances, open disputes and
+    /// chargeback rates added up by merchant and by program.
+    Rollup(RollupArgs),
+    /// Process CSV files and write statements of each client's
+    /// transactions in a bank format, for tools that only import those.
+    Statement(StatementArgs),
+    /// Attempt the records in a rejects file again, against the state
+    /// the inputs leave, and write which are now accepted and which are
+    /// rejected again.
+    Retry(RetryArgs),
+}
+
+#[derive(Args, Debug)]
+/// The policies applied while processing, shared by all commands
+/// that process records.
+struct PolicyArgs {
+    #[clap(long, value_parser)]
+    /// How amounts are written: `us` for `1,234.56` or `eu` for `1.234,56`.
+    decimal_style: Option<DecimalStyle>,
+    #[clap(long, value_parser, default_value = "auto")]
+    /// How input records are written: `csv`, `jsonl`, or `auto` to tell
+    /// them apart by the start of each input, which also explains
+    /// inputs that are gzip-compressed or Parquet.
+    input_format: InputFormat,
+    #[clap(long, value_parser)]
+    /// The largest amount allowed for a single deposit or withdrawal.
+    max_amount: Option<Decimal>,
+    #[clap(long, value_parser)]
+    /// The number of decimal places incoming amounts are rounded to.
+    round_dp: Option<u32>,
+    #[clap(long, value_parser)]
+    /// How far below zero a withdrawal may take the available funds.
+    overdraft: Option<Decimal>,
+    #[clap(long, value_parser)]
+    /// Reject withdrawals by clients with open disputes that would leave
+    /// less than this multiple of the disputed funds available.
+    dispute_hold_buffer: Option<Decimal>,
+    #[clap(long)]
+    /// Require every transaction to carry a timestamp.
+    timestamps: bool,
+    #[clap(long, value_parser)]
+    /// How many seconds after a transaction it may still be disputed.
+    dispute_window: Option<u64>,
+    #[clap(long, value_parser)]
+    /// Report the state hash on `stderr` every this many records.
+    hash_every: Option<u64>,
+    #[clap(long, value_parser)]
+    /// The client ID of the account chargebacked funds are moved to.
+    suspense_account: Option<u16>,
+    #[clap(long, value_parser)]
+    /// The fee charged for every chargeback.
+    chargeback_fee: Option<Decimal>,
+    #[clap(long, value_parser)]
+    /// The client ID of the account chargeback fees are charged to,
+    /// instead of the client.
+    chargeback_fee_account: Option<u16>,
+    #[clap(long)]
+    /// Skip records that repeat a transaction already applied, rather
+    /// than rejecting them. Amounts written with more or fewer decimal
+    /// places, such as `1.5000` for `1.5`, still match.
+    idempotent_replay: bool,
+    #[clap(long)]
+    /// Collect fees into a `fees` column of their own rather than taking
+    /// them from the available funds.
+    separate_fees: bool,
+    #[clap(long, value_parser)]
+    /// Give chargeback fees IDs of their own, as `sequential:<start>`
+    /// or `snowflake:<node>`, and reject input records using them.
+    synthetic_ids: Option<AllocatorSpec>,
+    #[clap(long)]
+    /// Accept adjustment transactions for manual ledger corrections.
+    allow_adjustments: bool,
+    #[clap(long)]
+    /// Fail on unknown, missing or miscased columns, with the row and
+    /// column of any malformed record.
+    strict_schema: bool,
+    #[clap(long, value_parser = parse_dispute_limit)]
+    /// Flag clients for review after too many disputes from one
+    /// initiator, as `initiator=count` or `initiator=count/seconds`,
+    /// e.g. `cardholder=3/2592000`. May be repeated.
+    dispute_limit: Vec<(DisputeInitiator, state::DisputeLimit)>,
+    #[clap(long, value_parser, default_value = "unlimited")]
+    /// How often a transaction may be disputed again once a dispute of
+    /// it is resolved: `unlimited`, `forbid`, `once` or a number.
+    redisputes: state::ReDisputePolicy,
+    #[clap(long, value_parser, default_value = "reject")]
+    /// What is done with a dispute of a transaction already under
+    /// dispute: `reject`, `ignore` or `update-metadata`.
+    duplicate_disputes: state::DuplicateDisputePolicy,
+    #[clap(long, value_parser, default_value = "include")]
+    /// What is done in the output with clients that have no funds and
+    /// never had a deposit or withdrawal applied: `include`, `suppress`
+    /// or `flag`.
+    never_active: state::NeverActivePolicy,
+    #[clap(long, value_parser)]
+    /// Move settled transactions out of memory into this append-only file.
+    archive: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Archive settled transactions once this many records have followed them.
+    archive_after_records: Option<u64>,
+    #[clap(long, value_parser)]
+    /// Archive settled transactions once they are this many seconds old.
+    archive_after_seconds: Option<u64>,
+    #[clap(long, value_parser)]
+    /// Reject records that would add a client once there are this many,
+    /// as `client_limit_reached`.
+    max_clients: Option<usize>,
+    #[clap(long, value_parser)]
+    /// Reject disputes once this many are open, as
+    /// `open_dispute_limit_reached`.
+    max_open_disputes: Option<usize>,
+    #[clap(long, value_parser)]
+    /// Cap the estimated memory of the state at this many MiB.
+    max_memory_mb: Option<u64>,
+    #[clap(long, value_parser, default_value = "reject")]
+    /// What is done when the memory cap is reached: `reject` records
+    /// that would add clients or transactions, or `spill` settled
+    /// transactions to the `--archive` first.
+    limit_policy: state::LimitPolicy,
+    #[clap(long, value_parser)]
+    /// Resolve records naming an `account` rather than a `client`
+    /// through this CSV file of `account` and `client` columns.
+    counterparties: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Reject records for clients, or naming accounts, in this CSV file
+    /// of `client` and `account` columns as `screened`, and flag the
+    /// clients. `serve` and `watch` pick up changes to the file.
+    denylist: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Reject disputes, resolves and chargebacks whose `reason_code`
+    /// is not in this CSV file of `code` and `description` columns.
+    reason_codes: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Flag clients whose available funds fall below this amount.
+    alert_available_below: Option<Decimal>,
+    #[clap(long, value_parser)]
+    /// Flag clients whose total funds rise above this amount.
+    alert_total_above: Option<Decimal>,
+    #[clap(long, value_parser)]
+    /// Override the balance thresholds of individual clients with this
+    /// CSV file of `client`, `available_below` and `total_above`
+    /// columns.
+    alert_thresholds: Option<PathBuf>,
+    #[clap(long, value_parser = parse_account_limit)]
+    /// Limit every client's accounts, as `kind=soft/hard` with a kind
+    /// of `transaction`, `daily` or `balance`, e.g. `daily=5000/20000`.
+    /// Deposits and withdrawals past a soft limit flag the client, and
+    /// past a hard limit are rejected. May be repeated.
+    account_limit: Vec<(LimitKind, LimitLevels)>,
+    #[clap(long, value_parser)]
+    /// Override the account limits of individual clients with this CSV
+    /// file of a `client` column and `soft` and `hard` columns for each
+    /// kind, such as `daily_soft` and `daily_hard`.
+    account_limits: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Hold only this share of the amount of each dispute, e.g. `0.8`
+    /// for 80%, leaving the rest available.
+    dispute_hold_rate: Option<Decimal>,
+    #[clap(long, value_parser)]
+    /// Override the dispute hold rate for individual clients or
+    /// currencies with this CSV file of `client`, `currency` and `rate`
+    /// columns, each row giving a client or a currency.
+    dispute_hold_rates: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Freeze the clients in this CSV file of `client`, `reason` and
+    /// optional `expires` columns, rejecting their deposits,
+    /// withdrawals and custom records until each freeze expires, and
+    /// add a `frozen` column to client output.
+    freezes: Option<PathBuf>,
+    #[clap(long, value_parser, requires = "timestamps")]
+    /// Hold back a share of the deposits of the merchant clients in this
+    /// CSV file of `client`, `rate` and `days` columns in a reserve,
+    /// releasing each share once its days have passed, and add a
+    /// `reserve` column to client output.
+    reserves: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Flag clients whose chargebacks exceed a share of their recent
+    /// deposits and withdrawals, as `rate/transactions`, e.g.
+    /// `0.01/10000` for 1% of the last 10,000.
+    chargeback_monitor: Option<ChargebackMonitor>,
+    #[clap(long, value_parser, requires = "chargeback-monitor")]
+    /// The rate at or below which the flag is cleared, half the
+    /// threshold by default.
+    chargeback_release: Option<Decimal>,
+    #[clap(long, value_parser, requires = "chargeback-monitor")]
+    /// How many deposits and withdrawals a client or merchant must have
+    /// made before its rate is acted on, 100 by default.
+    chargeback_min_transactions: Option<u64>,
+    #[clap(long, value_parser, default_value = "flag")]
+    /// What to do once the rate is exceeded: `flag` the accounts, or
+    /// `lock` them.
+    chargeback_action: MonitorAction,
+    #[clap(long, value_parser, requires = "chargeback-monitor")]
+    /// Monitor the merchants in this CSV file of `client`, `merchant`
+    /// and `program` columns as a whole, rather than each client.
+    monitor_hierarchy: Option<PathBuf>,
+    #[cfg(feature = "http")]
+    #[clap(long, value_parser = |url: &str| HttpAdjudicator::new(url))]
+    /// POST every dispute as it is opened to this `http://` URL, which
+    /// replies with `{"ruling": ...}`: `resolve`, `chargeback` or
+    /// `pending`.
+    adjudicator_url: Option<HttpAdjudicator>,
+    #[clap(long, value_parser)]
+    /// Fill in the `timestamp`, `currency`, `category` and `merchant` of
+    /// records that lack them from this CSV file, keyed by the
+    /// `--enrich-key` of each record in its `key` column.
+    enrich_file: Option<PathBuf>,
+    #[cfg(feature = "http")]
+    #[clap(long, value_parser, conflicts_with = "enrich-file")]
+    /// Fill in fields records lack by POSTing their `--enrich-key`s to
+    /// this `http://` URL as `{"keys": [...]}`.
+    enrich_url: Option<String>,
+    #[cfg(feature = "http")]
+    #[clap(long, value_parser, default_value_t = 100, requires = "enrich-url")]
+    /// How many keys are sent to `--enrich-url` at once.
+    enrich_batch: usize,
+    #[clap(long, value_parser, default_value = "client")]
+    /// The field records are looked up by: `client`, `account` or
+    /// `external_ref`.
+    enrich_key: LookupKey,
+    #[clap(long)]
+    /// Stop at the first record that leaves a client holding a negative
+    /// amount, or anything but the amounts of its open disputes.
+    fail_safe: bool,
+    #[clap(long, value_parser)]
+    /// Price funds held for disputes at this annual rate, e.g. `0.05`
+    /// for 5%, in the `--disputes` report. Requires timestamps.
+    float_rate: Option<Decimal>,
+    #[clap(long, value_parser)]
+    /// Date each dispute in the `--disputes` report with the deadline to
+    /// resolve or charge it back by, this many business days after it
+    /// was opened. Requires timestamps.
+    chargeback_deadline: Option<u32>,
+    #[clap(long, value_parser)]
+    /// Count business days without the holidays in this CSV file of
+    /// `date` (as `YYYY-MM-DD`) and `name` columns. May be repeated.
+    holidays: Vec<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Hold deposits and withdrawals above this amount for review.
+    quarantine_above: Option<Decimal>,
+    #[clap(long, value_parser)]
+    /// Hold deposits and withdrawals for review once a client makes more
+    /// than this many within a window, as `count/seconds`.
+    quarantine_velocity: Option<VelocityLimit>,
+    #[clap(long)]
+    /// Add a `risk_score` column from 0 to 100 to client output.
+    risk_score: bool,
+    #[cfg(feature = "chaos")]
+    #[clap(long, value_parser)]
+    /// Inject faults for resilience testing, as comma-separated chances
+    /// from 0 to 1 of each: `drop`, `duplicate`, `delay`, `corrupt` and
+    /// `backend`, with `delay-ms` and `seed`, such as
+    /// `drop=0.01,backend=0.001,seed=7`.
+    chaos: Option<ChaosConfig>,
+}
+
+#[derive(Args, Debug)]
+/// Which records of the inputs to process, for rerunning only the
+/// slice an investigation is about. Records left out are skipped as if
+/// they were not in the input.
+struct FilterArgs {
+    #[clap(long, value_parser, use_value_delimiter = true)]
+    /// Only process records for these clients, separated by commas.
+    filter_client: Vec<u16>,
+    #[clap(long, value_parser, use_value_delimiter = true)]
+    /// Only process records of these types, separated by commas.
+    filter_type: Vec<String>,
+    #[clap(long, value_parser)]
+    /// Only process records with this transaction ID or a higher one.
+    filter_tx_from: Option<u32>,
+    #[clap(long, value_parser)]
+    /// Only process records with this transaction ID or a lower one.
+    filter_tx_to: Option<u32>,
+    #[clap(long, value_parser)]
+    /// Only process records timestamped at or after this time, in
+    /// seconds since the Unix epoch.
+    filter_since: Option<u64>,
+    #[clap(long, value_parser)]
+    /// Only process records timestamped at or before this time, in
+    /// seconds since the Unix epoch.
+    filter_until: Option<u64>,
+}
+
+impl FilterArgs {
+    /// The filter the flags describe, if any are given.
+    fn filter(&self) -> Option<TxFilter> {
+        let mut filter = TxFilter::default();
+        if !self.filter_client.is_empty() {
+            filter = filter.clients(self.filter_client.iter().copied());
+        }
+        if !self.filter_type.is_empty() {
+            filter = filter.types(&self.filter_type);
+        }
+        if self.filter_tx_from.is_some() || self.filter_tx_to.is_some() {
+            filter = filter.ids(
+                self.filter_tx_from.unwrap_or(u32::MIN)..=self.filter_tx_to.unwrap_or(u32::MAX),
+            );
+        }
+        if self.filter_since.is_some() || self.filter_until.is_some() {
+            filter = filter.timestamps(
+                self.filter_since.unwrap_or(u64::MIN)..=self.filter_until.unwrap_or(u64::MAX),
+            );
+        }
+        (filter != TxFilter::default()).then_some(filter)
+    }
+}
+
+#[derive(Args, Debug)]
+/// The arguments shared by all commands that process input files.
+struct ProcessArgs {
+    #[clap(value_parser, required = true)]
+    /// The input CSV files to process. Multiple files are processed
+    /// independently and then merged.
+    inputs: Vec<PathBuf>,
+    #[clap(long, value_parser, default_value = "1")]
+    /// How many input files to process at the same time.
+    parallelism: NonZeroUsize,
+    #[clap(long, value_parser)]
+    /// How many threads parse the records of each input. Records are
+    /// still applied in order, so the results do not change.
+    parse_threads: Option<NonZeroUsize>,
+    #[clap(long)]
+    /// Apply consecutive records of the same client as a group, looking
+    /// the client up once for the group. Records are still applied in
+    /// order, so the results do not change.
+    group_clients: bool,
+    #[clap(long)]
+    /// Report progress on `stderr`, with the number of records and time
+    /// left estimated from the size of the inputs.
+    progress: bool,
+    #[clap(long, value_parser, default_value = "fail")]
+    /// What to do when a transaction ID appears in more than one input:
+    /// `fail`, `keep-first` or `keep-last`.
+    merge_conflicts: state::ConflictPolicy,
+    #[clap(long, value_parser)]
+    /// Apply records for different clients concurrently, using this many
+    /// client actors for each input.
+    actors: Option<NonZeroUsize>,
+    #[clap(long, value_parser, default_value = "threads")]
+    /// Where client actors run: `threads`, or `tokio` if built with the
+    /// `tokio` feature.
+    executor: Executor,
+    #[clap(flatten)]
+    policies: PolicyArgs,
+    #[clap(flatten)]
+    filter: FilterArgs,
+    #[clap(long, value_parser)]
+    /// Write transactions still held for review to this CSV file.
+    quarantine: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Leave clients with no funds out of the output, and write them to
+    /// this CSV file instead.
+    dormant: Option<PathBuf>,
+    #[clap(long, value_parser, requires = "dormant")]
+    /// Only treat clients as dormant once this many records have
+    /// followed their last one.
+    dormant_after_records: Option<u64>,
+    #[clap(long, value_parser, requires = "dormant")]
+    /// Only treat clients as dormant once this many days have passed
+    /// since their last record. Requires timestamps.
+    dormant_after_days: Option<u64>,
+    #[clap(long, value_parser)]
+    /// Write the funds held for every dispute, how long they were held
+    /// and what that cost, to this CSV file.
+    disputes: Option<PathBuf>,
+    #[clap(long, requires = "disputes")]
+    /// List the withdrawals that spent the funds of each disputed
+    /// deposit in the `--disputes` report, first in, first out.
+    trace_funds: bool,
+    #[clap(long, value_parser, requires = "disputes")]
+    /// Apply the case updates kept in this journal by `serve` before
+    /// writing the `--disputes` report.
+    case_journal: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Write the date every withdrawal settles on, `--settlement-days`
+    /// business days after it was made, to this CSV file.
+    settlements: Option<PathBuf>,
+    #[clap(long, value_parser, default_value_t = settlement::DEFAULT_SETTLEMENT_DAYS, requires = "settlements")]
+    /// How many business days after a withdrawal it settles.
+    settlement_days: u32,
+    #[clap(long, value_parser)]
+    /// Write a changelog of balance, lock and dispute events to this
+    /// file, as CSV if it ends in `.csv` and as JSON lines otherwise.
+    events: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Write a JSON summary of the run, and of each input, to this file.
+    summary: Option<PathBuf>,
+    #[clap(long, value_parser, default_value_t = 0.2, requires = "summary")]
+    /// Flag inputs in the summary whose deposit or withdrawal amounts are
+    /// distributed at least this far, from 0 to 1, from the input before.
+    shift_threshold: f64,
+    #[clap(long, value_parser)]
+    /// Once the inputs are processed, merge the clients in this CSV
+    /// file, with `source`, `destination` and optionally `force`
+    /// columns, into one another, in order.
+    merge_clients: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Write the audit log to this CSV file.
+    audit_log: Option<PathBuf>,
+    #[cfg(feature = "encryption")]
+    #[clap(long, value_parser, requires = "audit-log")]
+    /// Encrypt the audit log with the key in `env:<variable>` or
+    /// `file:<path>`, written as 64 hexadecimal digits.
+    encrypt_key: Option<KeySource>,
+    #[clap(long, value_parser)]
+    /// Write rejected records, with their rejection codes, to this CSV file.
+    rejects: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct OutputArgs {
+    #[clap(flatten)]
+    process: ProcessArgs,
+    #[clap(long, value_parser)]
+    /// Write the final client states to this file instead of `stdout`.
+    /// The file is only replaced once it is completely written.
+    output: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Write a JSON manifest describing the output to this file.
+    manifest: Option<PathBuf>,
+    #[clap(long, value_parser, requires = "output", conflicts_with = "manifest")]
+    /// Split the final client states into this many CSV files in the
+    /// `--output` directory, with an `index.json` describing them.
+    output_partitions: Option<NonZeroU16>,
+    #[clap(
+        long,
+        value_parser,
+        default_value = "hash",
+        requires = "output-partitions"
+    )]
+    /// How clients are assigned to partitions: `hash` or `range` of
+    /// client IDs.
+    partition_by: Partitioning,
+    #[clap(long, value_parser)]
+    /// Write the clients whose balances or lock status changed to this
+    /// file, with their old and new values.
+    delta: Option<PathBuf>,
+    #[clap(long, value_parser, requires = "delta")]
+    /// The final client states of the previous run, which the delta is
+    /// taken against. Without it, every client is new.
+    delta_from: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Reject deposits, withdrawals and adjustments whose IDs were
+    /// accepted by an earlier run given this file, which keeps them on
+    /// disk. The IDs accepted by this run are added once its output is
+    /// written.
+    seen_ids: Option<PathBuf>,
+    #[cfg(feature = "warehouse")]
+    #[clap(flatten)]
+    warehouse: WarehouseArgs,
+}
+
+#[cfg(feature = "warehouse")]
+#[derive(Args, Debug)]
+struct WarehouseArgs {
+    #[clap(
+        id = "warehouse",
+        long = "warehouse",
+        value_parser,
+        requires = "warehouse-dataset"
+    )]
+    /// Load the final client states, and the changelog, into a warehouse
+    /// through the API at this plain HTTP URL, such as a local proxy in
+    /// front of `https://bigquery.googleapis.com/bigquery/v2`.
+    url: Option<String>,
+    #[clap(long = "warehouse-api", value_parser, default_value = "bigquery")]
+    /// The API the warehouse is loaded through: `bigquery` or `rest`.
+    api: WarehouseApi,
+    #[clap(
+        id = "warehouse-dataset",
+        long = "warehouse-dataset",
+        value_parser,
+        requires = "warehouse"
+    )]
+    /// The dataset to load into, as `project.dataset` for BigQuery.
+    dataset: Option<String>,
+    #[clap(
+        long = "warehouse-clients-table",
+        value_parser,
+        default_value = "clients"
+    )]
+    /// The table the final client states are loaded into.
+    clients_table: String,
+    #[clap(
+        long = "warehouse-events-table",
+        value_parser,
+        default_value = "events"
+    )]
+    /// The table the changelog is loaded into.
+    events_table: String,
+}
+
+#[cfg(feature = "clickhouse")]
+#[derive(Args, Debug)]
+struct ClickHouseArgs {
+    #[clap(id = "clickhouse", long = "clickhouse", value_parser)]
+    /// Stream the changelog into ClickHouse through its HTTP interface
+    /// at this plain HTTP URL, such as `http://localhost:8123/`.
+    url: Option<String>,
+    #[clap(
+        long = "clickhouse-table",
+        value_parser,
+        default_value = "events",
+        requires = "clickhouse"
+    )]
+    /// The table events are inserted into, created if it does not exist.
+    table: String,
+    #[clap(
+        long = "clickhouse-engine",
+        value_parser,
+        default_value = "MergeTree ORDER BY (client, seq)",
+        requires = "clickhouse"
+    )]
+    /// The engine of the table, with its clauses, if it is created.
+    engine: String,
+    #[clap(
+        long = "clickhouse-batch-rows",
+        value_parser = clap::value_parser!(u64).range(1..),
+        default_value_t = 1000,
+        requires = "clickhouse"
+    )]
+    /// The most events sent in one insert.
+    batch_rows: u64,
+    #[clap(
+        long = "clickhouse-flush-ms",
+        value_parser,
+        default_value_t = 1000,
+        requires = "clickhouse"
+    )]
+    /// The longest in milliseconds an event waits for its batch to fill.
+    flush_ms: u64,
+    #[clap(
+        long = "clickhouse-retries",
+        value_parser,
+        default_value_t = 5,
+        requires = "clickhouse"
+    )]
+    /// How many times a failed insert is retried before its events
+    /// are dropped.
+    retries: u32,
+}
+
+#[cfg(feature = "clickhouse")]
+impl ClickHouseArgs {
+    /// The sink to stream events into, if one is configured.
+    fn sink(&self) -> Result<Option<ClickHouseSink>, errors::Error> {
+        let Some(url) = &self.url else {
+            return Ok(None);
+        };
+        let config = ClickHouseConfig {
+            table: self.table.clone(),
+            engine: self.engine.clone(),
+            batch_rows: self.batch_rows as usize,
+            flush_interval: Duration::from_millis(self.flush_ms),
+            retries: self.retries,
+        };
+        Ok(Some(ClickHouseSink::start(url, config)?))
+    }
+}
+
+#[cfg(feature = "warehouse")]
+impl WarehouseArgs {
+    /// The sink to load results into, if one is configured.
+    fn sink(&self) -> Option<Box<dyn WarehouseSink>> {
+        let (url, dataset) = (self.url.as_deref()?, self.dataset.as_deref()?);
+        fn invalid<T>(message: String) -> T {
+            Cli::command()
+                .error(clap::ErrorKind::ValueValidation, message)
+                .exit()
+        }
+        Some(match self.api {
+            WarehouseApi::BigQuery => {
+                let (project, dataset) = dataset.split_once('.').unwrap_or_else(|| {
+                    invalid(format!(
+                        "expected `project.dataset` for BigQuery, got `{}`",
+                        dataset
+                    ))
+                });
+                Box::new(BigQuerySink::new(url, project, dataset).unwrap_or_else(invalid))
+            }
+            WarehouseApi::Rest => Box::new(RestSink::new(url, dataset).unwrap_or_else(invalid)),
+        })
+    }
+
+    /// The tables to load results into.
+    fn tables(&self) -> WarehouseTables {
+        WarehouseTables {
+            clients: self.clients_table.clone(),
+            events: self.events_table.clone(),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct WatchArgs {
+    #[clap(flatten)]
+    process: ProcessArgs,
+    #[clap(long, value_parser, default_value_t = 1000)]
+    /// How often to check the input for changes, in milliseconds.
+    interval_ms: u64,
+}
+
+#[derive(Args, Debug)]
+struct QueryArgs {
+    #[clap(flatten)]
+    process: ProcessArgs,
+    #[clap(long, value_parser)]
+    /// The ID of the client to print.
+    client: u16,
+    #[clap(long, value_parser)]
+    /// Print the client as of `record:<number>`, counting from one in
+    /// each input, or `timestamp:<seconds>`, leaving out later records.
+    as_of: Option<state::AsOf>,
+}
+
+#[derive(Args, Debug)]
+struct RollupArgs {
+    #[clap(flatten)]
+    process: ProcessArgs,
+    #[clap(long, value_parser)]
+    /// A CSV file of `client`, `merchant` and `program` columns.
+    /// Clients not in it are added up under an empty name.
+    hierarchy: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct StatementArgs {
+    #[clap(flatten)]
+    process: ProcessArgs,
+    #[clap(long, value_parser, default_value = "qif")]
+    /// The statement format: `qif` or `ofx`.
+    format: StatementFormat,
+    #[clap(long, value_parser)]
+    /// Only write the statement of this client, to `stdout` unless
+    /// `--dir` is given.
+    client: Option<u16>,
+    #[clap(long, value_parser, required_unless_present = "client")]
+    /// Write one statement per client to this directory, as
+    /// `client-<id>.qif` or `client-<id>.ofx`.
+    dir: Option<PathBuf>,
+    #[clap(long, value_parser, default_value = "USD")]
+    /// The currency code OFX statements declare.
+    currency: String,
+}
+
+#[derive(Args, Debug)]
+struct RetryArgs {
+    #[clap(value_parser)]
+    /// The rejects file of an earlier run, as written by `--rejects`, or
+    /// the output of an earlier retry.
+    rejected: PathBuf,
+    #[clap(flatten)]
+    process: ProcessArgs,
+    #[clap(long, value_parser)]
+    /// Write the retried records, with their outcomes, to this file
+    /// instead of `stdout`.
+    output: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    /// Write the final client states, after the retries, to this file.
+    clients: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct StreamArgs {
+    #[clap(flatten)]
+    policies: PolicyArgs,
+}
+
+#[derive(Args, Debug)]
+struct ServeArgs {
+    #[clap(flatten)]
+    policies: PolicyArgs,
+    #[clap(long, value_parser)]
+    /// Answer a record identical to one submitted in the last this many
+    /// seconds with the original acknowledgement, so retries after a
+    /// timeout are not rejected as duplicates.
+    dedup_ttl: Option<u64>,
+    #[clap(long, value_parser)]
+    /// Stream every record to followers connecting to this address,
+    /// such as `0.0.0.0:7070`.
+    replicate_on: Option<String>,
+    #[clap(long, value_parser)]
+    /// Start as a warm standby of the leader at this address, applying
+    /// the records it streams, and only serve requests once the leader
+    /// goes away.
+    follow: Option<String>,
+    #[clap(long, value_parser)]
+    /// Answer `GET /healthz` on this address, such as `0.0.0.0:8080`,
+    /// with the status of the server's subsystems.
+    health_on: Option<String>,
+    #[clap(long, value_parser)]
+    #[cfg_attr(unix, clap(conflicts_with = "ipc"))]
+    /// Serve requests over HTTP on this address, such as
+    /// `127.0.0.1:8000`, instead of on `stdin`: JSON-RPC requests
+    /// `POST`ed to `/rpc`, searches of the clients at `GET /clients`,
+    /// client histories at `GET /clients/{id}/transactions`, and
+    /// dispute cases at `GET` and `POST /disputes/{tx}`.
+    http_on: Option<String>,
+    #[clap(long, value_parser)]
+    /// Keep updates to dispute cases in this file, and apply those
+    /// already there as their disputes are submitted.
+    case_journal: Option<PathBuf>,
+    #[clap(long)]
+    /// Keep every record applied to each client, so its history can be
+    /// listed page by page with `list_transactions`.
+    history: bool,
+    #[cfg(feature = "clickhouse")]
+    #[clap(flatten)]
+    clickhouse: ClickHouseArgs,
+    #[cfg(unix)]
+    /// Listen on a Unix socket at this path, serving one connection at
+    /// a time. A stale socket left at the path is replaced.
+    #[clap(long)]
+    ipc: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+#[cfg_attr(
+    unix,
+    clap(group(clap::ArgGroup::new("server").required(true).args(&["ipc", "http"])))
+)]
+struct AdminArgs {
+    #[cfg(unix)]
+    #[clap(long, value_parser)]
+    /// Connect to a server listening on the Unix socket at this path,
+    /// as started with `serve --ipc`.
+    ipc: Option<PathBuf>,
+    #[clap(long, value_parser)]
+    #[cfg_attr(not(unix), clap(required = true))]
+    /// Connect to a server listening for HTTP on this address, as
+    /// started with `serve --http-on`.
+    http: Option<String>,
+    #[clap(subcommand)]
+    operation: AdminOperation,
+}
+
+#[derive(Subcommand, Debug)]
+/// The administrative changes a server accepts.
+enum AdminOperation {
+    /// Merge one client into another, moving its balances, transactions
+    /// and disputes, as `--merge-clients` does for a batch run.
+    MergeClients {
+        #[clap(long, value_parser)]
+        /// The client merged away.
+        source: u16,
+        #[clap(long, value_parser)]
+        /// The client merged into.
+        destination: u16,
+        #[clap(long)]
+        /// Merge even if either client is locked.
+        force: bool,
+    },
+    /// Freeze a client, as `--freezes` does for a batch run, so that
+    /// its withdrawals and custom records are rejected.
+    Freeze {
+        #[clap(long, value_parser)]
+        /// The client to freeze.
+        client: u16,
+        #[clap(long, value_parser)]
+        /// Why the account is frozen, shown in the output.
+        reason: String,
+        #[clap(long, value_parser)]
+        /// The timestamp from which the freeze no longer applies, in
+        /// the units of record timestamps.
+        expires: Option<u64>,
+    },
+    /// Lift the freeze of a client.
+    Unfreeze {
+        #[clap(long, value_parser)]
+        /// The client to unfreeze.
+        client: u16,
+    },
+}
+
+impl AdminArgs {
+    /// The JSON-RPC method and parameters of the operation.
+    fn request(&self) -> (&'static str, serde_json::Value) {
+        match &self.operation {
+            AdminOperation::MergeClients {
+                source,
+                destination,
+                force,
+            } => (
+                "merge_clients",
+                serde_json::json!({
+                    "source": source,
+                    "destination": destination,
+                    "force": force,
+                }),
+            ),
+            AdminOperation::Freeze {
+                client,
+                reason,
+                expires,
+            } => (
+                "freeze",
+                serde_json::json!({
+                    "client": client,
+                    "reason": reason,
+                    "expires": expires,
+                }),
+            ),
+            AdminOperation::Unfreeze { client } => {
+                ("unfreeze", serde_json::json!({ "client": client }))
+            }
+        }
+    }
+
+    /// Sends the operation to the server, and returns its answer.
+    fn send(&self) -> Result<serde_json::Value, errors::Error> {
+        let (method, params) = self.request();
+        #[cfg(unix)]
+        if let Some(path) = &self.ipc {
+            let stream = std::os::unix::net::UnixStream::connect(path)?;
+            return rpc::call_stream(stream, method, params);
+        }
+        // One of the two is required.
+        let address = self.http.as_deref().unwrap_or_default();
+        rpc::call_http(address, method, params)
+    }
+}
+
+#[derive(Args, Debug)]
+struct GenerateArgs {
+    #[clap(long, value_parser, default_value_t = 1000)]
+    /// The number of records to generate.
+    records: u32,
+    #[clap(long, value_parser, default_value_t = 100)]
+    /// The number of distinct clients to generate records for.
+    clients: u16,
+    #[clap(long, value_parser, default_value_t = 0)]
+    /// The seed for the generator; the same seed gives the same file.
+    seed: u64,
+}
+
+#[derive(Args, Debug)]
+struct CapabilitiesArgs {
+    #[clap(long)]
+    /// Print them as one JSON object, for tooling to check.
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct CompletionsArgs {
+    #[clap(value_parser)]
+    /// The shell to complete in: `bash`, `zsh` or `fish`.
+    shell: Shell,
+}
+
+#[derive(Args, Debug)]
+struct DiffArgs {
+    #[clap(value_parser)]
+    /// The first file of final client states.
+    left: PathBuf,
+    #[clap(value_parser)]
+    /// The second file of final client states.
+    right: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct LintArgs {
+    #[clap(value_parser)]
+    /// The input CSV file to check.
+    input: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct ReconcileArgs {
     #[clap(value_parser)]
     /// The input CSV file to process.
     input: PathBuf,
+    #[clap(long, value_parser)]
+    /// The file of client states the input is expected to produce.
+    expected: PathBuf,
+    #[clap(flatten)]
+    policies: PolicyArgs,
+}
+
+#[derive(Args, Debug)]
+struct MinimizeArgs {
+    #[clap(value_parser)]
+    /// The failing input CSV file.
+    input: PathBuf,
+    #[clap(long, value_parser)]
+    /// Keep records until one is rejected with this code, such as
+    /// `internal_error`, rather than until processing aborts with the
+    /// same kind of error as the whole input.
+    rejected: Option<errors::RejectionCode>,
+    #[clap(short, long, value_parser)]
+    /// Write the reduced records to this file rather than `stdout`.
+    output: Option<PathBuf>,
+    #[clap(flatten)]
+    policies: PolicyArgs,
+}
+
+#[derive(Args, Debug)]
+struct ConformArgs {
+    #[clap(value_parser)]
+    /// The directory of test vectors, one `.json` file each.
+    dir: PathBuf,
+    #[clap(flatten)]
+    policies: PolicyArgs,
+}
+
+#[cfg(feature = "encryption")]
+#[derive(Args, Debug)]
+struct DecryptArgs {
+    #[clap(value_parser)]
+    /// The encrypted file.
+    input: PathBuf,
+    #[clap(long, value_parser)]
+    /// The key the file was encrypted with, as `env:<variable>` or
+    /// `file:<path>`.
+    decrypt_key: KeySource,
+}
+
+#[derive(Args, Debug)]
+struct VerifyManifestArgs {
+    #[clap(value_parser)]
+    /// The manifest to check against.
+    manifest: PathBuf,
+    #[clap(value_parser)]
+    /// The output file the manifest describes.
+    output: PathBuf,
+}
+
+impl PolicyArgs {
+    /// Collects the configured policies.
+    fn builder(&self) -> Result<state::CurrentStateBuilder, errors::Error> {
+        let mut builder = state::CurrentState::builder()
+            .timestamps(self.timestamps)
+            .allow_adjustments(self.allow_adjustments)
+            .strict_schema(self.strict_schema)
+            .risk_scores(self.risk_score)
+            .fail_safe(self.fail_safe)
+            .separate_fees(self.separate_fees)
+            .idempotent_replay(self.idempotent_replay)
+            .redisputes(self.redisputes)
+            .duplicate_disputes(self.duplicate_disputes)
+            .never_active(self.never_active)
+            .input_format(self.input_format)
+            .limits(state::ResourceLimits {
+                max_clients: self.max_clients,
+                max_open_disputes: self.max_open_disputes,
+                max_memory: self.max_memory_mb.map(|mb| mb.saturating_mul(1 << 20)),
+                policy: self.limit_policy,
+            });
+        if let Some(style) = self.decimal_style {
+            builder = builder.decimal_style(style);
+        }
+        if let Some(amount) = self.max_amount {
+            builder = builder.max_amount(amount);
+        }
+        if let Some(dp) = self.round_dp {
+            builder = builder.rounding(dp);
+        }
+        if let Some(limit) = self.overdraft {
+            builder = builder.overdraft(limit);
+        }
+        if let Some(ratio) = self.dispute_hold_buffer {
+            builder = builder.dispute_hold_buffer(ratio);
+        }
+        if let Some(seconds) = self.dispute_window {
+            builder = builder.dispute_window(seconds);
+        }
+        if let Some(records) = self.hash_every {
+            builder = builder.hash_every(records);
+        }
+        if let Some(id) = self.suspense_account {
+            builder = builder.suspense_account(id);
+        }
+        if let Some(path) = &self.archive {
+            let age = state::ArchiveAge {
+                records: self.archive_after_records,
+                seconds: self.archive_after_seconds,
+            };
+            builder = builder.archive(path, age);
+        }
+        for &(initiator, limit) in &self.dispute_limit {
+            builder = builder.dispute_limit(initiator, limit);
+        }
+        if let Some(fee) = self.chargeback_fee {
+            builder = builder.chargeback_fee(fee);
+        }
+        if let Some(id) = self.chargeback_fee_account {
+            builder = builder.fee_account(id);
+        }
+        if let Some(spec) = self.synthetic_ids {
+            builder = builder.id_allocator(spec.build());
+        }
+        if let Some(rate) = self.float_rate {
+            builder = builder.float_rate(rate);
+        }
+        if let Some(days) = self.chargeback_deadline {
+            builder = builder.chargeback_deadline(days);
+        }
+        if !self.holidays.is_empty() {
+            let mut calendar = Calendar::default();
+            for path in &self.holidays {
+                calendar.load_csv(File::open(path)?)?;
+            }
+            builder = builder.calendar(calendar);
+        }
+        if let Some(amount) = self.quarantine_above {
+            builder = builder.quarantine_above(amount);
+        }
+        if let Some(limit) = self.quarantine_velocity {
+            builder = builder.quarantine_velocity(limit);
+        }
+        if let Some(path) = &self.counterparties {
+            builder = builder.counterparties(CounterpartyMap::from_csv(File::open(path)?)?);
+        }
+        if let Some(path) = &self.denylist {
+            builder = builder.screening(Arc::new(Screener::open(path)?));
+        }
+        if let Some(path) = &self.reason_codes {
+            builder = builder.reason_codes(ReasonCodes::from_csv(File::open(path)?)?);
+        }
+        if let Some(path) = &self.enrich_file {
+            let enricher = FileEnricher::from_csv(self.enrich_key, File::open(path)?)?;
+            builder = builder.enricher(Arc::new(enricher));
+        }
+        let thresholds = BalanceThresholds {
+            available_below: self.alert_available_below,
+            total_above: self.alert_total_above,
+        };
+        match &self.alert_thresholds {
+            Some(path) => {
+                builder =
+                    builder.balance_alerts(BalanceAlerts::from_csv(thresholds, File::open(path)?)?);
+            }
+            None if thresholds != BalanceThresholds::default() => {
+                builder = builder.balance_alerts(BalanceAlerts::new(thresholds));
+            }
+            None => {}
+        }
+        let mut limits = LimitSet::default();
+        for &(kind, levels) in &self.account_limit {
+            limits.set(kind, levels);
+        }
+        match &self.account_limits {
+            Some(path) => {
+                builder =
+                    builder.account_limits(AccountLimits::from_csv(limits, File::open(path)?)?);
+            }
+            None if limits != LimitSet::default() => {
+                builder = builder.account_limits(AccountLimits::new(limits)?);
+            }
+            None => {}
+        }
+        let rate = self.dispute_hold_rate.unwrap_or(Decimal::ONE);
+        match &self.dispute_hold_rates {
+            Some(path) => {
+                builder = builder.hold_rates(HoldRates::from_csv(rate, File::open(path)?)?);
+            }
+            None if self.dispute_hold_rate.is_some() => {
+                builder = builder.hold_rates(HoldRates::new(rate)?);
+            }
+            None => {}
+        }
+        if let Some(path) = &self.freezes {
+            builder = builder.freezes(Freezes::from_csv(File::open(path)?)?);
+        }
+        if let Some(path) = &self.reserves {
+            builder = builder.reserves(Reserves::from_csv(File::open(path)?)?);
+        }
+        if let Some(mut monitor) = self.chargeback_monitor {
+            if let Some(rate) = self.chargeback_release {
+                monitor.release = rate;
+            }
+            if let Some(count) = self.chargeback_min_transactions {
+                monitor.min_transactions = count;
+            }
+            monitor.action = self.chargeback_action;
+            builder = builder.chargeback_monitor(monitor);
+        }
+        if let Some(path) = &self.monitor_hierarchy {
+            builder = builder.monitor_merchants(Hierarchy::from_csv(File::open(path)?)?);
+        }
+        #[cfg(feature = "http")]
+        if let Some(adjudicator) = &self.adjudicator_url {
+            builder = builder.adjudicator(Arc::new(adjudicator.clone()));
+        }
+        #[cfg(feature = "http")]
+        if let Some(url) = &self.enrich_url {
+            let enricher = HttpEnricher::new(url, self.enrich_key, self.enrich_batch)
+                .unwrap_or_else(|err| {
+                    Cli::command()
+                        .error(clap::ErrorKind::ValueValidation, err)
+                        .exit()
+                });
+            builder = builder.enricher(Arc::new(enricher));
+        }
+        #[cfg(feature = "chaos")]
+        if let Some(config) = self.chaos {
+            builder = builder.chaos(Chaos::new(config));
+        }
+        Ok(builder)
+    }
+}
+
+impl ProcessArgs {
+    /// Collects the configured policies and input handling.
+    fn builder(&self) -> Result<state::CurrentStateBuilder, errors::Error> {
+        let mut builder = self
+            .policies
+            .builder()?
+            .merge_conflicts(self.merge_conflicts)
+            .dispute_report(self.disputes.is_some())
+            .trace_funds(self.trace_funds)
+            .group_clients(self.group_clients);
+        if let Some(threads) = self.parse_threads {
+            builder = builder.parse_threads(threads);
+        }
+        if let Some(filter) = self.filter.filter() {
+            builder = builder.filter(filter);
+        }
+        if self.dormant.is_some() {
+            builder = builder.dormancy(state::Dormancy {
+                records: self.dormant_after_records,
+                seconds: self.dormant_after_days.map(|days| days * 24 * 60 * 60),
+            });
+        }
+        if self.progress {
+            let total_bytes = self
+                .inputs
+                .iter()
+                .map(|path| Ok(std::fs::metadata(path)?.len()))
+                .sum::<std::io::Result<u64>>()?;
+            let tracker = ProgressTracker::new(Arc::new(StderrProgress), Some(total_bytes));
+            builder = builder.progress(Arc::new(tracker));
+        }
+        Ok(builder)
+    }
+
+    /// Processes the input files into a fresh state.
+    fn run(&self) -> Result<state::CurrentState, errors::Error> {
+        self.run_with(self.builder()?)
+    }
+
+    /// Processes the input files into a fresh state built by `builder`.
+    fn run_with(
+        &self,
+        builder: state::CurrentStateBuilder,
+    ) -> Result<state::CurrentState, errors::Error> {
+        let events = self
+            .events
+            .as_deref()
+            .map(events::FileSink::create)
+            .transpose()?
+            .map(Arc::new);
+        let builder = match &events {
+            Some(sink) => builder.event_sink(sink.clone()),
+            None => builder,
+        };
+        let mut rejects_file = self.rejects.as_ref().map(AtomicFile::create).transpose()?;
+        let rejects = rejects_file
+            .as_mut()
+            .map(|file| Mutex::new(RejectsWriter::new(file)));
+        let multiple = self.inputs.len() > 1;
+        let on_reject = |path: &Path, tx: &Transaction, err: &errors::Error| {
+            if multiple {
+                eprintln!("Warning: {}: {}", path.display(), err);
+            } else {
+                eprintln!("Warning: {}", err);
+            }
+            if let Some(rejects) = &rejects {
+                rejects.lock().unwrap().write(tx, err)?;
+            }
+            Ok(())
+        };
+        let (mut program_state, summaries) = match self.actors {
+            Some(actors) => actor::process_files_actors(
+                &builder,
+                &self.inputs,
+                actors,
+                self.executor,
+                on_reject,
+            )?,
+            None => parallel::process_files_parallel(
+                &builder,
+                &self.inputs,
+                self.parallelism,
+                on_reject,
+            )?,
+        };
+        if let Some(tracker) = builder.progress_tracker() {
+            tracker.finish();
+        }
+        if let Some(mut rejects) = rejects.map(|rejects| rejects.into_inner().unwrap()) {
+            rejects.flush()?;
+        }
+        if let Some(file) = rejects_file {
+            file.commit()?;
+        }
+        if let Some(path) = &self.merge_clients {
+            for merge in ClientMerge::from_csv(File::open(path)?)? {
+                let result =
+                    program_state.merge_clients(merge.source, merge.destination, merge.force);
+                if let Err(err) = result {
+                    eprintln!("Warning: {}", err);
+                }
+            }
+        }
+        if let Some(path) = &self.quarantine {
+            write_atomically(path, |file| {
+                Ok(quarantine::write_csv(file, program_state.quarantined())?)
+            })?;
+        }
+        if let Some(path) = &self.summary {
+            write_atomically(path, |file| {
+                Ok(SummaryReport::new(summaries, self.shift_threshold).write(file)?)
+            })?;
+        }
+        if let Some(path) = &self.dormant {
+            write_atomically(path, |file| Ok(program_state.dormant_to_csv(file)?))?;
+        }
+        if let Some(path) = &self.case_journal {
+            let entries = cases::read_journal(File::open(path)?)?;
+            cases::replay(&mut program_state, &entries);
+        }
+        if let Some(path) = &self.disputes {
+            let held = program_state.held_funds();
+            write_atomically(path, |file| Ok(disputes::write_csv(file, &held)?))?;
+        }
+        if let Some(path) = &self.settlements {
+            let settlements = program_state.settlements(self.settlement_days);
+            write_atomically(path, |file| Ok(settlement::write_csv(file, &settlements)?))?;
+        }
+        if let Some(sink) = &events {
+            sink.finish()?;
+        }
+        if let Some(path) = &self.audit_log {
+            let entries = program_state.take_audit_entries();
+            write_atomically(path, |file| self.write_audit_log(file, &entries))?;
+        }
+        Ok(program_state)
+    }
+}
+
+impl ProcessArgs {
+    /// Writes the audit log, encrypted if a key is configured.
+    fn write_audit_log(
+        &self,
+        file: &mut AtomicFile,
+        entries: &[audit::AuditEntry],
+    ) -> Result<(), errors::Error> {
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.encrypt_key {
+            let mut plaintext = Vec::new();
+            audit::write_csv(&mut plaintext, entries)?;
+            file.write_all(&crypto::encrypt(key, &plaintext)?)?;
+            return Ok(());
+        }
+        Ok(audit::write_csv(file, entries)?)
+    }
+}
+
+impl OutputArgs {
+    /// Writes the final client states of a run that started at
+    /// `started`, returning the manifest describing them if one was
+    /// requested.
+    fn write(
+        &self,
+        program_state: state::CurrentState,
+        writer: impl Write,
+        started: SystemTime,
+    ) -> Result<Option<Manifest>, errors::Error> {
+        if self.manifest.is_none() {
+            program_state.into_csv(writer)?;
+            return Ok(None);
+        }
+        let mut manifest = Manifest::new(&program_state, &self.process.inputs, started)?;
+        let mut writer = DigestingWriter::new(writer);
+        program_state.into_csv(&mut writer)?;
+        manifest.sha256 = writer.digest();
+        Ok(Some(manifest))
+    }
+}
+
+#[derive(Debug)]
+/// Reports progress on a single, rewritten line of `stderr`.
+struct StderrProgress;
+
+impl ProgressObserver for StderrProgress {
+    fn progress(&self, progress: &Progress) {
+        let mut line = format!("Processed {} records", progress.records);
+        if let (Some(estimate), Some(eta)) = (progress.estimated_records, progress.eta) {
+            let percent = 100 * progress.records / estimate.max(1);
+            line += &format!(" of about {} ({}%)", estimate, percent);
+            if !progress.done {
+                line += &format!(", {}s left", eta.as_secs());
+            }
+        }
+        line += &format!(" in {:.1}s", progress.elapsed.as_secs_f64());
+        // Clears what is left of a longer previous line.
+        eprint!("\r{:<72}", line);
+        if progress.done {
+            eprintln!();
+        }
+    }
+}
+
+/// Writes a file with `write`, only replacing `path` if it succeeds.
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut AtomicFile) -> Result<(), errors::Error>,
+) -> Result<(), errors::Error> {
+    let mut file = AtomicFile::create(path)?;
+    write(&mut file)?;
+    file.commit()?;
+    Ok(())
+}
+
+#[cfg(unix)]
+/// Serves JSON-RPC connections on a Unix socket at `path`, one at a
+/// time, against the same session. A connection that fails is dropped
+/// without stopping the server.
+fn serve_ipc(session: &mut Session, path: &Path) -> Result<(), errors::Error> {
+    use std::io::BufReader;
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+
+    // Left behind by a server that did not shut down cleanly.
+    if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    for stream in listener.incoming() {
+        let result = stream
+            .and_then(|stream| Ok((BufReader::new(stream.try_clone()?), stream)))
+            .map_err(errors::Error::from)
+            .and_then(|(reader, writer)| rpc::serve(session, reader, writer));
+        if let Err(err) = result {
+            eprintln!("Warning: dropped connection: {}", err);
+        }
+    }
+    Ok(())
+}
+
+/// Parses a `--dispute-limit` value.
+fn parse_account_limit(s: &str) -> Result<(LimitKind, LimitLevels), String> {
+    let (kind, levels) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected `kind=soft/hard`, found `{}`", s))?;
+    Ok((kind.parse()?, levels.parse()?))
+}
+
+fn parse_dispute_limit(s: &str) -> Result<(DisputeInitiator, state::DisputeLimit), String> {
+    let (initiator, limit) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected `initiator=limit`, found `{}`", s))?;
+    Ok((initiator.parse()?, limit.parse()?))
+}
+
+/// Invocations shown under the help of each subcommand, and of the
+/// program for `""`, as the subcommand, what the invocation does, and
+/// the command line after the program name. Each was run as it is
+/// written here, against inputs of the kind its flags describe.
+const EXAMPLES: &[(&str, &str, &str)] = &[
+    ("", "Process a file, as `process` does", "transactions.csv"),
+    (
+        "",
+        "See the flags of a subcommand, and examples of it",
+        "help process",
+    ),
+    (
+        "process",
+        "Print the final client states",
+        "process transactions.csv",
+    ),
+    (
+        "process",
+        "Write them to a file, and the rejected records next to it",
+        "process transactions.csv --output clients.csv --rejects rejected.csv",
+    ),
+    (
+        "process",
+        "Process a day's files four at a time, and merge the results",
+        "process 2024-05-01/*.csv --parallelism 4",
+    ),
+    (
+        "process",
+        "Keep memory bounded on a long feed by archiving settled transactions",
+        "process feed.csv --archive settled.csv --archive-after-records 100000",
+    ),
+    (
+        "watch",
+        "Print the client states again whenever the file changes",
+        "watch transactions.csv --interval-ms 500",
+    ),
+    (
+        "query",
+        "Print one client",
+        "query transactions.csv --client 7",
+    ),
+    (
+        "query",
+        "Print it as it was after the first 1,000 records",
+        "query transactions.csv --client 7 --as-of record:1000",
+    ),
+    (
+        "generate",
+        "Write a reproducible corpus of 100,000 records over 500 clients",
+        "generate --records 100000 --clients 500 --seed 1 > corpus.csv",
+    ),
+    (
+        "diff",
+        "Compare the output of two runs",
+        "diff yesterday.csv today.csv",
+    ),
+    (
+        "lint",
+        "List every problem in a file before processing it",
+        "lint transactions.csv",
+    ),
+    (
+        "reconcile",
+        "Check a run against the balances of a legacy system",
+        "reconcile --expected legacy.csv transactions.csv",
+    ),
+    (
+        "conform",
+        "Run the test vectors shipped with the engine",
+        "conform vectors",
+    ),
+    (
+        "minimize",
+        "Cut a failing file down to the records that make it fail",
+        "minimize failing.csv --output smallest.csv",
+    ),
+    (
+        "verify-manifest",
+        "Check an output written with `--manifest clients.json`",
+        "verify-manifest clients.json clients.csv",
+    ),
+    (
+        "capabilities",
+        "Check what a deployed binary supports",
+        "capabilities --json",
+    ),
+    (
+        "completions",
+        "Complete in bash from now on",
+        "completions bash > ~/.local/share/bash-completion/completions/payment-engine",
+    ),
+    (
+        "completions",
+        "Complete in zsh, with `~/.zfunc` in `fpath`",
+        "completions zsh > ~/.zfunc/_payment-engine",
+    ),
+    (
+        "completions",
+        "Complete in fish",
+        "completions fish > ~/.config/fish/completions/payment-engine.fish",
+    ),
+    (
+        "decrypt",
+        "Read an audit log encrypted with `--encrypt-key env:AUDIT_KEY`",
+        "decrypt --decrypt-key env:AUDIT_KEY audit.log",
+    ),
+    (
+        "stream",
+        "Apply JSON records as they arrive, acknowledging each",
+        "stream < records.jsonl",
+    ),
+    (
+        "serve",
+        "Serve JSON-RPC on a Unix socket",
+        "serve --ipc /tmp/engine.sock",
+    ),
+    (
+        "serve",
+        "Serve over HTTP, with a health check for a load balancer",
+        "serve --http-on 127.0.0.1:8000 --health-on 0.0.0.0:8080",
+    ),
+    (
+        "admin",
+        "Merge a duplicate account on a server started with `serve --ipc /tmp/engine.sock`",
+        "admin --ipc /tmp/engine.sock merge-clients --source 7 --destination 3",
+    ),
+    (
+        "admin",
+        "Freeze a client under investigation on a server started with `serve --http-on 127.0.0.1:8000`",
+        "admin --http 127.0.0.1:8000 freeze --client 7 --reason \"under investigation\"",
+    ),
+    (
+        "risk-report",
+        "List the riskiest clients first",
+        "risk-report transactions.csv",
+    ),
+    (
+        "rollup",
+        "Add up clients by the merchants and programs they belong to",
+        "rollup transactions.csv --hierarchy merchants.csv",
+    ),
+    (
+        "statement",
+        "Write an OFX statement per client",
+        "statement transactions.csv --format ofx --dir statements",
+    ),
+    (
+        "retry",
+        "Retry the rejected records of a run under a higher limit",
+        "retry rejected.csv transactions.csv --max-amount 10000 --output retried.csv",
+    ),
+];
+
+/// The command-line interface, with the `EXAMPLES` of each subcommand
+/// after its help.
+fn cli_command() -> clap::Command<'static> {
+    let mut command = Cli::command();
+    let names: Vec<String> = command
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_owned())
+        .collect();
+    let bin = command.get_name().to_owned();
+    let examples = |name: &str| {
+        let text: Vec<String> = EXAMPLES
+            .iter()
+            .filter(|(subcommand, _, _)| *subcommand == name)
+            .map(|(_, about, line)| format!("    # {}\n    {} {}", about, bin, line))
+            .collect();
+        // Help is borrowed for the life of the command, which lasts as
+        // long as the program does, so the texts are leaked.
+        let text = format!("EXAMPLES:\n{}", text.join("\n\n"));
+        &*Box::leak(text.into_boxed_str())
+    };
+    command = command.after_help(examples(""));
+    for name in names
+        .iter()
+        .filter(|name| EXAMPLES.iter().any(|(subcommand, _, _)| subcommand == name))
+    {
+        command = command.mut_subcommand(name.as_str(), |sub| sub.after_help(examples(name)));
+    }
+    command
+}
+
+/// Parses the command line, treating a bare input file as `process`.
+fn parse_cli() -> Cli {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    let command = cli_command();
+    let is_subcommand = args.get(1).is_none_or(|arg| {
+        let arg = arg.to_string_lossy();
+        matches!(&*arg, "help" | "-h" | "--help" | "-V" | "--version")
+            || command.find_subcommand(&*arg).is_some()
+    });
+    if !is_subcommand {
+        args.insert(1, "process".into());
+    }
+    let matches = command.get_matches_from(args);
+    Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit())
 }
 
 fn main() -> Result<(), errors::Error> {
-    let args = Args::parse();
-    let mut program_state = state::CurrentState::default();
-    program_state.process_from_csv(File::open(args.input)?)?;
-    program_state.into_csv(std::io::stdout())?;
+    let result = run(parse_cli().command);
+    if let Err(errors::Error::Invariant(err)) = &result {
+        eprintln!("Error: invariant violated: {}", err);
+        eprintln!("The most recent records, oldest first, were:");
+        for tx in err.recent() {
+            let amount = tx.amount().map(|amount| amount.to_string());
+            eprintln!(
+                "  {}, client {}, tx {}, amount {}",
+                tx.type_name(),
+                tx.client,
+                tx.id,
+                amount.as_deref().unwrap_or("none"),
+            );
+        }
+        std::process::exit(1);
+    }
+    result
+}
+
+/// Runs `command` to completion.
+fn run(command: Command) -> Result<(), errors::Error> {
+    match command {
+        Command::Process(args) => {
+            let started = SystemTime::now();
+            #[cfg(feature = "warehouse")]
+            let sink = args.warehouse.sink();
+            #[allow(unused_mut)]
+            let mut builder = args.process.builder()?;
+            // The changelog is loaded along with the client states.
+            #[cfg(feature = "warehouse")]
+            if sink.is_some() {
+                builder = builder.events(true);
+            }
+            let seen = args
+                .seen_ids
+                .as_ref()
+                .map(SeenSet::open)
+                .transpose()?
+                .map(Arc::new);
+            if let Some(seen) = &seen {
+                builder = builder.seen_ids(seen.clone());
+            }
+            let mut program_state = args.process.run_with(builder)?;
+            #[cfg(feature = "warehouse")]
+            if let Some(sink) = &sink {
+                warehouse::export(sink.as_ref(), &program_state, &args.warehouse.tables())?;
+            }
+            if let Some(path) = &args.delta {
+                if let Some(from) = &args.delta_from {
+                    program_state.set_baseline(diff::read_balances(File::open(from)?)?);
+                }
+                write_atomically(path, |file| Ok(program_state.export_delta(file)?))?;
+            }
+            if let (Some(partitions), Some(dir)) = (args.output_partitions, &args.output) {
+                partition::write_partitions(
+                    &program_state,
+                    dir,
+                    args.partition_by,
+                    partitions,
+                    manifest::digest_inputs(&args.process.inputs)?,
+                    Provenance::new(&program_state, started),
+                )?;
+                if let Some(seen) = &seen {
+                    seen.commit()?;
+                }
+                return Ok(());
+            }
+            let manifest = match &args.output {
+                Some(path) => {
+                    let mut file = AtomicFile::create(path)?;
+                    let manifest = args.write(program_state, &mut file, started)?;
+                    file.commit()?;
+                    manifest
+                }
+                None => args.write(program_state, std::io::stdout(), started)?,
+            };
+            // The manifest is only written once the output is complete.
+            if let (Some(path), Some(manifest)) = (&args.manifest, manifest) {
+                write_atomically(path, |file| Ok(manifest.write(file)?))?;
+            }
+            if let Some(seen) = &seen {
+                seen.commit()?;
+            }
+        }
+        Command::Watch(args) => {
+            let interval = Duration::from_millis(args.interval_ms);
+            let mut last_modified = Vec::new();
+            loop {
+                // A changed denylist is also picked up by processing
+                // the inputs again.
+                let modified = args
+                    .process
+                    .inputs
+                    .iter()
+                    .chain(&args.process.policies.denylist)
+                    .map(|input| std::fs::metadata(input)?.modified())
+                    .collect::<Result<Vec<_>, _>>()?;
+                if last_modified != modified {
+                    last_modified = modified;
+                    args.process.run()?.into_csv(std::io::stdout())?;
+                }
+                thread::sleep(interval);
+            }
+        }
+        Command::Query(args) => {
+            let mut builder = args.process.builder()?;
+            if let Some(point) = args.as_of {
+                builder = builder.as_of(point);
+            }
+            let program_state = args.process.run_with(builder)?;
+            let mut wtr = csv::Writer::from_writer(std::io::stdout());
+            if let Some(client) = program_state.client(args.client) {
+                wtr.serialize(client)?;
+            }
+            wtr.flush()?;
+        }
+        Command::Generate(args) => {
+            generate::generate(std::io::stdout(), args.records, args.clients, args.seed)?;
+        }
+        Command::Diff(args) => {
+            let left = diff::read_balances(File::open(args.left)?)?;
+            let right = diff::read_balances(File::open(args.right)?)?;
+            let diffs = diff::diff_balances(&left, &right);
+            diff::write_diffs(std::io::stdout(), &diffs)?;
+            if !diffs.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Command::Lint(args) => {
+            let findings = lint::lint(File::open(args.input)?)?;
+            lint::write_csv(std::io::stdout(), &findings)?;
+            if !findings.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Command::Reconcile(args) => {
+            let expected = diff::read_balances(File::open(args.expected)?)?;
+            let mut program_state = args.policies.builder()?.build()?;
+            let discrepancies = reconcile::reconcile(
+                &mut program_state,
+                File::open(args.input)?,
+                &expected,
+                |_, err| {
+                    eprintln!("Warning: {}", err);
+                    Ok(())
+                },
+            )?;
+            reconcile::write_discrepancies(std::io::stdout(), &discrepancies)?;
+            if !discrepancies.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Command::Minimize(args) => {
+            let builder = args.policies.builder()?;
+            let failure = args.rejected.map(minimize::Failure::Rejects);
+            let Some((headers, rows)) =
+                minimize::minimize(&builder, File::open(&args.input)?, failure)?
+            else {
+                eprintln!("Error: the input does not fail that way");
+                std::process::exit(1);
+            };
+            eprintln!("Reduced to {} records", rows.len());
+            match &args.output {
+                Some(path) => {
+                    write_atomically(path, |file| Ok(minimize::write_csv(file, &headers, &rows)?))?
+                }
+                None => minimize::write_csv(std::io::stdout(), &headers, &rows)?,
+            }
+        }
+        Command::Conform(args) => {
+            let results = conform::run_dir(&args.policies.builder()?, &args.dir)?;
+            conform::write_report(std::io::stdout(), &results)?;
+            if !results.iter().all(VectorResult::passed) {
+                std::process::exit(1);
+            }
+        }
+        #[cfg(feature = "encryption")]
+        Command::Decrypt(args) => {
+            let data = std::fs::read(&args.input)?;
+            std::io::stdout().write_all(&crypto::decrypt(&args.decrypt_key, &data)?)?;
+        }
+        Command::VerifyManifest(args) => {
+            Manifest::read(File::open(args.manifest)?)?.verify(&args.output)?;
+        }
+        Command::Capabilities(args) => {
+            let capabilities = capabilities::capabilities();
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&capabilities)?);
+                return Ok(());
+            }
+            let list = |items: &[&str]| items.join(", ");
+            println!("version: {}", capabilities.version);
+            println!("features: {}", list(&capabilities.features));
+            println!("input formats: {}", list(&capabilities.input_formats));
+            println!("server modes: {}", list(&capabilities.server_modes));
+            println!("backends: {}", list(&capabilities.backends));
+            println!("balances: {}", capabilities.balances);
+            let codes: Vec<&str> = capabilities
+                .rejection_codes
+                .iter()
+                .map(|code| code.as_str())
+                .collect();
+            println!("rejection codes: {}", list(&codes));
+            println!("defaults:");
+            for (flag, value) in &capabilities.defaults {
+                println!("  --{} {}", flag, value);
+            }
+        }
+        Command::Admin(args) => {
+            println!("{}", args.send()?);
+        }
+        Command::Completions(args) => {
+            let mut out = std::io::stdout().lock();
+            completions::generate(args.shell, &mut cli_command(), &mut out)?;
+        }
+        Command::Stream(args) => {
+            let mut session = Session::new(args.policies.builder()?.build()?);
+            session.serve_jsonl(std::io::stdin().lock(), std::io::stdout().lock())?;
+        }
+        Command::Serve(args) => {
+            #[allow(unused_mut)]
+            let mut builder = args.policies.builder()?.history(args.history);
+            #[cfg(feature = "clickhouse")]
+            let events = args.clickhouse.sink()?;
+            #[cfg(feature = "clickhouse")]
+            if events.is_some() {
+                builder = builder.events(true);
+            }
+            let screener = builder.screener().cloned();
+            let mut session = Session::new(builder.build()?);
+            if let Some(seconds) = args.dedup_ttl {
+                session = session.deduplicate(Duration::from_secs(seconds));
+            }
+            if let Some(path) = &args.case_journal {
+                session = session.case_journal(path)?;
+            }
+            // Subsystems run on threads of their own, and failing
+            // repeatedly shuts the server down rather than leaving it
+            // running without them.
+            let supervisor =
+                Supervisor::new(RestartPolicy::default()).on_escalation(|name, err| {
+                    eprintln!(
+                        "Error: subsystem `{}` keeps failing, shutting down: {}",
+                        name, err
+                    );
+                    std::process::exit(1);
+                });
+            if let Some(screener) = screener {
+                supervisor.spawn("screening", move || loop {
+                    match screener.reload() {
+                        Ok(true) => eprintln!(
+                            "Reloaded the denylist, {} entries",
+                            screener.list().len()
+                        ),
+                        Ok(false) => {}
+                        Err(err) => eprintln!(
+                            "Warning: keeping the previous denylist, as it could not be reloaded: {}",
+                            err
+                        ),
+                    }
+                    thread::sleep(DENYLIST_INTERVAL);
+                });
+            }
+            if let Some(addr) = &args.replicate_on {
+                let leader = Leader::bind(addr)?;
+                let accepting = leader.clone();
+                supervisor.spawn("replication", move || accepting.accept());
+                session = session.replicate(leader);
+            }
+            if let Some(addr) = &args.health_on {
+                let listener = TcpListener::bind(addr)?;
+                let checked = supervisor.clone();
+                supervisor.spawn("health", move || {
+                    supervise::serve_health(&listener, &checked)
+                });
+            }
+            if let Some(addr) = &args.follow {
+                let records = replicate::follow(&mut session, addr)?;
+                eprintln!(
+                    "Leader at {} went away after {} records, serving requests",
+                    addr, records
+                );
+            }
+            // Followers only publish what they apply once promoted, as
+            // the leader published what came before.
+            #[cfg(feature = "clickhouse")]
+            if let Some(events) = events {
+                session = session.publish_events(Box::new(events));
+            }
+            if let Some(addr) = &args.http_on {
+                return rpc::serve_http(&mut session, &TcpListener::bind(addr)?);
+            }
+            #[cfg(unix)]
+            if let Some(path) = &args.ipc {
+                return serve_ipc(&mut session, path);
+            }
+            rpc::serve(
+                &mut session,
+                std::io::stdin().lock(),
+                std::io::stdout().lock(),
+            )?;
+        }
+        Command::RiskReport(args) => {
+            let program_state = args.run()?;
+            risk::write_report(std::io::stdout(), program_state.risk_report())?;
+        }
+        Command::Rollup(args) => {
+            let hierarchy = Hierarchy::from_csv(File::open(&args.hierarchy)?)?;
+            let program_state = args.process.run()?;
+            let rollups = hierarchy::rollup(&program_state, &hierarchy);
+            hierarchy::write_csv(std::io::stdout(), &rollups)?;
+        }
+        Command::Statement(args) => {
+            let program_state = args.process.run()?;
+            let dir = match &args.dir {
+                Some(dir) => dir,
+                None => {
+                    // `--client` is required without `--dir`.
+                    let id = args.client.unwrap();
+                    if let Some(statement) = Statement::new(&program_state, id) {
+                        statement.write(std::io::stdout(), args.format, &args.currency)?;
+                    }
+                    return Ok(());
+                }
+            };
+            std::fs::create_dir_all(dir)?;
+            let ids: Vec<u16> = match args.client {
+                Some(id) => vec![id],
+                None => program_state.active_clients().map(|c| c.client).collect(),
+            };
+            for id in ids {
+                let statement = match Statement::new(&program_state, id) {
+                    Some(statement) => statement,
+                    None => continue,
+                };
+                let path = dir.join(format!("client-{}.{}", id, args.format.extension()));
+                write_atomically(&path, |file| {
+                    Ok(statement.write(file, args.format, &args.currency)?)
+                })?;
+            }
+        }
+        Command::Retry(args) => {
+            let retries = Arc::new(Retries::from_csv(File::open(&args.rejected)?)?);
+            let builder = args.process.builder()?.retries(retries.clone());
+            let mut program_state = args.process.run_with(builder)?;
+            let counts = match &args.output {
+                Some(path) => {
+                    let mut counts = Default::default();
+                    write_atomically(path, |file| {
+                        let mut out = RetryWriter::new(file);
+                        counts = retries.retry(&mut program_state, &mut out)?;
+                        Ok(out.flush()?)
+                    })?;
+                    counts
+                }
+                None => {
+                    let mut out = RetryWriter::new(std::io::stdout());
+                    let counts = retries.retry(&mut program_state, &mut out)?;
+                    out.flush()?;
+                    counts
+                }
+            };
+            eprintln!(
+                "Retried {} records: {} accepted, {} quarantined, {} rejected again",
+                retries.records().len(),
+                counts.accepted,
+                counts.quarantined,
+                counts.rejected
+            );
+            if let Some(path) = &args.clients {
+                write_atomically(path, |file| Ok(program_state.into_csv(file)?))?;
+            }
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,207 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::diff;
+use crate::errors::{self, ManifestError};
+use crate::state::CurrentState;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+/// The digest of one input file.
+pub struct InputDigest {
+    pub path: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+/// How an output was produced, so it can be traced back to the run,
+/// and the configuration, behind it.
+pub struct Provenance {
+    /// The digest of the policies the run was configured with, from
+    /// `CurrentState::config_digest`.
+    pub config_sha256: String,
+    /// When processing started and finished, in seconds since the Unix
+    /// epoch.
+    pub started_at: u64,
+    pub finished_at: u64,
+    /// The records read, and what became of them.
+    pub records: u64,
+    pub accepted: u64,
+    pub quarantined: u64,
+    pub replayed: u64,
+    pub rejected: u64,
+}
+
+impl Provenance {
+    /// Describes the run that produced `state`, which started at
+    /// `started` and finishes now.
+    pub fn new(state: &CurrentState, started: SystemTime) -> Self {
+        let seconds = |time: SystemTime| {
+            time.duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs())
+        };
+        let summary = state.summary();
+        Provenance {
+            config_sha256: state.config_digest(),
+            started_at: seconds(started),
+            finished_at: seconds(SystemTime::now()),
+            records: summary.records,
+            accepted: summary.accepted,
+            quarantined: summary.quarantined,
+            replayed: summary.replayed,
+            rejected: summary.rejected,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+/// A sidecar describing an output file, so consumers can
+/// detect tampering or corruption.
+pub struct Manifest {
+    pub engine_version: String,
+    pub rows: u64,
+    /// The sum of the `total` column, kept as a string so
+    /// JSON readers cannot lose precision.
+    #[serde(with = "rust_decimal::serde::str")]
+    pub total: Decimal,
+    /// The SHA-256 digest of the output, in hexadecimal.
+    pub sha256: String,
+    pub inputs: Vec<InputDigest>,
+    /// Missing from manifests written by older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+/// Formats a digest as lowercase hexadecimal.
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Computes the SHA-256 digest of `bytes`, in hexadecimal.
+pub(crate) fn digest_bytes(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+/// Computes the digest of every file in `inputs`.
+pub fn digest_inputs(inputs: &[impl AsRef<Path>]) -> io::Result<Vec<InputDigest>> {
+    inputs
+        .iter()
+        .map(|path| {
+            let path = path.as_ref();
+            Ok(InputDigest {
+                path: path.display().to_string(),
+                sha256: digest_file(path)?,
+            })
+        })
+        .collect()
+}
+
+/// Computes the SHA-256 digest of a file, in hexadecimal.
+pub fn digest_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// A writer that digests everything written through it.
+pub struct DigestingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> DigestingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        DigestingWriter {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// The SHA-256 digest of everything written so far, in hexadecimal.
+    pub fn digest(&self) -> String {
+        hex(&self.hasher.clone().finalize())
+    }
+}
+
+impl<W: Write> Write for DigestingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Manifest {
+    /// Describes the output of `state`, produced from `inputs` by a run
+    /// that started at `started`. The output digest is left empty, to be
+    /// filled in from a `DigestingWriter` once the output has been
+    /// written.
+    pub fn new(
+        state: &CurrentState,
+        inputs: &[impl AsRef<Path>],
+        started: SystemTime,
+    ) -> Result<Self, io::Error> {
+        let (rows, total) = state
+            .active_clients()
+            .fold((0, Decimal::default()), |(rows, total), client| {
+                (rows + 1, total + client.total)
+            });
+        Ok(Manifest {
+            engine_version: env!("CARGO_PKG_VERSION").to_string(),
+            rows,
+            total,
+            sha256: String::new(),
+            inputs: digest_inputs(inputs)?,
+            provenance: Some(Provenance::new(state, started)),
+        })
+    }
+
+    /// Writes the manifest as JSON.
+    pub fn write(&self, writer: impl Write) -> Result<(), serde_json::Error> {
+        serde_json::to_writer_pretty(writer, self)
+    }
+
+    /// Reads a manifest from JSON.
+    pub fn read(reader: impl io::Read) -> Result<Self, serde_json::Error> {
+        serde_json::from_reader(reader)
+    }
+
+    /// Checks `output` and the recorded inputs against the manifest.
+    pub fn verify(&self, output: &Path) -> Result<(), errors::Error> {
+        let balances = diff::read_balances(File::open(output)?)?;
+        let rows = balances.len() as u64;
+        if rows != self.rows {
+            return Err(ManifestError::RowCountMismatch {
+                expected: self.rows,
+                actual: rows,
+            }
+            .into());
+        }
+        let total = balances.values().map(|client| client.total).sum();
+        if total != self.total {
+            return Err(ManifestError::TotalMismatch {
+                expected: self.total,
+                actual: total,
+            }
+            .into());
+        }
+        if digest_file(output)? != self.sha256 {
+            return Err(ManifestError::PayloadDigestMismatch.into());
+        }
+        for input in &self.inputs {
+            if digest_file(Path::new(&input.path))? != input.sha256 {
+                return Err(ManifestError::InputDigestMismatch(input.path.clone()).into());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use std::fmt::Debug;
+
+use crate::errors;
+use crate::state::StateView;
+use crate::transaction::Transaction;
+
+#[derive(Debug, Clone)]
+/// What a middleware decided about a record before it is applied.
+pub enum Verdict {
+    /// Pass the record on unchanged.
+    Continue,
+    /// Pass this record on instead, such as the record with fields
+    /// filled in.
+    Replace(Box<Transaction>),
+    /// Reject the record with this reason, without applying it or
+    /// calling later middleware.
+    Reject(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+/// What happened to a record, as told to middleware after it.
+pub enum Outcome<'a> {
+    /// The record was applied.
+    Accepted,
+    /// The record repeated one already applied, and was skipped.
+    Replayed,
+    /// The record was held for review.
+    Quarantined,
+    /// The record was rejected, by the engine or by middleware.
+    Rejected(&'a errors::Error),
+}
+
+/// Called around every record a state processes, so embedders can log,
+/// enrich or refuse records without changing the engine.
+///
+/// Middleware runs in the order it was added: `before` hooks in order
+/// until one rejects the record, each seeing the record as left by the
+/// ones before, and then every `after` hook in order with the record as
+/// applied. Records the engine applies itself, such as adjudicators'
+/// rulings, go through middleware too.
+pub trait TxMiddleware: Debug + Send + Sync {
+    /// Called before `tx` is applied.
+    fn before(&self, tx: &Transaction) -> Verdict {
+        let _ = tx;
+        Verdict::Continue
+    }
+
+    /// Called once `tx` has been processed, with what happened to it
+    /// and a view of the state after it.
+    fn after(&self, tx: &Transaction, outcome: &Outcome<'_>, state: &StateView<'_>) {
+        let _ = (tx, outcome, state);
+    }
+}
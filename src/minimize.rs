@@ -0,0 +1,141 @@
+use csv::StringRecord;
+
+use crate::errors::{self, RejectionCode};
+use crate::state::CurrentStateBuilder;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// How an input fails, which a smaller input must fail the same way to
+/// reproduce it.
+pub enum Failure {
+    /// Processing aborts with an error of this kind, such as
+    /// `Invariant(HeldMismatch`, as given by `kind`.
+    Aborts(String),
+    /// Some record is rejected with this code.
+    Rejects(RejectionCode),
+}
+
+impl Failure {
+    /// Whether processing `rows` under `headers` into a state built by
+    /// `builder` fails this way.
+    fn reproduces(
+        &self,
+        builder: &CurrentStateBuilder,
+        headers: &StringRecord,
+        rows: &[StringRecord],
+    ) -> Result<bool, errors::Error> {
+        let input = to_csv(headers, rows)?;
+        let mut state = builder.clone().build()?;
+        let mut rejected = false;
+        let result = state.process_from_csv_with(input.as_slice(), |_, err| {
+            rejected |=
+                matches!(self, Failure::Rejects(code) if err.rejection_code() == Some(*code));
+            Ok(())
+        });
+        Ok(match (self, result) {
+            (Failure::Aborts(expected), Err(err)) => kind(&err) == *expected,
+            (Failure::Aborts(_), Ok(())) => false,
+            (Failure::Rejects(_), _) => rejected,
+        })
+    }
+}
+
+/// The kind of an error that aborts processing: its variant and that of
+/// the error it wraps, such as `Invariant(NegativeHeld`, without the
+/// IDs and amounts that change as records are removed.
+pub fn kind(err: &errors::Error) -> String {
+    let debug = format!("{:?}", err);
+    let Some(open) = debug.find('(') else {
+        return debug;
+    };
+    let end = debug[open + 1..]
+        .find(|c: char| !c.is_alphanumeric() && c != '_')
+        .map_or(debug.len(), |end| open + 1 + end);
+    debug[..end].to_owned()
+}
+
+/// Reduces the records of a CSV stream that fails as `failure` does to a
+/// smaller sequence that still does, keeping their order. If `failure`
+/// is `None`, it is the error the whole input aborts with. Returns the
+/// header and the records kept, or `None` if the whole input does not
+/// fail that way.
+///
+/// This is delta debugging: the records are split into ever smaller
+/// chunks, and each chunk, or everything but it, is kept if it still
+/// fails, until no single record can be left out. Every step processes
+/// the records kept so far into a fresh state, so this takes as many
+/// runs as it takes steps, but each run is over fewer records.
+pub fn minimize(
+    builder: &CurrentStateBuilder,
+    reader: impl std::io::Read,
+    failure: Option<Failure>,
+) -> Result<Option<(StringRecord, Vec<StringRecord>)>, errors::Error> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .from_reader(reader);
+    let headers = rdr.headers()?.clone();
+    let mut rows = rdr.records().collect::<Result<Vec<_>, _>>()?;
+    let failure = match failure {
+        Some(failure) => failure,
+        None => {
+            let mut state = builder.clone().build()?;
+            let input = to_csv(&headers, &rows)?;
+            match state.process_from_csv_with(input.as_slice(), |_, _| Ok(())) {
+                Ok(()) => return Ok(None),
+                Err(err) => Failure::Aborts(kind(&err)),
+            }
+        }
+    };
+    if !failure.reproduces(builder, &headers, &rows)? {
+        return Ok(None);
+    }
+
+    let mut chunks = 2;
+    while rows.len() >= 2 {
+        let size = rows.len().div_ceil(chunks);
+        let mut reduced = false;
+        for start in (0..rows.len()).step_by(size) {
+            let end = (start + size).min(rows.len());
+            if failure.reproduces(builder, &headers, &rows[start..end])? {
+                rows = rows[start..end].to_vec();
+                chunks = 2;
+                reduced = true;
+                break;
+            }
+            let complement: Vec<StringRecord> = [&rows[..start], &rows[end..]].concat();
+            if failure.reproduces(builder, &headers, &complement)? {
+                rows = complement;
+                chunks = (chunks - 1).max(2);
+                reduced = true;
+                break;
+            }
+        }
+        if !reduced {
+            if chunks >= rows.len() {
+                break;
+            }
+            chunks = (chunks * 2).min(rows.len());
+        }
+    }
+    Ok(Some((headers, rows)))
+}
+
+/// Writes `headers` and `rows` as a CSV file.
+pub fn write_csv(
+    writer: impl std::io::Write,
+    headers: &StringRecord,
+    rows: &[StringRecord],
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new().flexible(true).from_writer(writer);
+    wtr.write_record(headers)?;
+    rows.iter().try_for_each(|row| wtr.write_record(row))?;
+    wtr.flush()?;
+    Ok(())
+}
+
+/// `headers` and `rows` as the bytes of a CSV file.
+fn to_csv(headers: &StringRecord, rows: &[StringRecord]) -> Result<Vec<u8>, csv::Error> {
+    let mut bytes = Vec::new();
+    write_csv(&mut bytes, headers, rows)?;
+    Ok(bytes)
+}
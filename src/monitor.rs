@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// What happens to the accounts under a client or merchant whose
+/// chargeback rate exceeds the threshold.
+pub enum MonitorAction {
+    /// Raise the `chargebacks` flag, and clear it again once the rate
+    /// falls below the release rate.
+    #[default]
+    Flag,
+    /// Lock the accounts. Locks are not lifted when the rate recovers.
+    Lock,
+}
+
+impl FromStr for MonitorAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flag" => Ok(MonitorAction::Flag),
+            "lock" => Ok(MonitorAction::Lock),
+            _ => Err(format!("expected `flag` or `lock`, got `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// Watches the share of each client's, or each merchant's, recent
+/// deposits and withdrawals that were charged back, as card networks
+/// require, and acts once it exceeds `threshold`.
+///
+/// The rate only counts again as exceeded once it has fallen to
+/// `release` or below, so a rate hovering around the threshold does
+/// not flap.
+pub struct ChargebackMonitor {
+    /// The rate, e.g. `0.01` for 1%, above which the action is taken.
+    pub threshold: Decimal,
+    /// How many of the most recent deposits and withdrawals the rate
+    /// is taken over.
+    pub window: u64,
+    /// The rate at or below which an exceeded threshold is cleared.
+    pub release: Decimal,
+    /// How many deposits and withdrawals must have been made before the
+    /// rate is acted on, so a single early chargeback does not count
+    /// as a rate of 100%.
+    pub min_transactions: u64,
+    pub action: MonitorAction,
+}
+
+impl ChargebackMonitor {
+    /// Monitors the rate over the last `window` deposits and
+    /// withdrawals, releasing at half the threshold, once 100 have
+    /// been made or the window is full, and flagging accounts.
+    pub fn new(threshold: Decimal, window: u64) -> Self {
+        ChargebackMonitor {
+            threshold,
+            window,
+            release: threshold / Decimal::from(2),
+            min_transactions: window.min(100),
+            action: MonitorAction::default(),
+        }
+    }
+}
+
+impl FromStr for ChargebackMonitor {
+    type Err = String;
+
+    /// Parses `rate/window`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (threshold, window) = s
+            .split_once('/')
+            .ok_or_else(|| format!("expected `rate/transactions`, found `{}`", s))?;
+        Ok(ChargebackMonitor::new(
+            threshold
+                .parse()
+                .map_err(|_| format!("invalid chargeback rate `{}`", threshold))?,
+            window
+                .parse()
+                .map_err(|_| format!("invalid chargeback window `{}`", window))?,
+        ))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+/// Whose chargeback rate is monitored.
+pub(crate) enum Monitored {
+    Client(u16),
+    Merchant(String),
+}
+
+impl fmt::Display for Monitored {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Monitored::Client(id) => write!(f, "client {}", id),
+            Monitored::Merchant(name) => write!(f, "merchant `{}`", name),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// A change in whether a monitored rate is over the threshold, with
+/// the rate and what it was taken over.
+pub(crate) enum Transition {
+    Exceeded {
+        chargebacks: usize,
+        transactions: u64,
+    },
+    Recovered {
+        chargebacks: usize,
+        transactions: u64,
+    },
+}
+
+#[derive(Debug, Default, Clone)]
+/// The recent deposits, withdrawals and chargebacks of one client or
+/// merchant.
+pub(crate) struct ChargebackWindow {
+    /// The deposits and withdrawals made so far.
+    transactions: u64,
+    /// The number of deposits and withdrawals made before each
+    /// chargeback still in the window, oldest first.
+    chargebacks: VecDeque<u64>,
+    /// Whether the threshold is exceeded.
+    pub(crate) exceeded: bool,
+}
+
+impl ChargebackWindow {
+    /// Counts a deposit or withdrawal, which may bring the rate down.
+    pub(crate) fn transaction(&mut self, monitor: &ChargebackMonitor) -> Option<Transition> {
+        self.transactions += 1;
+        while self
+            .chargebacks
+            .front()
+            .is_some_and(|&made| self.transactions - made > monitor.window)
+        {
+            self.chargebacks.pop_front();
+        }
+        self.check(monitor)
+    }
+
+    /// Counts a chargeback, which may bring the rate up.
+    pub(crate) fn chargeback(&mut self, monitor: &ChargebackMonitor) -> Option<Transition> {
+        self.chargebacks.push_back(self.transactions);
+        self.check(monitor)
+    }
+
+    /// Adds the transactions and chargebacks of `other`, as if they
+    /// were made after those here. The threshold stays exceeded if it
+    /// was in either, until the next transaction or chargeback checks
+    /// the combined rate.
+    pub(crate) fn merge(&mut self, other: ChargebackWindow) {
+        let offset = self.transactions;
+        self.transactions += other.transactions;
+        self.chargebacks
+            .extend(other.chargebacks.into_iter().map(|made| made + offset));
+        self.exceeded |= other.exceeded;
+    }
+
+    /// Moves across the threshold or release rate, if the rate has.
+    fn check(&mut self, monitor: &ChargebackMonitor) -> Option<Transition> {
+        if self.transactions < monitor.min_transactions.max(1) {
+            return None;
+        }
+        let chargebacks = self.chargebacks.len();
+        let transactions = self.transactions.min(monitor.window);
+        let rate = Decimal::from(chargebacks) / Decimal::from(transactions);
+        if !self.exceeded && rate > monitor.threshold {
+            self.exceeded = true;
+            return Some(Transition::Exceeded {
+                chargebacks,
+                transactions,
+            });
+        }
+        if self.exceeded && rate <= monitor.release {
+            self.exceeded = false;
+            return Some(Transition::Recovered {
+                chargebacks,
+                transactions,
+            });
+        }
+        None
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt::Debug;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+/// The changes clients can be notified of.
+pub enum Trigger {
+    /// The client's account was locked.
+    AccountLocked,
+    /// The client withdrew more than the configured threshold.
+    LargeWithdrawal,
+    /// One of the client's transactions was disputed.
+    DisputeOpened,
+    /// The client's available funds fell below its floor.
+    BalanceBelowFloor,
+    /// The client's total funds rose above its ceiling.
+    BalanceAboveCeiling,
+    /// The client deposited or withdrew more than its soft limit.
+    TransactionAboveSoftLimit,
+    /// The client's deposits and withdrawals of the day rose above its
+    /// soft limit.
+    DailyVolumeAboveSoftLimit,
+    /// The client's total funds rose above its soft limit.
+    BalanceAboveSoftLimit,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// Which changes are passed to the notification sink.
+pub struct Triggers {
+    pub account_locked: bool,
+    /// Notifies of withdrawals above this amount.
+    pub withdrawals_above: Option<Decimal>,
+    pub dispute_opened: bool,
+    /// Notifies of clients crossing their balance thresholds.
+    pub balance_thresholds: bool,
+    /// Notifies of clients going past their soft account limits.
+    pub soft_limits: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+/// A change a client should be told about.
+pub struct Notification {
+    pub trigger: Trigger,
+    pub client: u16,
+    pub tx: u32,
+    /// The amount withdrawn, for large withdrawals, the balance that
+    /// crossed the threshold, for balance thresholds, and the amount,
+    /// volume or balance past the limit, for soft limits.
+    pub amount: Option<Decimal>,
+    /// The sender's own ID for the record behind the change.
+    pub external_ref: Option<String>,
+}
+
+/// Receives notifications as records are applied, so embedders can
+/// alert customers without polling the changelog.
+///
+/// Notifications are sent from the thread applying records, so slow
+/// sinks slow down processing; sinks should queue work they cannot
+/// do quickly. A failed notification cannot reject the record behind
+/// it, so sinks report their own failures.
+pub trait NotificationSink: Debug + Send + Sync {
+    /// Delivers `notification`.
+    fn notify(&self, notification: &Notification);
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+/// A sink that drops every notification.
+pub struct NoopSink;
+
+impl NotificationSink for NoopSink {
+    fn notify(&self, _: &Notification) {}
+}
+
+#[cfg(feature = "smtp")]
+pub use smtp::SmtpSink;
+
+#[cfg(feature = "smtp")]
+mod smtp {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::time::Duration;
+
+    use super::{Notification, NotificationSink, Trigger};
+
+    /// How long to wait for the server before giving up.
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[derive(Debug, Clone)]
+    /// Emails each notification through an SMTP relay, to the address
+    /// on file for the client. Clients with no address are skipped.
+    ///
+    /// The relay is spoken to in plain SMTP without authentication,
+    /// so it should be a local relay, such as a Postfix instance,
+    /// that handles TLS and delivery.
+    pub struct SmtpSink {
+        relay: String,
+        from: String,
+        recipients: HashMap<u16, String>,
+    }
+
+    impl SmtpSink {
+        /// Sends mail through the relay at `relay`, such as
+        /// `localhost:25`, from the address `from`, to the address in
+        /// `recipients` for each client.
+        pub fn new(
+            relay: impl Into<String>,
+            from: impl Into<String>,
+            recipients: HashMap<u16, String>,
+        ) -> Self {
+            SmtpSink {
+                relay: relay.into(),
+                from: from.into(),
+                recipients,
+            }
+        }
+
+        /// Sends one message to `to` in a session of its own.
+        fn send(&self, to: &str, subject: &str, body: &str) -> std::io::Result<()> {
+            let addr =
+                self.relay.to_socket_addrs()?.next().ok_or_else(|| {
+                    std::io::Error::other(format!("cannot resolve `{}`", self.relay))
+                })?;
+            let stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+            stream.set_read_timeout(Some(TIMEOUT))?;
+            stream.set_write_timeout(Some(TIMEOUT))?;
+            let mut reader = BufReader::new(stream.try_clone()?);
+            let mut writer = stream;
+            expect(&mut reader, 220)?;
+            for (command, code) in [
+                ("HELO payment-engine".to_owned(), 250),
+                (format!("MAIL FROM:<{}>", self.from), 250),
+                (format!("RCPT TO:<{}>", to), 250),
+                ("DATA".to_owned(), 354),
+            ] {
+                write!(writer, "{}\r\n", command)?;
+                expect(&mut reader, code)?;
+            }
+            write!(
+                writer,
+                "From: <{}>\r\nTo: <{}>\r\nSubject: {}\r\n\r\n{}\r\n.\r\n",
+                self.from, to, subject, body
+            )?;
+            expect(&mut reader, 250)?;
+            write!(writer, "QUIT\r\n")?;
+            Ok(())
+        }
+    }
+
+    /// Reads a reply, which may span several lines, and checks its code.
+    fn expect(reader: &mut impl BufRead, code: u16) -> std::io::Result<()> {
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+            if !line.starts_with(&code.to_string()) {
+                return Err(std::io::Error::other(format!(
+                    "expected {}, got `{}`",
+                    code,
+                    line.trim_end()
+                )));
+            }
+            // `250-` continues a reply, and `250 ` ends it.
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+
+    impl NotificationSink for SmtpSink {
+        fn notify(&self, notification: &Notification) {
+            let to = match self.recipients.get(&notification.client) {
+                Some(to) => to,
+                None => return,
+            };
+            let (subject, body) = match notification.trigger {
+                Trigger::AccountLocked => (
+                    "Your account has been locked",
+                    format!(
+                        "Your account was locked by transaction {}.",
+                        notification.tx
+                    ),
+                ),
+                Trigger::LargeWithdrawal => (
+                    "Large withdrawal from your account",
+                    format!(
+                        "{} was withdrawn from your account in transaction {}.",
+                        notification.amount.unwrap_or_default(),
+                        notification.tx
+                    ),
+                ),
+                Trigger::DisputeOpened => (
+                    "A transaction has been disputed",
+                    format!(
+                        "Transaction {} on your account is under dispute, and its funds are held.",
+                        notification.tx
+                    ),
+                ),
+                Trigger::BalanceBelowFloor => (
+                    "Your available balance is low",
+                    format!(
+                        "Your available balance fell to {} after transaction {}.",
+                        notification.amount.unwrap_or_default(),
+                        notification.tx
+                    ),
+                ),
+                Trigger::BalanceAboveCeiling => (
+                    "Your balance is above its limit",
+                    format!(
+                        "Your total balance rose to {} after transaction {}.",
+                        notification.amount.unwrap_or_default(),
+                        notification.tx
+                    ),
+                ),
+                Trigger::TransactionAboveSoftLimit => (
+                    "A transaction is near your limit",
+                    format!(
+                        "Transaction {} of {} is above your soft transaction limit.",
+                        notification.tx,
+                        notification.amount.unwrap_or_default()
+                    ),
+                ),
+                Trigger::DailyVolumeAboveSoftLimit => (
+                    "Your daily volume is near its limit",
+                    format!(
+                        "Your transactions today came to {} after transaction {}, above your soft daily limit.",
+                        notification.amount.unwrap_or_default(),
+                        notification.tx
+                    ),
+                ),
+                Trigger::BalanceAboveSoftLimit => (
+                    "Your balance is near its limit",
+                    format!(
+                        "Your total balance rose to {} after transaction {}, above your soft balance limit.",
+                        notification.amount.unwrap_or_default(),
+                        notification.tx
+                    ),
+                ),
+            };
+            if let Err(err) = self.send(to, subject, &body) {
+                eprintln!(
+                    "Warning: failed to notify client {} of transaction {}: {}",
+                    notification.client, notification.tx, err
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+/// A file that only appears at its path once it is completely written.
+/// Writes go to a temporary file next to it, which `commit` renames
+/// over the destination. If the writer is dropped without committing,
+/// as when a run fails, the temporary file is removed and any existing
+/// file at the path is left untouched.
+pub struct AtomicFile {
+    path: PathBuf,
+    temp: PathBuf,
+    file: Option<BufWriter<File>>,
+}
+
+impl AtomicFile {
+    /// Starts writing a file that will replace `path` when committed.
+    pub fn create(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let name = path.file_name().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("`{}` does not name a file", path.display()),
+            )
+        })?;
+        // The temporary file must be on the same file system for
+        // the rename to be atomic, so it goes in the same directory.
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(".tmp");
+        let temp = path.with_file_name(temp_name);
+        let file = File::create(&temp)?;
+        Ok(AtomicFile {
+            path,
+            temp,
+            file: Some(BufWriter::new(file)),
+        })
+    }
+
+    /// The path the file will appear at.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flushes the file to disk and moves it to its path.
+    pub fn commit(mut self) -> io::Result<()> {
+        // `file` is only taken here and in `drop`.
+        let file = self.file.take().unwrap();
+        let file = file.into_inner().map_err(|err| err.into_error())?;
+        file.sync_all()?;
+        fs::rename(&self.temp, &self.path)
+    }
+
+    fn file(&mut self) -> &mut BufWriter<File> {
+        self.file.as_mut().unwrap()
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file().flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            // Nothing can be done about a failure here; at worst a
+            // stale temporary file is left behind.
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::errors::{self, ConfigError};
+use crate::state::{ConflictPolicy, CurrentState, CurrentStateBuilder};
+use crate::summary::InputSummary;
+use crate::transaction::Transaction;
+
+/// Processes independent input files concurrently, each into its own
+/// state built by `builder`, passing rejected records to `on_reject`
+/// along with the file they came from. The states are then merged
+/// in the order of `paths`, so the result does not depend on
+/// scheduling. The summary of each input is returned alongside.
+///
+/// Each file is applied in order, so the records of a client keep the
+/// order they have within it, but records of one client in different
+/// files are not interleaved. Sequence numbers given by the input are
+/// checked within each file, and the merge fails if a client's numbers
+/// in one file do not start above where they ended in the files
+/// before it.
+///
+/// A transaction ID accepted from more than one file fails the merge
+/// under `ConflictPolicy::Fail`. Under `keep-first` and `keep-last`,
+/// the files are instead processed one after another, as
+/// `process_files_in_turn` describes, so `parallelism` is not used.
+pub fn process_files_parallel(
+    builder: &CurrentStateBuilder,
+    paths: &[PathBuf],
+    parallelism: NonZeroUsize,
+    on_reject: impl Fn(&Path, &Transaction, &errors::Error) -> Result<(), errors::Error> + Sync,
+) -> Result<(CurrentState, Vec<InputSummary>), errors::Error> {
+    // Validate the policies once, before any file is opened.
+    let mut merged = builder.clone().build()?;
+    // Each state would append to the same archive independently.
+    if builder.archives() && paths.len() > 1 {
+        return Err(ConfigError::ArchiveWithMultipleInputs.into());
+    }
+    if builder.conflict_policy() != ConflictPolicy::Fail {
+        let process = |builder: CurrentStateBuilder, path: &Path| {
+            let mut state = builder.build()?;
+            let rejects = state.process_from_csv_collected(File::open(path)?)?;
+            Ok((state, rejects))
+        };
+        return process_files_in_turn(builder, paths, process, on_reject);
+    }
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<CurrentState, errors::Error>>>> =
+        Mutex::new(paths.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..parallelism.get().min(paths.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let path = match paths.get(index) {
+                    Some(path) => path,
+                    None => break,
+                };
+                let result = process_file(builder, path, &on_reject);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    let mut summaries = Vec::with_capacity(paths.len());
+    for (path, result) in paths.iter().zip(results.into_inner().unwrap()) {
+        // Every index below `paths.len()` is claimed by some thread.
+        let state = result.unwrap()?;
+        summaries.push(InputSummary {
+            path: path.clone(),
+            summary: state.summary().clone(),
+        });
+        merged.merge(state)?;
+    }
+    Ok((merged, summaries))
+}
+
+/// Processes a single file into a fresh state.
+fn process_file(
+    builder: &CurrentStateBuilder,
+    path: &Path,
+    on_reject: &(impl Fn(&Path, &Transaction, &errors::Error) -> Result<(), errors::Error> + Sync),
+) -> Result<CurrentState, errors::Error> {
+    let mut state = builder.clone().build()?;
+    state.process_from_csv_with(File::open(path)?, |tx, err| on_reject(path, tx, err))?;
+    Ok(state)
+}
+
+/// A rejected record and why it was rejected.
+pub(crate) type Rejection = (Transaction, errors::Error);
+
+/// Processes input files one after another with `process`, each into
+/// its own state built by `builder`, for the conflict policies that
+/// keep one copy of a transaction ID shared between files.
+///
+/// Under `keep-first` a file yields to the files before it, and under
+/// `keep-last` to those after it, which are then processed first. Each
+/// file is processed with the IDs accepted from the files it yields to
+/// taken, so its own copies of them are rejected as already existing,
+/// just as they would be by a single state applying the winning file
+/// first. Nothing is counted twice once the states are merged. The
+/// rejected records of every file are held back until the last one is
+/// processed, then passed to `on_reject` in the order of `paths`.
+pub(crate) fn process_files_in_turn(
+    builder: &CurrentStateBuilder,
+    paths: &[PathBuf],
+    mut process: impl FnMut(
+        CurrentStateBuilder,
+        &Path,
+    ) -> Result<(CurrentState, Vec<Rejection>), errors::Error>,
+    on_reject: impl Fn(&Path, &Transaction, &errors::Error) -> Result<(), errors::Error>,
+) -> Result<(CurrentState, Vec<InputSummary>), errors::Error> {
+    let mut merged = builder.clone().build()?;
+    let mut order: Vec<usize> = (0..paths.len()).collect();
+    if builder.conflict_policy() == ConflictPolicy::KeepLast {
+        order.reverse();
+    }
+    let mut taken = HashMap::new();
+    let mut results: Vec<Option<(CurrentState, Vec<Rejection>)>> =
+        paths.iter().map(|_| None).collect();
+    for index in order {
+        let builder = builder.clone().taken_ids(Arc::new(taken.clone()));
+        let (state, rejects) = process(builder, &paths[index])?;
+        taken.extend(state.accepted_ids());
+        results[index] = Some((state, rejects));
+    }
+
+    let mut summaries = Vec::with_capacity(paths.len());
+    for (path, result) in paths.iter().zip(results) {
+        // Every index is processed above.
+        let (state, rejects) = result.unwrap();
+        for (tx, err) in &rejects {
+            on_reject(path, tx, err)?;
+        }
+        summaries.push(InputSummary {
+            path: path.clone(),
+            summary: state.summary().clone(),
+        });
+        merged.merge(state)?;
+    }
+    Ok((merged, summaries))
+}
@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Mutex, PoisonError};
+use std::thread;
+
+use crate::errors;
+use crate::supervise::panic_message;
+
+/// How many records are handed to a parsing thread at once.
+const BATCH_SIZE: usize = 1024;
+
+/// How many batches may be waiting to be parsed or applied per thread.
+const BATCHES_PER_THREAD: usize = 2;
+
+/// Splits the records of `rdr` into batches, parses them with `parse`
+/// on `threads` threads, and passes the results to `each` in input
+/// order, stopping at the first error.
+///
+/// Records are split on the calling thread, so the reader does not
+/// need to be `Send`, and only turning them into values is spread
+/// over threads. The error a sequential run would stop at is the one
+/// returned, and `each` sees nothing after it. A panic while parsing
+/// is returned as `Error::ParserFailed` once the batches before it are
+/// applied.
+pub(crate) fn parse_parallel<R, T, E>(
+    rdr: &mut csv::Reader<R>,
+    threads: NonZeroUsize,
+    parse: impl Fn(&csv::StringRecord) -> Result<T, E> + Sync,
+    read_error: impl Fn(csv::Error) -> E,
+    mut each: impl FnMut(T) -> Result<(), E>,
+) -> Result<(), E>
+where
+    R: std::io::Read,
+    T: Send,
+    E: Send + From<errors::Error>,
+{
+    let max_in_flight = threads.get() * BATCHES_PER_THREAD;
+    let (work_tx, work_rx) = mpsc::channel::<(usize, Vec<csv::StringRecord>)>();
+    let (done_tx, done_rx) = mpsc::channel();
+    let work_rx = Mutex::new(work_rx);
+    thread::scope(|scope| {
+        // Dropped on return, which stops the threads once the
+        // batches already sent are parsed.
+        let work_tx = work_tx;
+        for _ in 0..threads.get() {
+            let done_tx = done_tx.clone();
+            let (work_rx, parse) = (&work_rx, &parse);
+            scope.spawn(move || loop {
+                // The lock is released before parsing starts, so no
+                // thread panics while holding it.
+                let batch = work_rx
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .recv();
+                let (index, records) = match batch {
+                    Ok(batch) => batch,
+                    Err(_) => break,
+                };
+                let parsed = panic::catch_unwind(AssertUnwindSafe(|| {
+                    records.iter().map(parse).collect::<Vec<Result<T, E>>>()
+                }))
+                .map_err(|payload| panic_message(&*payload).to_owned());
+                if done_tx.send((index, parsed)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(done_tx);
+
+        // Batches parsed out of order wait here until their turn.
+        let mut ready = BTreeMap::new();
+        let (mut sent, mut applied) = (0, 0);
+        let mut failed = None;
+        let mut end = false;
+        while !end {
+            let mut records = Vec::with_capacity(BATCH_SIZE);
+            let mut record = csv::StringRecord::new();
+            while !end && records.len() < BATCH_SIZE {
+                match rdr.read_record(&mut record) {
+                    Ok(true) => records.push(record.clone()),
+                    Ok(false) => end = true,
+                    // The records before it are still applied first.
+                    Err(err) => {
+                        failed = Some(err);
+                        end = true;
+                    }
+                }
+            }
+            if !records.is_empty() {
+                work_tx.send((sent, records)).map_err(|_| stopped())?;
+                sent += 1;
+            }
+            // Keep memory bounded by waiting for the oldest batches,
+            // and wait for all of them at the end.
+            while sent - applied >= max_in_flight || (end && applied < sent) {
+                let (index, parsed) = done_rx.recv().map_err(|_| stopped())?;
+                ready.insert(index, parsed);
+                while let Some(parsed) = ready.remove(&applied) {
+                    applied += 1;
+                    let parsed = parsed.map_err(errors::Error::ParserFailed)?;
+                    parsed.into_iter().try_for_each(|value| each(value?))?;
+                }
+            }
+        }
+        match failed {
+            Some(err) => Err(read_error(err)),
+            None => Ok(()),
+        }
+    })
+}
+
+/// The error for parsing threads that went away with batches unparsed.
+fn stopped<E: From<errors::Error>>() -> E {
+    errors::Error::ParserFailed("the parsing threads stopped".to_owned()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reader over `rows` numbered records, after a header.
+    fn reader(rows: usize) -> csv::Reader<std::io::Cursor<String>> {
+        let mut input = "n\n".to_owned();
+        (0..rows).for_each(|n| input.push_str(&format!("{}\n", n)));
+        csv::Reader::from_reader(std::io::Cursor::new(input))
+    }
+
+    fn parse(record: &csv::StringRecord) -> Result<usize, errors::Error> {
+        match record[0].parse::<usize>().unwrap() {
+            3000 => panic!("record 3000"),
+            n => Ok(n),
+        }
+    }
+
+    #[test]
+    fn keeps_input_order() {
+        let threads = NonZeroUsize::new(4).unwrap();
+        let mut seen = Vec::new();
+        let each = |n| {
+            seen.push(n);
+            Ok(())
+        };
+        parse_parallel(&mut reader(2500), threads, parse, errors::Error::from, each).unwrap();
+        assert_eq!(seen, (0..2500).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn returns_panics_as_errors() {
+        let threads = NonZeroUsize::new(4).unwrap();
+        let mut applied = 0;
+        let each = |_| {
+            applied += 1;
+            Ok(())
+        };
+        let result = parse_parallel(&mut reader(5000), threads, parse, errors::Error::from, each);
+        match result {
+            Err(errors::Error::ParserFailed(message)) => assert_eq!(message, "record 3000"),
+            other => panic!("expected a parser failure, got {:?}", other),
+        }
+        // The batches before the one that panicked are still applied.
+        assert_eq!(applied, 2 * BATCH_SIZE);
+    }
+}
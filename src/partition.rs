@@ -0,0 +1,181 @@
+use std::fs;
+use std::io::Write;
+use std::num::NonZeroU16;
+use std::path::Path;
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::errors;
+use crate::manifest::{DigestingWriter, InputDigest, Provenance};
+use crate::output::AtomicFile;
+use crate::state::{CsvClient, CurrentState};
+
+/// The name of the index written alongside the partitions.
+pub const INDEX_FILE: &str = "index.json";
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+/// How clients are assigned to partitions.
+pub enum Partitioning {
+    /// By a hash of the client ID, spreading clients evenly whatever
+    /// IDs are in use.
+    #[default]
+    Hash,
+    /// By contiguous ranges of client IDs, so downstream loaders can
+    /// prune partitions by ID.
+    Range,
+}
+
+impl Partitioning {
+    /// The partition `client` belongs to, out of `partitions`. The
+    /// assignment only depends on its arguments, so it is the same
+    /// from run to run and from version to version.
+    pub fn partition(self, client: u16, partitions: NonZeroU16) -> u16 {
+        let partitions = u32::from(partitions.get());
+        let partition = match self {
+            // Fibonacci hashing, keeping the high bits, which are the
+            // best mixed.
+            Partitioning::Hash => {
+                let hash = u32::from(client).wrapping_mul(0x9E37_79B1) >> 16;
+                (hash * partitions) >> 16
+            }
+            Partitioning::Range => (u32::from(client) * partitions) >> 16,
+        };
+        // Both are below `partitions`, which fits in a `u16`.
+        partition as u16
+    }
+
+    /// The lowest and highest client IDs `partition` may hold, for
+    /// range partitioning.
+    fn range(self, partition: u16, partitions: NonZeroU16) -> Option<(u16, u16)> {
+        if self != Partitioning::Range {
+            return None;
+        }
+        let partitions = u32::from(partitions.get());
+        let start = |partition: u32| (partition << 16).div_ceil(partitions);
+        let first = start(u32::from(partition));
+        let last = start(u32::from(partition) + 1) - 1;
+        Some((first as u16, last as u16))
+    }
+}
+
+impl FromStr for Partitioning {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hash" => Ok(Partitioning::Hash),
+            "range" => Ok(Partitioning::Range),
+            _ => Err(format!("expected `hash` or `range`, got `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+/// One partition file, as described by the index.
+pub struct PartitionEntry {
+    pub partition: u16,
+    /// The file name, relative to the index.
+    pub file: String,
+    pub rows: u64,
+    /// The sum of the `total` column, kept as a string so JSON readers
+    /// cannot lose precision.
+    #[serde(with = "rust_decimal::serde::str")]
+    pub total: Decimal,
+    /// The lowest and highest client IDs the partition may hold, for
+    /// range partitioning.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clients: Option<(u16, u16)>,
+    /// The SHA-256 digest of the file, in hexadecimal.
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+/// Describes a set of partition files, so loaders can find them all
+/// and check each is complete.
+pub struct PartitionIndex {
+    pub engine_version: String,
+    pub partitioning: Partitioning,
+    pub partitions: Vec<PartitionEntry>,
+    /// The digests of the inputs the partitions were produced from.
+    /// Missing from indexes written by older versions.
+    #[serde(default)]
+    pub inputs: Vec<InputDigest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+/// Writes the clients in the output of `state` into `partitions` CSV
+/// files in `dir`, which is created if needed, assigned by
+/// `partitioning`, with the index last. The index records `inputs`
+/// and the `provenance` of the run. Each file has the same columns
+/// as the output, with clients in ID order, and empty partitions are
+/// still written, so every file the index names exists.
+///
+/// Every file only appears once it is completely written, and the
+/// index only once every partition is, so loaders can wait for it.
+pub fn write_partitions(
+    state: &CurrentState,
+    dir: &Path,
+    partitioning: Partitioning,
+    partitions: NonZeroU16,
+    inputs: Vec<InputDigest>,
+    provenance: Provenance,
+) -> Result<PartitionIndex, errors::Error> {
+    let mut clients: Vec<Vec<CsvClient>> = vec![Vec::new(); usize::from(partitions.get())];
+    for client in state.active_clients() {
+        let partition = partitioning.partition(client.client, partitions);
+        clients[usize::from(partition)].push(client);
+    }
+    fs::create_dir_all(dir)?;
+    let mut entries = Vec::with_capacity(clients.len());
+    for (partition, mut clients) in (0..partitions.get()).zip(clients) {
+        clients.sort_unstable_by_key(|client| client.client);
+        let file = format!("part-{:05}.csv", partition);
+        let mut output = AtomicFile::create(dir.join(&file))?;
+        let mut writer = DigestingWriter::new(&mut output);
+        write_csv(&mut writer, &state.output_headers(), &clients)?;
+        let sha256 = writer.digest();
+        output.commit()?;
+        entries.push(PartitionEntry {
+            partition,
+            file,
+            rows: clients.len() as u64,
+            total: clients.iter().map(|client| client.total).sum(),
+            clients: partitioning.range(partition, partitions),
+            sha256,
+        });
+    }
+    let index = PartitionIndex {
+        engine_version: env!("CARGO_PKG_VERSION").to_string(),
+        partitioning,
+        partitions: entries,
+        inputs,
+        provenance: Some(provenance),
+    };
+    let mut output = AtomicFile::create(dir.join(INDEX_FILE))?;
+    serde_json::to_writer_pretty(&mut output, &index)?;
+    output.commit()?;
+    Ok(index)
+}
+
+/// Writes clients as CSV, with headers even if there are none.
+fn write_csv(
+    writer: impl Write,
+    headers: &[&str],
+    clients: &[CsvClient],
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    if clients.is_empty() {
+        wtr.write_record(headers)?;
+    }
+    clients
+        .iter()
+        .try_for_each(|client| wtr.serialize(client))?;
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use std::fmt::Debug;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often observers are told of progress.
+const REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How many records are read between checks of the clock.
+const CHECK_EVERY: u64 = 1024;
+
+/// How many bytes must be read before the record count is estimated,
+/// so the first, possibly unrepresentative, records do not skew it.
+const SAMPLE_BYTES: u64 = 64 * 1024;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// How far processing has come.
+pub struct Progress {
+    /// The records read so far.
+    pub records: u64,
+    /// The bytes read so far.
+    pub bytes: u64,
+    /// The size of the input, if known.
+    pub total_bytes: Option<u64>,
+    /// The number of records in the input, estimated from the average
+    /// size of the records read so far, once enough have been read.
+    pub estimated_records: Option<u64>,
+    pub elapsed: Duration,
+    /// The time left at the rate so far, once the total is estimated.
+    pub eta: Option<Duration>,
+    /// Whether processing has finished.
+    pub done: bool,
+}
+
+/// Told of progress while records are read, so embedders can surface
+/// it in their own interfaces.
+///
+/// Observers are called from the threads reading records, at most
+/// about twice a second, and once more when processing finishes.
+pub trait ProgressObserver: Debug + Send + Sync {
+    fn progress(&self, progress: &Progress);
+}
+
+#[derive(Debug)]
+/// Counts the records and bytes read from one or more inputs and
+/// reports them to an observer. States built from clones of the same
+/// builder share the tracker, so inputs read in parallel add up.
+pub struct ProgressTracker {
+    observer: Arc<dyn ProgressObserver>,
+    total_bytes: Option<u64>,
+    started: Instant,
+    records: AtomicU64,
+    bytes: AtomicU64,
+    last_report: Mutex<Instant>,
+    done: AtomicBool,
+}
+
+impl ProgressTracker {
+    /// Reports to `observer` on inputs adding up to `total_bytes`, if
+    /// known, without which no estimate or ETA is made.
+    pub fn new(observer: Arc<dyn ProgressObserver>, total_bytes: Option<u64>) -> Self {
+        let started = Instant::now();
+        ProgressTracker {
+            observer,
+            total_bytes,
+            started,
+            records: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            last_report: Mutex::new(started),
+            done: AtomicBool::new(false),
+        }
+    }
+
+    /// The progress so far.
+    pub fn progress(&self) -> Progress {
+        let records = self.records.load(Ordering::Relaxed);
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let elapsed = self.started.elapsed();
+        let done = self.done.load(Ordering::Relaxed);
+        let estimated_records = self
+            .total_bytes
+            .filter(|_| bytes >= SAMPLE_BYTES && records > 0)
+            .map(|total| {
+                let estimate = records as f64 * total as f64 / bytes as f64;
+                // Records still buffered by the reader are not yet
+                // counted, so the estimate can fall short.
+                (estimate.round() as u64).max(records)
+            });
+        let eta = estimated_records.map(|estimate| match done {
+            true => Duration::ZERO,
+            false => elapsed.mul_f64((estimate - records) as f64 / records as f64),
+        });
+        Progress {
+            records,
+            bytes,
+            total_bytes: self.total_bytes,
+            estimated_records: estimated_records.map(|estimate| match done {
+                true => records,
+                false => estimate,
+            }),
+            elapsed,
+            eta,
+            done,
+        }
+    }
+
+    /// Marks processing as finished, and tells the observer.
+    pub fn finish(&self) {
+        self.done.store(true, Ordering::Relaxed);
+        self.observer.progress(&self.progress());
+    }
+
+    /// Counts one record read, reporting if it is time to.
+    pub(crate) fn record(&self) {
+        let records = self.records.fetch_add(1, Ordering::Relaxed) + 1;
+        if !records.is_multiple_of(CHECK_EVERY) {
+            return;
+        }
+        // Another thread is already reporting.
+        let mut last_report = match self.last_report.try_lock() {
+            Ok(last_report) => last_report,
+            Err(_) => return,
+        };
+        if last_report.elapsed() >= REPORT_INTERVAL {
+            *last_report = Instant::now();
+            self.observer.progress(&self.progress());
+        }
+    }
+}
+
+/// Counts the bytes read through it toward a tracker, if any.
+pub(crate) struct TrackedReader<R> {
+    inner: R,
+    tracker: Option<Arc<ProgressTracker>>,
+}
+
+impl<R> TrackedReader<R> {
+    pub(crate) fn new(inner: R, tracker: Option<Arc<ProgressTracker>>) -> Self {
+        TrackedReader { inner, tracker }
+    }
+}
+
+impl<R: Read> Read for TrackedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if let Some(tracker) = &self.tracker {
+            tracker.bytes.fetch_add(read as u64, Ordering::Relaxed);
+        }
+        Ok(read)
+    }
+}
@@ -0,0 +1,82 @@
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::transaction::Transaction;
+
+/// A check that holds deposits and withdrawals for human review
+/// instead of processing them, such as a fraud scoring plugin.
+pub trait QuarantineRule: std::fmt::Debug + Send + Sync {
+    /// Why `tx` should be held for review, or `None` to process it as usual.
+    fn check(&self, tx: &Transaction) -> Option<String>;
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// At most `count` deposits and withdrawals per client within
+/// `window` seconds; any more are quarantined.
+pub struct VelocityLimit {
+    pub count: u32,
+    pub window: u64,
+}
+
+impl FromStr for VelocityLimit {
+    type Err = String;
+
+    /// Parses `count/window`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (count, window) = s
+            .split_once('/')
+            .ok_or_else(|| format!("expected `count/seconds`, found `{}`", s))?;
+        Ok(VelocityLimit {
+            count: count
+                .parse()
+                .map_err(|_| format!("invalid transaction count `{}`", count))?,
+            window: window
+                .parse()
+                .map_err(|_| format!("invalid velocity window `{}`", window))?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A transaction held for review, with why it was held.
+pub struct Quarantined {
+    pub tx: Transaction,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+/// A quarantined transaction in the input format, followed by
+/// why it was held. Used for serialization.
+struct CsvQuarantined<'a> {
+    #[serde(rename = "type")]
+    r#type: &'a str,
+    client: u16,
+    tx: u32,
+    amount: Option<Decimal>,
+    timestamp: Option<u64>,
+    reason: &'a str,
+}
+
+/// Writes quarantined transactions as CSV.
+pub fn write_csv<'a>(
+    writer: impl std::io::Write,
+    entries: impl IntoIterator<Item = &'a Quarantined>,
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    entries.into_iter().try_for_each(|entry| {
+        wtr.serialize(CsvQuarantined {
+            r#type: entry.tx.type_name(),
+            client: entry.tx.client,
+            tx: entry.tx.id,
+            amount: entry.tx.amount(),
+            timestamp: entry.tx.timestamp,
+            reason: &entry.reason,
+        })
+    })?;
+    wtr.flush()?;
+    Ok(())
+}
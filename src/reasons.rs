@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::errors::{ConfigError, Error};
+
+#[derive(Debug, Deserialize)]
+/// A row of a reason code list. Used for deserialization.
+struct CsvReasonCode {
+    code: String,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+/// The reason codes disputes, resolves and chargebacks may give, in the
+/// manner of a card network's list, such as Visa's `10.4` for card
+/// absent fraud. Records giving a code not on the list are rejected as
+/// `unknown_reason_code`.
+pub struct ReasonCodes {
+    codes: BTreeMap<String, Option<String>>,
+}
+
+impl ReasonCodes {
+    /// Reads a CSV file with a `code` column and an optional
+    /// `description` column. Each code may only appear once.
+    pub fn from_csv(reader: impl std::io::Read) -> Result<Self, Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut codes = ReasonCodes::default();
+        for row in rdr.deserialize() {
+            let row: CsvReasonCode = row?;
+            if codes.codes.contains_key(&row.code) {
+                return Err(ConfigError::DuplicateReasonCode(row.code).into());
+            }
+            codes.insert(row.code, row.description);
+        }
+        Ok(codes)
+    }
+
+    /// Adds `code` to the list, with what it means.
+    pub fn insert(&mut self, code: impl Into<String>, description: Option<String>) {
+        self.codes.insert(code.into(), description);
+    }
+
+    /// Whether `code` is on the list.
+    pub fn contains(&self, code: &str) -> bool {
+        self.codes.contains_key(code)
+    }
+
+    /// What `code` means, if the list says.
+    pub fn description(&self, code: &str) -> Option<&str> {
+        self.codes.get(code)?.as_deref()
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::diff::{self, Balances, ClientDiff};
+use crate::errors;
+use crate::state::{CsvClient, CurrentState};
+use crate::transaction::Transaction;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// The record after which a client stopped matching its expected state
+/// for the last time.
+pub struct Divergence {
+    /// The number of the record in the input, starting from one.
+    pub record: u64,
+    /// The ID of the record.
+    pub tx: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// A client whose computed state differs from the expected one.
+pub struct Discrepancy {
+    pub diff: ClientDiff,
+    /// Where the client diverged, if any of its records did.
+    pub divergence: Option<Divergence>,
+}
+
+/// Whether a computed client state matches the expected one. Risk
+/// scores are not part of a client's balances, so they are ignored.
+fn matches(expected: Option<CsvClient>, actual: Option<CsvClient>) -> bool {
+    let balances = |client: Option<CsvClient>| {
+        client.map(|client| CsvClient {
+            risk_score: None,
+            ..client
+        })
+    };
+    balances(expected) == balances(actual)
+}
+
+/// Processes a CSV stream into `state` and compares the result against
+/// `expected`, listing the clients that differ in order of client ID.
+///
+/// Every client starts out matching, as the expected state was built
+/// from nothing too. After each record, its client is compared against
+/// its expected state; the divergence reported for a client is the last
+/// record that took it from matching to not matching. Clients changed
+/// only as a side effect, such as the suspense account, have no
+/// divergence.
+pub fn reconcile(
+    state: &mut CurrentState,
+    reader: impl std::io::Read,
+    expected: &Balances,
+    on_reject: impl FnMut(&Transaction, &errors::Error) -> Result<(), errors::Error>,
+) -> Result<Vec<Discrepancy>, errors::Error> {
+    let mut records = 0;
+    let mut diverged: HashMap<u16, Option<Divergence>> = HashMap::new();
+    state.process_from_csv_observed(reader, on_reject, |state, tx| {
+        records += 1;
+        let client = match &tx.account {
+            Some(account) => match state.counterparties().client(account) {
+                Some(client) => client,
+                None => return,
+            },
+            None => tx.client,
+        };
+        let now = matches(expected.get(&client).copied(), state.client(client));
+        let divergence = diverged.entry(client).or_default();
+        match (divergence.is_some(), now) {
+            (_, true) => *divergence = None,
+            (false, false) => {
+                *divergence = Some(Divergence {
+                    record: records,
+                    tx: tx.id,
+                })
+            }
+            (true, false) => {}
+        }
+    })?;
+    let actual: Balances = state
+        .clients()
+        .map(|client| (client.client, client))
+        .collect();
+    Ok(diff::diff_balances(expected, &actual)
+        .into_iter()
+        .filter(|diff| !matches(diff.left, diff.right))
+        .map(|diff| Discrepancy {
+            diff,
+            divergence: diverged.get(&diff.client).copied().flatten(),
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+/// A `Discrepancy`, used for serialization.
+struct CsvDiscrepancy {
+    client: u16,
+    expected_available: Option<Decimal>,
+    expected_held: Option<Decimal>,
+    expected_total: Option<Decimal>,
+    expected_locked: Option<bool>,
+    actual_available: Option<Decimal>,
+    actual_held: Option<Decimal>,
+    actual_total: Option<Decimal>,
+    actual_locked: Option<bool>,
+    diverged_at_record: Option<u64>,
+    diverged_at_tx: Option<u32>,
+}
+
+/// Writes discrepancies as CSV, one row per client.
+pub fn write_discrepancies(
+    writer: impl std::io::Write,
+    discrepancies: &[Discrepancy],
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    discrepancies.iter().try_for_each(|discrepancy| {
+        let ClientDiff {
+            client,
+            left: expected,
+            right: actual,
+        } = discrepancy.diff;
+        wtr.serialize(CsvDiscrepancy {
+            client,
+            expected_available: expected.map(|s| s.available),
+            expected_held: expected.map(|s| s.held),
+            expected_total: expected.map(|s| s.total),
+            expected_locked: expected.map(|s| s.locked),
+            actual_available: actual.map(|s| s.available),
+            actual_held: actual.map(|s| s.held),
+            actual_total: actual.map(|s| s.total),
+            actual_locked: actual.map(|s| s.locked),
+            diverged_at_record: discrepancy.divergence.map(|d| d.record),
+            diverged_at_tx: discrepancy.divergence.map(|d| d.tx),
+        })
+    })?;
+    wtr.flush()?;
+    Ok(())
+}
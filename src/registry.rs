@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::errors::{self, ConfigError};
+use crate::state::StateView;
+use crate::transaction::{Transaction, TransactionType};
+
+/// Applies records of a custom transaction type, such as bonuses or
+/// promotions, that the engine does not know about.
+pub trait TransactionHandler: Debug + Send + Sync {
+    /// Applies `tx` through `state`. If this returns an error, the
+    /// record is rejected with it and no change made through `state`
+    /// is applied.
+    fn apply(&self, tx: &Transaction, state: &mut StateView<'_>) -> Result<(), errors::Error>;
+}
+
+#[derive(Debug, Default, Clone)]
+/// The handlers for custom transaction types, by the name written in
+/// the `type` column.
+pub struct TransactionRegistry {
+    handlers: HashMap<String, Arc<dyn TransactionHandler>>,
+}
+
+impl TransactionRegistry {
+    /// Registers `handler` for records whose type is `tag`. Built-in
+    /// types cannot be replaced, and each tag has one handler.
+    pub fn register(
+        &mut self,
+        tag: impl Into<String>,
+        handler: Arc<dyn TransactionHandler>,
+    ) -> Result<(), ConfigError> {
+        let tag = tag.into();
+        if tag.parse::<TransactionType>().is_ok() {
+            return Err(ConfigError::BuiltInTransactionType(tag));
+        }
+        if self.handlers.contains_key(&tag) {
+            return Err(ConfigError::DuplicateTransactionType(tag));
+        }
+        self.handlers.insert(tag, handler);
+        Ok(())
+    }
+
+    /// The handler registered for `tag`, if any.
+    pub fn get(&self, tag: &str) -> Option<&Arc<dyn TransactionHandler>> {
+        self.handlers.get(tag)
+    }
+
+    /// The number of registered types.
+    pub fn len(&self) -> usize {
+        self.handlers.len()
+    }
+
+    /// Whether no types are registered.
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+}
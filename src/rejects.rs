@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::errors::{self, RejectionCode};
+use crate::state::CurrentState;
+use crate::transaction::{DisputeInitiator, Transaction};
+
+#[derive(Debug, Serialize)]
+/// A rejected record in the input format, followed by
+/// why it was rejected. Used for serialization.
+struct CsvRejection<'a> {
+    #[serde(rename = "type")]
+    r#type: &'a str,
+    client: Option<u16>,
+    tx: u32,
+    amount: Option<Decimal>,
+    timestamp: Option<u64>,
+    reference: Option<&'a str>,
+    initiator: Option<DisputeInitiator>,
+    account: Option<&'a str>,
+    external_ref: Option<&'a str>,
+    reason_code: Option<&'a str>,
+    currency: Option<&'a str>,
+    category: Option<&'a str>,
+    merchant: Option<&'a str>,
+    seq: Option<u64>,
+    code: u16,
+    reason: RejectionCode,
+    message: String,
+}
+
+/// Writes rejected records to a CSV sidecar file.
+pub struct RejectsWriter<W: std::io::Write> {
+    wtr: csv::Writer<W>,
+}
+
+impl<W: std::io::Write> RejectsWriter<W> {
+    /// Creates a writer for the rejects sidecar.
+    pub fn new(writer: W) -> Self {
+        RejectsWriter {
+            wtr: csv::WriterBuilder::new()
+                .has_headers(true)
+                .from_writer(writer),
+        }
+    }
+
+    /// Records `tx` as rejected with `err`. Errors that are not caused
+    /// by the record itself have no rejection code and are skipped.
+    pub fn write(&mut self, tx: &Transaction, err: &errors::Error) -> Result<(), csv::Error> {
+        let reason = match err.rejection_code() {
+            Some(reason) => reason,
+            None => return Ok(()),
+        };
+        self.wtr.serialize(CsvRejection {
+            r#type: tx.type_name(),
+            // Records naming an account have no client until resolved.
+            client: tx.account.is_none().then_some(tx.client),
+            tx: tx.id,
+            amount: tx.amount(),
+            timestamp: tx.timestamp,
+            reference: tx.reference.as_deref(),
+            initiator: tx.initiator,
+            account: tx.account.as_deref(),
+            external_ref: tx.external_ref.as_deref(),
+            reason_code: tx.reason_code.as_deref(),
+            currency: tx.currency.as_deref(),
+            category: tx.category.as_deref(),
+            merchant: tx.merchant.as_deref(),
+            seq: tx.seq,
+            code: reason.code(),
+            reason,
+            message: err.to_string(),
+        })
+    }
+
+    /// Flushes any buffered rejections.
+    pub fn flush(&mut self) -> Result<(), csv::Error> {
+        self.wtr.flush()?;
+        Ok(())
+    }
+
+    /// Flushes any buffered rejections and returns the underlying writer.
+    pub fn into_inner(self) -> Result<W, csv::Error> {
+        self.wtr
+            .into_inner()
+            .map_err(|err| std::io::Error::from(err.error().kind()).into())
+    }
+}
+
+/// The key a record is matched by: the record as it is written in the
+/// input, which is how the rejects sidecar writes it.
+fn record_key(tx: &Transaction) -> String {
+    serde_json::to_string(&tx.to_input()).expect("records serialize to JSON")
+}
+
+#[derive(Debug)]
+/// Records rejected by an earlier run, read back from its rejects
+/// sidecar, to be attempted again once the state they were rejected
+/// from has been rebuilt, such as after a limit was raised.
+///
+/// While the state is rebuilt, records read from input that match a
+/// rejected one in every field are held back, each as many times as it
+/// was rejected, so a policy that now admits it does not apply it
+/// early, and the rejected records are then retried in order.
+pub struct Retries {
+    records: Vec<Transaction>,
+    /// How many more times each record may still be held back.
+    held_back: Mutex<HashMap<String, usize>>,
+}
+
+impl Retries {
+    /// Reads a rejects sidecar, or the output of a retry, whose
+    /// columns other than those of the input format are ignored.
+    pub fn from_csv(reader: impl std::io::Read) -> Result<Self, errors::Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut records = Vec::new();
+        let mut held_back = HashMap::new();
+        for record in rdr.deserialize() {
+            let tx: Transaction = record?;
+            *held_back.entry(record_key(&tx)).or_default() += 1;
+            records.push(tx);
+        }
+        Ok(Retries {
+            records,
+            held_back: Mutex::new(held_back),
+        })
+    }
+
+    /// The records to retry, in the order they were rejected.
+    pub fn records(&self) -> &[Transaction] {
+        &self.records
+    }
+
+    /// Whether `tx`, read from input, is held back for a retry.
+    pub(crate) fn holds_back(&self, tx: &Transaction) -> bool {
+        let mut held_back = self.held_back.lock().unwrap();
+        match held_back.get_mut(&record_key(tx)) {
+            Some(remaining) if *remaining > 0 => {
+                *remaining -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Attempts every record again on `state`, in order, and writes
+    /// what became of each to `out`.
+    pub fn retry<W: std::io::Write>(
+        &self,
+        state: &mut CurrentState,
+        out: &mut RetryWriter<W>,
+    ) -> Result<RetryCounts, errors::Error> {
+        let mut counts = RetryCounts::default();
+        for tx in &self.records {
+            match state.add(tx) {
+                Err(err @ errors::Error::Invariant(_)) => return Err(err),
+                Err(err) => {
+                    counts.rejected += 1;
+                    out.write(tx, "rejected", Some(&err))?;
+                }
+                Ok(()) if state.is_quarantined(tx.id) => {
+                    counts.quarantined += 1;
+                    out.write(tx, "quarantined", None)?;
+                }
+                Ok(()) => {
+                    counts.accepted += 1;
+                    out.write(tx, "accepted", None)?;
+                }
+            }
+        }
+        Ok(counts)
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// How many retried records each outcome had.
+pub struct RetryCounts {
+    pub accepted: u64,
+    /// Accepted, but held for review.
+    pub quarantined: u64,
+    /// Rejected again.
+    pub rejected: u64,
+}
+
+#[derive(Debug, Serialize)]
+/// A retried record in the input format, followed by what became of
+/// it. Used for serialization.
+struct CsvRetry<'a> {
+    #[serde(rename = "type")]
+    r#type: &'a str,
+    client: Option<u16>,
+    tx: u32,
+    amount: Option<Decimal>,
+    timestamp: Option<u64>,
+    reference: Option<&'a str>,
+    initiator: Option<DisputeInitiator>,
+    account: Option<&'a str>,
+    external_ref: Option<&'a str>,
+    reason_code: Option<&'a str>,
+    currency: Option<&'a str>,
+    category: Option<&'a str>,
+    merchant: Option<&'a str>,
+    seq: Option<u64>,
+    outcome: &'a str,
+    code: Option<u16>,
+    reason: Option<RejectionCode>,
+    message: Option<String>,
+}
+
+/// Writes retried records, annotated with their outcomes, to CSV. The
+/// output reads back as rejects, so it can be retried in turn, such as
+/// after another limit change.
+pub struct RetryWriter<W: std::io::Write> {
+    wtr: csv::Writer<W>,
+}
+
+impl<W: std::io::Write> RetryWriter<W> {
+    /// Creates a writer for retried records.
+    pub fn new(writer: W) -> Self {
+        RetryWriter {
+            wtr: csv::WriterBuilder::new()
+                .has_headers(true)
+                .from_writer(writer),
+        }
+    }
+
+    /// Records `tx` as retried with `outcome`, and `err` if it was
+    /// rejected again.
+    fn write(
+        &mut self,
+        tx: &Transaction,
+        outcome: &str,
+        err: Option<&errors::Error>,
+    ) -> Result<(), csv::Error> {
+        let reason = err.and_then(errors::Error::rejection_code);
+        self.wtr.serialize(CsvRetry {
+            r#type: tx.type_name(),
+            client: tx.account.is_none().then_some(tx.client),
+            tx: tx.id,
+            amount: tx.amount(),
+            timestamp: tx.timestamp,
+            reference: tx.reference.as_deref(),
+            initiator: tx.initiator,
+            account: tx.account.as_deref(),
+            external_ref: tx.external_ref.as_deref(),
+            reason_code: tx.reason_code.as_deref(),
+            currency: tx.currency.as_deref(),
+            category: tx.category.as_deref(),
+            merchant: tx.merchant.as_deref(),
+            seq: tx.seq,
+            outcome,
+            code: reason.map(RejectionCode::code),
+            reason,
+            message: err.map(ToString::to_string),
+        })
+    }
+
+    /// Flushes any buffered records.
+    pub fn flush(&mut self) -> Result<(), csv::Error> {
+        self.wtr.flush()?;
+        Ok(())
+    }
+}
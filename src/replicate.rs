@@ -0,0 +1,146 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::errors;
+use crate::session::Session;
+use crate::transaction::Transaction;
+
+/// How long a follower may take to accept a record before it is
+/// dropped, so a stalled follower cannot stall the leader.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+/// Every record replicated so far, and the followers receiving them.
+struct ReplicationLog {
+    /// The records, as JSON lines, for followers that join late.
+    records: Vec<String>,
+    followers: Vec<(SocketAddr, TcpStream)>,
+}
+
+impl ReplicationLog {
+    /// Sends the records so far to a new follower, and then keeps it
+    /// up to date.
+    fn attach(&mut self, stream: TcpStream) -> std::io::Result<()> {
+        let addr = stream.peer_addr()?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut writer = std::io::BufWriter::new(&stream);
+        for record in &self.records {
+            writer.write_all(record.as_bytes())?;
+        }
+        writer.flush()?;
+        drop(writer);
+        self.followers.push((addr, stream));
+        Ok(())
+    }
+
+    /// Sends one record to every follower, dropping those that fail.
+    fn append(&mut self, record: String) {
+        self.followers
+            .retain_mut(|(addr, stream)| match stream.write_all(record.as_bytes()) {
+                Ok(()) => true,
+                Err(err) => {
+                    eprintln!("Warning: dropping follower {}: {}", addr, err);
+                    false
+                }
+            });
+        self.records.push(record);
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Streams every record a session processes to followers over TCP, so
+/// they hold the same state and one can take over as a warm standby.
+///
+/// Followers may connect at any time, and first receive every record
+/// since the leader started, so the leader keeps them all in memory.
+/// Replication is asynchronous: a record is acknowledged without
+/// waiting for followers, so the last few records before a failure may
+/// not have reached them.
+pub struct Leader {
+    log: Arc<Mutex<ReplicationLog>>,
+    listener: Arc<TcpListener>,
+    addr: SocketAddr,
+}
+
+impl Leader {
+    /// Listens for followers on `addr`, accepting them on a thread of
+    /// its own.
+    pub fn listen(addr: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let leader = Leader::bind(addr)?;
+        let accepting = leader.clone();
+        thread::spawn(move || {
+            if let Err(err) = accepting.accept() {
+                eprintln!("Warning: stopped accepting followers: {}", err);
+            }
+        });
+        Ok(leader)
+    }
+
+    /// Binds `addr` for followers, without accepting them until
+    /// `accept` is called, such as by a `Supervisor`.
+    pub fn bind(addr: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        Ok(Leader {
+            log: Arc::new(Mutex::new(ReplicationLog::default())),
+            listener: Arc::new(listener),
+            addr,
+        })
+    }
+
+    /// Accepts followers until the listener fails. Followers that
+    /// cannot be attached are dropped with a warning.
+    pub fn accept(&self) -> Result<(), errors::Error> {
+        loop {
+            let (stream, _) = self.listener.accept()?;
+            // Attaching under the lock keeps the follower from missing
+            // records applied in the meantime.
+            if let Err(err) = self.log.lock().unwrap().attach(stream) {
+                eprintln!("Warning: could not attach follower: {}", err);
+            }
+        }
+    }
+
+    /// The address followers connect to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Sends `tx` to the followers, as it is about to be processed.
+    pub(crate) fn replicate(&self, tx: &Transaction) {
+        // Records are plain data, which always serializes.
+        let mut record = serde_json::to_string(&tx.to_input()).expect("records serialize to JSON");
+        record.push('\n');
+        self.log.lock().unwrap().append(record);
+    }
+}
+
+/// Follows the leader at `addr`, processing every record it streams in
+/// `session`, which should be built with the same policies, until the
+/// stream ends. Returns the number of records processed; the session
+/// may then be promoted by serving it.
+///
+/// A follower cannot tell a failed leader from a broken connection, so
+/// whatever fails clients over to it should make sure the old leader is
+/// really gone.
+pub fn follow(session: &mut Session, addr: impl ToSocketAddrs) -> Result<u64, errors::Error> {
+    let stream = TcpStream::connect(addr)?;
+    let mut processed = 0;
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("Warning: lost the leader: {}", err);
+                break;
+            }
+        };
+        // The leader only streams records it could read.
+        let tx: Transaction = serde_json::from_str(&line)?;
+        session.submit(&tx);
+        processed += 1;
+    }
+    Ok(processed)
+}
@@ -0,0 +1,147 @@
+use std::collections::{BTreeMap, HashMap};
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::errors::{ConfigError, Error};
+
+/// The seconds in a day, as reserve periods are counted in days of
+/// record timestamps.
+const DAY: u64 = 86_400;
+
+#[derive(Debug, Deserialize)]
+/// A row of a file of reserve terms. Used for deserialization.
+struct CsvReserveTerms {
+    client: u16,
+    rate: Decimal,
+    days: u32,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// How much of each deposit of a merchant is held back, and for how
+/// long, such as 10% for 90 days.
+pub struct ReserveTerms {
+    /// The share of each deposit held back, above zero and at most one.
+    pub rate: Decimal,
+    /// The days after a deposit its share is released.
+    pub days: u32,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+/// The merchant clients whose deposits are partly held in a rolling
+/// reserve, each with its terms, so acquirers can cover chargebacks
+/// that arrive after the funds would otherwise have been paid out.
+///
+/// The share held back of each deposit is moved from the available
+/// funds to the client's reserve as it is credited, and back once the
+/// records reach the deposit's timestamp plus the reserve period.
+pub struct Reserves {
+    clients: HashMap<u16, ReserveTerms>,
+}
+
+impl Reserves {
+    /// Reads a CSV file with `client`, `rate` and `days` columns, such
+    /// as `12,0.1,90`. Each client may only appear once.
+    pub fn from_csv(reader: impl std::io::Read) -> Result<Self, Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut reserves = Reserves::default();
+        for record in rdr.deserialize() {
+            let record: CsvReserveTerms = record?;
+            let terms = ReserveTerms {
+                rate: record.rate,
+                days: record.days,
+            };
+            reserves.set_client(record.client, terms)?;
+        }
+        Ok(reserves)
+    }
+
+    /// Holds back part of the deposits of `client` on `terms`.
+    pub fn set_client(&mut self, client: u16, terms: ReserveTerms) -> Result<(), Error> {
+        if terms.rate <= Decimal::ZERO || terms.rate > Decimal::ONE {
+            return Err(ConfigError::InvalidReserveRate(terms.rate).into());
+        }
+        if self.clients.insert(client, terms).is_some() {
+            return Err(ConfigError::DuplicateReserve(client).into());
+        }
+        Ok(())
+    }
+
+    /// The terms of the reserve of `client`, if it has one.
+    pub fn terms(&self, client: u16) -> Option<ReserveTerms> {
+        self.clients.get(&client).copied()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// The share of one deposit held in reserve.
+pub(crate) struct Tranche {
+    /// The client the deposit was made by.
+    pub(crate) client: u16,
+    /// The ID of the deposit.
+    pub(crate) tx: u32,
+    pub(crate) amount: Decimal,
+    /// The timestamp of the deposit.
+    pub(crate) timestamp: u64,
+    /// The timestamp from which the amount is released.
+    pub(crate) releases: u64,
+}
+
+#[derive(Debug, Default, Clone)]
+/// The tranches of every reserve still held, ordered by when they are
+/// released.
+pub(crate) struct ReserveSchedule {
+    tranches: BTreeMap<(u64, u32), Tranche>,
+}
+
+impl ReserveSchedule {
+    /// Holds `amount` of the deposit with ID `tx` by `client`, made at
+    /// `timestamp`, for the days `terms` give.
+    pub(crate) fn hold(
+        &mut self,
+        client: u16,
+        tx: u32,
+        amount: Decimal,
+        timestamp: u64,
+        terms: ReserveTerms,
+    ) {
+        let releases = timestamp.saturating_add(u64::from(terms.days) * DAY);
+        self.push(Tranche {
+            client,
+            tx,
+            amount,
+            timestamp,
+            releases,
+        });
+    }
+
+    /// Puts `tranche` back in the schedule, such as one whose release
+    /// failed.
+    pub(crate) fn push(&mut self, tranche: Tranche) {
+        self.tranches
+            .insert((tranche.releases, tranche.tx), tranche);
+    }
+
+    /// Removes and returns the tranches released at or before `now`,
+    /// earliest first.
+    pub(crate) fn due(&mut self, now: u64) -> Vec<Tranche> {
+        let later = self.tranches.split_off(&(now.saturating_add(1), 0));
+        std::mem::replace(&mut self.tranches, later)
+            .into_values()
+            .collect()
+    }
+
+    /// The tranches still held, earliest release first.
+    pub(crate) fn pending(&self) -> impl Iterator<Item = &Tranche> {
+        self.tranches.values()
+    }
+
+    /// Absorbs the tranches held by `other`, which processed other
+    /// clients.
+    pub(crate) fn merge(&mut self, other: ReserveSchedule) {
+        self.tranches.extend(other.tranches);
+    }
+}
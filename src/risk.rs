@@ -0,0 +1,158 @@
+use serde::Serialize;
+
+#[derive(Debug, Default, Clone, Copy)]
+/// Running mean and variance of a series, by Welford's method, so
+/// samples need not be kept and partial results can be merged.
+struct RunningStats {
+    count: u64,
+    mean: f64,
+    /// The sum of squared differences from the mean.
+    m2: f64,
+}
+
+impl RunningStats {
+    fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Combines the statistics of two disjoint series.
+    fn merge(&mut self, other: RunningStats) {
+        if other.count == 0 {
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.m2 += other.m2 + delta * delta * (self.count * other.count) as f64 / count as f64;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.count = count;
+    }
+
+    fn std_dev(&self) -> f64 {
+        match self.count {
+            0 => 0.0,
+            count => (self.m2 / count as f64).sqrt(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+/// What the engine has seen a client do, for scoring risk and
+/// finding dormant clients.
+pub struct ClientActivity {
+    /// Applied deposits and withdrawals.
+    pub transactions: u32,
+    /// Disputes opened against the client's transactions.
+    pub disputes: u32,
+    /// Disputes that ended in a chargeback.
+    pub chargebacks: u32,
+    /// Transactions quarantined for exceeding the velocity limit.
+    pub velocity_flags: u32,
+    /// The client's total balance after each applied record.
+    balance: RunningStats,
+    /// The number of the last record applied to the client.
+    pub last_record: u64,
+    /// The latest timestamp of a record applied to the client.
+    pub last_timestamp: Option<u64>,
+}
+
+/// How many chargebacks or velocity flags count as the most risky.
+const SATURATION: f64 = 5.0;
+
+impl ClientActivity {
+    /// Records the client's total balance after a change.
+    pub(crate) fn sample_balance(&mut self, total: f64) {
+        self.balance.push(total);
+    }
+
+    /// Combines activity seen in separate inputs.
+    pub fn merge(&mut self, other: ClientActivity) {
+        self.transactions += other.transactions;
+        self.disputes += other.disputes;
+        self.chargebacks += other.chargebacks;
+        self.velocity_flags += other.velocity_flags;
+        self.balance.merge(other.balance);
+        self.last_record = self.last_record.max(other.last_record);
+        self.last_timestamp = self.last_timestamp.max(other.last_timestamp);
+    }
+
+    /// The share of deposits and withdrawals that were disputed,
+    /// at most one.
+    pub fn dispute_rate(&self) -> f64 {
+        (self.disputes as f64 / self.transactions.max(1) as f64).min(1.0)
+    }
+
+    /// How much the total balance varies relative to its mean, at most
+    /// one. A balance that varies around zero is as volatile as it gets.
+    pub fn volatility(&self) -> f64 {
+        let std_dev = self.balance.std_dev();
+        let mean = self.balance.mean.abs();
+        if mean == 0.0 {
+            if std_dev > 0.0 {
+                1.0
+            } else {
+                0.0
+            }
+        } else {
+            (std_dev / mean).min(1.0)
+        }
+    }
+
+    /// A first-pass risk score from 0 to 100, weighting chargebacks by
+    /// 40, the dispute rate by 25, velocity flags by 15 and balance
+    /// volatility by 20. Counts saturate at five.
+    pub fn score(&self) -> u32 {
+        let chargebacks = (self.chargebacks as f64 / SATURATION).min(1.0);
+        let velocity = (self.velocity_flags as f64 / SATURATION).min(1.0);
+        let score = 40.0 * chargebacks
+            + 25.0 * self.dispute_rate()
+            + 15.0 * velocity
+            + 20.0 * self.volatility();
+        score.round() as u32
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy)]
+/// One row of the risk report. Used for serialization.
+pub struct RiskRow {
+    pub client: u16,
+    pub score: u32,
+    pub transactions: u32,
+    pub disputes: u32,
+    pub chargebacks: u32,
+    pub velocity_flags: u32,
+    pub dispute_rate: f64,
+    pub volatility: f64,
+}
+
+impl RiskRow {
+    pub fn new(client: u16, activity: &ClientActivity) -> Self {
+        RiskRow {
+            client,
+            score: activity.score(),
+            transactions: activity.transactions,
+            disputes: activity.disputes,
+            chargebacks: activity.chargebacks,
+            velocity_flags: activity.velocity_flags,
+            dispute_rate: activity.dispute_rate(),
+            volatility: activity.volatility(),
+        }
+    }
+}
+
+/// Writes a risk report as CSV, riskiest clients first.
+pub fn write_report(
+    writer: impl std::io::Write,
+    rows: impl IntoIterator<Item = RiskRow>,
+) -> Result<(), csv::Error> {
+    let mut rows: Vec<_> = rows.into_iter().collect();
+    rows.sort_by(|a, b| b.score.cmp(&a.score).then(a.client.cmp(&b.client)));
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    rows.iter().try_for_each(|row| wtr.serialize(row))?;
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,462 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::cases::CaseUpdate;
+use crate::errors;
+use crate::freeze::Freeze;
+use crate::history::{HistoryPage, HistoryQuery};
+use crate::search::{ClientPage, ClientQuery, DEFAULT_PAGE_SIZE};
+use crate::session::Session;
+use crate::state::CsvClient;
+use crate::transaction::Transaction;
+
+/// The request could not be parsed as JSON.
+const PARSE_ERROR: i32 = -32700;
+/// The request was JSON, but not a JSON-RPC request.
+const INVALID_REQUEST: i32 = -32600;
+/// The method does not exist.
+const METHOD_NOT_FOUND: i32 = -32601;
+/// The parameters did not fit the method.
+const INVALID_PARAMS: i32 = -32602;
+/// The method failed for a reason other than its parameters.
+const INTERNAL_ERROR: i32 = -32603;
+
+#[derive(Debug, Deserialize)]
+/// A JSON-RPC 2.0 request. Requests without an `id` are
+/// notifications, and get no response.
+struct Request {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+/// A JSON-RPC 2.0 error object.
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+#[derive(Debug, Serialize)]
+/// A JSON-RPC 2.0 response, with either a result or an error.
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+/// The parameters of `query`.
+struct QueryParams {
+    client: u16,
+}
+
+#[derive(Debug, Deserialize)]
+/// The parameters of `freeze`.
+struct FreezeParams {
+    client: u16,
+    #[serde(flatten)]
+    freeze: Freeze,
+}
+
+#[derive(Debug, Deserialize)]
+/// The parameters of `merge_clients`.
+struct MergeParams {
+    source: u16,
+    destination: u16,
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Debug, Deserialize)]
+/// The parameters of `case`.
+struct CaseParams {
+    tx: u32,
+}
+
+#[derive(Debug, Deserialize)]
+/// The parameters of `update_case`.
+struct CaseUpdateParams {
+    tx: u32,
+    #[serde(flatten)]
+    update: CaseUpdate,
+}
+
+#[derive(Debug, Deserialize)]
+/// The parameters of `list_transactions`.
+struct HistoryParams {
+    client: u16,
+    #[serde(flatten)]
+    query: HistoryQuery,
+}
+
+/// Serves JSON-RPC 2.0 requests, one per line, and writes one response
+/// line per request, flushing each. Blank lines are skipped.
+///
+/// The methods are:
+/// - `submit`, whose parameters are a record as in `Session::serve_jsonl`,
+///   returning its acknowledgement;
+/// - `query`, with a `client` parameter, returning that client's state,
+///   or `null` if it does not exist;
+/// - `snapshot`, returning the state of every client, in ID order;
+/// - `list_clients`, with the filters, `sort`, `cursor` and `limit` of
+///   a `ClientQuery`, returning a page of clients and the cursor of the
+///   next page;
+/// - `list_transactions`, with a `client` parameter and the `cursor`
+///   and `limit` of a `HistoryQuery`, returning a page of the records
+///   applied to that client and the cursor of the next page, if the
+///   history is kept;
+/// - `freeze`, with a `client`, a `reason` and optionally an `expires`
+///   timestamp, freezing that client and returning its state;
+/// - `unfreeze`, with a `client` parameter, lifting its freeze and
+///   returning it, or `null` if it was not frozen;
+/// - `merge_clients`, with a `source` and a `destination` client and
+///   optionally `force`, merging the source into the destination and
+///   returning the destination's state;
+/// - `case`, with a `tx` parameter, returning the case of the dispute
+///   of that transaction, or `null` if it was never disputed;
+/// - `cases`, returning every case, in transaction ID order;
+/// - `update_case`, with a `tx` and any of a `status`, with a `note`,
+///   a `reason` and `evidence` references to add, updating the case of
+///   that transaction and returning it;
+/// - `stats`, returning the summary of the records processed so far,
+///   with the distributions of deposit and withdrawal amounts.
+pub fn serve(
+    session: &mut Session,
+    reader: impl BufRead,
+    mut writer: impl Write,
+) -> Result<(), errors::Error> {
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle(session, &line) {
+            serde_json::to_writer(&mut writer, &response)?;
+            writer.write_all(b"\n")?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Handles one request line, returning the response, if any.
+fn handle(session: &mut Session, line: &str) -> Option<Response> {
+    let error = |id, code, message: String| Response {
+        jsonrpc: "2.0",
+        id,
+        result: None,
+        error: Some(RpcError { code, message }),
+    };
+    let value: Value = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(err) => return Some(error(Value::Null, PARSE_ERROR, err.to_string())),
+    };
+    let request = match serde_json::from_value::<Request>(value) {
+        Ok(request) if request.jsonrpc == "2.0" => request,
+        Ok(_) => {
+            let message = "`jsonrpc` must be \"2.0\"".to_owned();
+            return Some(error(Value::Null, INVALID_REQUEST, message));
+        }
+        Err(err) => return Some(error(Value::Null, INVALID_REQUEST, err.to_string())),
+    };
+    let result = call(session, &request.method, request.params);
+    let id = request.id?;
+    Some(match result {
+        Ok(result) => Response {
+            jsonrpc: "2.0",
+            id,
+            result: Some(result),
+            error: None,
+        },
+        Err((code, message)) => error(id, code, message),
+    })
+}
+
+/// Calls `method` with `params`, returning its result or an error
+/// code and message.
+fn call(session: &mut Session, method: &str, params: Value) -> Result<Value, (i32, String)> {
+    let invalid = |err: serde_json::Error| (INVALID_PARAMS, err.to_string());
+    let result = match method {
+        "submit" => {
+            let tx: Transaction = serde_json::from_value(params).map_err(invalid)?;
+            serde_json::to_value(session.submit(&tx))
+        }
+        "query" => {
+            let params: QueryParams = serde_json::from_value(params).map_err(invalid)?;
+            serde_json::to_value(session.state().client(params.client))
+        }
+        "snapshot" => {
+            let mut clients: Vec<CsvClient> = session.state().clients().collect();
+            clients.sort_unstable_by_key(|client| client.client);
+            serde_json::to_value(clients)
+        }
+        "list_clients" => {
+            let query: ClientQuery = serde_json::from_value(params).map_err(invalid)?;
+            serde_json::to_value(list_clients(session, &query))
+        }
+        "list_transactions" => {
+            let params: HistoryParams = serde_json::from_value(params).map_err(invalid)?;
+            serde_json::to_value(list_transactions(session, params.client, &params.query))
+        }
+        "freeze" => {
+            let params: FreezeParams = serde_json::from_value(params).map_err(invalid)?;
+            if params.freeze.reason.is_empty() {
+                return Err((INVALID_PARAMS, "`reason` must not be empty".to_owned()));
+            }
+            session.freeze(params.client, params.freeze);
+            serde_json::to_value(session.state().client(params.client))
+        }
+        "unfreeze" => {
+            let params: QueryParams = serde_json::from_value(params).map_err(invalid)?;
+            serde_json::to_value(session.unfreeze(params.client))
+        }
+        "merge_clients" => {
+            let params: MergeParams = serde_json::from_value(params).map_err(invalid)?;
+            session
+                .merge_clients(params.source, params.destination, params.force)
+                .map_err(merge_error)?;
+            serde_json::to_value(session.state().client(params.destination))
+        }
+        "case" => {
+            let params: CaseParams = serde_json::from_value(params).map_err(invalid)?;
+            serde_json::to_value(session.state().case(params.tx))
+        }
+        "cases" => serde_json::to_value(session.state().cases()),
+        "update_case" => {
+            let params: CaseUpdateParams = serde_json::from_value(params).map_err(invalid)?;
+            let case = session
+                .update_case(params.tx, params.update)
+                .map_err(case_error)?;
+            serde_json::to_value(case)
+        }
+        "stats" => serde_json::to_value(session.state().summary()),
+        _ => return Err((METHOD_NOT_FOUND, format!("unknown method `{}`", method))),
+    };
+    // Results are built from plain data, which always serializes.
+    Ok(result.expect("results serialize to JSON"))
+}
+
+/// The error code and message for a failed case update: invalid
+/// parameters, unless the journal could not be written.
+fn case_error(err: errors::Error) -> (i32, String) {
+    match err {
+        errors::Error::Case(_) => (INVALID_PARAMS, err.to_string()),
+        _ => (INTERNAL_ERROR, err.to_string()),
+    }
+}
+
+/// The error code and message for a failed client merge: invalid
+/// parameters, unless the merge broke an invariant.
+fn merge_error(err: errors::Error) -> (i32, String) {
+    match err {
+        errors::Error::ClientMerge(_) => (INVALID_PARAMS, err.to_string()),
+        _ => (INTERNAL_ERROR, err.to_string()),
+    }
+}
+
+/// The page of clients `query` asks for.
+fn list_clients(session: &Session, query: &ClientQuery) -> ClientPage {
+    session.state().list_clients(
+        &query.filter,
+        query.sort,
+        query.cursor,
+        query.limit.unwrap_or(DEFAULT_PAGE_SIZE),
+    )
+}
+
+/// The page of the history of `client` that `query` asks for.
+fn list_transactions(session: &Session, client: u16, query: &HistoryQuery) -> HistoryPage {
+    session.state().transaction_page(
+        client,
+        query.cursor,
+        query.limit.unwrap_or(DEFAULT_PAGE_SIZE),
+    )
+}
+
+/// Serves requests over HTTP on `listener`, one connection at a time,
+/// so requests are still applied in the order they arrive:
+/// - `POST /rpc`, with one JSON-RPC request as the body, answered with
+///   its response as by `serve`, or `204 No Content` for notifications;
+/// - `GET /clients`, with the parameters of `list_clients` in the
+///   query, such as `/clients?locked=true&limit=50`, answered with the
+///   page of clients;
+/// - `GET /clients/{id}/transactions`, with the `cursor` and `limit` of
+///   `list_transactions` in the query, answered with the page of that
+///   client's history;
+/// - `GET /disputes/{tx}`, answered with the case of the dispute of
+///   that transaction;
+/// - `POST /disputes/{tx}`, with the parameters of `update_case` other
+///   than `tx` as the body, answered with the updated case.
+///
+/// Other paths get `404 Not Found`. Returns if the listener fails.
+pub fn serve_http(session: &mut Session, listener: &TcpListener) -> Result<(), errors::Error> {
+    for stream in listener.incoming() {
+        // A slow or broken client only loses its own answer.
+        if let Err(err) = answer(session, stream?) {
+            eprintln!("Warning: could not answer HTTP request: {}", err);
+        }
+    }
+    Ok(())
+}
+
+/// Reads one HTTP request from `stream` and answers it.
+fn answer(session: &mut Session, mut stream: TcpStream) -> Result<(), errors::Error> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or_default();
+            }
+        }
+    }
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let history = path
+        .strip_prefix("/clients/")
+        .and_then(|rest| rest.strip_suffix("/transactions"))
+        .and_then(|id| id.parse::<u16>().ok());
+    let case = path
+        .strip_prefix("/disputes/")
+        .and_then(|tx| tx.parse::<u32>().ok());
+    let bad_request = |message| {
+        serde_json::to_string(&RpcError {
+            code: INVALID_PARAMS,
+            message,
+        })
+    };
+    let (status, body) = match (method, path, history, case) {
+        ("POST", "/rpc", _, _) => {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            match handle(session, &String::from_utf8_lossy(&body)) {
+                Some(response) => ("200 OK", serde_json::to_string(&response)?),
+                None => ("204 No Content", String::new()),
+            }
+        }
+        ("GET", "/clients", _, _) => match ClientQuery::from_url_query(query) {
+            Ok(query) => (
+                "200 OK",
+                serde_json::to_string(&list_clients(session, &query))?,
+            ),
+            Err(message) => ("400 Bad Request", bad_request(message)?),
+        },
+        ("GET", _, Some(client), _) => match HistoryQuery::from_url_query(query) {
+            Ok(query) => (
+                "200 OK",
+                serde_json::to_string(&list_transactions(session, client, &query))?,
+            ),
+            Err(message) => ("400 Bad Request", bad_request(message)?),
+        },
+        ("GET", _, _, Some(tx)) => match session.state().case(tx) {
+            Some(case) => ("200 OK", serde_json::to_string(case)?),
+            None => ("404 Not Found", String::new()),
+        },
+        ("POST", _, _, Some(tx)) => {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            let update = serde_json::from_slice::<CaseUpdate>(&body)
+                .map_err(|err| (INVALID_PARAMS, err.to_string()))
+                .and_then(|update| session.update_case(tx, update).map_err(case_error));
+            match update {
+                Ok(case) => ("200 OK", serde_json::to_string(&case)?),
+                Err((INTERNAL_ERROR, message)) => {
+                    let body = serde_json::to_string(&RpcError {
+                        code: INTERNAL_ERROR,
+                        message,
+                    })?;
+                    ("500 Internal Server Error", body)
+                }
+                Err((_, message)) => ("400 Bad Request", bad_request(message)?),
+            }
+        }
+        _ => ("404 Not Found", String::new()),
+    };
+    write!(
+        stream,
+        "HTTP/1.0 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+/// A request for `method` with `params`, as one line.
+fn request_line(method: &str, params: Value) -> String {
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": method,
+        "params": params,
+    });
+    request.to_string()
+}
+
+/// The result of a response, or the error it carries.
+fn response_result(mut response: Value) -> Result<Value, errors::Error> {
+    match response.get("error") {
+        Some(error) => {
+            let code = error["code"].as_i64().unwrap_or_default() as i32;
+            let message = error["message"].as_str().unwrap_or_default().to_owned();
+            Err(errors::Error::Rpc(code, message))
+        }
+        None => Ok(response["result"].take()),
+    }
+}
+
+/// Calls `method` with `params` on a server run by `serve` at the
+/// other end of `stream`, such as a Unix socket, and returns its
+/// result, or the error it answered with.
+pub fn call_stream(
+    mut stream: impl Read + Write,
+    method: &str,
+    params: Value,
+) -> Result<Value, errors::Error> {
+    stream.write_all(request_line(method, params).as_bytes())?;
+    stream.write_all(b"\n")?;
+    stream.flush()?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    response_result(serde_json::from_str(&line)?)
+}
+
+/// Calls `method` with `params` on a server run by `serve_http` at
+/// `address`, and returns its result, or the error it answered with.
+pub fn call_http(address: &str, method: &str, params: Value) -> Result<Value, errors::Error> {
+    let mut stream = TcpStream::connect(address)?;
+    let body = request_line(method, params);
+    write!(
+        stream,
+        "POST /rpc HTTP/1.0\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+    // The server closes the connection once it has answered.
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let body = response.split_once("\r\n\r\n").map_or("", |(_, body)| body);
+    response_result(serde_json::from_str(body)?)
+}
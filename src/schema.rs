@@ -0,0 +1,109 @@
+use crate::errors::SchemaError;
+
+/// Every column of the input format, by its canonical name.
+pub const COLUMNS: [&str; 14] = [
+    "type",
+    "client",
+    "tx",
+    "amount",
+    "timestamp",
+    "reference",
+    "initiator",
+    "account",
+    "external_ref",
+    "reason_code",
+    "currency",
+    "category",
+    "merchant",
+    "seq",
+];
+
+/// Other names accepted for columns, with the column they stand for.
+const ALIASES: [(&str, &str); 1] = [("id", "tx")];
+
+/// Resolves a header to the canonical column it names, if any.
+fn canonical(name: &str) -> Option<&'static str> {
+    COLUMNS
+        .iter()
+        .copied()
+        .find(|&column| column == name)
+        .or_else(|| {
+            ALIASES
+                .iter()
+                .find(|&&(alias, _)| alias == name)
+                .map(|&(_, column)| column)
+        })
+}
+
+/// Checks a header row against the input format. Columns are numbered
+/// from one, so diagnostics match what spreadsheets show.
+pub fn check_headers(headers: &csv::StringRecord) -> Result<(), SchemaError> {
+    let mut seen: Vec<&'static str> = Vec::new();
+    for (index, name) in headers.iter().enumerate() {
+        let column = index + 1;
+        let resolved = match canonical(name) {
+            Some(resolved) => resolved,
+            None => {
+                let lowercase = name.to_lowercase();
+                return Err(match canonical(&lowercase) {
+                    Some(_) => SchemaError::MixedCase {
+                        column,
+                        name: name.to_owned(),
+                        expected: lowercase,
+                    },
+                    None => SchemaError::UnknownColumn {
+                        column,
+                        name: name.to_owned(),
+                    },
+                });
+            }
+        };
+        if seen.contains(&resolved) {
+            return Err(SchemaError::DuplicateColumn {
+                column,
+                name: name.to_owned(),
+            });
+        }
+        seen.push(resolved);
+    }
+    for required in ["type", "tx", "amount"] {
+        if !seen.contains(&required) {
+            return Err(SchemaError::MissingColumn(required));
+        }
+    }
+    if !seen.contains(&"client") && !seen.contains(&"account") {
+        return Err(SchemaError::MissingColumn("client"));
+    }
+    Ok(())
+}
+
+/// Converts a CSV error on a record into a diagnostic naming its row
+/// and column, where the error has one.
+pub fn diagnose(err: csv::Error, headers: &csv::StringRecord) -> crate::errors::Error {
+    match err.kind() {
+        csv::ErrorKind::UnequalLengths {
+            pos: Some(pos),
+            expected_len,
+            len,
+        } => SchemaError::FieldCount {
+            row: pos.line(),
+            expected: *expected_len,
+            actual: *len,
+        }
+        .into(),
+        csv::ErrorKind::Deserialize {
+            pos: Some(pos),
+            err: inner,
+        } => match inner.field() {
+            Some(field) => SchemaError::InvalidField {
+                row: pos.line(),
+                column: field + 1,
+                name: headers.get(field as usize).unwrap_or_default().to_owned(),
+                message: inner.kind().to_string(),
+            }
+            .into(),
+            None => err.into(),
+        },
+        _ => err.into(),
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+use serde::Deserialize;
+
+use crate::counterparty::normalize;
+use crate::errors::{ConfigError, Error};
+
+#[derive(Debug, Deserialize)]
+/// A row of a denylist file.
+struct CsvDenied {
+    #[serde(default)]
+    client: Option<u16>,
+    #[serde(default)]
+    account: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+/// Clients and external accounts, such as those on a sanctions list,
+/// that no record may be applied for.
+pub struct Denylist {
+    clients: HashSet<u16>,
+    /// Normalized as counterparty accounts are.
+    accounts: HashSet<String>,
+}
+
+impl Denylist {
+    /// Reads a CSV file with `client` and `account` columns, either of
+    /// which may be left out or empty, but not both in the same row.
+    pub fn from_csv(reader: impl std::io::Read) -> Result<Self, Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut list = Denylist::default();
+        for (row, record) in (1..).zip(rdr.deserialize()) {
+            let record: CsvDenied = record?;
+            let account = record.account.filter(|account| !account.is_empty());
+            if record.client.is_none() && account.is_none() {
+                return Err(ConfigError::EmptyDenylistEntry(row).into());
+            }
+            if let Some(client) = record.client {
+                list.insert_client(client);
+            }
+            if let Some(account) = account {
+                list.insert_account(&account);
+            }
+        }
+        Ok(list)
+    }
+
+    /// Denies the client with ID `client`.
+    pub fn insert_client(&mut self, client: u16) {
+        self.clients.insert(client);
+    }
+
+    /// Denies the external account `account`.
+    pub fn insert_account(&mut self, account: &str) {
+        self.accounts.insert(normalize(account));
+    }
+
+    /// Whether a record for `client`, if it is known, naming `account`,
+    /// if it names one, is denied.
+    pub fn denies(&self, client: Option<u16>, account: Option<&str>) -> bool {
+        client.is_some_and(|client| self.clients.contains(&client))
+            || account.is_some_and(|account| self.accounts.contains(&normalize(account)))
+    }
+
+    /// The number of clients and accounts denied.
+    pub fn len(&self) -> usize {
+        self.clients.len() + self.accounts.len()
+    }
+
+    /// Whether nothing is denied.
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty() && self.accounts.is_empty()
+    }
+}
+
+#[derive(Debug)]
+/// The file a screener was loaded from, and when it was last changed.
+struct Source {
+    path: PathBuf,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+#[derive(Debug)]
+/// Screens every record against a denylist before it is applied.
+/// Records for a denied client, or naming a denied account, are
+/// rejected as `screened`, and the client, if it exists, is flagged.
+///
+/// The list can be replaced while records are being applied, such as
+/// when the file it was loaded from changes, and applies from the next
+/// record on.
+pub struct Screener {
+    list: RwLock<Arc<Denylist>>,
+    source: Option<Source>,
+}
+
+impl Screener {
+    /// Screens records against `list`.
+    pub fn new(list: Denylist) -> Self {
+        Screener {
+            list: RwLock::new(Arc::new(list)),
+            source: None,
+        }
+    }
+
+    /// Screens records against the denylist in the CSV file at `path`,
+    /// which `reload` reads again once it changes.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let modified = std::fs::metadata(&path)?.modified().ok();
+        let list = Denylist::from_csv(File::open(&path)?)?;
+        Ok(Screener {
+            list: RwLock::new(Arc::new(list)),
+            source: Some(Source {
+                path,
+                modified: Mutex::new(modified),
+            }),
+        })
+    }
+
+    /// The file the list was loaded from, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.source.as_ref().map(|source| source.path.as_path())
+    }
+
+    /// The list records are screened against.
+    pub fn list(&self) -> Arc<Denylist> {
+        self.list.read().unwrap().clone()
+    }
+
+    /// Screens records against `list` from now on.
+    pub fn replace(&self, list: Denylist) {
+        *self.list.write().unwrap() = Arc::new(list);
+    }
+
+    /// Reads the list again if its file has changed since it was last
+    /// read, returning whether it was replaced. A file that cannot be
+    /// read leaves the list as it was, and is not read again until it
+    /// changes once more.
+    pub fn reload(&self) -> Result<bool, Error> {
+        let source = match &self.source {
+            Some(source) => source,
+            None => return Ok(false),
+        };
+        let modified = std::fs::metadata(&source.path)?.modified().ok();
+        {
+            let mut last = source.modified.lock().unwrap();
+            if modified.is_some() && *last == modified {
+                return Ok(false);
+            }
+            *last = modified;
+        }
+        self.replace(Denylist::from_csv(File::open(&source.path)?)?);
+        Ok(true)
+    }
+
+    /// Whether a record for `client`, if it is known, naming `account`,
+    /// if it names one, is denied.
+    pub fn denies(&self, client: Option<u16>, account: Option<&str>) -> bool {
+        self.list.read().unwrap().denies(client, account)
+    }
+}
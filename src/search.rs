@@ -0,0 +1,259 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::state::CsvClient;
+
+/// The number of clients in a page if no limit is given.
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// The most clients in one page, whatever limit is given.
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone)]
+/// Which clients to list. Every condition set must hold.
+pub struct ClientFilter {
+    #[serde(default)]
+    pub locked: Option<bool>,
+    /// Whether the available funds are below zero.
+    #[serde(default)]
+    pub negative_available: Option<bool>,
+    /// Whether the client has any dispute open.
+    #[serde(default)]
+    pub open_disputes: Option<bool>,
+    /// The least total funds, inclusive.
+    #[serde(default)]
+    pub min_total: Option<Decimal>,
+    /// The most total funds, inclusive.
+    #[serde(default)]
+    pub max_total: Option<Decimal>,
+}
+
+impl ClientFilter {
+    /// Whether `client`, which has open disputes if `disputed`, is listed.
+    pub(crate) fn matches(&self, client: &CsvClient, disputed: bool) -> bool {
+        self.locked.is_none_or(|locked| client.locked == locked)
+            && self
+                .negative_available
+                .is_none_or(|negative| client.available.is_sign_negative() == negative)
+            && self.open_disputes.is_none_or(|open| disputed == open)
+            && self.min_total.is_none_or(|min| client.total >= min)
+            && self.max_total.is_none_or(|max| client.total <= max)
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// The field clients are listed by.
+pub enum SortKey {
+    #[default]
+    Id,
+    Available,
+    Held,
+    Total,
+}
+
+impl SortKey {
+    /// The value of the field for `client`. Clients are listed by ID
+    /// within the same value, so the IDs themselves need no value.
+    fn of(self, client: &CsvClient) -> Decimal {
+        match self {
+            SortKey::Id => Decimal::ZERO,
+            SortKey::Available => client.available,
+            SortKey::Held => client.held,
+            SortKey::Total => client.total,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// The order clients are listed in, written as the field, such as
+/// `total`, or the field after a `-` for descending order.
+pub struct ClientSort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl ClientSort {
+    /// The position of `client` in this order.
+    fn position(self, client: &CsvClient) -> Cursor {
+        Cursor {
+            key: self.key.of(client),
+            client: client.client,
+        }
+    }
+
+    /// Compares two positions in this order.
+    fn compare(self, a: &Cursor, b: &Cursor) -> Ordering {
+        let ordering = (a.key, a.client).cmp(&(b.key, b.client));
+        match self.descending {
+            true => ordering.reverse(),
+            false => ordering,
+        }
+    }
+}
+
+impl FromStr for ClientSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (descending, field) = match s.strip_prefix('-') {
+            Some(field) => (true, field),
+            None => (false, s),
+        };
+        let key = match field {
+            "id" | "client" => SortKey::Id,
+            "available" => SortKey::Available,
+            "held" => SortKey::Held,
+            "total" => SortKey::Total,
+            _ => {
+                return Err(format!(
+                    "expected `id`, `available`, `held` or `total`, optionally after `-`, got `{}`",
+                    s
+                ))
+            }
+        };
+        Ok(ClientSort { key, descending })
+    }
+}
+
+impl<'de> Deserialize<'de> for ClientSort {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// Where a page ends, so the next one starts after it. Only meaningful
+/// with the filter and order it was returned for.
+pub struct Cursor {
+    key: Decimal,
+    client: u16,
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.key.normalize(), self.client)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid cursor `{}`", s);
+        let (key, client) = s.rsplit_once('_').ok_or_else(invalid)?;
+        Ok(Cursor {
+            key: key.parse().map_err(|_| invalid())?,
+            client: client.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Serialize for Cursor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cursor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Eq, Clone)]
+/// A request for a page of clients, as the parameters of the
+/// `list_clients` method or the query of `GET /clients`.
+pub struct ClientQuery {
+    #[serde(flatten)]
+    pub filter: ClientFilter,
+    #[serde(default)]
+    pub sort: ClientSort,
+    /// The cursor of the previous page, if this is not the first.
+    #[serde(default)]
+    pub cursor: Option<Cursor>,
+    /// The most clients in the page, from one up to `MAX_PAGE_SIZE`.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl ClientQuery {
+    /// Parses a URL query, such as `locked=true&sort=-total&limit=50`.
+    /// Values are taken as they are, without percent-decoding, as none
+    /// of them need it.
+    pub fn from_url_query(query: &str) -> Result<Self, String> {
+        fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("invalid value `{}` for `{}`", value, name))
+        }
+
+        let mut parsed = ClientQuery::default();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let filter = &mut parsed.filter;
+            match name {
+                "locked" => filter.locked = Some(parse(name, value)?),
+                "negative_available" => filter.negative_available = Some(parse(name, value)?),
+                "open_disputes" => filter.open_disputes = Some(parse(name, value)?),
+                "min_total" => filter.min_total = Some(parse(name, value)?),
+                "max_total" => filter.max_total = Some(parse(name, value)?),
+                "sort" => parsed.sort = value.parse()?,
+                "cursor" => parsed.cursor = Some(value.parse()?),
+                "limit" => parsed.limit = Some(parse(name, value)?),
+                _ => return Err(format!("unknown parameter `{}`", name)),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+/// One page of clients.
+pub struct ClientPage {
+    pub clients: Vec<CsvClient>,
+    /// The cursor to pass for the next page, if there are more clients.
+    pub next_cursor: Option<Cursor>,
+}
+
+/// The page of `clients` in `sort` order that starts after `cursor`,
+/// with at most `limit` clients. Only the clients in the page are
+/// sorted, so listing a page takes time linear in the number of
+/// clients, however deep it is.
+pub(crate) fn paginate(
+    clients: impl Iterator<Item = CsvClient>,
+    sort: ClientSort,
+    cursor: Option<Cursor>,
+    limit: usize,
+) -> ClientPage {
+    let limit = limit.clamp(1, MAX_PAGE_SIZE);
+    let mut clients: Vec<CsvClient> = clients
+        .filter(|client| {
+            cursor.is_none_or(|cursor| {
+                sort.compare(&sort.position(client), &cursor) == Ordering::Greater
+            })
+        })
+        .collect();
+    let order = |a: &CsvClient, b: &CsvClient| sort.compare(&sort.position(a), &sort.position(b));
+    let more = clients.len() > limit;
+    if more {
+        clients.select_nth_unstable_by(limit, order);
+        clients.truncate(limit);
+    }
+    clients.sort_unstable_by(order);
+    let next_cursor = match more {
+        true => clients.last().map(|client| sort.position(client)),
+        false => None,
+    };
+    ClientPage {
+        clients,
+        next_cursor,
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::errors::Error;
+
+/// The number of bytes read from the file at once.
+const PAGE_SIZE: u64 = 4096;
+
+/// The most pages kept in memory between reads.
+const CACHED_PAGES: usize = 256;
+
+#[derive(Debug)]
+/// The IDs of every transaction accepted by earlier runs, kept on disk,
+/// so a transaction replayed in a later file is rejected as
+/// `already_exists` even after a restart, without holding the history
+/// in memory.
+///
+/// The file is a bitmap with one bit per transaction ID, read a page at
+/// a time. It only grows as far as the highest ID seen, and on most
+/// filesystems pages of IDs never seen take no space, so it stays small
+/// however sparse the IDs are.
+///
+/// IDs accepted during a run are only written when the run is
+/// committed, so a run that fails can be processed again.
+///
+/// The file is locked while it is open, as two processes committing to
+/// it would overwrite each other's pages, so only one process at a time
+/// may use it; others fail to open it as busy.
+pub struct SeenSet {
+    path: PathBuf,
+    file: Mutex<File>,
+    /// Pages read from the file, by page number.
+    pages: Mutex<HashMap<u64, Box<[u8]>>>,
+    /// IDs accepted since the last commit.
+    staged: Mutex<HashSet<u32>>,
+}
+
+impl SeenSet {
+    /// Opens the seen set at `path`, creating it if it does not exist.
+    /// Fails with `StateBackendBusy` if another process has it open.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Err(Error::StateBackendBusy(path)),
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+        }
+        Ok(SeenSet {
+            path,
+            file: Mutex::new(file),
+            pages: Mutex::default(),
+            staged: Mutex::default(),
+        })
+    }
+
+    /// The file backing the seen set.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the transaction with ID `id` was accepted by an earlier
+    /// run. IDs accepted since the last commit are left to the state
+    /// that accepted them, so duplicates within a run, and across the
+    /// inputs of one run, are handled as they are without a seen set.
+    pub fn contains(&self, id: u32) -> std::io::Result<bool> {
+        let (page, byte, bit) = position(id);
+        let mut pages = self.pages.lock().unwrap();
+        if !pages.contains_key(&page) {
+            if pages.len() == CACHED_PAGES {
+                pages.clear();
+            }
+            let bytes = self.read_page(page)?;
+            pages.insert(page, bytes);
+        }
+        Ok(pages[&page][byte] & bit != 0)
+    }
+
+    /// Marks the transaction with ID `id` as accepted, once the run is
+    /// committed.
+    pub fn insert(&self, id: u32) {
+        self.staged.lock().unwrap().insert(id);
+    }
+
+    /// Writes the IDs accepted since the last commit to disk, returning
+    /// how many there were.
+    pub fn commit(&self) -> std::io::Result<usize> {
+        let mut staged = self.staged.lock().unwrap();
+        let mut pages: BTreeMap<u64, Vec<(usize, u8)>> = BTreeMap::new();
+        for &id in staged.iter() {
+            let (page, byte, bit) = position(id);
+            pages.entry(page).or_default().push((byte, bit));
+        }
+        // Locked in the same order as by `contains`.
+        let mut cache = self.pages.lock().unwrap();
+        let mut file = self.file.lock().unwrap();
+        for (page, bits) in pages {
+            let mut contents = read_page(&mut file, page)?;
+            for (byte, bit) in bits {
+                contents[byte] |= bit;
+            }
+            file.seek(SeekFrom::Start(page * PAGE_SIZE))?;
+            file.write_all(&contents)?;
+            cache.remove(&page);
+        }
+        file.sync_all()?;
+        let committed = staged.len();
+        staged.clear();
+        Ok(committed)
+    }
+
+    /// Reads page `page` of the file.
+    fn read_page(&self, page: u64) -> std::io::Result<Box<[u8]>> {
+        read_page(&mut self.file.lock().unwrap(), page)
+    }
+}
+
+/// The page, the byte within it, and the bit within that, of `id`.
+fn position(id: u32) -> (u64, usize, u8) {
+    let byte = u64::from(id) / 8;
+    (byte / PAGE_SIZE, (byte % PAGE_SIZE) as usize, 1 << (id % 8))
+}
+
+/// Reads page `page` of `file`. Parts past the end of the file are
+/// zeroes, as no ID there was seen.
+fn read_page(file: &mut File, page: u64) -> std::io::Result<Box<[u8]>> {
+    let mut bytes = vec![0; PAGE_SIZE as usize].into_boxed_slice();
+    file.seek(SeekFrom::Start(page * PAGE_SIZE))?;
+    let mut filled = 0;
+    while filled < bytes.len() {
+        match file.read(&mut bytes[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(bytes)
+}
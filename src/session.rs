@@ -0,0 +1,332 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::cases::{self, Case, CaseJournal, CaseUpdate, JournalEntry};
+use crate::errors::{self, RejectionCode};
+use crate::events::EventSink;
+use crate::freeze::Freeze;
+use crate::replicate::Leader;
+use crate::state::{CsvClient, CurrentState};
+use crate::transaction::Transaction;
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+/// What happened to a submitted record.
+pub enum Outcome {
+    /// The record was applied.
+    Accepted,
+    /// The record was held for review.
+    Quarantined,
+    /// The record was valid, but rejected by the engine.
+    Rejected,
+    /// The record could not be read.
+    Invalid,
+}
+
+#[derive(Debug, Serialize, Clone)]
+/// The acknowledgement for one submitted record.
+pub struct Ack {
+    /// The position of the record in the session, counting from one.
+    pub seq: u64,
+    /// The transaction ID, if the record could be read.
+    pub tx: Option<u32>,
+    pub outcome: Outcome,
+    /// Why the record was rejected, if it was.
+    pub code: Option<RejectionCode>,
+    pub message: Option<String>,
+    /// The client's balances after the record, if the client exists.
+    pub balances: Option<CsvClient>,
+    /// Whether this repeats the acknowledgement of an identical record
+    /// submitted earlier, which was not applied again.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub retried: bool,
+}
+
+/// Identifies a submitted record: its client, its transaction ID and
+/// a digest of its contents.
+type RecordKey = (u16, u32, u128);
+
+#[derive(Debug)]
+/// The acknowledgements of recently submitted records, so that a sender
+/// retrying after a network timeout gets the original outcome back
+/// rather than an `already_exists` rejection.
+///
+/// Entries expire by the server's monotonic clock, never by record
+/// timestamps or the wall clock, so skewed sender clocks and clock
+/// adjustments on the server cannot expire them early or keep them
+/// forever.
+struct Deduplicator {
+    ttl: Duration,
+    acks: HashMap<RecordKey, Ack>,
+    /// When each entry was added, oldest first.
+    added: VecDeque<(Instant, RecordKey)>,
+}
+
+impl Deduplicator {
+    /// The key for `tx`.
+    fn key(tx: &Transaction) -> RecordKey {
+        let mut hasher = Sha256::new();
+        // Records are plain data, which always serializes.
+        let record = serde_json::to_vec(&tx.to_input()).expect("records serialize to JSON");
+        hasher.update(record);
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&hasher.finalize()[..16]);
+        (tx.client, tx.id, u128::from_be_bytes(bytes))
+    }
+
+    /// Drops the entries older than the TTL.
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(added, key)) = self.added.front() {
+            if now.duration_since(added) < self.ttl {
+                break;
+            }
+            self.added.pop_front();
+            self.acks.remove(&key);
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Processes records one at a time, in the order they arrive, and
+/// acknowledges each with its outcome. Independent of any transport.
+pub struct Session {
+    state: CurrentState,
+    seq: u64,
+    /// Recognises retried records, if enabled.
+    deduplicator: Option<Deduplicator>,
+    /// Streams records to followers, if any.
+    leader: Option<Leader>,
+    /// Receives the changelog events of each record, if anything does.
+    events: Option<Box<dyn EventSink>>,
+    /// Keeps case updates on disk, if they are kept.
+    journal: Option<CaseJournal>,
+    /// The journaled updates to cases not opened yet, by transaction
+    /// ID, applied once the dispute is submitted again.
+    pending_cases: HashMap<u32, Vec<JournalEntry>>,
+}
+
+impl Session {
+    /// Starts a session that applies records to `state`.
+    pub fn new(state: CurrentState) -> Self {
+        Session {
+            state,
+            seq: 0,
+            deduplicator: None,
+            leader: None,
+            events: None,
+            journal: None,
+            pending_cases: HashMap::new(),
+        }
+    }
+
+    /// Streams every record processed, other than retries, to the
+    /// followers of `leader`.
+    pub fn replicate(mut self, leader: Leader) -> Self {
+        self.leader = Some(leader);
+        self
+    }
+
+    /// Publishes the changelog events of every record applied from here
+    /// on to `sink`, instead of keeping them in the state. The state
+    /// must be built to record events. Events recorded before, such as
+    /// while following a leader that published them, are dropped.
+    pub fn publish_events(mut self, sink: Box<dyn EventSink>) -> Self {
+        self.state.take_events();
+        self.events = Some(sink);
+        self
+    }
+
+    /// Answers a record identical to one submitted in the last `ttl`,
+    /// with the same client, ID and contents, with the original
+    /// acknowledgement instead of applying it again.
+    pub fn deduplicate(mut self, ttl: Duration) -> Self {
+        self.deduplicator = Some(Deduplicator {
+            ttl,
+            acks: HashMap::new(),
+            added: VecDeque::new(),
+        });
+        self
+    }
+
+    /// Freezes a client, as `CurrentState::freeze` does. Freezes are
+    /// not records, so they are not streamed to followers.
+    pub fn freeze(&mut self, client: u16, freeze: Freeze) -> Option<Freeze> {
+        self.state.freeze(client, freeze)
+    }
+
+    /// Lifts the freeze on a client, as `CurrentState::unfreeze` does.
+    pub fn unfreeze(&mut self, client: u16) -> Option<Freeze> {
+        self.state.unfreeze(client)
+    }
+
+    /// Merges client `src` into client `dst`, as
+    /// `CurrentState::merge_clients` does. Merges are not records, so
+    /// they are not streamed to followers.
+    pub fn merge_clients(&mut self, src: u16, dst: u16, force: bool) -> Result<(), errors::Error> {
+        self.state.merge_clients(src, dst, force)?;
+        if let Some(sink) = &self.events {
+            let events = self.state.take_events();
+            if !events.is_empty() {
+                sink.publish(events);
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps case updates in the journal at `path`, which is created if
+    /// it does not exist, so they outlast the session. The updates
+    /// already there are applied to the cases open, and to the others
+    /// once their disputes are submitted again, such as by a sender
+    /// replaying its records after a restart.
+    pub fn case_journal(mut self, path: &Path) -> Result<Self, errors::Error> {
+        if path.exists() {
+            for entry in cases::read_journal(File::open(path)?)? {
+                self.pending_cases.entry(entry.tx).or_default().push(entry);
+            }
+            let open: Vec<u32> = self.pending_cases.keys().copied().collect();
+            open.into_iter().for_each(|tx| self.apply_pending_cases(tx));
+        }
+        self.journal = Some(CaseJournal::open(path)?);
+        Ok(self)
+    }
+
+    /// Applies the journaled updates to the case of `tx`, if it is open.
+    fn apply_pending_cases(&mut self, tx: u32) {
+        if self.state.case(tx).is_none() {
+            return;
+        }
+        if let Some(entries) = self.pending_cases.remove(&tx) {
+            cases::replay(&mut self.state, &entries);
+        }
+    }
+
+    /// Applies a case handler's update to the case of the transaction
+    /// with ID `tx`, as made at the latest timestamp seen, and returns
+    /// the updated case. Case updates are not records, so they are not
+    /// streamed to followers.
+    pub fn update_case(&mut self, tx: u32, update: CaseUpdate) -> Result<Case, errors::Error> {
+        let at = self.state.latest_timestamp();
+        let case = self.state.update_case(tx, &update, at)?.clone();
+        if let Some(journal) = &mut self.journal {
+            journal.append(&JournalEntry { tx, at, update })?;
+        }
+        Ok(case)
+    }
+
+    /// The state records have been applied to.
+    pub fn state(&self) -> &CurrentState {
+        &self.state
+    }
+
+    /// Ends the session, returning its state.
+    pub fn into_state(self) -> CurrentState {
+        self.state
+    }
+
+    /// Applies one record and acknowledges it, unless it is a retry.
+    pub fn submit(&mut self, tx: &Transaction) -> Ack {
+        let deduplicator = match &mut self.deduplicator {
+            Some(deduplicator) => deduplicator,
+            None => return self.apply(tx),
+        };
+        let now = Instant::now();
+        deduplicator.expire(now);
+        let key = Deduplicator::key(tx);
+        if let Some(ack) = deduplicator.acks.get(&key) {
+            return Ack {
+                retried: true,
+                ..ack.clone()
+            };
+        }
+        let ack = self.apply(tx);
+        // Only reborrowed here, as applying needs the whole session.
+        let deduplicator = self.deduplicator.as_mut().unwrap();
+        deduplicator.acks.insert(key, ack.clone());
+        deduplicator.added.push_back((now, key));
+        ack
+    }
+
+    /// Applies one record and acknowledges it.
+    fn apply(&mut self, tx: &Transaction) -> Ack {
+        self.seq += 1;
+        if let Some(leader) = &self.leader {
+            leader.replicate(tx);
+        }
+        let result = self.state.add(tx);
+        if !self.pending_cases.is_empty() {
+            self.apply_pending_cases(tx.id);
+        }
+        if let Some(sink) = &self.events {
+            let events = self.state.take_events();
+            if !events.is_empty() {
+                sink.publish(events);
+            }
+        }
+        let client = match &tx.account {
+            Some(account) => self.state.counterparties().client(account),
+            None => Some(tx.client),
+        };
+        let (outcome, code, message) = match &result {
+            Ok(()) if self.state.is_quarantined(tx.id) => (Outcome::Quarantined, None, None),
+            Ok(()) => (Outcome::Accepted, None, None),
+            Err(err) => (
+                Outcome::Rejected,
+                err.rejection_code(),
+                Some(err.to_string()),
+            ),
+        };
+        Ack {
+            seq: self.seq,
+            tx: Some(tx.id),
+            outcome,
+            code,
+            message,
+            balances: client.and_then(|client| self.state.client(client)),
+            retried: false,
+        }
+    }
+
+    /// Acknowledges a record that could not be read.
+    pub fn invalid(&mut self, err: &errors::Error) -> Ack {
+        self.seq += 1;
+        Ack {
+            seq: self.seq,
+            tx: None,
+            outcome: Outcome::Invalid,
+            code: None,
+            message: Some(err.to_string()),
+            balances: None,
+            retried: false,
+        }
+    }
+
+    /// Reads records as JSON lines and writes one acknowledgement line
+    /// per record, flushing each so the sender can pace itself.
+    /// Blank lines are skipped.
+    pub fn serve_jsonl(
+        &mut self,
+        reader: impl BufRead,
+        mut writer: impl Write,
+    ) -> Result<(), errors::Error> {
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let ack = match serde_json::from_str::<Transaction>(&line) {
+                Ok(tx) => self.submit(&tx),
+                Err(err) => self.invalid(&err.into()),
+            };
+            serde_json::to_writer(&mut writer, &ack)?;
+            writer.write_all(b"\n")?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::calendar::Date;
+
+/// The business days after a withdrawal it settles by default, T+2.
+pub const DEFAULT_SETTLEMENT_DAYS: u32 = 2;
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+/// What moves when a settlement is made.
+pub enum SettlementKind {
+    /// The funds of a withdrawal leave the books.
+    Withdrawal,
+    /// The share of a deposit held in reserve is released to the
+    /// client's available funds.
+    ReserveRelease,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone)]
+/// When the funds of one withdrawal leave the books, or the reserve
+/// held back from one deposit is released.
+/// Used for serialization.
+pub struct Settlement {
+    pub client: u16,
+    /// The ID of the withdrawal, or of the deposit the reserve was
+    /// held back from.
+    pub tx: u32,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub amount: Decimal,
+    /// When the transaction was made, if timestamps are present.
+    pub timestamp: Option<u64>,
+    /// The business day the funds settle on.
+    pub settles: Option<Date>,
+    pub kind: SettlementKind,
+}
+
+/// Writes settlements as CSV.
+pub fn write_csv<'a>(
+    writer: impl std::io::Write,
+    entries: impl IntoIterator<Item = &'a Settlement>,
+) -> Result<(), csv::Error> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(true)
+        .from_writer(writer);
+    entries
+        .into_iter()
+        .try_for_each(|entry| wtr.serialize(entry))?;
+    wtr.flush()?;
+    Ok(())
+}
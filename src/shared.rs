@@ -0,0 +1,88 @@
+use std::collections::HashSet;
+use std::sync::{Condvar, Mutex};
+
+use crate::errors::{self, TransactionError};
+use crate::state::{CsvClient, CurrentState};
+use crate::transaction::Transaction;
+
+#[derive(Debug)]
+/// A state shared between threads, such as request handlers, with a
+/// lock for each client. Each call locks the state only for as long
+/// as it takes, so a handler can hold a client's lock across several
+/// calls (for example, checking a balance and then withdrawing) while
+/// handlers for other clients carry on.
+pub struct SharedState {
+    state: Mutex<CurrentState>,
+    /// The clients currently locked for update.
+    locked: Mutex<HashSet<u16>>,
+    /// Signalled whenever a client is unlocked.
+    unlocked: Condvar,
+}
+
+impl SharedState {
+    /// Shares `state`.
+    pub fn new(state: CurrentState) -> Self {
+        SharedState {
+            state: Mutex::new(state),
+            locked: Mutex::new(HashSet::new()),
+            unlocked: Condvar::new(),
+        }
+    }
+
+    /// Locks the client with ID `id` for update, waiting for any other
+    /// guard of the same client to be dropped first.
+    pub fn lock_client_for_update(&self, id: u16) -> ClientGuard<'_> {
+        let mut locked = self.locked.lock().unwrap();
+        while locked.contains(&id) {
+            locked = self.unlocked.wait(locked).unwrap();
+        }
+        locked.insert(id);
+        ClientGuard { shared: self, id }
+    }
+
+    /// Runs `read` with the state locked, for reads that do not need
+    /// a client's lock.
+    pub fn read<R>(&self, read: impl FnOnce(&CurrentState) -> R) -> R {
+        read(&self.state.lock().unwrap())
+    }
+
+    /// Stops sharing the state.
+    pub fn into_inner(self) -> CurrentState {
+        self.state.into_inner().unwrap()
+    }
+}
+
+#[derive(Debug)]
+/// Exclusive access to one client of a `SharedState`, until dropped.
+pub struct ClientGuard<'a> {
+    shared: &'a SharedState,
+    id: u16,
+}
+
+impl ClientGuard<'_> {
+    /// The ID of the locked client.
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// The current state of the client, if it exists.
+    pub fn client(&self) -> Option<CsvClient> {
+        self.shared.state.lock().unwrap().client(self.id)
+    }
+
+    /// Processes one record for the client. Records for other clients,
+    /// including any naming an account, are rejected.
+    pub fn add(&mut self, tx: &Transaction) -> Result<(), errors::Error> {
+        if tx.client != self.id || tx.account.is_some() {
+            return Err(TransactionError::ClientMismatch(tx.id).into());
+        }
+        self.shared.state.lock().unwrap().add(tx)
+    }
+}
+
+impl Drop for ClientGuard<'_> {
+    fn drop(&mut self) {
+        self.shared.locked.lock().unwrap().remove(&self.id);
+        self.shared.unlocked.notify_all();
+    }
+}
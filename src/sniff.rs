@@ -0,0 +1,67 @@
+use std::str::FromStr;
+
+use crate::errors::FormatError;
+
+/// The UTF-8 byte order mark some tools write at the start of a file.
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// The magic bytes gzip streams start with.
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+
+/// The magic bytes Parquet files start with.
+const PARQUET_MAGIC: &[u8] = b"PAR1";
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// How input records are written.
+pub enum InputFormat {
+    #[default]
+    /// Told apart by the first bytes of each input, with `detect`.
+    Auto,
+    /// Comma-separated values, with a header row.
+    Csv,
+    /// One JSON object per line, with the fields the CSV columns have,
+    /// such as `{"type": "deposit", "client": 1, "tx": 1, "amount": "1.0"}`.
+    Jsonl,
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    /// Parses `auto`, `csv` or `jsonl`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(InputFormat::Auto),
+            "csv" => Ok(InputFormat::Csv),
+            "jsonl" => Ok(InputFormat::Jsonl),
+            _ => Err(format!("invalid input format `{}`", s)),
+        }
+    }
+}
+
+/// The format of an input starting with `head`: JSON Lines if its
+/// first character other than whitespace opens an object, and CSV if it
+/// is any other text, including nothing. Gzip streams and Parquet files
+/// are known by their magic bytes, and are errors, as they cannot be
+/// read yet, as is anything else that is not text.
+pub fn detect(head: &[u8]) -> Result<InputFormat, FormatError> {
+    if head.starts_with(GZIP_MAGIC) {
+        return Err(FormatError::Gzip);
+    }
+    if head.starts_with(PARQUET_MAGIC) {
+        return Err(FormatError::Parquet);
+    }
+    let text = head.strip_prefix(BOM).unwrap_or(head);
+    // Only the start of the input is given, which may end in the middle
+    // of a character.
+    let valid = match std::str::from_utf8(text) {
+        Ok(_) => true,
+        Err(err) => err.error_len().is_none(),
+    };
+    if !valid || text.contains(&0) {
+        return Err(FormatError::Unrecognized);
+    }
+    match text.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'{') => Ok(InputFormat::Jsonl),
+        _ => Ok(InputFormat::Csv),
+    }
+}
@@ -1,21 +1,85 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{hash_map::Entry, BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::{BufRead, BufReader};
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
 
-use crate::errors::{self, ClientError, TransactionError};
-use crate::transaction::{self, Transaction, TransactionType};
+use crate::adjudicate::{Adjudicator, DisputeCase, Ruling};
+use crate::alerts::BalanceAlerts;
+use crate::archive::Archive;
+use crate::audit::{AuditEntry, AuditEvent};
+use crate::balance::Balance;
+use crate::calendar::{Calendar, Date};
+use crate::cases::{Case, CaseStatus, CaseUpdate};
+#[cfg(feature = "chaos")]
+use crate::chaos::Chaos;
+use crate::counterparty::CounterpartyMap;
+use crate::decimal::DecimalStyle;
+use crate::diff::{self, Balances};
+use crate::digest::StateHash;
+use crate::disputes::{DisputeOutcome, HeldFunds};
+use crate::enrich::{Enricher, Enrichment};
+use crate::errors::{
+    self, CaseError, ClientError, ClientMergeError, ConfigError, InvariantError, MergeError,
+    RejectionCode, TransactionError,
+};
+use crate::events::{Event, EventKind, EventSink};
+use crate::filter::TxFilter;
+use crate::flags::ClientFlags;
+use crate::freeze::{Freeze, Freezes};
+use crate::hierarchy::Hierarchy;
+use crate::history::{ClientHistory, HistoryEntry, HistoryPage};
+use crate::holds::HoldRates;
+use crate::ids::TxIdAllocator;
+use crate::limits::{self, AccountLimits, LimitSet};
+use crate::lots::FundsTracer;
+use crate::middleware::{Outcome, TxMiddleware, Verdict};
+use crate::monitor::{ChargebackMonitor, ChargebackWindow, MonitorAction, Monitored, Transition};
+use crate::notify::{Notification, NotificationSink, Trigger, Triggers};
+use crate::parse;
+use crate::progress::{ProgressTracker, TrackedReader};
+use crate::quarantine::{QuarantineRule, Quarantined, VelocityLimit};
+use crate::reasons::ReasonCodes;
+use crate::registry::{TransactionHandler, TransactionRegistry};
+use crate::rejects::Retries;
+use crate::reserve::{ReserveSchedule, Reserves};
+use crate::risk::{ClientActivity, RiskRow};
+use crate::schema;
+use crate::screen::Screener;
+use crate::search::{self, ClientFilter, ClientPage, ClientSort, Cursor};
+use crate::seen::SeenSet;
+use crate::settlement::{Settlement, SettlementKind};
+use crate::sniff::{self, InputFormat};
+use crate::summary::{Stats, Summary};
+use crate::transaction::{
+    self, DisputeInitiator, StoredTx, Transaction, TransactionKind, TransactionType,
+};
+use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
-/// The state of one client at any given time.
+#[derive(Debug, Clone, Copy)]
+/// The state of one client at any given time, as copied out of the
+/// columns of `ClientStates`.
 struct Client {
     /// The client's unique ID.
     id: u16,
     /// The available funds.
-    available: Decimal,
+    available: Balance,
     /// The held/disputed funds.
-    held: Decimal,
+    held: Balance,
+    /// The fees collected from the client, if kept apart from the
+    /// available funds.
+    fees: Balance,
+    /// The share of the client's deposits held back in reserve.
+    reserve: Balance,
     /// Flag indicating whether the account is locked
     locked: bool,
+    /// Flags raised for follow-up.
+    flags: ClientFlags,
 }
 
 impl Client {
@@ -23,162 +87,5326 @@ impl Client {
     pub fn from_id(id: u16) -> Client {
         Client {
             id,
-            available: Decimal::default(),
-            held: Decimal::default(),
+            available: Balance::default(),
+            held: Balance::default(),
+            fees: Balance::default(),
+            reserve: Balance::default(),
             locked: false,
+            flags: ClientFlags::default(),
         }
     }
+
+    /// The available and held funds together.
+    fn total(&self) -> Decimal {
+        self.available.to_decimal() + self.held.to_decimal()
+    }
+
+    /// A copy of the balances, for rolling back failed operations.
+    fn balances(&self) -> ClientBalances {
+        ClientBalances {
+            available: self.available,
+            held: self.held,
+            fees: self.fees,
+            reserve: self.reserve,
+            locked: self.locked,
+        }
+    }
+
+    /// The balances of this client with those of `other` added, or
+    /// `None` if any of them, or the total, would overflow.
+    fn combined(&self, other: &Client) -> Option<ClientBalances> {
+        let balances = ClientBalances {
+            available: self.available.checked_add(other.available)?,
+            held: self.held.checked_add(other.held)?,
+            fees: self.fees.checked_add(other.fees)?,
+            reserve: self.reserve.checked_add(other.reserve)?,
+            locked: self.locked || other.locked,
+        };
+        balances.available.checked_add(balances.held)?;
+        Some(balances)
+    }
+
+    /// Restores balances copied by `balances`.
+    fn restore(&mut self, balances: ClientBalances) {
+        self.available = balances.available;
+        self.held = balances.held;
+        self.fees = balances.fees;
+        self.reserve = balances.reserve;
+        self.locked = balances.locked;
+    }
+
+    /// The entry representing this client in the state hash.
+    fn hash_entry(&self) -> Vec<u8> {
+        let mut entry = format!(
+            "client,{},{},{},{},{}",
+            self.id,
+            self.available.to_decimal().normalize(),
+            self.held.to_decimal().normalize(),
+            self.locked,
+            self.flags
+        );
+        // Left out when unused, so hashes match those of older versions.
+        if !self.fees.is_zero() {
+            entry += &format!(",{}", self.fees.to_decimal().normalize());
+        }
+        if !self.reserve.is_zero() {
+            entry += &format!(",reserve,{}", self.reserve.to_decimal().normalize());
+        }
+        entry.into_bytes()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// The parts of a `Client` changed by a `BalanceOp`.
+struct ClientBalances {
+    available: Balance,
+    held: Balance,
+    fees: Balance,
+    reserve: Balance,
+    locked: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// A single change to one client, applied with `CurrentState::apply_atomic`.
+enum BalanceOp {
+    /// Adds `amount` to the available funds.
+    Credit { client: u16, amount: Decimal },
+    /// Removes `amount` from the available funds, failing
+    /// if that would leave them below `-overdraft`.
+    Debit {
+        client: u16,
+        amount: Decimal,
+        overdraft: Decimal,
+    },
+    /// Moves `amount` from the available to the held funds.
+    Hold { client: u16, amount: Decimal },
+    /// Moves `amount` from the held to the available funds.
+    Release { client: u16, amount: Decimal },
+    /// Removes `amount` from the held funds.
+    ChargeOff { client: u16, amount: Decimal },
+    /// Removes `amount` from the available funds, which may take them
+    /// below zero, for the part of a charged back transaction that was
+    /// not held.
+    Forfeit { client: u16, amount: Decimal },
+    /// Removes a fee of `amount` from the available funds, which may
+    /// take them below zero.
+    Fee { client: u16, amount: Decimal },
+    /// Adds a fee of `amount` to the fees collected, leaving the
+    /// available funds as they are.
+    CollectFee { client: u16, amount: Decimal },
+    /// Moves `amount` from the available funds to the reserve.
+    Reserve { client: u16, amount: Decimal },
+    /// Moves `amount` from the reserve to the available funds.
+    ReleaseReserve { client: u16, amount: Decimal },
+    /// Locks the account.
+    Lock { client: u16 },
+}
+
+impl BalanceOp {
+    /// The ID of the client this operation changes.
+    fn client(&self) -> u16 {
+        match *self {
+            BalanceOp::Credit { client, .. }
+            | BalanceOp::Debit { client, .. }
+            | BalanceOp::Hold { client, .. }
+            | BalanceOp::Release { client, .. }
+            | BalanceOp::ChargeOff { client, .. }
+            | BalanceOp::Forfeit { client, .. }
+            | BalanceOp::Fee { client, .. }
+            | BalanceOp::CollectFee { client, .. }
+            | BalanceOp::Reserve { client, .. }
+            | BalanceOp::ReleaseReserve { client, .. }
+            | BalanceOp::Lock { client } => client,
+        }
+    }
+
+    /// Applies the operation for the record with ID `tx`. Fails,
+    /// leaving the balances for the caller to restore, if any balance
+    /// or the client's total would overflow, or if the amount cannot be
+    /// held in a balance.
+    fn apply(&self, tx: u32, client: &mut Client) -> Result<(), crate::errors::Error> {
+        let overflow = || TransactionError::ArithmeticOverflow(tx);
+        let add = |balance: Balance, amount: Decimal| {
+            let amount =
+                Balance::from_decimal(amount).ok_or(TransactionError::UnrepresentableAmount(tx))?;
+            balance.checked_add(amount).ok_or_else(overflow)
+        };
+        let sub = |balance: Balance, amount: Decimal| {
+            let amount =
+                Balance::from_decimal(amount).ok_or(TransactionError::UnrepresentableAmount(tx))?;
+            balance.checked_sub(amount).ok_or_else(overflow)
+        };
+        match *self {
+            BalanceOp::Credit { amount, .. } => client.available = add(client.available, amount)?,
+            BalanceOp::Debit {
+                amount, overdraft, ..
+            } => {
+                let remaining = sub(client.available, amount)?;
+                // Checked to fit when the state was built.
+                if remaining < Balance::from_decimal(-overdraft).unwrap() {
+                    return Err(ClientError::InsufficientFunds(tx).into());
+                }
+                client.available = remaining;
+            }
+            BalanceOp::Hold { amount, .. } => {
+                client.available = sub(client.available, amount)?;
+                client.held = add(client.held, amount)?;
+            }
+            BalanceOp::Release { amount, .. } => {
+                client.held = sub(client.held, amount)?;
+                client.available = add(client.available, amount)?;
+            }
+            BalanceOp::ChargeOff { amount, .. } => client.held = sub(client.held, amount)?,
+            BalanceOp::Forfeit { amount, .. } => client.available = sub(client.available, amount)?,
+            BalanceOp::Fee { amount, .. } => client.available = sub(client.available, amount)?,
+            BalanceOp::CollectFee { amount, .. } => client.fees = add(client.fees, amount)?,
+            BalanceOp::Reserve { amount, .. } => {
+                client.available = sub(client.available, amount)?;
+                client.reserve = add(client.reserve, amount)?;
+            }
+            BalanceOp::ReleaseReserve { amount, .. } => {
+                client.reserve = sub(client.reserve, amount)?;
+                client.available = add(client.available, amount)?;
+            }
+            BalanceOp::Lock { .. } => client.locked = true,
+        }
+        // Totals are reported as the sum of both, so it must fit too.
+        client
+            .available
+            .checked_add(client.held)
+            .ok_or_else(overflow)?;
+        Ok(())
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The entry representing an open dispute in the state hash.
+fn dispute_hash_entry(tx: &Transaction) -> Vec<u8> {
+    format!("dispute,{},{}", tx.id, tx.client).into_bytes()
+}
+
+/// The entry representing a client merged into another in the state
+/// hash.
+fn merged_hash_entry(src: u16, dst: u16) -> Vec<u8> {
+    format!("merged,{},{}", src, dst).into_bytes()
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+/// A request to merge the client `source` into `destination`, for
+/// `CurrentState::merge_clients`.
+pub struct ClientMerge {
+    pub source: u16,
+    pub destination: u16,
+    /// Whether to merge even if either client is locked.
+    #[serde(default)]
+    pub force: bool,
+}
+
+impl ClientMerge {
+    /// Reads a CSV file with `source` and `destination` columns, and
+    /// optionally `force`, in the order the merges should be made.
+    pub fn from_csv(reader: impl std::io::Read) -> Result<Vec<Self>, csv::Error> {
+        csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader)
+            .deserialize()
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
 /// Final client state, with fields the same as `Client`.
 /// An additional field is provided for total, but
 /// calculated on the fly.
 /// Used for serialization.
-struct CsvClient {
-    client: u16,
-    available: Decimal,
-    held: Decimal,
-    total: Decimal,
-    locked: bool,
+pub struct CsvClient {
+    pub client: u16,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    /// The fees collected, if kept apart from the available funds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fees: Option<Decimal>,
+    /// The share of deposits held back in reserve, if reserves are
+    /// enabled. It is not part of `total`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserve: Option<Decimal>,
+    pub locked: bool,
+    #[serde(default)]
+    pub flags: ClientFlags,
+    /// Whether an administrative freeze applies, if freezes are enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frozen: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub risk_score: Option<u32>,
+}
+
+impl From<&Client> for CsvClient {
+    fn from(in_state: &Client) -> Self {
+        CsvClient {
+            client: in_state.id,
+            available: in_state.available.to_decimal(),
+            held: in_state.held.to_decimal(),
+            total: in_state.total(),
+            fees: None,
+            reserve: None,
+            locked: in_state.locked,
+            flags: in_state.flags,
+            frozen: None,
+            risk_score: None,
+        }
+    }
+}
+
+/// A custom transaction handler's view of the state. Changes are
+/// collected and applied together, all or nothing, once the handler
+/// returns, so reads do not reflect them.
+pub struct StateView<'a> {
+    state: &'a CurrentState,
+    ops: Vec<BalanceOp>,
+}
+
+impl StateView<'_> {
+    /// The current state of the client with ID `id`, if it exists.
+    pub fn client(&self, id: u16) -> Option<CsvClient> {
+        self.state.client(id)
+    }
+
+    /// The deposit, withdrawal or adjustment with ID `id`, if it is
+    /// held in memory.
+    pub fn transaction(&self, id: u32) -> Option<Transaction> {
+        let tx = self.state.transactions.get(&id)?;
+        Some(tx.to_transaction(id))
+    }
+
+    /// Adds `amount` to the client's available funds.
+    pub fn credit(&mut self, client: u16, amount: Decimal) {
+        self.ops.push(BalanceOp::Credit { client, amount });
+    }
+
+    /// Takes `amount` from the client's available funds, rejecting the
+    /// record if that would exceed the overdraft limit.
+    pub fn debit(&mut self, client: u16, amount: Decimal) {
+        self.ops.push(BalanceOp::Debit {
+            client,
+            amount,
+            overdraft: self.state.policies.overdraft,
+        });
+    }
+
+    /// Moves `amount` of the client's available funds to held.
+    pub fn hold(&mut self, client: u16, amount: Decimal) {
+        self.ops.push(BalanceOp::Hold { client, amount });
+    }
+
+    /// Moves `amount` of the client's held funds back to available.
+    pub fn release(&mut self, client: u16, amount: Decimal) {
+        self.ops.push(BalanceOp::Release { client, amount });
+    }
+
+    /// Locks the client.
+    pub fn lock(&mut self, client: u16) {
+        self.ops.push(BalanceOp::Lock { client });
+    }
+}
+
+type Transactions = HashMap<u32, StoredTx>;
+type Disputes = HashMap<u32, Transaction>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A transaction ID accepted by another state this one is merged with.
+pub(crate) struct TakenId {
+    /// The client it was accepted for.
+    pub(crate) client: u16,
+    /// Whether it was applied, rather than held in quarantine, so
+    /// disputes may refer to it.
+    pub(crate) applied: bool,
+}
+
+/// The timestamps of a client's recent disputes, for initiators with a
+/// limit.
+type DisputeHistory = HashMap<DisputeInitiator, VecDeque<u64>>;
+
+#[derive(Debug, Default)]
+/// The state of every client, kept as a column for each field of
+/// `Client` rather than as a map of them, so that scans over a few
+/// fields, such as the balances, only touch the memory holding those,
+/// and each client takes no more than its fields and one index entry.
+///
+/// Clients are copied out of the columns and written back, rather than
+/// borrowed. Removing a client moves the last one into its place, so
+/// iteration is in the order clients were added only until then.
+struct ClientStates {
+    /// The position of each client in the columns.
+    index: HashMap<u16, u32>,
+    ids: Vec<u16>,
+    available: Vec<Balance>,
+    held: Vec<Balance>,
+    fees: Vec<Balance>,
+    reserve: Vec<Balance>,
+    locked: Vec<bool>,
+    flags: Vec<ClientFlags>,
+    /// The dispute history of the clients that have one, which few do.
+    dispute_history: HashMap<u16, DisputeHistory>,
+    /// The client a group of records is being applied for, with its
+    /// position once it exists, so that it is looked up once for the
+    /// group rather than once for each record.
+    group: Option<(u16, Option<u32>)>,
+}
+
+impl ClientStates {
+    /// The number of clients.
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Whether the client with ID `id` exists.
+    fn contains_key(&self, id: u16) -> bool {
+        self.position(id).is_some()
+    }
+
+    /// The position of the client with ID `id` in the columns, if it
+    /// exists.
+    fn position(&self, id: u16) -> Option<usize> {
+        match self.group {
+            Some((client, Some(i))) if client == id => Some(i as usize),
+            _ => self.index.get(&id).map(|&i| i as usize),
+        }
+    }
+
+    /// Starts a group of records for the client with ID `id`, looking
+    /// it up once for all of them.
+    fn start_group(&mut self, id: u16) {
+        self.group = Some((id, self.index.get(&id).copied()));
+    }
+
+    /// Ends the group of records started last.
+    fn end_group(&mut self) {
+        self.group = None;
+    }
+
+    /// A copy of the client at position `i` of the columns.
+    fn at(&self, i: usize) -> Client {
+        Client {
+            id: self.ids[i],
+            available: self.available[i],
+            held: self.held[i],
+            fees: self.fees[i],
+            reserve: self.reserve[i],
+            locked: self.locked[i],
+            flags: self.flags[i],
+        }
+    }
+
+    /// A copy of the client with ID `id`, if it exists.
+    fn get(&self, id: u16) -> Option<Client> {
+        let i = self.position(id)?;
+        Some(self.at(i))
+    }
+
+    /// Writes `client` back, adding it if it does not exist.
+    fn insert(&mut self, client: Client) {
+        let i = match self.position(client.id) {
+            Some(i) => i,
+            None => {
+                // Client IDs are `u16`s, so positions always fit.
+                let i = self.ids.len() as u32;
+                self.index.insert(client.id, i);
+                if let Some((id, position)) = &mut self.group {
+                    if *id == client.id {
+                        *position = Some(i);
+                    }
+                }
+                self.ids.push(client.id);
+                self.available.push(client.available);
+                self.held.push(client.held);
+                self.fees.push(client.fees);
+                self.reserve.push(client.reserve);
+                self.locked.push(client.locked);
+                self.flags.push(client.flags);
+                return;
+            }
+        };
+        self.available[i] = client.available;
+        self.held[i] = client.held;
+        self.fees[i] = client.fees;
+        self.reserve[i] = client.reserve;
+        self.locked[i] = client.locked;
+        self.flags[i] = client.flags;
+    }
+
+    /// Changes the client with ID `id` with `change`, if it exists,
+    /// returning what `change` did.
+    fn update<R>(&mut self, id: u16, change: impl FnOnce(&mut Client) -> R) -> Option<R> {
+        let mut client = self.get(id)?;
+        let result = change(&mut client);
+        self.insert(client);
+        Some(result)
+    }
+
+    /// Removes the client with ID `id` and its dispute history,
+    /// returning the client if it existed.
+    fn remove(&mut self, id: u16) -> Option<Client> {
+        let i = self.index.remove(&id)? as usize;
+        let client = self.at(i);
+        self.ids.swap_remove(i);
+        self.available.swap_remove(i);
+        self.held.swap_remove(i);
+        self.fees.swap_remove(i);
+        self.reserve.swap_remove(i);
+        self.locked.swap_remove(i);
+        self.flags.swap_remove(i);
+        if let Some(&moved) = self.ids.get(i) {
+            self.index.insert(moved, i as u32);
+        }
+        // The grouped client may be the one removed or the one moved.
+        if let Some((id, position)) = &mut self.group {
+            *position = self.index.get(id).copied();
+        }
+        self.dispute_history.remove(&id);
+        Some(client)
+    }
+
+    /// The IDs of every client.
+    fn keys(&self) -> impl Iterator<Item = u16> + '_ {
+        self.ids.iter().copied()
+    }
+
+    /// Copies of every client.
+    fn values(&self) -> impl Iterator<Item = Client> + '_ {
+        (0..self.len()).map(|i| self.at(i))
+    }
+
+    /// Removes and returns the dispute history of the client with ID
+    /// `id`, which is empty if it has none.
+    fn take_dispute_history(&mut self, id: u16) -> DisputeHistory {
+        self.dispute_history.remove(&id).unwrap_or_default()
+    }
+
+    /// Adds `history` to that of the client with ID `id`, keeping each
+    /// initiator's disputes in order.
+    fn extend_dispute_history(&mut self, id: u16, history: DisputeHistory) {
+        let existing = self.dispute_history.entry(id).or_default();
+        for (initiator, history) in history {
+            let existing = existing.entry(initiator).or_default();
+            existing.extend(history);
+            existing.make_contiguous().sort_unstable();
+        }
+    }
+
+    /// Records a dispute opened by `initiator` at `timestamp` against
+    /// the client with ID `id`, which exists, and the initiator's limit,
+    /// raising the review flag once the limit is reached. Returns
+    /// whether the flag was newly raised.
+    fn record_dispute(
+        &mut self,
+        id: u16,
+        initiator: DisputeInitiator,
+        timestamp: Option<u64>,
+        limit: DisputeLimit,
+    ) -> bool {
+        let history = self
+            .dispute_history
+            .entry(id)
+            .or_default()
+            .entry(initiator)
+            .or_default();
+        // Timestamps are guaranteed to be present when a window is set.
+        let now = timestamp.unwrap_or_default();
+        history.push_back(now);
+        if let Some(window) = limit.window {
+            while history
+                .front()
+                .is_some_and(|&opened| now.saturating_sub(opened) > window)
+            {
+                history.pop_front();
+            }
+        }
+        let reached = history.len() >= limit.count as usize;
+        // The client exists, so it has a position.
+        let i = self.position(id).unwrap_or_default();
+        let newly_flagged = reached && !self.flags[i].contains(ClientFlags::REVIEW);
+        if reached {
+            self.flags[i].insert(ClientFlags::REVIEW);
+        }
+        newly_flagged
+    }
+
+    /// A rough estimate of the bytes taken by the columns and the index,
+    /// leaving out dispute histories.
+    fn estimated_memory(&self) -> u64 {
+        let column = |capacity: usize, size: usize| (capacity * size) as u64;
+        // Hash maps keep one control byte per slot besides the entry.
+        column(self.index.capacity(), size_of::<(u16, u32)>() + 1)
+            + column(self.ids.capacity(), size_of::<u16>())
+            + column(self.available.capacity(), size_of::<Balance>())
+            + column(self.held.capacity(), size_of::<Balance>())
+            + column(self.fees.capacity(), size_of::<Balance>())
+            + column(self.reserve.capacity(), size_of::<Balance>())
+            + column(self.locked.capacity(), size_of::<bool>())
+            + column(self.flags.capacity(), size_of::<ClientFlags>())
+    }
+}
+
+/// Reads every record of a CSV or JSON Lines stream under `policies`,
+/// checking the schema and normalizing amounts of CSV as configured,
+/// and passes each up to the `as_of` point, if any, to `each`.
+fn read_records(
+    policies: Policies,
+    progress: Option<Arc<ProgressTracker>>,
+    reader: impl std::io::Read,
+    mut each: impl FnMut(Transaction) -> Result<(), errors::Error>,
+) -> Result<(), errors::Error> {
+    let tracker = progress.clone();
+    let mut reader = BufReader::new(TrackedReader::new(reader, progress));
+    let format = match policies.input_format {
+        InputFormat::Auto => sniff::detect(reader.fill_buf()?)?,
+        format => format,
+    };
+    let mut records = 0;
+    let mut apply = |tx: Transaction| {
+        records += 1;
+        if let Some(tracker) = &tracker {
+            tracker.record();
+        }
+        // Later records may still be timestamped earlier, so the rest
+        // of the input is read rather than abandoned.
+        if policies
+            .as_of
+            .is_some_and(|as_of| as_of.excludes(records, &tx))
+        {
+            return Ok(());
+        }
+        each(tx)
+    };
+    if format == InputFormat::Jsonl {
+        for (line, text) in (1..).zip(reader.lines()) {
+            let text = text?;
+            if text.trim().is_empty() {
+                continue;
+            }
+            let tx = serde_json::from_str(&text)
+                .map_err(|err| errors::FormatError::InvalidRecord(line, err))?;
+            apply(tx)?;
+        }
+        return Ok(());
+    }
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let strict = policies.strict_schema;
+    let headers = rdr.headers()?.clone();
+    if strict {
+        schema::check_headers(&headers)?;
+    }
+    let diagnose = |err: csv::Error| match strict {
+        true => schema::diagnose(err, &headers),
+        false => err.into(),
+    };
+    let localized = policies.decimal_style.and_then(|style| {
+        let column = headers.iter().position(|header| header == "amount")?;
+        Some((style, column))
+    });
+    let parse = |record: &csv::StringRecord| -> Result<Transaction, errors::Error> {
+        let tx = match localized {
+            Some((style, column)) => style
+                .normalize_record(record, column)?
+                .deserialize(Some(&headers)),
+            None => record.deserialize(Some(&headers)),
+        };
+        tx.map_err(diagnose)
+    };
+    match policies.parse_threads {
+        Some(threads) => parse::parse_parallel(&mut rdr, threads, parse, diagnose, apply),
+        None => {
+            let mut record = csv::StringRecord::new();
+            while rdr.read_record(&mut record).map_err(diagnose)? {
+                apply(parse(&record)?)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The largest number of decimal places a `Decimal` can represent.
+const MAX_DECIMAL_PLACES: u32 = 28;
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// What to do when a transaction ID is accepted from more than one
+/// of the inputs being merged.
+pub enum ConflictPolicy {
+    /// Fail the merge.
+    #[default]
+    Fail,
+    /// Keep the transaction from the first input, and reject the
+    /// copies in the others.
+    KeepFirst,
+    /// Keep the transaction from the last input, and reject the
+    /// copies in the others.
+    KeepLast,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(ConflictPolicy::Fail),
+            "keep-first" => Ok(ConflictPolicy::KeepFirst),
+            "keep-last" => Ok(ConflictPolicy::KeepLast),
+            _ => Err(format!(
+                "unknown conflict policy `{}`, expected one of `fail`, `keep-first` or `keep-last`",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// How many disputes one initiator may open against a client
+/// before the client is flagged for review.
+pub struct DisputeLimit {
+    /// The number of disputes that raises the flag.
+    pub count: u32,
+    /// Only disputes opened within this many seconds of each other
+    /// are counted; if `None`, all disputes are counted.
+    pub window: Option<u64>,
+}
+
+impl FromStr for DisputeLimit {
+    type Err = String;
+
+    /// Parses `count` or `count/window`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (count, window) = match s.split_once('/') {
+            Some((count, window)) => (count, Some(window)),
+            None => (s, None),
+        };
+        Ok(DisputeLimit {
+            count: count
+                .parse()
+                .map_err(|_| format!("invalid dispute count `{}`", count))?,
+            window: window
+                .map(|window| {
+                    window
+                        .parse()
+                        .map_err(|_| format!("invalid dispute window `{}`", window))
+                })
+                .transpose()?,
+        })
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// How often a transaction may be disputed again after a dispute
+/// of it was resolved.
+pub enum ReDisputePolicy {
+    #[default]
+    /// Resolved transactions may be disputed again indefinitely.
+    Unlimited,
+    /// Resolved transactions may be disputed again this many times;
+    /// zero forbids re-disputes.
+    Limit(u32),
+}
+
+impl FromStr for ReDisputePolicy {
+    type Err = String;
+
+    /// Parses `unlimited`, `forbid`, `once` or a number of re-disputes.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unlimited" => Ok(ReDisputePolicy::Unlimited),
+            "forbid" => Ok(ReDisputePolicy::Limit(0)),
+            "once" => Ok(ReDisputePolicy::Limit(1)),
+            _ => s
+                .parse()
+                .map(ReDisputePolicy::Limit)
+                .map_err(|_| format!("invalid re-dispute policy `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// What is done with a dispute of a transaction already under dispute.
+pub enum DuplicateDisputePolicy {
+    #[default]
+    /// Duplicates are rejected as `dispute_already_exists`.
+    Reject,
+    /// Duplicates are accepted, and change nothing.
+    Ignore,
+    /// Duplicates are accepted, and their reference, external
+    /// reference and reason code replace those of the open dispute,
+    /// such as when a processor sends a new reason code.
+    UpdateMetadata,
+}
+
+impl FromStr for DuplicateDisputePolicy {
+    type Err = String;
+
+    /// Parses `reject`, `ignore` or `update-metadata`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(DuplicateDisputePolicy::Reject),
+            "ignore" => Ok(DuplicateDisputePolicy::Ignore),
+            "update-metadata" => Ok(DuplicateDisputePolicy::UpdateMetadata),
+            _ => Err(format!("invalid duplicate dispute policy `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// What is done in the output with clients that have no funds and never
+/// had a deposit or withdrawal applied, such as those only named by
+/// custom records that moved nothing.
+pub enum NeverActivePolicy {
+    #[default]
+    /// They are written like any other client.
+    Include,
+    /// They are left out.
+    Suppress,
+    /// They are written with the `never_active` flag.
+    Flag,
+}
+
+impl FromStr for NeverActivePolicy {
+    type Err = String;
+
+    /// Parses `include`, `suppress` or `flag`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "include" => Ok(NeverActivePolicy::Include),
+            "suppress" => Ok(NeverActivePolicy::Suppress),
+            "flag" => Ok(NeverActivePolicy::Flag),
+            _ => Err(format!("invalid never-active client policy `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// How old a settled transaction must be before it is archived.
+/// When both are set, a transaction must be old enough by both.
+pub struct ArchiveAge {
+    /// The number of records processed since the transaction.
+    pub records: Option<u64>,
+    /// The number of seconds between the transaction and the
+    /// latest timestamp seen.
+    pub seconds: Option<u64>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// How long a client with no funds must go without activity before
+/// it is considered dormant. When both are set, a client must be
+/// inactive by both; when neither is, no funds is enough.
+pub struct Dormancy {
+    /// The number of records processed since the client's last one.
+    pub records: Option<u64>,
+    /// The number of seconds between the client's last record and the
+    /// latest timestamp seen.
+    pub seconds: Option<u64>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// What is done when the state's estimated memory reaches its limit.
+pub enum LimitPolicy {
+    #[default]
+    /// Records that would add clients or transactions are rejected.
+    Reject,
+    /// Settled transactions are moved to the archive, however recent,
+    /// and records are only rejected if that does not free enough.
+    Spill,
+}
+
+impl FromStr for LimitPolicy {
+    type Err = String;
+
+    /// Parses `reject` or `spill`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(LimitPolicy::Reject),
+            "spill" => Ok(LimitPolicy::Spill),
+            _ => Err(format!("invalid limit policy `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// Caps on what a state may hold, so a run on a shared host does not
+/// grow without bound. Records that would take the state past a cap
+/// are rejected with a code naming it; what is already held is kept.
+pub struct ResourceLimits {
+    /// The most clients, so records for new ones are rejected once
+    /// there are this many.
+    pub max_clients: Option<usize>,
+    /// The most disputes open at once.
+    pub max_open_disputes: Option<usize>,
+    /// The most bytes the state's maps are estimated to take, as given
+    /// by `CurrentState::estimated_memory`.
+    pub max_memory: Option<u64>,
+    /// What is done when `max_memory` is reached.
+    pub policy: LimitPolicy,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// A point in the input to reconstruct the state as of. Later
+/// records are read but not applied.
+pub enum AsOf {
+    /// After the record with this number, counting from one within
+    /// each input, as in the `seq` of events.
+    Record(u64),
+    /// After every record timestamped at or before this time.
+    Timestamp(u64),
+}
+
+impl AsOf {
+    /// Whether the record numbered `record` is past this point.
+    pub(crate) fn excludes(self, record: u64, tx: &Transaction) -> bool {
+        match self {
+            AsOf::Record(last) => record > last,
+            AsOf::Timestamp(last) => tx.timestamp.is_some_and(|timestamp| timestamp > last),
+        }
+    }
+}
+
+impl FromStr for AsOf {
+    type Err = String;
+
+    /// Parses `record:<number>` or `timestamp:<seconds>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "expected `record:<number>` or `timestamp:<seconds>`, got `{}`",
+                s
+            )
+        };
+        let (kind, value) = s.split_once(':').ok_or_else(invalid)?;
+        let value = value.parse().map_err(|_| invalid())?;
+        match kind {
+            "record" => Ok(AsOf::Record(value)),
+            "timestamp" => Ok(AsOf::Timestamp(value)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// How many of the most recent records a fail-safe state keeps,
+/// to report with a broken invariant.
+const FAIL_SAFE_HISTORY: usize = 10;
+
+/// How many records are processed between automatic compactions.
+const COMPACT_INTERVAL: u64 = 10_000;
+
+/// How many keys of looked up records are cached before the cache is
+/// emptied.
+const ENRICHMENT_CACHE_SIZE: usize = 100_000;
+
+/// How many consecutive records of one client are held back to be
+/// applied as a group, at most.
+pub const MAX_GROUP: usize = 1024;
+
+#[derive(Debug, Default, Clone, Copy)]
+/// The configurable policies applied while processing.
+struct Policies {
+    /// The largest amount allowed for a single deposit or withdrawal.
+    max_amount: Option<Decimal>,
+    /// The number of decimal places incoming amounts are rounded to.
+    rounding: Option<u32>,
+    /// How far below zero a withdrawal may take the available funds.
+    overdraft: Decimal,
+    /// Whether every transaction must carry a timestamp.
+    timestamps: bool,
+    /// How many seconds after a transaction it may still be disputed.
+    dispute_window: Option<u64>,
+    /// How many records to process between reports of the state hash.
+    hash_every: Option<u64>,
+    /// The account chargebacked funds are moved to.
+    suspense_account: Option<u16>,
+    /// Whether adjustment transactions are accepted.
+    allow_adjustments: bool,
+    /// How transaction IDs shared between merged states are handled.
+    merge_conflicts: ConflictPolicy,
+    /// The dispute limits for each initiator, indexed by initiator.
+    dispute_limits: [Option<DisputeLimit>; DisputeInitiator::ALL.len()],
+    /// How old settled transactions must be before they are archived.
+    archive_age: Option<ArchiveAge>,
+    /// Whether input must match the expected columns exactly.
+    strict_schema: bool,
+    /// Deposits and withdrawals above this amount are quarantined.
+    quarantine_above: Option<Decimal>,
+    /// How many deposits and withdrawals a client may make in a
+    /// window before further ones are quarantined.
+    quarantine_velocity: Option<VelocityLimit>,
+    /// Whether a changelog of events is recorded.
+    events: bool,
+    /// The fee assessed for every chargeback.
+    chargeback_fee: Option<Decimal>,
+    /// The account chargeback fees are charged to, instead of the client.
+    fee_account: Option<u16>,
+    /// How amounts in the input are written, if not in the plain form.
+    decimal_style: Option<DecimalStyle>,
+    /// Whether client output includes a risk score.
+    risk_scores: bool,
+    /// How often resolved transactions may be disputed again.
+    redisputes: ReDisputePolicy,
+    /// What is done with disputes of transactions already disputed.
+    duplicate_disputes: DuplicateDisputePolicy,
+    /// Whether the funds held for each dispute are recorded.
+    dispute_report: bool,
+    /// Whether the withdrawals spending each deposit are traced.
+    trace_funds: bool,
+    /// Whether every record applied to each client is kept.
+    history: bool,
+    /// The annual rate at which held funds are priced.
+    float_rate: Option<Decimal>,
+    /// The business days after a dispute is opened that it must be
+    /// resolved or charged back by.
+    chargeback_deadline: Option<u32>,
+    /// When clients with no funds are left out of the output.
+    dormancy: Option<Dormancy>,
+    /// What is done with clients that have no funds and never had a
+    /// deposit or withdrawal applied.
+    never_active: NeverActivePolicy,
+    /// Whether processing stops at the first broken invariant.
+    fail_safe: bool,
+    /// The point in the input after which records are not applied.
+    as_of: Option<AsOf>,
+    /// How many threads turn input records into transactions.
+    parse_threads: Option<NonZeroUsize>,
+    /// Whether consecutive records of a client are applied as a group.
+    group_clients: bool,
+    /// Which changes clients are notified of.
+    triggers: Triggers,
+    /// Whether fees are collected apart from the available funds.
+    separate_fees: bool,
+    /// Whether resent records are skipped rather than rejected.
+    idempotent_replay: bool,
+    /// When accounts with too high a chargeback rate are acted on.
+    chargeback_monitor: Option<ChargebackMonitor>,
+    /// The share of the funds held for its open disputes that a client
+    /// must keep available after a withdrawal.
+    dispute_hold_buffer: Option<Decimal>,
+    /// Caps on what the state may hold.
+    limits: ResourceLimits,
+    /// How input records are written.
+    input_format: InputFormat,
+}
+
+#[derive(Debug, Default, Clone)]
+/// Collects the policies for a `CurrentState`, along with the
+/// backends it keeps on disk and the rule plugins it calls, and
+/// validates them together before any processing begins.
+pub struct CurrentStateBuilder {
+    policies: Policies,
+    /// The file settled transactions are archived to.
+    archive_path: Option<PathBuf>,
+    /// Resolves records naming an account rather than a client.
+    counterparties: CounterpartyMap,
+    /// Additional checks that may hold transactions for review.
+    quarantine_rules: Vec<Arc<dyn QuarantineRule>>,
+    /// Handlers for custom transaction types, by name.
+    transaction_types: Vec<(String, Arc<dyn TransactionHandler>)>,
+    /// Issues IDs for entries the engine creates itself.
+    id_allocator: Option<Arc<dyn TxIdAllocator>>,
+    /// Receives notifications for clients.
+    notifications: Option<Arc<dyn NotificationSink>>,
+    /// Receives the changelog events of every record, if anything does.
+    event_sink: Option<Arc<dyn EventSink>>,
+    /// Counts the records read.
+    progress: Option<Arc<ProgressTracker>>,
+    /// The merchants whose chargeback rates are monitored.
+    monitor_hierarchy: Option<Arc<Hierarchy>>,
+    /// Rules on disputes as they are opened.
+    adjudicator: Option<Arc<dyn Adjudicator>>,
+    /// Called around every record, in order.
+    middleware: Vec<Arc<dyn TxMiddleware>>,
+    /// Selects the records read from input, if only some are.
+    filter: Option<Arc<TxFilter>>,
+    /// The rejected records held back from input to be retried, if any.
+    retries: Option<Arc<Retries>>,
+    /// The reason codes records may give, if they are checked.
+    reason_codes: Option<Arc<ReasonCodes>>,
+    /// The balances clients are alerted on, if any.
+    balance_alerts: Option<Arc<BalanceAlerts>>,
+    /// The soft and hard limits of clients' accounts, if any.
+    account_limits: Option<Arc<AccountLimits>>,
+    /// The shares of disputed amounts held, if not all of them.
+    hold_rates: Option<Arc<HoldRates>>,
+    /// The clients whose deposits are partly held in reserve, if any.
+    reserves: Option<Arc<Reserves>>,
+    /// The clients frozen from the start, if freezes are enabled.
+    freezes: Option<Freezes>,
+    /// Looks up fields records lack.
+    enricher: Option<Arc<dyn Enricher>>,
+    /// The business days settlement dates and deadlines are counted in.
+    calendar: Option<Arc<Calendar>>,
+    /// The IDs accepted by earlier runs, if they are kept.
+    seen: Option<Arc<SeenSet>>,
+    /// The IDs accepted from the inputs this one yields to, if any.
+    taken: Option<Arc<HashMap<u32, TakenId>>>,
+    /// Blocks records for denied clients and accounts.
+    screener: Option<Arc<Screener>>,
+    #[cfg(feature = "chaos")]
+    /// Injects faults into records and backends, if any.
+    chaos: Option<Arc<Chaos>>,
 }
 
-impl From<&Client> for CsvClient {
-    fn from(in_state: &Client) -> Self {
-        CsvClient {
-            client: in_state.id,
-            available: in_state.available,
-            held: in_state.held,
-            total: in_state.available + in_state.held,
-            locked: in_state.locked,
+impl CurrentStateBuilder {
+    /// Rejects deposits and withdrawals larger than `amount`.
+    pub fn max_amount(mut self, amount: Decimal) -> Self {
+        self.policies.max_amount = Some(amount);
+        self
+    }
+
+    /// Rounds incoming amounts to `dp` decimal places.
+    pub fn rounding(mut self, dp: u32) -> Self {
+        self.policies.rounding = Some(dp);
+        self
+    }
+
+    /// Allows withdrawals to take available funds down to `-limit`.
+    pub fn overdraft(mut self, limit: Decimal) -> Self {
+        self.policies.overdraft = limit;
+        self
+    }
+
+    /// Rejects withdrawals by a client with open disputes that would
+    /// leave less than `ratio` times the funds held for them available,
+    /// as a cushion should the disputes be charged back.
+    pub fn dispute_hold_buffer(mut self, ratio: Decimal) -> Self {
+        self.policies.dispute_hold_buffer = Some(ratio);
+        self
+    }
+
+    /// Requires every transaction to carry a timestamp.
+    pub fn timestamps(mut self, enabled: bool) -> Self {
+        self.policies.timestamps = enabled;
+        self
+    }
+
+    /// Rejects disputes opened more than `seconds` after the transaction.
+    pub fn dispute_window(mut self, seconds: u64) -> Self {
+        self.policies.dispute_window = Some(seconds);
+        self
+    }
+
+    /// Reports the state hash on `stderr` every `records` records.
+    pub fn hash_every(mut self, records: u64) -> Self {
+        self.policies.hash_every = Some(records);
+        self
+    }
+
+    /// Moves chargebacked funds into the account with ID `id`,
+    /// rather than removing them from the system.
+    pub fn suspense_account(mut self, id: u16) -> Self {
+        self.policies.suspense_account = Some(id);
+        self
+    }
+
+    /// Accepts adjustment transactions, which are always audited.
+    pub fn allow_adjustments(mut self, enabled: bool) -> Self {
+        self.policies.allow_adjustments = enabled;
+        self
+    }
+
+    /// Sets how transaction IDs shared between merged states are handled.
+    pub fn merge_conflicts(mut self, policy: ConflictPolicy) -> Self {
+        self.policies.merge_conflicts = policy;
+        self
+    }
+
+    /// Flags clients for review once `initiator` reaches `limit` disputes.
+    pub fn dispute_limit(mut self, initiator: DisputeInitiator, limit: DisputeLimit) -> Self {
+        self.policies.dispute_limits[initiator as usize] = Some(limit);
+        self
+    }
+
+    /// Moves settled transactions older than `age` out of memory and
+    /// into an append-only archive at `path`, from which they can still
+    /// be looked up, more slowly.
+    pub fn archive(mut self, path: impl Into<PathBuf>, age: ArchiveAge) -> Self {
+        self.archive_path = Some(path.into());
+        self.policies.archive_age = Some(age);
+        self
+    }
+
+    /// Fails on unknown, missing or miscased columns, and reports the
+    /// row and column of malformed records.
+    pub fn strict_schema(mut self, enabled: bool) -> Self {
+        self.policies.strict_schema = enabled;
+        self
+    }
+
+    /// Charges a fee of `amount` for every chargeback.
+    pub fn chargeback_fee(mut self, amount: Decimal) -> Self {
+        self.policies.chargeback_fee = Some(amount);
+        self
+    }
+
+    /// Skips records repeating a deposit, withdrawal or adjustment
+    /// already applied, as partners resending a batch produce, rather
+    /// than rejecting them as `already_exists`. Records reusing an ID
+    /// for a different transaction are still rejected. Amounts are
+    /// compared by value, so reformatted resends such as `1.5000` for
+    /// `1.5` still match.
+    pub fn idempotent_replay(mut self, enabled: bool) -> Self {
+        self.policies.idempotent_replay = enabled;
+        self
+    }
+
+    /// Collects fees into a bucket of their own for each client,
+    /// reported in a `fees` column, rather than taking them from the
+    /// available funds, so gross and net amounts can be told apart.
+    pub fn separate_fees(mut self, enabled: bool) -> Self {
+        self.policies.separate_fees = enabled;
+        self
+    }
+
+    /// Charges chargeback fees to the account with ID `id`, such as a
+    /// merchant suspense account, rather than to the client.
+    pub fn fee_account(mut self, id: u16) -> Self {
+        self.policies.fee_account = Some(id);
+        self
+    }
+
+    /// Leaves clients with no funds and no recent activity out of the
+    /// output, listing them separately with `dormant_clients`.
+    pub fn dormancy(mut self, dormancy: Dormancy) -> Self {
+        self.policies.dormancy = Some(dormancy);
+        self
+    }
+
+    /// Checks the ledger's invariants after every record, and stops
+    /// processing at the first one that is broken: no client may hold
+    /// a negative amount, or hold anything but the amounts of its
+    /// open disputes.
+    pub fn fail_safe(mut self, enabled: bool) -> Self {
+        self.policies.fail_safe = enabled;
+        self
+    }
+
+    /// Turns input records into transactions on `threads` threads,
+    /// while they are still applied in order on the calling thread.
+    /// Results are the same as parsing on the calling thread.
+    pub fn parse_threads(mut self, threads: NonZeroUsize) -> Self {
+        self.policies.parse_threads = Some(threads);
+        self
+    }
+
+    /// Holds back consecutive input records of the same client, up to
+    /// `MAX_GROUP` of them, and applies them as a group that looks the
+    /// client up once. Records are still applied in order, so the
+    /// results do not change; only feeds roughly sorted by client gain.
+    pub fn group_clients(mut self, enabled: bool) -> Self {
+        self.policies.group_clients = enabled;
+        self
+    }
+
+    /// Reconstructs the state as of `point` in the input, such as the
+    /// balances when a chargeback hit, by not applying later records.
+    /// A timestamp requires timestamps to be enabled.
+    pub fn as_of(mut self, point: AsOf) -> Self {
+        self.policies.as_of = Some(point);
+        self
+    }
+
+    /// Skips the records read from input that `filter` does not
+    /// select, as `CurrentState::process_from_csv_filtered` does.
+    pub fn filter(mut self, filter: TxFilter) -> Self {
+        self.filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Holds back the records read from input that `retries` is to
+    /// retry, so they can be retried once the inputs are processed.
+    pub fn retries(mut self, retries: Arc<Retries>) -> Self {
+        self.retries = Some(retries);
+        self
+    }
+
+    /// Rejects disputes, resolves and chargebacks giving a reason code
+    /// that is not in `codes`.
+    pub fn reason_codes(mut self, codes: ReasonCodes) -> Self {
+        self.reason_codes = Some(Arc::new(codes));
+        self
+    }
+
+    /// Flags clients whose balances cross the thresholds in `alerts`,
+    /// with a changelog event and a notification when they do.
+    pub fn balance_alerts(mut self, alerts: BalanceAlerts) -> Self {
+        self.balance_alerts = Some(Arc::new(alerts));
+        self
+    }
+
+    /// Rejects deposits and withdrawals past the hard limits in
+    /// `limits`, and flags clients going past the soft limits, with a
+    /// changelog event and a notification when they do. Daily limits
+    /// require timestamps.
+    pub fn account_limits(mut self, limits: AccountLimits) -> Self {
+        self.account_limits = Some(Arc::new(limits));
+        self
+    }
+
+    /// Holds only the share of each disputed amount given by `rates`,
+    /// leaving the rest available.
+    pub fn hold_rates(mut self, rates: HoldRates) -> Self {
+        self.hold_rates = Some(Arc::new(rates));
+        self
+    }
+
+    /// Holds back part of the deposits of the clients in `reserves`,
+    /// releasing each share once its reserve period has passed, and
+    /// adds a `reserve` column to the output. Requires timestamps.
+    pub fn reserves(mut self, reserves: Reserves) -> Self {
+        self.reserves = Some(Arc::new(reserves));
+        self
+    }
+
+    /// Starts with the clients in `freezes` frozen, and adds a `frozen`
+    /// column to the output. Freezes can also be placed and lifted as
+    /// records are processed, with `CurrentState::freeze`.
+    pub fn freezes(mut self, freezes: Freezes) -> Self {
+        self.freezes = Some(freezes);
+        self
+    }
+
+    /// Records how long the funds of each dispute are held.
+    pub fn dispute_report(mut self, enabled: bool) -> Self {
+        self.policies.dispute_report = enabled;
+        self
+    }
+
+    /// Keeps every record applied to each client, so its history can be
+    /// listed page by page with `transaction_page`.
+    pub fn history(mut self, enabled: bool) -> Self {
+        self.policies.history = enabled;
+        self
+    }
+
+    /// Traces which withdrawals spent the funds of each deposit, first
+    /// in, first out, so the funds held for a dispute list those of the
+    /// disputed deposit that were already withdrawn.
+    pub fn trace_funds(mut self, enabled: bool) -> Self {
+        self.policies.trace_funds = enabled;
+        self
+    }
+
+    /// Prices held funds at an annual `rate`, such as `0.05` for 5%.
+    pub fn float_rate(mut self, rate: Decimal) -> Self {
+        self.policies.float_rate = Some(rate);
+        self
+    }
+
+    /// Dates the deadline of each dispute in the held funds report,
+    /// `days` business days after it was opened.
+    pub fn chargeback_deadline(mut self, days: u32) -> Self {
+        self.policies.chargeback_deadline = Some(days);
+        self
+    }
+
+    /// Counts settlement dates and deadlines in the business days of
+    /// `calendar`, rather than in every weekday.
+    pub fn calendar(mut self, calendar: Calendar) -> Self {
+        self.calendar = Some(Arc::new(calendar));
+        self
+    }
+
+    /// Rejects deposits, withdrawals and adjustments whose IDs are in
+    /// `seen`, and adds those accepted to it. The caller commits `seen`
+    /// once the run's results are written.
+    pub fn seen_ids(mut self, seen: Arc<SeenSet>) -> Self {
+        self.seen = Some(seen);
+        self
+    }
+
+    /// Rejects deposits, withdrawals and adjustments whose IDs were
+    /// accepted from another input, which wins the conflict.
+    pub(crate) fn taken_ids(mut self, taken: Arc<HashMap<u32, TakenId>>) -> Self {
+        self.taken = Some(taken);
+        self
+    }
+
+    /// Rejects records for clients, or naming accounts, that
+    /// `screener` denies, as `screened`, before anything else is
+    /// checked, and flags the clients.
+    pub fn screening(mut self, screener: Arc<Screener>) -> Self {
+        self.screener = Some(screener);
+        self
+    }
+
+    #[cfg(feature = "chaos")]
+    /// Drops, duplicates, delays and corrupts records as they are read,
+    /// and fails reads and writes of the archive and the seen IDs, as
+    /// `chaos` says. Only for testing how runs cope with failures.
+    pub fn chaos(mut self, chaos: Chaos) -> Self {
+        self.chaos = Some(Arc::new(chaos));
+        self
+    }
+
+    /// Records a changelog of balance, lock and dispute events.
+    pub fn events(mut self, enabled: bool) -> Self {
+        self.policies.events = enabled;
+        self
+    }
+
+    /// Publishes the changelog events of every record to `sink` as it
+    /// is applied. They are only kept in the state as well if `events`
+    /// is enabled.
+    pub fn event_sink(mut self, sink: Arc<dyn EventSink>) -> Self {
+        self.event_sink = Some(sink);
+        self
+    }
+
+    /// Holds deposits and withdrawals above `amount` for review.
+    pub fn quarantine_above(mut self, amount: Decimal) -> Self {
+        self.policies.quarantine_above = Some(amount);
+        self
+    }
+
+    /// Holds deposits and withdrawals for review once a client
+    /// exceeds `limit`.
+    pub fn quarantine_velocity(mut self, limit: VelocityLimit) -> Self {
+        self.policies.quarantine_velocity = Some(limit);
+        self
+    }
+
+    /// Holds deposits and withdrawals for review whenever `rule` asks to.
+    pub fn quarantine_rule(mut self, rule: Arc<dyn QuarantineRule>) -> Self {
+        self.quarantine_rules.push(rule);
+        self
+    }
+
+    /// Calls `middleware` around every record, after any added before.
+    pub fn middleware(mut self, middleware: Arc<dyn TxMiddleware>) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
+
+    /// Applies records whose type is `tag` with `handler`.
+    pub fn transaction_type(
+        mut self,
+        tag: impl Into<String>,
+        handler: Arc<dyn TransactionHandler>,
+    ) -> Self {
+        self.transaction_types.push((tag.into(), handler));
+        self
+    }
+
+    /// Reads amounts written in `style`, such as `1.234,56`.
+    pub fn decimal_style(mut self, style: DecimalStyle) -> Self {
+        self.policies.decimal_style = Some(style);
+        self
+    }
+
+    /// Limits how often a transaction may be disputed again once a
+    /// dispute of it has been resolved.
+    pub fn redisputes(mut self, policy: ReDisputePolicy) -> Self {
+        self.policies.redisputes = policy;
+        self
+    }
+
+    /// The builder's policies that have a default on the command line,
+    /// keyed by the name of their flag and written as the flag takes
+    /// them, such as `duplicate-disputes` and `reject`.
+    pub fn describe_policies(&self) -> BTreeMap<&'static str, String> {
+        let policies = &self.policies;
+        let redisputes = match policies.redisputes {
+            ReDisputePolicy::Unlimited => "unlimited".to_owned(),
+            ReDisputePolicy::Limit(limit) => limit.to_string(),
+        };
+        let duplicate_disputes = match policies.duplicate_disputes {
+            DuplicateDisputePolicy::Reject => "reject",
+            DuplicateDisputePolicy::Ignore => "ignore",
+            DuplicateDisputePolicy::UpdateMetadata => "update-metadata",
+        };
+        let never_active = match policies.never_active {
+            NeverActivePolicy::Include => "include",
+            NeverActivePolicy::Suppress => "suppress",
+            NeverActivePolicy::Flag => "flag",
+        };
+        let merge_conflicts = match policies.merge_conflicts {
+            ConflictPolicy::Fail => "fail",
+            ConflictPolicy::KeepFirst => "keep-first",
+            ConflictPolicy::KeepLast => "keep-last",
+        };
+        let limit_policy = match policies.limits.policy {
+            LimitPolicy::Reject => "reject",
+            LimitPolicy::Spill => "spill",
+        };
+        let input_format = match policies.input_format {
+            InputFormat::Auto => "auto",
+            InputFormat::Csv => "csv",
+            InputFormat::Jsonl => "jsonl",
+        };
+        BTreeMap::from([
+            ("allow-adjustments", policies.allow_adjustments.to_string()),
+            ("duplicate-disputes", duplicate_disputes.to_owned()),
+            ("fail-safe", policies.fail_safe.to_string()),
+            ("idempotent-replay", policies.idempotent_replay.to_string()),
+            ("input-format", input_format.to_owned()),
+            ("limit-policy", limit_policy.to_owned()),
+            ("merge-conflicts", merge_conflicts.to_owned()),
+            ("never-active", never_active.to_owned()),
+            ("overdraft", policies.overdraft.to_string()),
+            ("redisputes", redisputes),
+            ("risk-score", policies.risk_scores.to_string()),
+            ("separate-fees", policies.separate_fees.to_string()),
+            ("strict-schema", policies.strict_schema.to_string()),
+            ("timestamps", policies.timestamps.to_string()),
+        ])
+    }
+
+    /// Reads input records as `format`, rather than telling CSV and
+    /// JSON Lines apart by the start of each input.
+    pub fn input_format(mut self, format: InputFormat) -> Self {
+        self.policies.input_format = format;
+        self
+    }
+
+    /// Caps the clients, open disputes and estimated memory the state
+    /// may hold. Spilling when the memory limit is reached requires an
+    /// archive.
+    pub fn limits(mut self, limits: ResourceLimits) -> Self {
+        self.policies.limits = limits;
+        self
+    }
+
+    /// Sets what is done in the output with clients that have no funds
+    /// and never had a deposit or withdrawal applied, instead of
+    /// writing them as usual.
+    pub fn never_active(mut self, policy: NeverActivePolicy) -> Self {
+        self.policies.never_active = policy;
+        self
+    }
+
+    /// Sets what is done with a dispute of a transaction already under
+    /// dispute, instead of rejecting it.
+    pub fn duplicate_disputes(mut self, policy: DuplicateDisputePolicy) -> Self {
+        self.policies.duplicate_disputes = policy;
+        self
+    }
+
+    /// Adds each client's risk score to the output.
+    pub fn risk_scores(mut self, enabled: bool) -> Self {
+        self.policies.risk_scores = enabled;
+        self
+    }
+
+    /// Resolves records naming an external account, rather than a
+    /// client, through `map`.
+    pub fn counterparties(mut self, map: CounterpartyMap) -> Self {
+        self.counterparties = map;
+        self
+    }
+
+    /// Gives entries the engine creates itself, such as chargeback
+    /// fees, IDs of their own from `allocator`, and rejects partner
+    /// records using IDs it may issue. States built from clones of
+    /// this builder share the allocator.
+    pub fn id_allocator(mut self, allocator: Arc<dyn TxIdAllocator>) -> Self {
+        self.id_allocator = Some(allocator);
+        self
+    }
+
+    /// Passes the changes selected by `triggers` to `sink` as records
+    /// are applied. Without a sink, no notifications are made.
+    pub fn notifications(mut self, sink: Arc<dyn NotificationSink>, triggers: Triggers) -> Self {
+        self.notifications = Some(sink);
+        self.policies.triggers = triggers;
+        self
+    }
+
+    /// Counts the records read toward `tracker`, which reports them to
+    /// its observer. States built from clones of this builder share
+    /// the tracker.
+    pub fn progress(mut self, tracker: Arc<ProgressTracker>) -> Self {
+        self.progress = Some(tracker);
+        self
+    }
+
+    /// Acts on clients whose recent chargeback rate exceeds the
+    /// threshold of `monitor`, recording each crossing in the audit log.
+    pub fn chargeback_monitor(mut self, monitor: ChargebackMonitor) -> Self {
+        self.policies.chargeback_monitor = Some(monitor);
+        self
+    }
+
+    /// Monitors the chargeback rate of the merchants in `hierarchy`
+    /// rather than of each client under them, acting on all of a
+    /// merchant's clients at once. Clients not in it are monitored on
+    /// their own.
+    pub fn monitor_merchants(mut self, hierarchy: Hierarchy) -> Self {
+        self.monitor_hierarchy = Some(Arc::new(hierarchy));
+        self
+    }
+
+    /// Puts every dispute to `adjudicator` as it is opened, and applies
+    /// its ruling straight away as a resolve or chargeback record of
+    /// its own. Each ruling is written to the audit log.
+    pub fn adjudicator(mut self, adjudicator: Arc<dyn Adjudicator>) -> Self {
+        self.adjudicator = Some(adjudicator);
+        self
+    }
+
+    /// Fills in fields records lack with what `enricher` looks up for
+    /// them, before anything else sees them.
+    pub fn enricher(mut self, enricher: Arc<dyn Enricher>) -> Self {
+        self.enricher = Some(enricher);
+        self
+    }
+
+    /// The tracker records are counted toward, if any.
+    pub fn progress_tracker(&self) -> Option<&Arc<ProgressTracker>> {
+        self.progress.as_ref()
+    }
+
+    /// The screener records are checked by, if any.
+    pub fn screener(&self) -> Option<&Arc<Screener>> {
+        self.screener.as_ref()
+    }
+
+    /// Whether settled transactions are archived to a file.
+    pub fn archives(&self) -> bool {
+        self.archive_path.is_some()
+    }
+
+    /// How transaction IDs shared between merged states are handled.
+    pub fn conflict_policy(&self) -> ConflictPolicy {
+        self.policies.merge_conflicts
+    }
+
+    /// Validates the collected policies and creates the state.
+    pub fn build(self) -> Result<CurrentState, ConfigError> {
+        let policies = self.policies;
+        if let Some(amount) = policies.max_amount {
+            if amount <= Decimal::default() {
+                return Err(ConfigError::LimitNotPositive(amount));
+            }
+        }
+        if let Some(dp) = policies.rounding {
+            if dp > MAX_DECIMAL_PLACES {
+                return Err(ConfigError::InvalidRounding(dp));
+            }
+        }
+        if policies.overdraft < Decimal::default() {
+            return Err(ConfigError::NegativeOverdraft(policies.overdraft));
+        }
+        #[cfg(feature = "fixed-point")]
+        if let Some(dp) = policies.rounding.filter(|&dp| dp > crate::balance::PLACES) {
+            return Err(ConfigError::RoundingBeyondBalances(
+                dp,
+                crate::balance::PLACES,
+            ));
+        }
+        if Balance::from_decimal(-policies.overdraft).is_none() {
+            return Err(ConfigError::UnrepresentableOverdraft(policies.overdraft));
+        }
+        if let Some(ratio) = policies.dispute_hold_buffer {
+            if ratio < Decimal::default() {
+                return Err(ConfigError::NegativeDisputeHoldBuffer(ratio));
+            }
+        }
+        if policies.dispute_window.is_some() && !policies.timestamps {
+            return Err(ConfigError::DisputeWindowWithoutTimestamps);
+        }
+        if let Some(amount) = policies.quarantine_above {
+            if amount <= Decimal::default() {
+                return Err(ConfigError::LimitNotPositive(amount));
+            }
+        }
+        if let Some(limit) = policies.quarantine_velocity {
+            if limit.count == 0 {
+                return Err(ConfigError::ZeroVelocityLimit);
+            }
+            if !policies.timestamps {
+                return Err(ConfigError::VelocityWithoutTimestamps);
+            }
+        }
+        if self
+            .account_limits
+            .as_ref()
+            .is_some_and(|limits| limits.has_daily())
+            && !policies.timestamps
+        {
+            return Err(ConfigError::DailyLimitWithoutTimestamps);
+        }
+        if let Some(fee) = policies.chargeback_fee {
+            if fee <= Decimal::default() {
+                return Err(ConfigError::FeeNotPositive(fee));
+            }
+        }
+        if policies.fee_account.is_some() && policies.chargeback_fee.is_none() {
+            return Err(ConfigError::FeeAccountWithoutFee);
+        }
+        if policies
+            .dormancy
+            .is_some_and(|dormancy| dormancy.seconds.is_some() && !policies.timestamps)
+        {
+            return Err(ConfigError::DormancyAgeWithoutTimestamps);
+        }
+        if let Some(rate) = policies.float_rate {
+            if rate < Decimal::default() {
+                return Err(ConfigError::NegativeFloatRate(rate));
+            }
+            if !policies.timestamps {
+                return Err(ConfigError::FloatRateWithoutTimestamps);
+            }
+        }
+        if policies.chargeback_deadline.is_some() && !policies.timestamps {
+            return Err(ConfigError::DeadlineWithoutTimestamps);
+        }
+        if matches!(policies.as_of, Some(AsOf::Timestamp(_))) && !policies.timestamps {
+            return Err(ConfigError::AsOfTimestampWithoutTimestamps);
+        }
+        if let Some(monitor) = policies.chargeback_monitor {
+            if monitor.threshold <= Decimal::default() || monitor.threshold > Decimal::ONE {
+                return Err(ConfigError::InvalidChargebackThreshold(monitor.threshold));
+            }
+            if monitor.release < Decimal::default() || monitor.release > monitor.threshold {
+                return Err(ConfigError::InvalidChargebackRelease(monitor.release));
+            }
+            if monitor.window == 0 {
+                return Err(ConfigError::ZeroChargebackWindow);
+            }
+        }
+        if policies.hash_every == Some(0) {
+            return Err(ConfigError::ZeroHashInterval);
+        }
+        if self.reserves.is_some() && !policies.timestamps {
+            return Err(ConfigError::ReservesWithoutTimestamps);
+        }
+        if policies.limits.policy == LimitPolicy::Spill && policies.archive_age.is_none() {
+            return Err(ConfigError::SpillWithoutArchive);
+        }
+        if let Some(age) = policies.archive_age {
+            if age.records.is_none() && age.seconds.is_none() {
+                return Err(ConfigError::ArchiveWithoutAge);
+            }
+            if age.seconds.is_some() && !policies.timestamps {
+                return Err(ConfigError::ArchiveAgeWithoutTimestamps);
+            }
+        }
+        for initiator in DisputeInitiator::ALL {
+            if let Some(limit) = policies.dispute_limits[initiator as usize] {
+                if limit.count == 0 {
+                    return Err(ConfigError::ZeroDisputeLimit(initiator));
+                }
+                if limit.window.is_some() && !policies.timestamps {
+                    return Err(ConfigError::DisputeLimitWindowWithoutTimestamps);
+                }
+            }
+        }
+
+        let mut transaction_types = TransactionRegistry::default();
+        for (tag, handler) in self.transaction_types {
+            transaction_types.register(tag, handler)?;
+        }
+
+        let mut state = CurrentState {
+            policies,
+            archive: self.archive_path.map(Archive::new),
+            counterparties: self.counterparties,
+            quarantine_rules: self.quarantine_rules,
+            transaction_types,
+            id_allocator: self.id_allocator,
+            notifications: self.notifications,
+            event_sink: self.event_sink,
+            progress: self.progress,
+            monitor_hierarchy: self.monitor_hierarchy,
+            adjudicator: self.adjudicator,
+            middleware: self.middleware,
+            filter: self.filter,
+            retries: self.retries,
+            reason_codes: self.reason_codes,
+            balance_alerts: self.balance_alerts,
+            account_limits: self.account_limits,
+            hold_rates: self.hold_rates,
+            reserves: self.reserves,
+            freezes: self.freezes,
+            enricher: self.enricher,
+            calendar: self.calendar,
+            seen: self.seen,
+            taken: self.taken,
+            screener: self.screener,
+            #[cfg(feature = "chaos")]
+            chaos: self.chaos,
+            ..CurrentState::default()
+        };
+        if let Some(id) = policies.suspense_account {
+            let account = Client::from_id(id);
+            state.hash.insert(&account.hash_entry());
+            state.client_states.insert(account);
+        }
+        Ok(state)
+    }
+}
+
+#[derive(Debug, Default)]
+/// The overall state of the program at any given time.
+pub struct CurrentState {
+    /// A map from transaction IDs to deposits/withdrawals.
+    transactions: Transactions,
+    /// A list of active disputes.
+    disputes: Disputes,
+    /// The case of every transaction ever disputed.
+    cases: HashMap<u32, Case>,
+    /// The amounts held for open disputes that hold only part of the
+    /// disputed amount, by ID. Other disputes hold all of it.
+    partial_holds: HashMap<u32, Decimal>,
+    /// The intermediate client states.
+    client_states: ClientStates,
+    /// The policies this state was built with.
+    policies: Policies,
+    /// A digest of the client states and open disputes.
+    hash: StateHash,
+    /// Audit entries recorded since they were last taken.
+    audit: Vec<AuditEntry>,
+    /// The number of records processed.
+    records: u64,
+    /// The latest timestamp seen on any record.
+    latest_timestamp: Option<u64>,
+    /// The record number, timestamp and ID of each transaction in
+    /// memory, in the order they were processed, for compaction.
+    history: VecDeque<(u64, Option<u64>, u32)>,
+    /// Where settled transactions are archived, if anywhere.
+    archive: Option<Archive>,
+    /// The record number before which the state is not spilled again
+    /// after a spill that freed too little, which would otherwise be
+    /// retried on every record.
+    next_spill: u64,
+    /// Maps external accounts to client IDs.
+    counterparties: CounterpartyMap,
+    /// Additional checks that may hold transactions for review.
+    quarantine_rules: Vec<Arc<dyn QuarantineRule>>,
+    /// Handlers for custom transaction types.
+    transaction_types: TransactionRegistry,
+    /// The changelog, if events are recorded.
+    events: Vec<Event>,
+    /// How many of the events kept have been published to the sink.
+    published: usize,
+    /// Statistics about the records processed so far.
+    summary: Summary,
+    /// Statistics about the records processed so far, per client.
+    client_summaries: HashMap<u16, Summary>,
+    /// Transactions held for review, by ID.
+    quarantine: BTreeMap<u32, Quarantined>,
+    /// The timestamps of recent deposits and withdrawals per client,
+    /// for the velocity limit.
+    velocity: HashMap<u16, VecDeque<u64>>,
+    /// The day of each client's last deposit or withdrawal, and what
+    /// it deposited and withdrew that day, for daily limits.
+    daily_volumes: HashMap<u16, (u64, Decimal)>,
+    /// What each client has done, for scoring risk.
+    activity: HashMap<u16, ClientActivity>,
+    /// How many disputes of each transaction have been resolved.
+    resolutions: HashMap<u32, u32>,
+    /// The funds held for disputes that have been closed, if recorded.
+    closed_disputes: Vec<HeldFunds>,
+    /// Which withdrawals spent each deposit, if funds are traced.
+    funds: FundsTracer,
+    /// Every record applied to each client, if kept.
+    ledger: ClientHistory,
+    /// The most recent records, oldest first, if fail-safe.
+    recent: VecDeque<Transaction>,
+    /// Issues IDs for entries the engine creates itself.
+    id_allocator: Option<Arc<dyn TxIdAllocator>>,
+    /// Receives notifications for clients.
+    notifications: Option<Arc<dyn NotificationSink>>,
+    /// Receives the changelog events of every record, if anything does.
+    event_sink: Option<Arc<dyn EventSink>>,
+    /// Counts the records read.
+    progress: Option<Arc<ProgressTracker>>,
+    /// The merchants whose chargeback rates are monitored.
+    monitor_hierarchy: Option<Arc<Hierarchy>>,
+    /// The recent chargeback rate of each monitored client or merchant.
+    chargeback_windows: HashMap<Monitored, ChargebackWindow>,
+    /// Rules on disputes as they are opened.
+    adjudicator: Option<Arc<dyn Adjudicator>>,
+    /// The client states deltas are taken against.
+    baseline: Balances,
+    /// Called around every record, in order.
+    middleware: Vec<Arc<dyn TxMiddleware>>,
+    /// The clients merged into others, with the client each was
+    /// merged into.
+    merged: HashMap<u16, u16>,
+    /// Selects the records read from input, if only some are.
+    filter: Option<Arc<TxFilter>>,
+    /// The rejected records held back from input to be retried, if any.
+    retries: Option<Arc<Retries>>,
+    /// The reason codes records may give, if they are checked.
+    reason_codes: Option<Arc<ReasonCodes>>,
+    /// The balances clients are alerted on, if any.
+    balance_alerts: Option<Arc<BalanceAlerts>>,
+    /// The soft and hard limits of clients' accounts, if any.
+    account_limits: Option<Arc<AccountLimits>>,
+    /// The shares of disputed amounts held, if not all of them.
+    hold_rates: Option<Arc<HoldRates>>,
+    /// The clients whose deposits are partly held in reserve, if any.
+    reserves: Option<Arc<Reserves>>,
+    /// The reserve held back from each deposit until it is released.
+    reserve_schedule: ReserveSchedule,
+    /// The frozen clients, if freezes are enabled.
+    freezes: Option<Freezes>,
+    /// Looks up fields records lack.
+    enricher: Option<Arc<dyn Enricher>>,
+    /// The business days settlement dates and deadlines are counted in.
+    calendar: Option<Arc<Calendar>>,
+    /// The IDs accepted by earlier runs, if they are kept.
+    seen: Option<Arc<SeenSet>>,
+    /// The IDs accepted from the inputs this one yields to, if any.
+    taken: Option<Arc<HashMap<u32, TakenId>>>,
+    /// Blocks records for denied clients and accounts.
+    screener: Option<Arc<Screener>>,
+    #[cfg(feature = "chaos")]
+    /// Injects faults into records and backends, if any.
+    chaos: Option<Arc<Chaos>>,
+    /// What was looked up for each key, `None` for unknown keys.
+    enrichments: HashMap<String, Option<Enrichment>>,
+    /// The first and last sequence numbers given by each client's
+    /// records, for those that give them.
+    sequences: HashMap<u16, (u64, u64)>,
+}
+
+impl CurrentState {
+    /// Creates a builder for configuring the processing policies.
+    pub fn builder() -> CurrentStateBuilder {
+        CurrentStateBuilder::default()
+    }
+
+    /// Performs various checks on deposits and withdrawals of `amount`.
+    fn check_regular(&self, tx: &Transaction, amount: Decimal) -> Result<(), crate::errors::Error> {
+        if self.transactions.contains_key(&tx.id)
+            || self.quarantine.contains_key(&tx.id)
+            || self.is_archived(tx.id)
+            || self.was_seen(tx.id)?
+            || self
+                .taken
+                .as_ref()
+                .is_some_and(|taken| taken.contains_key(&tx.id))
+        {
+            return Err(TransactionError::AlreadyExists(tx.id).into());
+        }
+        if self
+            .id_allocator
+            .as_ref()
+            .is_some_and(|allocator| allocator.reserved().contains(&tx.id))
+        {
+            return Err(TransactionError::ReservedId(tx.id).into());
+        }
+        if let Some(max_amount) = self.policies.max_amount {
+            if tx.r#type() != TransactionType::Adjustment && amount > max_amount {
+                return Err(TransactionError::AmountAboveLimit(tx.id).into());
+            }
+        }
+        if self
+            .client_states
+            .get(tx.client)
+            .is_some_and(|client| client.locked)
+        {
+            return Err(ClientError::Locked(tx.id).into());
+        }
+        // Adjustments are corrections by operators, so they still apply.
+        if tx.r#type() != TransactionType::Adjustment && self.freeze_of(tx.client).is_some() {
+            return Err(ClientError::Frozen(tx.id).into());
+        }
+        if let Some(limits) = self.limits_of(tx) {
+            if limits.transaction.past_hard(amount) {
+                return Err(TransactionError::AmountAboveLimit(tx.id).into());
+            }
+            if limits
+                .daily
+                .past_hard(self.daily_volume(tx).saturating_add(amount))
+            {
+                return Err(ClientError::DailyLimitExceeded(tx.id).into());
+            }
+            let total = self
+                .client_states
+                .get(tx.client)
+                .map_or(Decimal::ZERO, |client| client.total());
+            if tx.r#type() == TransactionType::Deposit
+                && limits.balance.past_hard(total.saturating_add(amount))
+            {
+                return Err(ClientError::BalanceLimitExceeded(tx.id).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The account limits `tx` is checked against, if it is a deposit
+    /// or withdrawal and limits are set.
+    fn limits_of(&self, tx: &Transaction) -> Option<LimitSet> {
+        match tx.r#type() {
+            TransactionType::Deposit | TransactionType::Withdrawal => self
+                .account_limits
+                .as_ref()
+                .map(|limits| limits.limits(tx.client)),
+            _ => None,
+        }
+    }
+
+    /// What the client of `tx` has deposited and withdrawn on the day
+    /// of `tx`, before it.
+    fn daily_volume(&self, tx: &Transaction) -> Decimal {
+        match (self.daily_volumes.get(&tx.client), tx.timestamp) {
+            (Some(&(day, volume)), Some(timestamp)) if day == limits::day(timestamp) => volume,
+            _ => Decimal::ZERO,
+        }
+    }
+
+    /// Performs checks on dispute and dispute results,
+    /// returning the transaction being disputed.
+    fn check_irregular(&self, tx: &Transaction) -> Result<&StoredTx, crate::errors::Error> {
+        if let (Some(codes), Some(code)) = (&self.reason_codes, &tx.reason_code) {
+            if !codes.contains(code) {
+                return Err(TransactionError::UnknownReasonCode(tx.id, code.clone()).into());
+            }
+        }
+        let rtx = match self.transactions.get(&tx.id) {
+            Some(rtx) => rtx,
+            // Applied for another client by a state this one is merged
+            // with, which a single state would have found.
+            None => match self.taken.as_ref().and_then(|taken| taken.get(&tx.id)) {
+                Some(taken) if taken.applied && taken.client != tx.client => {
+                    return Err(TransactionError::ClientMismatch(tx.id).into());
+                }
+                _ => return Err(TransactionError::NonexistentTransaction(tx.id).into()),
+            },
+        };
+        if tx.client != rtx.client {
+            return Err(TransactionError::ClientMismatch(tx.id).into());
+        }
+        if rtx.r#type() == TransactionType::Adjustment {
+            return Err(TransactionError::NotDisputable(tx.id).into());
+        }
+        // If the transaction exists, the client is guaranteed to exist.
+        if self.client_states.get(tx.client).unwrap().locked {
+            return Err(ClientError::Locked(tx.id).into());
+        }
+
+        if tx.r#type() != transaction::TransactionType::Dispute {
+            if !self.disputes.contains_key(&tx.id) {
+                return Err(TransactionError::NoxexistentDispute(tx.id).into());
+            }
+        } else if self.disputes.contains_key(&tx.id) {
+            if self.policies.duplicate_disputes == DuplicateDisputePolicy::Reject {
+                return Err(TransactionError::DisputeAlreadyExists(tx.id).into());
+            }
+        } else {
+            if let Some(window) = self.policies.dispute_window {
+                // Timestamps are guaranteed to be present when a window is set.
+                if tx
+                    .timestamp
+                    .unwrap()
+                    .saturating_sub(rtx.timestamp().unwrap())
+                    > window
+                {
+                    return Err(TransactionError::DisputeWindowElapsed(tx.id).into());
+                }
+            }
+            // Every resolved dispute makes the next one a re-dispute.
+            if let ReDisputePolicy::Limit(limit) = self.policies.redisputes {
+                if self
+                    .resolutions
+                    .get(&tx.id)
+                    .is_some_and(|&count| count > limit)
+                {
+                    return Err(TransactionError::ReDisputeLimitReached(tx.id).into());
+                }
+            }
+        }
+
+        Ok(rtx)
+    }
+
+    /// Applies every operation for the record with ID `tx`, or none of
+    /// them: if any operation fails, the clients touched so far are
+    /// restored, including removing any that did not exist before.
+    fn apply_atomic(&mut self, tx: u32, ops: &[BalanceOp]) -> Result<(), crate::errors::Error> {
+        let mut undo: Vec<(u16, Option<Client>)> = Vec::new();
+        for op in ops {
+            let id = op.client();
+            let existing = self.client_states.get(id);
+            if !undo.iter().any(|&(touched, _)| touched == id) {
+                undo.push((id, existing));
+            }
+            let mut client = existing.unwrap_or_else(|| Client::from_id(id));
+            let result = op.apply(tx, &mut client);
+            self.client_states.insert(client);
+            if let Err(err) = result {
+                for (id, client) in undo.into_iter().rev() {
+                    match client {
+                        Some(client) => self.client_states.insert(client),
+                        None => {
+                            self.client_states.remove(id);
+                        }
+                    }
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves settled transactions that are old enough into the archive.
+    /// A transaction is settled once it has no open dispute and, if
+    /// there is a dispute window, the window has elapsed. Returns the
+    /// number of transactions archived.
+    pub fn compact(&mut self) -> Result<usize, crate::errors::Error> {
+        match self.policies.archive_age {
+            Some(age) => self.archive_settled(age),
+            None => Ok(0),
+        }
+    }
+
+    /// Moves settled transactions at least `age` old into the archive,
+    /// returning how many were moved.
+    fn archive_settled(&mut self, age: ArchiveAge) -> Result<usize, crate::errors::Error> {
+        if self.archive.is_some() {
+            self.inject_backend_fault("a write to the archive")?;
+        }
+        let archive = match &mut self.archive {
+            Some(archive) => archive,
+            None => return Ok(0),
+        };
+        let latest = self.latest_timestamp.unwrap_or_default();
+        let old_enough = |seq: u64, timestamp: Option<u64>, seconds: Option<u64>| {
+            seconds.is_none_or(|seconds| {
+                latest.saturating_sub(timestamp.unwrap_or_default()) > seconds
+            }) && age
+                .records
+                .is_none_or(|records| self.records - seq >= records)
+        };
+        let mut archived = 0;
+        let mut retained = Vec::new();
+        // Transactions are queued in processing order, so stop at the
+        // first one that is too new; later ones are no older.
+        while let Some(&(seq, timestamp, id)) = self.history.front() {
+            if !old_enough(seq, timestamp, age.seconds) {
+                break;
+            }
+            self.history.pop_front();
+            let settled = !self.disputes.contains_key(&id)
+                && old_enough(seq, timestamp, self.policies.dispute_window);
+            if !settled {
+                retained.push((seq, timestamp, id));
+                continue;
+            }
+            // Entries whose transaction was merged away have nothing to move.
+            if let Some(tx) = self.transactions.remove(&id) {
+                archive.append(&tx.to_transaction(id))?;
+                archived += 1;
+            }
+        }
+        for entry in retained.into_iter().rev() {
+            self.history.push_front(entry);
+        }
+        archive.flush()?;
+        Ok(archived)
+    }
+
+    /// A rough estimate of the bytes taken by the state's largest maps:
+    /// the transactions, open disputes, dispute cases and clients, and
+    /// the queue of transactions kept for compaction. Text fields, other
+    /// maps and the allocator's overhead are left out, so the process
+    /// takes more.
+    pub fn estimated_memory(&self) -> u64 {
+        fn map<K, V>(capacity: usize) -> u64 {
+            // Hash maps keep one control byte per slot besides the entry.
+            (capacity * (size_of::<(K, V)>() + 1)) as u64
+        }
+        map::<u32, StoredTx>(self.transactions.capacity())
+            + map::<u32, Transaction>(self.disputes.capacity())
+            + map::<u32, Case>(self.cases.capacity())
+            + self.client_states.estimated_memory()
+            + (self.history.capacity() * size_of::<(u64, Option<u64>, u32)>()) as u64
+    }
+
+    /// Rejects a record that would take the state past its resource
+    /// limits. When the memory limit is reached and the policy is to
+    /// spill, every settled transaction is archived first, and the
+    /// record is only rejected if the state is still over the limit.
+    fn check_limits(&mut self, tx: &Transaction) -> Result<(), crate::errors::Error> {
+        let limits = self.policies.limits;
+        let stored = matches!(
+            tx.r#type(),
+            TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Adjustment
+        );
+        // Only records that are stored, or custom ones, create clients.
+        let new_client = (stored || tx.r#type() == TransactionType::Custom)
+            && !self.client_states.contains_key(tx.client);
+        if new_client
+            && limits
+                .max_clients
+                .is_some_and(|max| self.client_states.len() >= max)
+        {
+            return Err(TransactionError::ClientLimitReached(tx.id).into());
+        }
+        if tx.r#type() == TransactionType::Dispute
+            && !self.disputes.contains_key(&tx.id)
+            && limits
+                .max_open_disputes
+                .is_some_and(|max| self.disputes.len() >= max)
+        {
+            return Err(TransactionError::OpenDisputeLimitReached(tx.id).into());
+        }
+        let max = match limits.max_memory {
+            Some(max) if stored || new_client => max,
+            _ => return Ok(()),
+        };
+        if self.estimated_memory() < max {
+            return Ok(());
+        }
+        if limits.policy == LimitPolicy::Spill && self.records >= self.next_spill {
+            self.archive_settled(ArchiveAge::default())?;
+            self.transactions.shrink_to_fit();
+            self.history.shrink_to_fit();
+            if self.estimated_memory() < max {
+                return Ok(());
+            }
+            self.next_spill = self.records + COMPACT_INTERVAL;
+        }
+        Err(TransactionError::MemoryLimitReached(tx.id).into())
+    }
+
+    /// Moves an archived transaction back into memory.
+    fn restore_archived(&mut self, id: u32) -> Result<(), crate::errors::Error> {
+        self.inject_backend_fault("a read of the archive")?;
+        // Only archived IDs are looked up, so the archive exists.
+        let archive = self.archive.as_mut().unwrap();
+        if let Some(mut tx) = archive.find(id)? {
+            archive.remove(id);
+            tx.client = self.merged.get(&tx.client).copied().unwrap_or(tx.client);
+            self.history.push_back((self.records, tx.timestamp, id));
+            self.transactions.insert(id, StoredTx::new(&tx));
+        }
+        Ok(())
+    }
+
+    /// Looks up a deposit, withdrawal or adjustment by ID, falling
+    /// back to the much slower archive if it is no longer in memory.
+    pub fn transaction(&mut self, id: u32) -> Result<Option<Transaction>, crate::errors::Error> {
+        if let Some(tx) = self.transactions.get(&id) {
+            return Ok(Some(tx.to_transaction(id)));
+        }
+        if self.archive.is_some() {
+            self.inject_backend_fault("a read of the archive")?;
+        }
+        let tx = match &mut self.archive {
+            Some(archive) => archive.find(id)?,
+            None => None,
+        };
+        Ok(tx.map(|tx| Transaction {
+            client: self.merged.get(&tx.client).copied().unwrap_or(tx.client),
+            ..tx
+        }))
+    }
+
+    /// Whether the transaction with ID `id` has been archived.
+    fn is_archived(&self, id: u32) -> bool {
+        self.archive
+            .as_ref()
+            .is_some_and(|archive| archive.contains(id))
+    }
+
+    /// Whether the transaction with ID `id` was accepted by an earlier
+    /// run.
+    fn was_seen(&self, id: u32) -> std::io::Result<bool> {
+        match &self.seen {
+            Some(seen) => {
+                self.inject_backend_fault("a read of the seen IDs")?;
+                seen.contains(id)
+            }
+            None => Ok(false),
+        }
+    }
+
+    #[cfg(feature = "chaos")]
+    /// The records to process in place of `tx`, which are other than
+    /// `tx` only if faults are injected into records.
+    fn perturb(&self, tx: Transaction) -> impl Iterator<Item = Transaction> {
+        match &self.chaos {
+            Some(chaos) => chaos.perturb(tx),
+            None => vec![tx],
+        }
+        .into_iter()
+    }
+
+    #[cfg(not(feature = "chaos"))]
+    /// The records to process in place of `tx`, which are other than
+    /// `tx` only if faults are injected into records.
+    fn perturb(&self, tx: Transaction) -> impl Iterator<Item = Transaction> {
+        std::iter::once(tx)
+    }
+
+    #[cfg(feature = "chaos")]
+    /// Fails `operation` on a backend, if faults are injected into
+    /// backends and one happens.
+    fn inject_backend_fault(&self, operation: &str) -> std::io::Result<()> {
+        match &self.chaos {
+            Some(chaos) => chaos.backend(operation),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(feature = "chaos"))]
+    /// Fails `operation` on a backend, if faults are injected into
+    /// backends and one happens.
+    fn inject_backend_fault(&self, _operation: &str) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// A stable digest of all client balances and open disputes,
+    /// independent of the order in which they are stored.
+    pub fn state_hash(&self) -> StateHash {
+        self.hash
+    }
+
+    /// Removes and returns the audit entries recorded so far.
+    pub fn take_audit_entries(&mut self) -> Vec<AuditEntry> {
+        std::mem::take(&mut self.audit)
+    }
+
+    /// The funds held for every dispute, closed ones first in the order
+    /// they were closed, then open ones in ID order. Open disputes are
+    /// priced up to the latest timestamp seen, and disputes are given
+    /// a deadline if `chargeback_deadline` is set. Only recorded if
+    /// `dispute_report` is enabled.
+    pub fn held_funds(&self) -> Vec<HeldFunds> {
+        if !self.policies.dispute_report {
+            return Vec::new();
+        }
+        let mut open: Vec<HeldFunds> = self
+            .disputes
+            .values()
+            .filter_map(|dispute| {
+                let amount = self.transactions.get(&dispute.id)?.amount()?;
+                let amount = self.held_amount(dispute.id, amount);
+                let held = HeldFunds {
+                    reason_code: dispute.reason_code.clone(),
+                    traced_withdrawals: self.funds.spent(dispute.id).to_vec(),
+                    ..HeldFunds::new(
+                        dispute.client,
+                        dispute.id,
+                        amount,
+                        DisputeOutcome::Open,
+                        dispute.timestamp,
+                        None,
+                    )
+                };
+                Some(held.priced(self.latest_timestamp, self.policies.float_rate))
+            })
+            .collect();
+        open.sort_unstable_by_key(|held| held.tx);
+        let weekdays = Calendar::default();
+        let calendar = self.calendar.as_deref().unwrap_or(&weekdays);
+        self.closed_disputes
+            .iter()
+            .cloned()
+            .chain(open)
+            .map(|held| {
+                let case = self.cases.get(&held.tx);
+                held.due(calendar, self.policies.chargeback_deadline)
+                    .with_case(case)
+            })
+            .collect()
+    }
+
+    /// The settlement date of every withdrawal held in memory, `days`
+    /// business days after it was made, in the order they were applied,
+    /// followed by every share of a deposit still held in reserve, with
+    /// the business day it is released on, earliest first. Withdrawals
+    /// compacted into the archive are left out.
+    pub fn settlements(&self, days: u32) -> Vec<Settlement> {
+        let weekdays = Calendar::default();
+        let calendar = self.calendar.as_deref().unwrap_or(&weekdays);
+        let releases = self.reserve_schedule.pending().map(|tranche| Settlement {
+            client: self
+                .merged
+                .get(&tranche.client)
+                .copied()
+                .unwrap_or(tranche.client),
+            tx: tranche.tx,
+            amount: tranche.amount,
+            timestamp: Some(tranche.timestamp),
+            settles: Some(calendar.add_business_days(Date::from_timestamp(tranche.releases), 0)),
+            kind: SettlementKind::ReserveRelease,
+        });
+        self.history
+            .iter()
+            .filter_map(|&(_, timestamp, id)| {
+                let stored = self.transactions.get(&id)?;
+                if stored.r#type() != TransactionType::Withdrawal {
+                    return None;
+                }
+                Some(Settlement {
+                    client: stored.client,
+                    tx: id,
+                    amount: stored.amount().unwrap_or_default(),
+                    timestamp,
+                    settles: timestamp.map(|timestamp| {
+                        calendar.add_business_days(Date::from_timestamp(timestamp), days)
+                    }),
+                    kind: SettlementKind::Withdrawal,
+                })
+            })
+            .chain(releases)
+            .collect()
+    }
+
+    /// The events recorded so far, in processing order.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Removes and returns the events recorded so far, in processing order.
+    pub fn take_events(&mut self) -> Vec<Event> {
+        self.published = 0;
+        std::mem::take(&mut self.events)
+    }
+
+    /// Merges a state built from an independent input into this one.
+    /// Balances of clients present in both are summed, and so is what
+    /// the velocity and daily limits and the chargeback monitor count,
+    /// so the merged state can go on processing records as if it had
+    /// read both inputs.
+    ///
+    /// A transaction ID accepted by both fails the merge whatever
+    /// `merge_conflicts` says, as both copies have already moved
+    /// balances. `keep-first` and `keep-last` are applied before the
+    /// states are merged, by processing the input that loses with the
+    /// winner's IDs taken, as `process_files_parallel` does.
+    pub fn merge(&mut self, other: CurrentState) -> Result<(), MergeError> {
+        if let Some((id, _)) = other.accepted_ids().find(|&(id, _)| {
+            self.transactions.contains_key(&id) || self.quarantine.contains_key(&id)
+        }) {
+            return Err(MergeError::DuplicateTransaction(id));
+        }
+        // Checked up front, so a failed merge changes nothing.
+        for client in other.client_states.values() {
+            if let Some(existing) = self.client_states.get(client.id) {
+                if existing.combined(&client).is_none() {
+                    return Err(MergeError::BalanceOverflow(client.id));
+                }
+            }
+        }
+        if !self.summary.can_merge(&other.summary) {
+            return Err(MergeError::SummaryOverflow);
+        }
+        // Inputs are merged in order, so a client's numbers in each
+        // must start above where they ended in the ones before.
+        for (id, &(first, _)) in &other.sequences {
+            if self
+                .sequences
+                .get(id)
+                .is_some_and(|&(_, last)| first <= last)
+            {
+                return Err(MergeError::OutOfOrder(*id));
+            }
+        }
+        for (id, summary) in &other.client_summaries {
+            if let Some(existing) = self.client_summaries.get(id) {
+                if !existing.can_merge(summary) {
+                    return Err(MergeError::ClientSummaryOverflow(*id));
+                }
+            }
+        }
+        self.transactions.extend(other.transactions);
+        self.disputes.extend(other.disputes);
+        self.cases.extend(other.cases);
+        self.partial_holds.extend(other.partial_holds);
+        let mut others = other.client_states;
+        for client in others.values().collect::<Vec<_>>() {
+            let merged = match self.client_states.get(client.id) {
+                Some(mut existing) => {
+                    // Checked not to overflow above.
+                    let balances = existing.combined(&client).unwrap();
+                    existing.restore(balances);
+                    existing.flags.insert(client.flags);
+                    existing
+                }
+                None => client,
+            };
+            self.client_states.insert(merged);
+            let history = others.take_dispute_history(client.id);
+            self.client_states
+                .extend_dispute_history(client.id, history);
+        }
+        self.audit.extend(other.audit);
+        self.records += other.records;
+        self.latest_timestamp = self.latest_timestamp.max(other.latest_timestamp);
+        self.history.extend(other.history);
+        self.quarantine.extend(other.quarantine);
+        // Both states published their own events as they went.
+        self.events.extend(other.events);
+        self.published = self.events.len();
+        self.closed_disputes.extend(other.closed_disputes);
+        self.funds.merge(other.funds);
+        self.ledger.merge(other.ledger);
+        self.reserve_schedule.merge(other.reserve_schedule);
+        self.merged.extend(other.merged);
+        self.summary.merge(other.summary);
+        for (id, summary) in other.client_summaries {
+            self.client_summaries.entry(id).or_default().merge(summary);
+        }
+        for (id, count) in other.resolutions {
+            *self.resolutions.entry(id).or_default() += count;
+        }
+        for (id, activity) in other.activity {
+            self.activity.entry(id).or_default().merge(activity);
+        }
+        for (id, (first, last)) in other.sequences {
+            self.sequences.entry(id).or_insert((first, last)).1 = last;
+        }
+        for (id, history) in other.velocity {
+            let existing = self.velocity.entry(id).or_default();
+            existing.extend(history);
+            existing.make_contiguous().sort_unstable();
+        }
+        for (id, (day, volume)) in other.daily_volumes {
+            // Only the volume of the later day still counts.
+            let merged = match self.daily_volumes.get(&id) {
+                Some(&(existing, other)) if existing == day => (day, volume.saturating_add(other)),
+                Some(&(existing, other)) if existing > day => (existing, other),
+                _ => (day, volume),
+            };
+            self.daily_volumes.insert(id, merged);
+        }
+        for (monitored, window) in other.chargeback_windows {
+            self.chargeback_windows
+                .entry(monitored)
+                .or_default()
+                .merge(window);
+        }
+        if self.enrichments.len() + other.enrichments.len() <= ENRICHMENT_CACHE_SIZE {
+            for (key, enrichment) in other.enrichments {
+                self.enrichments.entry(key).or_insert(enrichment);
+            }
+        }
+        match (&mut self.archive, other.archive) {
+            (Some(archive), Some(other)) => archive.absorb(other),
+            (archive @ None, other) => *archive = other,
+            (Some(_), None) => {}
+        }
+
+        // Merged clients no longer match either hash, so start over.
+        self.rehash();
+        Ok(())
+    }
+
+    /// The IDs of the deposits, withdrawals and adjustments accepted,
+    /// including those held in quarantine.
+    pub(crate) fn accepted_ids(&self) -> impl Iterator<Item = (u32, TakenId)> + '_ {
+        let applied = self.transactions.iter().map(|(&id, tx)| {
+            let taken = TakenId {
+                client: tx.client,
+                applied: true,
+            };
+            (id, taken)
+        });
+        let held = self.quarantine.iter().map(|(&id, held)| {
+            let taken = TakenId {
+                client: held.tx.client,
+                applied: false,
+            };
+            (id, taken)
+        });
+        applied.chain(held)
+    }
+
+    /// How the transaction with ID `id` was accepted, if it was.
+    pub(crate) fn accepted_id(&self, id: u32) -> Option<TakenId> {
+        match (self.transactions.get(&id), self.quarantine.get(&id)) {
+            (Some(tx), _) => Some(TakenId {
+                client: tx.client,
+                applied: true,
+            }),
+            (None, Some(held)) => Some(TakenId {
+                client: held.tx.client,
+                applied: false,
+            }),
+            (None, None) => None,
+        }
+    }
+
+    /// Takes the ID `id`, accepted by a state this one is merged with,
+    /// so records reusing it are rejected as a single state would.
+    pub(crate) fn take_id(&mut self, id: u32, taken: TakenId) {
+        Arc::make_mut(self.taken.get_or_insert_with(Default::default)).insert(id, taken);
+    }
+
+    /// Computes the state hash again from every client, open dispute
+    /// and merged client.
+    fn rehash(&mut self) {
+        self.hash = StateHash::default();
+        for client in self.client_states.values() {
+            self.hash.insert(&client.hash_entry());
+        }
+        for dispute in self.disputes.values() {
+            self.hash.insert(&dispute_hash_entry(dispute));
+        }
+        for (&src, &dst) in &self.merged {
+            self.hash.insert(&merged_hash_entry(src, dst));
+        }
+    }
+
+    /// The current states of all clients, in no particular order.
+    pub fn clients(&self) -> impl Iterator<Item = CsvClient> + '_ {
+        self.client_states
+            .values()
+            .map(|client| self.csv_client(&client))
+    }
+
+    /// A page of the clients matching `filter`, in `sort` order, of at
+    /// most `limit` clients, starting after `cursor` if given, for
+    /// browsing clients without dumping all of them.
+    pub fn list_clients(
+        &self,
+        filter: &ClientFilter,
+        sort: ClientSort,
+        cursor: Option<Cursor>,
+        limit: usize,
+    ) -> ClientPage {
+        let disputed: HashSet<u16> = match filter.open_disputes {
+            Some(_) => self
+                .disputes
+                .values()
+                .map(|dispute| dispute.client)
+                .collect(),
+            None => HashSet::new(),
+        };
+        let clients = self
+            .clients()
+            .filter(|client| filter.matches(client, disputed.contains(&client.client)));
+        search::paginate(clients, sort, cursor, limit)
+    }
+
+    /// A page of the records applied to the client with ID `id`, oldest
+    /// first, of at most `limit` entries, starting after `cursor` if
+    /// given. Empty unless the history is kept.
+    pub fn transaction_page(&self, id: u16, cursor: Option<u64>, limit: usize) -> HistoryPage {
+        self.ledger.page(id, cursor, limit)
+    }
+
+    /// The current state of the client with ID `id`, if it exists.
+    pub fn client(&self, id: u16) -> Option<CsvClient> {
+        self.client_states
+            .get(id)
+            .map(|client| self.csv_client(&client))
+    }
+
+    /// The clients that are not dormant, nor suppressed for never
+    /// having been active, which are the ones written to the output.
+    pub fn active_clients(&self) -> impl Iterator<Item = CsvClient> + '_ {
+        self.client_states
+            .values()
+            .filter(|client| !self.is_dormant(client) && !self.is_suppressed(client))
+            .map(|client| self.csv_client(&client))
+    }
+
+    /// The clients left out of the output as dormant, if a dormancy
+    /// policy is set.
+    pub fn dormant_clients(&self) -> impl Iterator<Item = CsvClient> + '_ {
+        self.client_states
+            .values()
+            .filter(|client| self.is_dormant(client))
+            .map(|client| self.csv_client(&client))
+    }
+
+    /// Whether `client` has no funds and has been inactive for long
+    /// enough to be left out of the output.
+    fn is_dormant(&self, client: &Client) -> bool {
+        let dormancy = match self.policies.dormancy {
+            Some(dormancy) => dormancy,
+            None => return false,
+        };
+        let special = [self.policies.suspense_account, self.policies.fee_account];
+        if special.contains(&Some(client.id))
+            || !client.available.is_zero()
+            || !client.held.is_zero()
+            || !client.fees.is_zero()
+            || !client.reserve.is_zero()
+        {
+            return false;
+        }
+        let activity = self.activity.get(&client.id).copied().unwrap_or_default();
+        let records = dormancy
+            .records
+            .is_none_or(|records| self.records.saturating_sub(activity.last_record) >= records);
+        let seconds = dormancy.seconds.is_none_or(|seconds| {
+            let latest = self.latest_timestamp.unwrap_or_default();
+            latest.saturating_sub(activity.last_timestamp.unwrap_or_default()) >= seconds
+        });
+        records && seconds
+    }
+
+    /// Whether `client` has no funds and never had a deposit or
+    /// withdrawal applied. The suspense and fee accounts are only
+    /// credited by the engine, so they never count.
+    fn is_never_active(&self, client: &Client) -> bool {
+        let special = [self.policies.suspense_account, self.policies.fee_account];
+        !special.contains(&Some(client.id))
+            && client.available.is_zero()
+            && client.held.is_zero()
+            && client.fees.is_zero()
+            && client.reserve.is_zero()
+            && self
+                .activity
+                .get(&client.id)
+                .is_none_or(|activity| activity.transactions == 0)
+    }
+
+    /// Whether `client` is left out of the output for never having had
+    /// a deposit or withdrawal applied.
+    fn is_suppressed(&self, client: &Client) -> bool {
+        self.policies.never_active == NeverActivePolicy::Suppress && self.is_never_active(client)
+    }
+
+    /// The output row for `client`, with its risk score if enabled,
+    /// and the `never_active` flag if it has earned it and such clients
+    /// are flagged.
+    fn csv_client(&self, client: &Client) -> CsvClient {
+        let mut flags = client.flags;
+        if self.policies.never_active == NeverActivePolicy::Flag && self.is_never_active(client) {
+            flags.insert(ClientFlags::NEVER_ACTIVE);
+        }
+        CsvClient {
+            flags,
+            fees: self
+                .policies
+                .separate_fees
+                .then(|| client.fees.to_decimal()),
+            reserve: self.reserves.as_ref().map(|_| client.reserve.to_decimal()),
+            frozen: self
+                .freezes
+                .as_ref()
+                .map(|_| self.freeze_of(client.id).is_some()),
+            risk_score: self.policies.risk_scores.then(|| {
+                self.activity
+                    .get(&client.id)
+                    .map_or(0, ClientActivity::score)
+            }),
+            ..CsvClient::from(client)
+        }
+    }
+
+    /// The columns of the output, which depend on the policies.
+    pub(crate) fn output_headers(&self) -> Vec<&'static str> {
+        let mut headers = vec!["client", "available", "held", "total"];
+        if self.policies.separate_fees {
+            headers.push("fees");
+        }
+        if self.reserves.is_some() {
+            headers.push("reserve");
+        }
+        headers.extend(["locked", "flags"]);
+        if self.freezes.is_some() {
+            headers.push("frozen");
+        }
+        if self.policies.risk_scores {
+            headers.push("risk_score");
+        }
+        headers
+    }
+
+    /// The risk score and the activity behind it for every client.
+    pub fn risk_report(&self) -> impl Iterator<Item = RiskRow> + '_ {
+        self.client_states
+            .keys()
+            .map(|id| RiskRow::new(id, &self.activity.get(&id).copied().unwrap_or_default()))
+    }
+
+    /// The deposits, withdrawals and adjustments held in memory, in no
+    /// particular order. Archived transactions are not included.
+    pub fn transactions(&self) -> impl Iterator<Item = Transaction> + '_ {
+        self.transactions
+            .iter()
+            .map(|(&id, tx)| tx.to_transaction(id))
+    }
+
+    /// The deposits, withdrawals and adjustments of the client with ID
+    /// `id` held in memory, in the order they were applied. Those
+    /// compacted into the archive are left out.
+    pub fn client_history(&self, id: u16) -> impl Iterator<Item = Transaction> + '_ {
+        self.history.iter().filter_map(move |&(_, _, tx)| {
+            self.transactions
+                .get(&tx)
+                .filter(|stored| stored.client == id)
+                .map(|stored| stored.to_transaction(tx))
+        })
+    }
+
+    /// The latest timestamp seen on any record.
+    pub fn latest_timestamp(&self) -> Option<u64> {
+        self.latest_timestamp
+    }
+
+    /// Freezes the client with ID `client` for `freeze.reason`, until
+    /// it is lifted or the records reach `freeze.expires`, returning
+    /// the freeze it replaces, if any. Enables freezes if they were
+    /// not already.
+    pub fn freeze(&mut self, client: u16, freeze: Freeze) -> Option<Freeze> {
+        self.freezes.get_or_insert_default().freeze(client, freeze)
+    }
+
+    /// Lifts the freeze on the client with ID `client`, returning it,
+    /// if there was one.
+    pub fn unfreeze(&mut self, client: u16) -> Option<Freeze> {
+        self.freezes.as_mut()?.unfreeze(client)
+    }
+
+    /// The freeze on the client with ID `client` that still applies at
+    /// the latest timestamp seen, if any.
+    pub fn freeze_of(&self, client: u16) -> Option<&Freeze> {
+        self.freezes.as_ref()?.get(client, self.latest_timestamp)
+    }
+
+    /// Whether the transaction with ID `id` is under dispute.
+    pub fn is_disputed(&self, id: u32) -> bool {
+        self.disputes.contains_key(&id)
+    }
+
+    /// The case of the transaction with ID `id`, if it was ever
+    /// disputed.
+    pub fn case(&self, id: u32) -> Option<&Case> {
+        self.cases.get(&id)
+    }
+
+    /// Every case, in transaction ID order.
+    pub fn cases(&self) -> Vec<&Case> {
+        let mut cases: Vec<&Case> = self.cases.values().collect();
+        cases.sort_unstable_by_key(|case| case.tx);
+        cases
+    }
+
+    /// Applies a case handler's `update` to the case of the transaction
+    /// with ID `id`, as made at `at`, returning the updated case.
+    pub fn update_case(
+        &mut self,
+        id: u32,
+        update: &CaseUpdate,
+        at: Option<u64>,
+    ) -> Result<&Case, CaseError> {
+        let case = self.cases.get_mut(&id).ok_or(CaseError::NoCase(id))?;
+        case.apply(update, at)?;
+        Ok(case)
+    }
+
+    /// The mapping from external accounts to client IDs.
+    pub fn counterparties(&self) -> &CounterpartyMap {
+        &self.counterparties
+    }
+
+    /// The mapping from external accounts to client IDs, which may be
+    /// changed between records.
+    pub fn counterparties_mut(&mut self) -> &mut CounterpartyMap {
+        &mut self.counterparties
+    }
+
+    /// The SHA-256 digest of the policies the state was built with, in
+    /// hexadecimal, so outputs of runs configured alike can be told
+    /// apart from the rest. Files that policies are loaded from, such
+    /// as a counterparty map, are not part of it, and digests are only
+    /// comparable between runs of the same engine version.
+    pub fn config_digest(&self) -> String {
+        crate::manifest::digest_bytes(format!("{:?}", self.policies).as_bytes())
+    }
+
+    /// Statistics about the records processed so far.
+    pub fn summary(&self) -> &Summary {
+        &self.summary
+    }
+
+    /// Statistics about the records processed so far, overall and for
+    /// each client.
+    pub fn stats(&self) -> Stats<'_> {
+        Stats {
+            total: &self.summary,
+            clients: &self.client_summaries,
+        }
+    }
+
+    /// What the enricher, if any, found for `tx`, looking it up if it
+    /// is not cached.
+    fn enrichment(&mut self, tx: &Transaction) -> Option<Enrichment> {
+        let enricher = self.enricher.clone()?;
+        let key = enricher.key(tx)?;
+        if !self.enrichments.contains_key(&key) {
+            self.look_up(&*enricher, std::slice::from_ref(&key));
+        }
+        self.enrichments.get(&key)?.clone()
+    }
+
+    /// Looks up every key of `batch` that is not cached at once.
+    fn prefetch_enrichments(&mut self, batch: &[Transaction]) {
+        let enricher = match &self.enricher {
+            Some(enricher) => enricher.clone(),
+            None => return,
+        };
+        let mut keys: Vec<String> = batch
+            .iter()
+            .filter_map(|tx| enricher.key(tx))
+            .filter(|key| !self.enrichments.contains_key(key))
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        if !keys.is_empty() {
+            self.look_up(&*enricher, &keys);
+        }
+    }
+
+    /// Looks up `keys` and caches the result. If the lookup fails, the
+    /// keys are cached as unknown, so records are left as they are
+    /// rather than waiting on a failed source again and again.
+    fn look_up(&mut self, enricher: &dyn Enricher, keys: &[String]) {
+        let mut found = enricher.lookup(keys).unwrap_or_else(|err| {
+            eprintln!(
+                "Warning: could not look up {} enrichment keys: {}",
+                keys.len(),
+                err
+            );
+            HashMap::new()
+        });
+        if self.enrichments.len() + keys.len() > ENRICHMENT_CACHE_SIZE {
+            self.enrichments.clear();
+        }
+        for key in keys {
+            let enrichment = found.remove(key);
+            self.enrichments.insert(key.clone(), enrichment);
+        }
+    }
+
+    /// Calls `middleware` around every record, after any added before.
+    pub fn use_middleware(&mut self, middleware: impl TxMiddleware + 'static) {
+        self.middleware.push(Arc::new(middleware));
+    }
+
+    /// Processes one record, and updates the state.
+    pub fn add(&mut self, tx: &Transaction) -> Result<(), crate::errors::Error> {
+        if self.policies.fail_safe {
+            if self.recent.len() == FAIL_SAFE_HISTORY {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(tx.clone());
+        }
+        let middleware = self.middleware.clone();
+        let mut refusal = None;
+        let mut tx = Cow::Borrowed(tx);
+        if let Some(enrichment) = self.enrichment(&tx) {
+            enrichment.fill(tx.to_mut());
+        }
+        for middleware in &middleware {
+            match middleware.before(&tx) {
+                Verdict::Continue => {}
+                Verdict::Replace(replacement) => tx = Cow::Owned(*replacement),
+                Verdict::Reject(reason) => {
+                    refusal = Some(TransactionError::RefusedByMiddleware(tx.id, reason));
+                    break;
+                }
+            }
+        }
+        // Records closing a dispute give its reason code, unless they
+        // give one of their own.
+        if tx.reason_code.is_none()
+            && matches!(
+                tx.r#type(),
+                TransactionType::Resolve | TransactionType::Chargeback
+            )
+        {
+            if let Some(code) = self
+                .disputes
+                .get(&tx.id)
+                .and_then(|dispute| dispute.reason_code.clone())
+            {
+                tx.to_mut().reason_code = Some(code);
+            }
+        }
+        let tx = tx.as_ref();
+        let result = match refusal {
+            Some(err) => Err(err.into()),
+            None => self.add_record_guarded(tx),
+        };
+        let replayed = matches!(result, Ok(true));
+        let result = result.map(|_| ());
+        // Records naming an unknown account are only counted overall.
+        let client = match &tx.account {
+            Some(account) => self.counterparties.client(account),
+            None => Some(tx.client),
+        };
+        let quarantined = self.is_quarantined(tx.id);
+        self.summarize(client, |summary| {
+            summary.records += 1;
+            match &result {
+                Err(err) => summary.record_rejection(err.rejection_code()),
+                Ok(()) if replayed => summary.replayed += 1,
+                Ok(()) if quarantined => summary.quarantined += 1,
+                Ok(()) => summary.accepted += 1,
+            }
+        });
+        if result.is_ok() && !quarantined && !replayed {
+            self.summarize_amount(client, tx);
+            if let (true, Some(client)) = (self.policies.history, client) {
+                self.ledger
+                    .record(client, HistoryEntry::new(self.records, tx));
+            }
+        }
+        if !middleware.is_empty() {
+            let outcome = match &result {
+                Err(err) => Outcome::Rejected(err),
+                Ok(()) if replayed => Outcome::Replayed,
+                Ok(()) if quarantined => Outcome::Quarantined,
+                Ok(()) => Outcome::Accepted,
+            };
+            let view = StateView {
+                state: self,
+                ops: Vec::new(),
+            };
+            for middleware in &middleware {
+                middleware.after(tx, &outcome, &view);
+            }
+        }
+        let adjudicated = match (&result, client) {
+            (Ok(()), Some(client)) if !replayed && tx.r#type() == TransactionType::Dispute => {
+                self.adjudicate(tx, client)
+            }
+            _ => Ok(()),
+        };
+        self.publish_events();
+        adjudicated?;
+        result
+    }
+
+    /// Whether events are recorded, to be kept or published.
+    fn records_events(&self) -> bool {
+        self.policies.events || self.event_sink.is_some()
+    }
+
+    /// Publishes the events recorded since the last call to the sink,
+    /// if any, keeping them only if the state keeps events.
+    fn publish_events(&mut self) {
+        let sink = match &self.event_sink {
+            Some(sink) => sink,
+            None => return,
+        };
+        if self.published == self.events.len() {
+            return;
+        }
+        let events = match self.policies.events {
+            true => self.events[self.published..].to_vec(),
+            false => std::mem::take(&mut self.events),
+        };
+        sink.publish(events);
+        self.published = self.events.len();
+    }
+
+    /// Puts the dispute just opened by `tx` to the adjudicator, if any,
+    /// and applies its ruling as a record of its own. A ruling that
+    /// cannot be applied leaves the dispute open, with the rejection in
+    /// the audit log; only broken invariants are returned.
+    fn adjudicate(&mut self, tx: &Transaction, client: u16) -> Result<(), crate::errors::Error> {
+        let adjudicator = match &self.adjudicator {
+            Some(adjudicator) => adjudicator.clone(),
+            None => return Ok(()),
+        };
+        let case = DisputeCase {
+            client,
+            tx: tx.id,
+            amount: self
+                .transactions
+                .get(&tx.id)
+                .and_then(StoredTx::amount)
+                .unwrap_or_default(),
+            initiator: tx.initiator,
+            timestamp: tx.timestamp,
+            external_ref: tx.external_ref.clone(),
+            reason_code: tx.reason_code.clone(),
+        };
+        let ruling = adjudicator.adjudicate(&case);
+        let kind = match ruling {
+            Ruling::Pending => None,
+            Ruling::Resolve => Some(TransactionKind::Resolve),
+            Ruling::Chargeback => Some(TransactionKind::Chargeback),
+        };
+        let result = match kind {
+            Some(kind) => self.add(&Transaction {
+                kind,
+                client,
+                id: tx.id,
+                timestamp: tx.timestamp,
+                reference: None,
+                initiator: None,
+                account: None,
+                external_ref: tx.external_ref.clone(),
+                reason_code: None,
+                currency: None,
+                category: None,
+                merchant: None,
+                seq: None,
+            }),
+            None => Ok(()),
+        };
+        if let Err(err @ errors::Error::Invariant(_)) = result {
+            return Err(err);
+        }
+        self.audit.push(AuditEntry {
+            event: AuditEvent::DisputeAdjudicated,
+            client,
+            tx: tx.id,
+            amount: Some(case.amount),
+            reference: None,
+            rejection: result
+                .err()
+                .as_ref()
+                .and_then(errors::Error::rejection_code),
+            initiator: tx.initiator,
+            reason: Some(ruling.name().to_owned()),
+            external_ref: tx.external_ref.clone(),
+        });
+        Ok(())
+    }
+
+    /// Applies `update` to the overall statistics and to those of `client`.
+    fn summarize(&mut self, client: Option<u16>, update: impl Fn(&mut Summary)) {
+        update(&mut self.summary);
+        if let Some(client) = client {
+            update(self.client_summaries.entry(client).or_default());
+        }
+    }
+
+    /// Adds the amount of an applied record to the statistics. Disputes
+    /// and their outcomes count the amount of the disputed transaction,
+    /// and every amount is counted after rounding.
+    fn summarize_amount(&mut self, client: Option<u16>, tx: &Transaction) {
+        let amount = self.transactions.get(&tx.id).and_then(StoredTx::amount);
+        let fee = self
+            .policies
+            .chargeback_fee
+            .filter(|_| tx.r#type() == TransactionType::Chargeback);
+        self.summarize(client, |summary| {
+            if let Some(amount) = amount {
+                summary.record_applied(tx.r#type(), amount);
+                if let Some(code) = &tx.reason_code {
+                    summary.record_reason(tx.r#type(), code, amount);
+                }
+            }
+            if let Some(fee) = fee {
+                summary.fees += fee;
+            }
+        });
+    }
+
+    /// Runs `add_record`, turning a panic, whether from a bug or from
+    /// a custom transaction handler, into a rejection as
+    /// `internal_error` with an audit entry, so one record cannot abort
+    /// a run. The balances of the clients the record touches are put
+    /// back as they were. Other changes it made before panicking, such
+    /// as to its dispute, are kept, so under the fail-safe policy the
+    /// panic is returned as a broken invariant instead.
+    fn add_record_guarded(&mut self, tx: &Transaction) -> Result<bool, crate::errors::Error> {
+        let client = match &tx.account {
+            Some(account) => self.counterparties.client(account),
+            None => Some(tx.client),
+        };
+        let touched = client.map(|client| {
+            self.touched_clients(&Transaction {
+                client,
+                ..tx.clone()
+            })
+        });
+        let before: Vec<(u16, Option<ClientBalances>)> = touched
+            .iter()
+            .flatten()
+            .flatten()
+            .map(|&id| {
+                (
+                    id,
+                    self.client_states.get(id).map(|client| client.balances()),
+                )
+            })
+            .collect();
+        let payload = match panic::catch_unwind(AssertUnwindSafe(|| self.add_record(tx))) {
+            Ok(result) => return result,
+            Err(payload) => payload,
+        };
+        let message = match (
+            payload.downcast_ref::<&str>(),
+            payload.downcast_ref::<String>(),
+        ) {
+            (Some(message), _) => (*message).to_owned(),
+            (None, Some(message)) => message.clone(),
+            (None, None) => "unknown panic".to_owned(),
+        };
+        for (id, balances) in before {
+            match balances {
+                Some(balances) => {
+                    self.client_states
+                        .update(id, |client| client.restore(balances));
+                }
+                None => {
+                    self.client_states.remove(id);
+                }
+            }
+        }
+        self.rehash();
+        self.audit.push(AuditEntry {
+            event: AuditEvent::InternalError,
+            client: client.unwrap_or(tx.client),
+            tx: tx.id,
+            amount: tx.amount(),
+            reference: tx.reference.clone(),
+            rejection: Some(RejectionCode::InternalError),
+            initiator: tx.initiator,
+            reason: Some(message.clone()),
+            external_ref: tx.external_ref.clone(),
+        });
+        if self.policies.fail_safe {
+            return Err(InvariantError::Panicked {
+                tx: tx.id,
+                message,
+                recent: self.recent.iter().cloned().collect(),
+            }
+            .into());
+        }
+        Err(TransactionError::Internal(tx.id, message).into())
+    }
+
+    /// Resolves and processes one record. Returns whether the record
+    /// was skipped as a replay of a transaction already applied.
+    fn add_record(&mut self, tx: &Transaction) -> Result<bool, crate::errors::Error> {
+        self.records += 1;
+        if let Some(timestamp) = tx.timestamp {
+            self.latest_timestamp = self.latest_timestamp.max(Some(timestamp));
+        }
+        self.release_reserves();
+        self.screen(tx)?;
+        // Records naming an account are stored under the resolved client.
+        let resolved;
+        let tx = match &tx.account {
+            Some(account) => {
+                let client = self
+                    .counterparties
+                    .client(account)
+                    .ok_or(TransactionError::UnknownAccount(tx.id))?;
+                resolved = Transaction {
+                    client,
+                    account: None,
+                    ..tx.clone()
+                };
+                &resolved
+            }
+            None => tx,
+        };
+        if self.is_replay(tx) {
+            return Ok(true);
+        }
+        self.check_sequence(tx)?;
+        self.check_limits(tx)?;
+        // Duplicate disputes that are not rejected only update the open
+        // dispute, so they are counted as replays, and skip the events,
+        // notifications and adjudication of a dispute being opened.
+        if tx.r#type() == TransactionType::Dispute
+            && self.disputes.contains_key(&tx.id)
+            && self.policies.duplicate_disputes != DuplicateDisputePolicy::Reject
+        {
+            return self.apply(tx, false).map(|()| true);
+        }
+        self.check_summary_room(tx)?;
+        self.process(tx, true).map(|()| false)
+    }
+
+    /// Releases every share of a deposit held in reserve whose reserve
+    /// period has passed by the latest timestamp seen, into the
+    /// available funds of its client or the client it was merged into.
+    /// A release that would overflow is retried with the next record.
+    fn release_reserves(&mut self) {
+        let now = match (&self.reserves, self.latest_timestamp) {
+            (Some(_), Some(now)) => now,
+            _ => return,
+        };
+        for tranche in self.reserve_schedule.due(now) {
+            let client = self
+                .merged
+                .get(&tranche.client)
+                .copied()
+                .unwrap_or(tranche.client);
+            if let Some(state) = self.client_states.get(client) {
+                self.hash.remove(&state.hash_entry());
+            }
+            let release = BalanceOp::ReleaseReserve {
+                client,
+                amount: tranche.amount,
+            };
+            let result = self.apply_atomic(tranche.tx, &[release]);
+            // The client held the reserve, so it still exists.
+            let state = self.client_states.get(client).unwrap();
+            self.hash.insert(&state.hash_entry());
+            if result.is_err() {
+                self.reserve_schedule.push(tranche);
+                continue;
+            }
+            if self.records_events() {
+                self.events.push(Event {
+                    seq: self.records,
+                    kind: EventKind::BalanceChanged,
+                    client,
+                    tx: tranche.tx,
+                    available: Some(state.available.to_decimal()),
+                    held: Some(state.held.to_decimal()),
+                    total: Some(state.total()),
+                    external_ref: None,
+                });
+            }
+            self.audit.push(AuditEntry {
+                event: AuditEvent::ReserveReleased,
+                client,
+                tx: tranche.tx,
+                amount: Some(tranche.amount),
+                reference: None,
+                rejection: None,
+                initiator: None,
+                reason: None,
+                external_ref: None,
+            });
+        }
+    }
+
+    /// Rejects a record for a client, or naming an account, on the
+    /// denylist, whatever else it would be rejected for, and flags the
+    /// client if it exists.
+    fn screen(&mut self, tx: &Transaction) -> Result<(), TransactionError> {
+        let screener = match &self.screener {
+            Some(screener) => screener,
+            None => return Ok(()),
+        };
+        let client = match &tx.account {
+            Some(account) => self.counterparties.client(account),
+            None => Some(tx.client),
+        };
+        if !screener.denies(client, tx.account.as_deref()) {
+            return Ok(());
+        }
+        if let Some(client) = client {
+            self.update_client(client, |client| client.flags.insert(ClientFlags::SCREENED));
+        }
+        Err(TransactionError::Screened(tx.id))
+    }
+
+    /// Rejects a record whose sequence number is not above the last one
+    /// its client gave. A record that passes moves its client on to its
+    /// number, whether or not it is then applied, as it still arrived
+    /// in order.
+    fn check_sequence(&mut self, tx: &Transaction) -> Result<(), TransactionError> {
+        let seq = match tx.seq {
+            Some(seq) => seq,
+            None => return Ok(()),
+        };
+        match self.sequences.entry(tx.client) {
+            Entry::Vacant(entry) => {
+                entry.insert((seq, seq));
+            }
+            Entry::Occupied(mut entry) => {
+                let (_, last) = entry.get_mut();
+                if seq <= *last {
+                    return Err(TransactionError::OutOfOrder(tx.id, seq, *last));
+                }
+                *last = seq;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects a record that would take a sum in the statistics, overall
+    /// or of its client, beyond the range of a decimal, before it is
+    /// applied, so every applied record is counted.
+    fn check_summary_room(&self, tx: &Transaction) -> Result<(), TransactionError> {
+        let amount = match tx.r#type() {
+            TransactionType::Deposit
+            | TransactionType::Withdrawal
+            | TransactionType::Adjustment => self.rounded(tx.amount()),
+            _ => self.transactions.get(&tx.id).and_then(StoredTx::amount),
+        };
+        let fee = self
+            .policies
+            .chargeback_fee
+            .filter(|_| tx.r#type() == TransactionType::Chargeback);
+        let fits = |summary: &Summary| {
+            summary.has_room(tx.r#type(), amount, tx.reason_code.as_deref(), fee)
+        };
+        if !fits(&self.summary)
+            || self
+                .client_summaries
+                .get(&tx.client)
+                .is_some_and(|s| !fits(s))
+        {
+            return Err(TransactionError::ArithmeticOverflow(tx.id));
+        }
+        Ok(())
+    }
+
+    /// `amount` rounded as incoming amounts are stored.
+    fn rounded(&self, amount: Option<Decimal>) -> Option<Decimal> {
+        match (self.policies.rounding, amount) {
+            (Some(dp), Some(amount)) => Some(amount.round_dp(dp)),
+            (_, amount) => amount,
+        }
+    }
+
+    /// Whether `tx` repeats a deposit, withdrawal or adjustment
+    /// already applied, under the idempotent replay policy.
+    fn is_replay(&self, tx: &Transaction) -> bool {
+        if !self.policies.idempotent_replay {
+            return false;
+        }
+        let existing = match self.transactions.get(&tx.id) {
+            Some(existing) => existing,
+            None => return false,
+        };
+        // The stored amount has already been rounded.
+        let amount = self.rounded(tx.amount());
+        existing.r#type() == tx.r#type()
+            && existing.client == tx.client
+            && existing.timestamp() == tx.timestamp
+            && existing.reference() == tx.reference.as_deref()
+            && existing.amount() == amount
+    }
+
+    /// Applies one record and keeps the state hash and audit log
+    /// up to date. Only `screen`ed records may be quarantined.
+    fn process(&mut self, tx: &Transaction, screen: bool) -> Result<(), crate::errors::Error> {
+        // A record only touches its own client and dispute, and the
+        // suspense account, so only their entries need to be rehashed.
+        let touched = self.touched_clients(tx);
+        let before = touched.map(|id| {
+            id.and_then(|id| self.client_states.get(id))
+                .map(|client| client.balances())
+        });
+        for id in touched.iter().flatten() {
+            if let Some(client) = self.client_states.get(*id) {
+                self.hash.remove(&client.hash_entry());
+            }
+        }
+        if let Some(dispute) = self.disputes.get(&tx.id) {
+            self.hash.remove(&dispute_hash_entry(dispute));
+        }
+        let result = self.apply(tx, screen);
+        if self.records_events() && result.is_ok() {
+            self.record_events(tx, touched, before);
+        }
+        if result.is_ok() && !self.quarantine.contains_key(&tx.id) {
+            self.notify(tx, touched, before);
+        }
+        if result.is_ok() && !self.quarantine.contains_key(&tx.id) {
+            self.record_activity(tx);
+            self.check_balance_alerts(tx, touched);
+            self.check_account_limits(tx, before[0]);
+        }
+        if self.policies.fail_safe && result.is_ok() {
+            touched
+                .iter()
+                .flatten()
+                .try_for_each(|&id| self.check_invariants(id))?;
+        }
+        for id in touched.iter().flatten() {
+            if let Some(client) = self.client_states.get(*id) {
+                self.hash.insert(&client.hash_entry());
+            }
+        }
+        if let Some(dispute) = self.disputes.get(&tx.id) {
+            self.hash.insert(&dispute_hash_entry(dispute));
+        }
+        if result.is_ok() && !self.quarantine.contains_key(&tx.id) {
+            self.monitor_chargebacks(tx);
+        }
+        // Adjustments are audited whether or not they are accepted.
+        if tx.r#type() == TransactionType::Adjustment {
+            self.audit.push(AuditEntry {
+                event: AuditEvent::Adjustment,
+                client: tx.client,
+                tx: tx.id,
+                amount: tx.amount(),
+                reference: tx.reference.clone(),
+                rejection: result.as_ref().err().and_then(errors::Error::rejection_code),
+                initiator: None,
+                reason: None,
+                external_ref: tx.external_ref.clone(),
+            });
+        }
+        result
+    }
+
+    /// Counts an applied deposit, withdrawal or chargeback toward the
+    /// chargeback rate of its client, or of the client's merchant, and
+    /// acts on the clients under it when the rate crosses the threshold
+    /// or release rate. Clients joining while the threshold is exceeded
+    /// are acted on too.
+    fn monitor_chargebacks(&mut self, tx: &Transaction) {
+        let monitor = match self.policies.chargeback_monitor {
+            Some(monitor) => monitor,
+            None => return,
+        };
+        let hierarchy = self.monitor_hierarchy.clone();
+        let merchant = hierarchy
+            .as_deref()
+            .and_then(|hierarchy| hierarchy.merchant(tx.client));
+        let monitored = match merchant {
+            Some(merchant) => Monitored::Merchant(merchant.to_owned()),
+            None => Monitored::Client(tx.client),
+        };
+        let window = self
+            .chargeback_windows
+            .entry(monitored.clone())
+            .or_default();
+        let transition = match tx.r#type() {
+            TransactionType::Deposit | TransactionType::Withdrawal => window.transaction(&monitor),
+            TransactionType::Chargeback => window.chargeback(&monitor),
+            _ => return,
+        };
+        let exceeded = window.exceeded;
+        let (event, chargebacks, transactions) = match transition {
+            Some(Transition::Exceeded {
+                chargebacks,
+                transactions,
+            }) => (
+                AuditEvent::ChargebackRateExceeded,
+                chargebacks,
+                transactions,
+            ),
+            Some(Transition::Recovered {
+                chargebacks,
+                transactions,
+            }) => (
+                AuditEvent::ChargebackRateRecovered,
+                chargebacks,
+                transactions,
+            ),
+            None => {
+                if exceeded {
+                    self.suspend(tx.client, monitor.action);
+                }
+                return;
+            }
+        };
+        let clients: Vec<u16> = match (hierarchy.as_deref(), merchant) {
+            (Some(hierarchy), Some(merchant)) => self
+                .client_states
+                .keys()
+                .filter(|&id| hierarchy.merchant(id) == Some(merchant))
+                .collect(),
+            _ => vec![tx.client],
+        };
+        for id in clients {
+            match event {
+                AuditEvent::ChargebackRateExceeded => self.suspend(id, monitor.action),
+                // Locks are only lifted by hand.
+                _ => self.update_client(id, |client| client.flags.remove(ClientFlags::CHARGEBACKS)),
+            }
+        }
+        self.audit.push(AuditEntry {
+            event,
+            client: tx.client,
+            tx: tx.id,
+            amount: None,
+            reference: None,
+            rejection: None,
+            initiator: None,
+            reason: Some(format!(
+                "{}: {} chargebacks in the last {} deposits and withdrawals",
+                monitored, chargebacks, transactions
+            )),
+            external_ref: tx.external_ref.clone(),
+        });
+    }
+
+    /// Flags or locks the client with ID `id` for its chargeback rate.
+    fn suspend(&mut self, id: u16, action: MonitorAction) {
+        self.update_client(id, |client| match action {
+            MonitorAction::Flag => client.flags.insert(ClientFlags::CHARGEBACKS),
+            MonitorAction::Lock => client.locked = true,
+        });
+    }
+
+    /// Changes the client with ID `id`, if it exists, keeping the state
+    /// hash up to date.
+    fn update_client(&mut self, id: u16, change: impl FnOnce(&mut Client)) {
+        let hash = &mut self.hash;
+        self.client_states.update(id, |client| {
+            hash.remove(&client.hash_entry());
+            change(client);
+            hash.insert(&client.hash_entry());
+        });
+    }
+
+    /// Checks that the client with ID `id` holds exactly the amounts
+    /// of its open disputes, and nothing negative.
+    fn check_invariants(&self, id: u16) -> Result<(), InvariantError> {
+        let client = match self.client_states.get(id) {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+        let recent = || self.recent.iter().cloned().collect();
+        let held = client.held.to_decimal();
+        if held < Decimal::default() {
+            return Err(InvariantError::NegativeHeld {
+                client: id,
+                held,
+                recent: recent(),
+            });
+        }
+        let disputed: Decimal = self
+            .disputes
+            .values()
+            .filter(|dispute| dispute.client == id)
+            .filter_map(|dispute| {
+                let amount = self.transactions.get(&dispute.id)?.amount()?;
+                Some(self.held_amount(dispute.id, amount))
+            })
+            .sum();
+        if held != disputed {
+            return Err(InvariantError::HeldMismatch {
+                client: id,
+                held,
+                disputed,
+                recent: recent(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Counts `tx` towards its client's activity, for risk scores
+    /// and dormancy.
+    fn record_activity(&mut self, tx: &Transaction) {
+        let activity = self.activity.entry(tx.client).or_default();
+        activity.last_record = self.records;
+        activity.last_timestamp = activity.last_timestamp.max(tx.timestamp);
+        match tx.r#type() {
+            TransactionType::Deposit | TransactionType::Withdrawal => activity.transactions += 1,
+            TransactionType::Dispute => activity.disputes += 1,
+            TransactionType::Chargeback => activity.chargebacks += 1,
+            _ => {}
+        }
+        if let Some(client) = self.client_states.get(tx.client) {
+            let total = client.total();
+            activity.sample_balance(total.to_f64().unwrap_or_default());
+        }
+    }
+
+    /// Records the events caused by `tx`, given the balances of the
+    /// clients it touched from before it was applied.
+    fn record_events(
+        &mut self,
+        tx: &Transaction,
+        touched: [Option<u16>; 3],
+        before: [Option<ClientBalances>; 3],
+    ) {
+        let event = |kind, client| Event {
+            seq: self.records,
+            kind,
+            client,
+            tx: tx.id,
+            available: None,
+            held: None,
+            total: None,
+            external_ref: tx.external_ref.clone(),
+        };
+        let dispute = match tx.r#type() {
+            TransactionType::Dispute => Some(EventKind::DisputeOpened),
+            TransactionType::Resolve => Some(EventKind::DisputeResolved),
+            TransactionType::Chargeback => Some(EventKind::DisputeChargedBack),
+            _ => None,
+        };
+        let mut events: Vec<Event> = dispute
+            .map(|kind| event(kind, tx.client))
+            .into_iter()
+            .collect();
+        for (id, before) in touched.into_iter().zip(before) {
+            let client = match id.and_then(|id| self.client_states.get(id)) {
+                Some(client) => client,
+                None => continue,
+            };
+            let after = client.balances();
+            if before.is_none_or(|before| {
+                (before.available, before.held) != (after.available, after.held)
+            }) {
+                events.push(Event {
+                    available: Some(client.available.to_decimal()),
+                    held: Some(client.held.to_decimal()),
+                    total: Some(client.total()),
+                    ..event(EventKind::BalanceChanged, client.id)
+                });
+            }
+            if after.locked && !before.is_some_and(|before| before.locked) {
+                events.push(event(EventKind::AccountLocked, client.id));
+            }
+        }
+        self.events.extend(events);
+    }
+
+    /// Passes the notifications caused by `tx` to the sink, given the
+    /// balances of the clients it touched from before it was applied.
+    fn notify(
+        &self,
+        tx: &Transaction,
+        touched: [Option<u16>; 3],
+        before: [Option<ClientBalances>; 3],
+    ) {
+        let sink = match &self.notifications {
+            Some(sink) => sink,
+            None => return,
+        };
+        let triggers = self.policies.triggers;
+        let notification = |trigger, client, amount| Notification {
+            trigger,
+            client,
+            tx: tx.id,
+            amount,
+            external_ref: tx.external_ref.clone(),
+        };
+        match tx.r#type() {
+            TransactionType::Withdrawal
+                if triggers
+                    .withdrawals_above
+                    .is_some_and(|limit| tx.amount().is_some_and(|amount| amount > limit)) =>
+            {
+                sink.notify(&notification(
+                    Trigger::LargeWithdrawal,
+                    tx.client,
+                    tx.amount(),
+                ));
+            }
+            TransactionType::Dispute if triggers.dispute_opened => {
+                sink.notify(&notification(Trigger::DisputeOpened, tx.client, None));
+            }
+            _ => {}
+        }
+        if triggers.account_locked {
+            for (id, before) in touched.into_iter().zip(before) {
+                let locked = id
+                    .and_then(|id| self.client_states.get(id))
+                    .filter(|client| client.locked);
+                if let (Some(client), false) = (locked, before.is_some_and(|before| before.locked))
+                {
+                    sink.notify(&notification(Trigger::AccountLocked, client.id, None));
+                }
+            }
+        }
+    }
+
+    /// Raises or clears the balance flags of the clients `tx` touched,
+    /// and records an event and notifies the sink for each threshold
+    /// newly crossed. The flags stand for the thresholds crossed before
+    /// `tx`, so a client staying past a threshold is only alerted once.
+    fn check_balance_alerts(&mut self, tx: &Transaction, touched: [Option<u16>; 3]) {
+        let alerts = match &self.balance_alerts {
+            Some(alerts) => alerts.clone(),
+            None => return,
+        };
+        for id in touched.into_iter().flatten() {
+            let mut client = match self.client_states.get(id) {
+                Some(client) => client,
+                None => continue,
+            };
+            let thresholds = alerts.thresholds(id);
+            let (available, held, total) = (
+                client.available.to_decimal(),
+                client.held.to_decimal(),
+                client.total(),
+            );
+            let crossings = [
+                (
+                    ClientFlags::LOW_BALANCE,
+                    thresholds.below_floor(available),
+                    EventKind::BalanceBelowFloor,
+                    Trigger::BalanceBelowFloor,
+                    available,
+                ),
+                (
+                    ClientFlags::HIGH_BALANCE,
+                    thresholds.above_ceiling(total),
+                    EventKind::BalanceAboveCeiling,
+                    Trigger::BalanceAboveCeiling,
+                    total,
+                ),
+            ];
+            let mut crossed = Vec::new();
+            for (flag, past, kind, trigger, amount) in crossings {
+                match (past, client.flags.contains(flag)) {
+                    (true, false) => {
+                        client.flags.insert(flag);
+                        crossed.push((kind, trigger, amount));
+                    }
+                    (false, true) => client.flags.remove(flag),
+                    _ => {}
+                }
+            }
+            self.client_states.insert(client);
+            for (kind, trigger, amount) in crossed {
+                if self.records_events() {
+                    self.events.push(Event {
+                        seq: self.records,
+                        kind,
+                        client: id,
+                        tx: tx.id,
+                        available: Some(available),
+                        held: Some(held),
+                        total: Some(total),
+                        external_ref: tx.external_ref.clone(),
+                    });
+                }
+                if let (Some(sink), true) = (
+                    &self.notifications,
+                    self.policies.triggers.balance_thresholds,
+                ) {
+                    sink.notify(&Notification {
+                        trigger,
+                        client: id,
+                        tx: tx.id,
+                        amount: Some(amount),
+                        external_ref: tx.external_ref.clone(),
+                    });
+                }
+            }
         }
     }
-}
 
-type Transactions = HashMap<u32, Transaction>;
-type Disputes = HashMap<u32, Transaction>;
-type ClientStates = HashMap<u16, Client>;
+    /// Adds a deposit or withdrawal to its client's daily volume, and
+    /// raises or clears the `soft_limit` flag by whether it left the
+    /// client past a soft limit, given the client's balances from
+    /// before it was applied. An event is recorded and the sink
+    /// notified for every transaction past its soft limit, and for a
+    /// daily volume or balance only when it first goes past.
+    fn check_account_limits(&mut self, tx: &Transaction, before: Option<ClientBalances>) {
+        let limits = match self.limits_of(tx) {
+            Some(limits) => limits,
+            None => return,
+        };
+        // The stored amount has already been rounded.
+        let amount = self.rounded(tx.amount()).unwrap_or_default();
+        let volume_before = self.daily_volume(tx);
+        let volume = volume_before.saturating_add(amount);
+        if let Some(timestamp) = tx.timestamp {
+            self.daily_volumes
+                .insert(tx.client, (limits::day(timestamp), volume));
+        }
+        let mut client = match self.client_states.get(tx.client) {
+            Some(client) => client,
+            None => return,
+        };
+        let (available, held, total) = (
+            client.available.to_decimal(),
+            client.held.to_decimal(),
+            client.total(),
+        );
+        let total_before = before.map_or(Decimal::ZERO, |before| {
+            before.available.to_decimal() + before.held.to_decimal()
+        });
+        let soft = [
+            (
+                limits.transaction.past_soft(amount),
+                true,
+                EventKind::TransactionAboveSoftLimit,
+                Trigger::TransactionAboveSoftLimit,
+                amount,
+            ),
+            (
+                limits.daily.past_soft(volume),
+                !limits.daily.past_soft(volume_before),
+                EventKind::DailyVolumeAboveSoftLimit,
+                Trigger::DailyVolumeAboveSoftLimit,
+                volume,
+            ),
+            (
+                limits.balance.past_soft(total),
+                !limits.balance.past_soft(total_before),
+                EventKind::BalanceAboveSoftLimit,
+                Trigger::BalanceAboveSoftLimit,
+                total,
+            ),
+        ];
+        match soft.iter().any(|&(past, ..)| past) {
+            true => client.flags.insert(ClientFlags::SOFT_LIMIT),
+            false => client.flags.remove(ClientFlags::SOFT_LIMIT),
+        }
+        self.client_states.insert(client);
+        for (_, _, kind, trigger, value) in
+            soft.into_iter().filter(|&(past, newly, ..)| past && newly)
+        {
+            if self.records_events() {
+                self.events.push(Event {
+                    seq: self.records,
+                    kind,
+                    client: tx.client,
+                    tx: tx.id,
+                    available: Some(available),
+                    held: Some(held),
+                    total: Some(total),
+                    external_ref: tx.external_ref.clone(),
+                });
+            }
+            if let (Some(sink), true) = (&self.notifications, self.policies.triggers.soft_limits) {
+                sink.notify(&Notification {
+                    trigger,
+                    client: tx.client,
+                    tx: tx.id,
+                    amount: Some(value),
+                    external_ref: tx.external_ref.clone(),
+                });
+            }
+        }
+    }
 
-#[derive(Debug, Default)]
-/// The overall state of the program at any given time.
-pub struct CurrentState {
-    /// A map from transaction IDs to deposits/withdrawals.
-    transactions: Transactions,
-    /// A list of active disputes.
-    disputes: Disputes,
-    /// The intermediate client states.
-    client_states: ClientStates,
-}
+    /// Whether the transaction with ID `id` is held for review.
+    pub fn is_quarantined(&self, id: u32) -> bool {
+        self.quarantine.contains_key(&id)
+    }
 
-impl CurrentState {
-    /// Performs various checks on deposits and withdrawals.
-    fn check_regular(
+    /// The transactions held for review, in ID order.
+    pub fn quarantined(&self) -> impl Iterator<Item = &Quarantined> {
+        self.quarantine.values()
+    }
+
+    /// Processes a held transaction as if it had just arrived, without
+    /// screening it again. If it is rejected, it is discarded.
+    pub fn approve(&mut self, id: u32) -> Result<(), crate::errors::Error> {
+        let held = self
+            .quarantine
+            .remove(&id)
+            .ok_or(TransactionError::NotQuarantined(id))?;
+        let result = self
+            .check_summary_room(&held.tx)
+            .map_err(Into::into)
+            .and_then(|()| self.process(&held.tx, false));
+        if result.is_ok() {
+            self.summarize(Some(held.tx.client), |summary| summary.approved += 1);
+            self.summarize_amount(Some(held.tx.client), &held.tx);
+        }
+        self.audit.push(AuditEntry {
+            event: AuditEvent::QuarantineApproved,
+            client: held.tx.client,
+            tx: id,
+            amount: held.tx.amount(),
+            reference: None,
+            rejection: result
+                .as_ref()
+                .err()
+                .and_then(errors::Error::rejection_code),
+            initiator: None,
+            reason: Some(held.reason),
+            external_ref: held.tx.external_ref.clone(),
+        });
+        result
+    }
+
+    /// Discards a held transaction.
+    pub fn deny(&mut self, id: u32) -> Result<(), crate::errors::Error> {
+        let held = self
+            .quarantine
+            .remove(&id)
+            .ok_or(TransactionError::NotQuarantined(id))?;
+        self.summarize(Some(held.tx.client), |summary| summary.denied += 1);
+        self.audit.push(AuditEntry {
+            event: AuditEvent::QuarantineDenied,
+            client: held.tx.client,
+            tx: id,
+            amount: held.tx.amount(),
+            reference: None,
+            rejection: None,
+            initiator: None,
+            reason: Some(held.reason),
+            external_ref: held.tx.external_ref.clone(),
+        });
+        Ok(())
+    }
+
+    /// Merges the client with ID `src` into the client with ID `dst`,
+    /// such as an account opened twice for the same customer.
+    ///
+    /// `dst` takes over the funds, flags, dispute history and monitored
+    /// chargeback rate of `src`, and every transaction of `src`, so its
+    /// open disputes stay open and can be resolved or charged back
+    /// through `dst`. `dst` keeps its own sequence numbers, as those of
+    /// `src` no longer follow on from any record accepted. A locked
+    /// client is only merged if `force`d, and then `dst` is locked too.
+    /// `src` is removed and tombstoned: records naming it are rejected
+    /// as `merged` from then on. Every transaction moved, including
+    /// held ones, is audited, but archived transactions are moved as
+    /// they are read back, without an entry of their own.
+    pub fn merge_clients(
         &mut self,
-        tx: &Transaction,
-    ) -> Result<&mut Client, crate::errors::Error> {
-        if self.transactions.contains_key(&tx.id) {
-            return Err(TransactionError::AlreadyExists(tx.id).into());
+        src: u16,
+        dst: u16,
+        force: bool,
+    ) -> Result<(), crate::errors::Error> {
+        if src == dst {
+            return Err(ClientMergeError::SameClient(src).into());
+        }
+        for id in [src, dst] {
+            if let Some(&into) = self.merged.get(&id) {
+                return Err(ClientMergeError::AlreadyMerged(id, into).into());
+            }
+            if [self.policies.suspense_account, self.policies.fee_account].contains(&Some(id)) {
+                return Err(ClientMergeError::ReservedAccount(id).into());
+            }
+            let client = self
+                .client_states
+                .get(id)
+                .ok_or(ClientMergeError::UnknownClient(id))?;
+            if client.locked && !force {
+                return Err(ClientMergeError::Locked(id).into());
+            }
         }
-        let client = self
+        // Both clients were just checked to exist.
+        let balances = self
             .client_states
-            .entry(tx.client)
-            .or_insert_with(|| Client::from_id(tx.client));
-        if client.locked {
-            return Err(ClientError::Locked(tx.id).into());
+            .get(dst)
+            .unwrap()
+            .combined(&self.client_states.get(src).unwrap())
+            .ok_or(ClientMergeError::BalanceOverflow(src, dst))?;
+        if let (Some(summary), Some(source)) = (
+            self.client_summaries.get(&dst),
+            self.client_summaries.get(&src),
+        ) {
+            if !summary.can_merge(source) {
+                return Err(ClientMergeError::BalanceOverflow(src, dst).into());
+            }
         }
 
-        Ok(client)
-    }
+        let history = self.client_states.take_dispute_history(src);
+        let source = self.client_states.remove(src).unwrap();
+        self.hash.remove(&source.hash_entry());
+        let moved = source.total();
+        self.update_client(dst, |client| {
+            client.restore(balances);
+            client.flags.insert(source.flags);
+        });
+        self.client_states.extend_dispute_history(dst, history);
 
-    /// Performs checks on dispute and dispute results.
-    fn check_irregular(
-        &mut self,
-        tx: &Transaction,
-    ) -> Result<(&mut Client, &Transaction), crate::errors::Error> {
-        let rtx = self
+        let mut reassigned: Vec<(u32, Option<Decimal>)> = self
             .transactions
-            .get(&tx.id)
-            .ok_or(TransactionError::NonexistentTransaction(tx.id))?;
-        if tx.client != rtx.client {
-            return Err(TransactionError::ClientMismatch(tx.id).into());
+            .iter_mut()
+            .filter(|(_, stored)| stored.client == src)
+            .map(|(&id, stored)| {
+                stored.client = dst;
+                (id, stored.amount())
+            })
+            .collect();
+        reassigned.extend(
+            self.quarantine
+                .values_mut()
+                .filter(|held| held.tx.client == src)
+                .map(|held| {
+                    held.tx.client = dst;
+                    (held.tx.id, held.tx.amount())
+                }),
+        );
+        reassigned.sort_unstable_by_key(|&(id, _)| id);
+        for dispute in self.disputes.values_mut() {
+            if dispute.client == src {
+                self.hash.remove(&dispute_hash_entry(dispute));
+                dispute.client = dst;
+                self.hash.insert(&dispute_hash_entry(dispute));
+            }
         }
-        // If the transaction exists, the client is guaranteed to exist.
-        let client = self.client_states.get_mut(&tx.client).unwrap();
-        if client.locked {
-            return Err(ClientError::Locked(tx.id).into());
+        if let Some(history) = self.velocity.remove(&src) {
+            let existing = self.velocity.entry(dst).or_default();
+            existing.extend(history);
+            existing.make_contiguous().sort_unstable();
+        }
+        if let Some((day, volume)) = self.daily_volumes.remove(&src) {
+            // Only the volume of the later day still counts.
+            let merged = match self.daily_volumes.get(&dst) {
+                Some(&(existing, other)) if existing == day => (day, volume.saturating_add(other)),
+                Some(&(existing, other)) if existing > day => (existing, other),
+                _ => (day, volume),
+            };
+            self.daily_volumes.insert(dst, merged);
+        }
+        if let Some(summary) = self.client_summaries.remove(&src) {
+            self.client_summaries.entry(dst).or_default().merge(summary);
+        }
+        if let Some(activity) = self.activity.remove(&src) {
+            self.activity.entry(dst).or_default().merge(activity);
+        }
+        if let Some(window) = self.chargeback_windows.remove(&Monitored::Client(src)) {
+            self.chargeback_windows
+                .entry(Monitored::Client(dst))
+                .or_default()
+                .merge(window);
+        }
+        self.counterparties.reassign(src, dst);
+        self.funds.merge_clients(src, dst);
+        self.ledger.merge_clients(src, dst);
+        if let Some(freezes) = &mut self.freezes {
+            freezes.merge_clients(src, dst);
         }
 
-        if tx.r#type != transaction::TransactionType::Dispute {
-            let dispute = self.disputes.remove(&tx.id);
-            if dispute.is_none() {
-                return Err(TransactionError::NoxexistentDispute(tx.id).into());
+        // Clients merged into `src` earlier now live on in `dst`.
+        for (&merged, into) in self.merged.iter_mut().filter(|(_, into)| **into == src) {
+            self.hash.remove(&merged_hash_entry(merged, src));
+            *into = dst;
+            self.hash.insert(&merged_hash_entry(merged, dst));
+        }
+        self.merged.insert(src, dst);
+        self.hash.insert(&merged_hash_entry(src, dst));
+
+        self.audit.push(AuditEntry {
+            event: AuditEvent::ClientMerged,
+            client: src,
+            tx: 0,
+            amount: Some(moved),
+            reference: None,
+            rejection: None,
+            initiator: None,
+            reason: Some(format!("merged into client {}", dst)),
+            external_ref: None,
+        });
+        self.audit
+            .extend(reassigned.into_iter().map(|(id, amount)| AuditEntry {
+                event: AuditEvent::TransactionReassigned,
+                client: dst,
+                tx: id,
+                amount,
+                reference: None,
+                rejection: None,
+                initiator: None,
+                reason: Some(format!("reassigned from client {}", src)),
+                external_ref: None,
+            }));
+        if self.records_events() {
+            let event = |kind, client| Event {
+                seq: self.records,
+                kind,
+                client,
+                tx: 0,
+                available: None,
+                held: None,
+                total: None,
+                external_ref: None,
+            };
+            let merged = event(EventKind::ClientMerged, src);
+            let client = self.client_states.get(dst).unwrap();
+            let balances = Event {
+                available: Some(client.available.to_decimal()),
+                held: Some(client.held.to_decimal()),
+                total: Some(client.total()),
+                ..event(EventKind::BalanceChanged, dst)
+            };
+            self.events.extend([merged, balances]);
+            self.publish_events();
+        }
+        Ok(())
+    }
+
+    /// Quarantines `tx` if any rule asks to, returning whether it did.
+    fn hold_for_review(&mut self, tx: &Transaction) -> bool {
+        let reason = match self.quarantine_reason(tx) {
+            Some(reason) => reason,
+            None => return false,
+        };
+        self.audit.push(AuditEntry {
+            event: AuditEvent::Quarantined,
+            client: tx.client,
+            tx: tx.id,
+            amount: tx.amount(),
+            reference: None,
+            rejection: None,
+            initiator: None,
+            reason: Some(reason.clone()),
+            external_ref: tx.external_ref.clone(),
+        });
+        self.quarantine.insert(
+            tx.id,
+            Quarantined {
+                tx: tx.clone(),
+                reason,
+            },
+        );
+        true
+    }
+
+    /// Why a deposit or withdrawal should be held for review, if it should.
+    fn quarantine_reason(&mut self, tx: &Transaction) -> Option<String> {
+        // Every screened transaction counts towards the velocity limit.
+        if let Some(limit) = self.policies.quarantine_velocity {
+            let history = self.velocity.entry(tx.client).or_default();
+            // Timestamps are guaranteed to be present when a limit is set.
+            let now = tx.timestamp.unwrap_or_default();
+            history.push_back(now);
+            while history
+                .front()
+                .is_some_and(|&seen| now.saturating_sub(seen) > limit.window)
+            {
+                history.pop_front();
+            }
+            if history.len() > limit.count as usize {
+                self.activity.entry(tx.client).or_default().velocity_flags += 1;
+                return Some(format!(
+                    "more than {} transactions within {} seconds",
+                    limit.count, limit.window
+                ));
+            }
+        }
+        if let Some(limit) = self.policies.quarantine_above {
+            if tx.amount().is_some_and(|amount| amount > limit) {
+                return Some(format!("amount above {}", limit));
             }
-        } else if self.disputes.contains_key(&tx.id) {
-            return Err(TransactionError::DisputeAlreadyExists(tx.id).into());
         }
+        self.quarantine_rules.iter().find_map(|rule| rule.check(tx))
+    }
 
-        Ok((client, rtx))
+    /// The IDs of the clients a record may modify.
+    fn touched_clients(&self, tx: &Transaction) -> [Option<u16>; 3] {
+        let suspense = self
+            .policies
+            .suspense_account
+            .filter(|&id| id != tx.client);
+        let fees = self
+            .policies
+            .fee_account
+            .filter(|&id| id != tx.client && Some(id) != suspense);
+        [Some(tx.client), suspense, fees]
     }
 
-    /// Processes one record, and updates the state.
-    pub fn add(&mut self, tx: &Transaction) -> Result<(), crate::errors::Error> {
-        match tx.r#type {
-            TransactionType::Withdrawal => {
-                let client = self.check_regular(tx)?;
-                if tx.amount.unwrap() >= client.available {
-                    return Err(ClientError::InsufficientFunds(tx.id).into());
+    /// Applies the operations a custom handler asked for. Only the
+    /// clients a record touches are rehashed by `process`, so any other
+    /// client the handler changed is rehashed here.
+    fn apply_custom(&mut self, tx: &Transaction, ops: &[BalanceOp]) -> Result<(), errors::Error> {
+        let touched = self.touched_clients(tx);
+        let mut others: Vec<u16> = ops
+            .iter()
+            .map(BalanceOp::client)
+            .filter(|&id| !touched.contains(&Some(id)))
+            .collect();
+        others.sort_unstable();
+        others.dedup();
+        for id in &others {
+            if let Some(client) = self.client_states.get(*id) {
+                self.hash.remove(&client.hash_entry());
+            }
+        }
+        let result = self.apply_atomic(tx.id, ops);
+        for id in &others {
+            if let Some(client) = self.client_states.get(*id) {
+                self.hash.insert(&client.hash_entry());
+            }
+        }
+        result
+    }
+
+    /// Updates the open dispute `tx` duplicates, under the duplicate
+    /// dispute policy.
+    fn update_dispute(&mut self, tx: &Transaction) {
+        if self.policies.duplicate_disputes != DuplicateDisputePolicy::UpdateMetadata {
+            return;
+        }
+        let dispute = self.disputes.get_mut(&tx.id).unwrap();
+        if tx.reference.is_some() {
+            dispute.reference.clone_from(&tx.reference);
+        }
+        if tx.external_ref.is_some() {
+            dispute.external_ref.clone_from(&tx.external_ref);
+        }
+        if tx.reason_code.is_some() {
+            dispute.reason_code.clone_from(&tx.reason_code);
+        }
+        self.audit.push(AuditEntry {
+            event: AuditEvent::DisputeUpdated,
+            client: tx.client,
+            tx: tx.id,
+            amount: None,
+            reference: dispute.reference.clone(),
+            rejection: None,
+            initiator: dispute.initiator,
+            reason: dispute.reason_code.clone(),
+            external_ref: dispute.external_ref.clone(),
+        });
+    }
+
+    /// Closes the dispute of `tx`, which held `amount`.
+    fn close_dispute(&mut self, tx: &Transaction, amount: Decimal, outcome: DisputeOutcome) {
+        let dispute = self.disputes.remove(&tx.id);
+        self.partial_holds.remove(&tx.id);
+        let status = match outcome {
+            DisputeOutcome::ChargedBack => CaseStatus::ChargedBack,
+            _ => CaseStatus::Resolved,
+        };
+        if let Some(case) = self.cases.get_mut(&tx.id) {
+            case.record_status(status, tx.timestamp);
+        }
+        if let (true, Some(dispute)) = (self.policies.dispute_report, dispute) {
+            let held = HeldFunds {
+                reason_code: tx.reason_code.clone(),
+                traced_withdrawals: self.funds.spent(tx.id).to_vec(),
+                ..HeldFunds::new(
+                    tx.client,
+                    tx.id,
+                    amount,
+                    outcome,
+                    dispute.timestamp,
+                    tx.timestamp,
+                )
+            };
+            self.closed_disputes
+                .push(held.priced(None, self.policies.float_rate));
+        }
+    }
+
+    /// The amount held for the open dispute of the transaction with ID
+    /// `id`, which disputed `amount`.
+    fn held_amount(&self, id: u32, amount: Decimal) -> Decimal {
+        self.partial_holds.get(&id).copied().unwrap_or(amount)
+    }
+
+    /// Applies one record to the state, without updating the hash.
+    fn apply(&mut self, tx: &Transaction, screen: bool) -> Result<(), crate::errors::Error> {
+        if self.policies.timestamps && tx.timestamp.is_none() {
+            return Err(TransactionError::MissingTimestamp(tx.id).into());
+        }
+        if self.policies.suspense_account == Some(tx.client) {
+            return Err(ClientError::SuspenseAccount(tx.id).into());
+        }
+        if self.merged.contains_key(&tx.client) {
+            return Err(ClientError::Merged(tx.id).into());
+        }
+        if tx.r#type() == TransactionType::Adjustment && !self.policies.allow_adjustments {
+            return Err(TransactionError::AdjustmentsDisabled(tx.id).into());
+        }
+        let rounded;
+        let tx = match (self.policies.rounding, tx.amount()) {
+            (Some(dp), Some(amount)) => {
+                let amount = amount.round_dp(dp);
+                if amount.is_zero() {
+                    return Err(TransactionError::ZeroAmount(tx.id).into());
+                }
+                rounded = tx.with_amount(amount);
+                &rounded
+            }
+            _ => tx,
+        };
+        let overdraft = self.policies.overdraft;
+        if matches!(
+            tx.r#type(),
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback
+        ) && self.is_archived(tx.id)
+        {
+            self.restore_archived(tx.id)?;
+        }
+
+        match &tx.kind {
+            &TransactionKind::Withdrawal { amount } => {
+                self.check_regular(tx, amount)?;
+                self.check_hold_buffer(tx)?;
+                if screen && self.hold_for_review(tx) {
+                    return Ok(());
+                }
+                self.apply_atomic(
+                    tx.id,
+                    &[BalanceOp::Debit {
+                        client: tx.client,
+                        amount,
+                        overdraft,
+                    }],
+                )?;
+                self.record_transaction(tx);
+                if self.policies.trace_funds {
+                    self.funds.withdraw(tx.client, tx.id, amount);
+                }
+            }
+            // Adjustments are authoritative corrections, so they are
+            // credited as they are and may take the available funds
+            // below zero.
+            &TransactionKind::Deposit { amount } | &TransactionKind::Adjustment { amount } => {
+                self.check_regular(tx, amount)?;
+                if screen
+                    && matches!(tx.kind, TransactionKind::Deposit { .. })
+                    && self.hold_for_review(tx)
+                {
+                    return Ok(());
+                }
+                let reserve = match (&tx.kind, &self.reserves, tx.timestamp) {
+                    (TransactionKind::Deposit { .. }, Some(reserves), Some(timestamp)) => reserves
+                        .terms(tx.client)
+                        .map(|terms| ((amount * terms.rate).round_dp(amount.scale()), terms))
+                        .filter(|(held, _)| !held.is_zero())
+                        .map(|(held, terms)| (held, timestamp, terms)),
+                    _ => None,
+                };
+                let credit = BalanceOp::Credit {
+                    client: tx.client,
+                    amount,
+                };
+                match reserve {
+                    Some((held, timestamp, terms)) => {
+                        let hold = BalanceOp::Reserve {
+                            client: tx.client,
+                            amount: held,
+                        };
+                        self.apply_atomic(tx.id, &[credit, hold])?;
+                        self.reserve_schedule
+                            .hold(tx.client, tx.id, held, timestamp, terms);
+                    }
+                    None => self.apply_atomic(tx.id, &[credit])?,
+                }
+                self.record_transaction(tx);
+                if self.policies.trace_funds && matches!(tx.kind, TransactionKind::Deposit { .. }) {
+                    self.funds.deposit(tx.client, tx.id, amount);
                 }
-                client.available -= tx.amount.unwrap();
             }
-            TransactionType::Deposit => {
-                let client = self.check_regular(tx)?;
-                client.available += tx.amount.unwrap();
+            TransactionKind::Custom { tag, .. } => {
+                let tag = tag.as_str();
+                let handler = self
+                    .transaction_types
+                    .get(tag)
+                    .cloned()
+                    .ok_or_else(|| TransactionError::UnknownType(tx.id, tag.to_owned()))?;
+                if self
+                    .client_states
+                    .get(tx.client)
+                    .is_some_and(|client| client.locked)
+                {
+                    return Err(ClientError::Locked(tx.id).into());
+                }
+                if self.freeze_of(tx.client).is_some() {
+                    return Err(ClientError::Frozen(tx.id).into());
+                }
+                let mut view = StateView {
+                    state: self,
+                    ops: Vec::new(),
+                };
+                handler.apply(tx, &mut view)?;
+                let ops = view.ops;
+                self.apply_custom(tx, &ops)?;
             }
-            TransactionType::Dispute => {
-                let (client, rtx) = self.check_irregular(tx)?;
-                client.held += rtx.amount.unwrap();
-                client.available -= rtx.amount.unwrap();
-                self.disputes.insert(tx.id, *tx);
+            TransactionKind::Dispute => {
+                let disputed = self.check_irregular(tx)?;
+                let amount = disputed
+                    .amount()
+                    .ok_or(TransactionError::NotDisputable(tx.id))?;
+                let rate = self.hold_rates.as_ref().map_or(Decimal::ONE, |rates| {
+                    rates.rate(tx.client, disputed.currency())
+                });
+                let held = (amount * rate).round_dp(amount.scale());
+                if self.disputes.contains_key(&tx.id) {
+                    self.update_dispute(tx);
+                    return Ok(());
+                }
+                self.apply_atomic(
+                    tx.id,
+                    &[BalanceOp::Hold {
+                        client: tx.client,
+                        amount: held,
+                    }],
+                )?;
+                if held != amount {
+                    self.partial_holds.insert(tx.id, held);
+                }
+                self.funds.hold(tx.client, tx.id, true);
+                if let Some(initiator) = tx.initiator {
+                    if let Some(limit) = self.policies.dispute_limits[initiator as usize] {
+                        if self.client_states.record_dispute(
+                            tx.client,
+                            initiator,
+                            tx.timestamp,
+                            limit,
+                        ) {
+                            self.audit.push(AuditEntry {
+                                event: AuditEvent::DisputeLimitReached,
+                                client: tx.client,
+                                tx: tx.id,
+                                amount: None,
+                                reference: None,
+                                rejection: None,
+                                initiator: Some(initiator),
+                                reason: None,
+                                external_ref: tx.external_ref.clone(),
+                            });
+                        }
+                    }
+                }
+                match self.cases.entry(tx.id) {
+                    Entry::Occupied(mut case) => {
+                        case.get_mut().record_status(CaseStatus::Open, tx.timestamp);
+                    }
+                    Entry::Vacant(case) => {
+                        case.insert(Case::open(tx.id, tx.timestamp, tx.initiator));
+                    }
+                }
+                self.disputes.insert(tx.id, tx.clone());
             }
-            TransactionType::Resolve => {
-                let (client, rtx) = self.check_irregular(tx)?;
-                client.held += rtx.amount.unwrap();
-                client.available -= rtx.amount.unwrap();
+            TransactionKind::Resolve => {
+                let amount = self
+                    .check_irregular(tx)?
+                    .amount()
+                    .ok_or(TransactionError::NotDisputable(tx.id))?;
+                let held = self.held_amount(tx.id, amount);
+                self.apply_atomic(
+                    tx.id,
+                    &[BalanceOp::Release {
+                        client: tx.client,
+                        amount: held,
+                    }],
+                )?;
+                self.funds.hold(tx.client, tx.id, false);
+                self.close_dispute(tx, held, DisputeOutcome::Resolved);
+                *self.resolutions.entry(tx.id).or_default() += 1;
             }
-            TransactionType::Chargeback => {
-                let (client, rtx) = self.check_irregular(tx)?;
-                client.locked = true;
-                client.held -= rtx.amount.unwrap();
+            TransactionKind::Chargeback => {
+                let amount = self
+                    .check_irregular(tx)?
+                    .amount()
+                    .ok_or(TransactionError::NotDisputable(tx.id))?;
+                let held = self.held_amount(tx.id, amount);
+                let mut ops = vec![
+                    BalanceOp::ChargeOff {
+                        client: tx.client,
+                        amount: held,
+                    },
+                    BalanceOp::Lock { client: tx.client },
+                ];
+                if held != amount {
+                    ops.push(BalanceOp::Forfeit {
+                        client: tx.client,
+                        amount: amount - held,
+                    });
+                }
+                if let Some(id) = self.policies.suspense_account {
+                    ops.push(BalanceOp::Credit { client: id, amount });
+                }
+                let fee = match self.policies.chargeback_fee {
+                    Some(fee) => {
+                        let payer = self.policies.fee_account.unwrap_or(tx.client);
+                        let id = match &self.id_allocator {
+                            Some(allocator) => allocator
+                                .allocate()
+                                .ok_or(TransactionError::IdsExhausted(tx.id))?,
+                            None => tx.id,
+                        };
+                        ops.push(match self.policies.separate_fees {
+                            true => BalanceOp::CollectFee {
+                                client: payer,
+                                amount: fee,
+                            },
+                            false => BalanceOp::Fee {
+                                client: payer,
+                                amount: fee,
+                            },
+                        });
+                        Some((payer, fee, id))
+                    }
+                    None => None,
+                };
+                if let Err(err) = self.apply_atomic(tx.id, &ops) {
+                    // The fee was never charged, so neither is its ID.
+                    if let (Some(allocator), Some((_, _, id))) = (&self.id_allocator, fee) {
+                        allocator.release(id);
+                    }
+                    return Err(err);
+                }
+                self.funds.charge_back(tx.client, tx.id);
+                self.close_dispute(tx, held, DisputeOutcome::ChargedBack);
+                // Fees are a ledger entry of their own, separate from the
+                // chargeback, so they can be passed through.
+                if let Some((payer, fee, id)) = fee {
+                    self.audit.push(AuditEntry {
+                        event: AuditEvent::ChargebackFee,
+                        client: payer,
+                        tx: id,
+                        amount: Some(fee),
+                        reference: None,
+                        rejection: None,
+                        initiator: None,
+                        reason: None,
+                        external_ref: tx.external_ref.clone(),
+                    });
+                }
             }
         }
         Ok(())
     }
 
+    /// Rejects a withdrawal that would leave less available than the
+    /// buffer kept for the client's open disputes, if one is required.
+    fn check_hold_buffer(&self, tx: &Transaction) -> Result<(), crate::errors::Error> {
+        let (ratio, client) = match (
+            self.policies.dispute_hold_buffer,
+            self.client_states.get(tx.client),
+        ) {
+            (Some(ratio), Some(client)) => (ratio, client),
+            _ => return Ok(()),
+        };
+        // Funds are held by open disputes, and by any custom types
+        // that hold funds themselves.
+        let overflow = || TransactionError::ArithmeticOverflow(tx.id);
+        let buffer = client
+            .held
+            .to_decimal()
+            .checked_mul(ratio)
+            .ok_or_else(overflow)?;
+        if !buffer.is_zero()
+            && tx.amount().unwrap_or_default()
+                > client
+                    .available
+                    .to_decimal()
+                    .checked_sub(buffer)
+                    .ok_or_else(overflow)?
+        {
+            return Err(ClientError::DisputeHoldBuffer(tx.id).into());
+        }
+        Ok(())
+    }
+
+    /// Keeps a successfully applied deposit, withdrawal or
+    /// adjustment, so it can be disputed later.
+    fn record_transaction(&mut self, tx: &Transaction) {
+        self.history.push_back((self.records, tx.timestamp, tx.id));
+        self.transactions.insert(tx.id, StoredTx::new(tx));
+        if let Some(seen) = &self.seen {
+            seen.insert(tx.id);
+        }
+    }
+
     /// Processes everything from a CSV stream.
     pub fn process_from_csv(&mut self, reader: impl std::io::Read) -> Result<(), crate::errors::Error> {
-        let mut rdr = csv::ReaderBuilder::new()
-            .has_headers(true)
-            .trim(csv::Trim::All)
-            .from_reader(reader);
-        rdr.deserialize().try_for_each(|tx| {
-            let tx = tx?;
-            let result = self.add(&tx);
-            if let Err(err) = result {
-                eprintln!("Warning: {}", err);
+        self.process_from_csv_with(reader, |_, err| {
+            eprintln!("Warning: {}", err);
+            Ok(())
+        })
+    }
+
+    /// Processes everything from a CSV stream, passing each rejected
+    /// record to `on_reject`. An error from `on_reject` aborts processing.
+    pub fn process_from_csv_with(
+        &mut self,
+        reader: impl std::io::Read,
+        on_reject: impl FnMut(&Transaction, &errors::Error) -> Result<(), errors::Error>,
+    ) -> Result<(), crate::errors::Error> {
+        self.process_from_csv_observed(reader, on_reject, |_, _| {})
+    }
+
+    /// Processes everything from a CSV stream like
+    /// `process_from_csv_with`, and passes the state to `observe`
+    /// after every record, whether or not it was accepted.
+    pub fn process_from_csv_observed(
+        &mut self,
+        reader: impl std::io::Read,
+        mut on_reject: impl FnMut(&Transaction, &errors::Error) -> Result<(), errors::Error>,
+        observe: impl FnMut(&CurrentState, &Transaction),
+    ) -> Result<(), crate::errors::Error> {
+        let filter = self.filter.clone();
+        let on_reject = |tx: &Transaction, err: errors::Error| on_reject(tx, &err);
+        self.process_csv(reader, filter.as_deref(), on_reject, observe)
+    }
+
+    /// Processes everything from a CSV stream, and returns the records
+    /// rejected, in order, rather than reporting them.
+    pub(crate) fn process_from_csv_collected(
+        &mut self,
+        reader: impl std::io::Read,
+    ) -> Result<Vec<(Transaction, errors::Error)>, crate::errors::Error> {
+        let mut rejects = Vec::new();
+        let filter = self.filter.clone();
+        let on_reject = |tx: &Transaction, err: errors::Error| {
+            rejects.push((tx.clone(), err));
+            Ok(())
+        };
+        self.process_csv(reader, filter.as_deref(), on_reject, |_, _| {})?;
+        Ok(rejects)
+    }
+
+    /// Processes only the records of a CSV stream that `filter`
+    /// selects, such as one client's, into this state, reporting
+    /// rejected records as `process_from_csv` does. Any filter the
+    /// state was built with is ignored.
+    pub fn process_from_csv_filtered(
+        &mut self,
+        reader: impl std::io::Read,
+        filter: &TxFilter,
+    ) -> Result<(), crate::errors::Error> {
+        let on_reject = |_: &Transaction, err: errors::Error| {
+            eprintln!("Warning: {}", err);
+            Ok(())
+        };
+        self.process_csv(reader, Some(filter), on_reject, |_, _| {})
+    }
+
+    /// Processes the records of a CSV stream that `filter` selects.
+    fn process_csv(
+        &mut self,
+        reader: impl std::io::Read,
+        filter: Option<&TxFilter>,
+        mut on_reject: impl FnMut(&Transaction, errors::Error) -> Result<(), errors::Error>,
+        mut observe: impl FnMut(&CurrentState, &Transaction),
+    ) -> Result<(), crate::errors::Error> {
+        let mut records = 0;
+        let mut process = |state: &mut CurrentState, tx: Transaction| {
+            let result = state.add(&tx);
+            match result {
+                Err(err @ errors::Error::Invariant(_)) => return Err(err),
+                Err(err) => on_reject(&tx, err)?,
+                Ok(()) => {}
             }
-            Ok::<_, errors::Error>(())
+            observe(state, &tx);
+            records += 1;
+            if state.archive.is_some() && records % COMPACT_INTERVAL == 0 {
+                state.compact()?;
+            }
+            if let Some(every) = state.policies.hash_every {
+                if records % every == 0 {
+                    eprintln!("State hash after {} records: {}", records, state.hash);
+                }
+            }
+            Ok(())
+        };
+        // Records are held back until a batch of them can be looked up
+        // at once, and when grouping, until the client changes.
+        let grouped = self.policies.group_clients;
+        let mut batch_size = self
+            .enricher
+            .as_ref()
+            .map_or(1, |enricher| enricher.batch_size().max(1));
+        if grouped {
+            batch_size = batch_size.max(MAX_GROUP);
+        }
+        let mut apply = |state: &mut CurrentState, batch: &mut Vec<Transaction>| {
+            state.prefetch_enrichments(batch);
+            if let (true, Some(first)) = (grouped, batch.first()) {
+                state.client_states.start_group(first.client);
+            }
+            let result = batch.drain(..).try_for_each(|tx| process(state, tx));
+            state.client_states.end_group();
+            result
+        };
+        let mut batch = Vec::with_capacity(batch_size);
+        read_records(self.policies, self.progress.clone(), reader, |tx| {
+            for tx in self.perturb(tx) {
+                if !self.selects(filter, &tx) {
+                    continue;
+                }
+                if grouped
+                    && batch
+                        .last()
+                        .is_some_and(|last: &Transaction| last.client != tx.client)
+                {
+                    apply(self, &mut batch)?;
+                }
+                batch.push(tx);
+                if batch.len() == batch_size {
+                    apply(self, &mut batch)?;
+                }
+            }
+            Ok(())
         })?;
+        apply(self, &mut batch)?;
+        if let Some(every) = self.policies.hash_every {
+            if records % every != 0 {
+                eprintln!("State hash after {} records: {}", records, self.hash);
+            }
+        }
         Ok(())
     }
 
+    /// Reads every record of a CSV stream as this state would, passing
+    /// each its filter selects to `each` without processing it.
+    pub(crate) fn read_csv(
+        &self,
+        reader: impl std::io::Read,
+        mut each: impl FnMut(Transaction) -> Result<(), errors::Error>,
+    ) -> Result<(), crate::errors::Error> {
+        let filter = self.filter.as_deref();
+        read_records(self.policies, self.progress.clone(), reader, |tx| {
+            self.perturb(tx)
+                .filter(|tx| self.selects(filter, tx))
+                .try_for_each(&mut each)
+        })
+    }
+
+    /// Whether `filter`, if any, selects `tx`, and it is not held back
+    /// to be retried.
+    fn selects(&self, filter: Option<&TxFilter>, tx: &Transaction) -> bool {
+        let selected = filter.is_none_or(|filter| {
+            let client = match &tx.account {
+                Some(account) => self.counterparties.client(account),
+                None => Some(tx.client),
+            };
+            filter.selects(tx, client)
+        });
+        selected
+            && !self
+                .retries
+                .as_ref()
+                .is_some_and(|retries| retries.holds_back(tx))
+    }
+
     /// Writes results into a CSV stream.
     pub fn into_csv(self, writer: impl std::io::Write) -> Result<(), csv::Error> {
         let mut wtr = csv::WriterBuilder::new()
             .has_headers(true)
             .from_writer(writer);
-        self.client_states
-            .values()
-            .try_for_each(|item| wtr.serialize(CsvClient::from(item)))?;
+        self.active_clients()
+            .try_for_each(|client| wtr.serialize(client))?;
+        Ok(())
+    }
+
+    /// Sets the client states `export_delta` compares against, such as
+    /// the output of the previous run read by `diff::read_balances`.
+    /// Without a baseline, every client in the output is new.
+    pub fn set_baseline(&mut self, baseline: Balances) {
+        self.baseline = baseline;
+    }
+
+    /// Takes the current output as the baseline, so the next delta only
+    /// holds what changes from here.
+    pub fn mark_baseline(&mut self) {
+        self.baseline = self
+            .active_clients()
+            .map(|client| (client.client, client))
+            .collect();
+    }
+
+    /// Writes the clients whose balances or lock status differ from the
+    /// baseline into a CSV stream, with their old and new values, so
+    /// downstream systems can apply the changes rather than reload
+    /// every client.
+    pub fn export_delta(&self, writer: impl std::io::Write) -> Result<(), csv::Error> {
+        let current: Balances = self
+            .active_clients()
+            .map(|client| (client.client, client))
+            .collect();
+        diff::write_delta(writer, &diff::delta_balances(&self.baseline, &current))
+    }
+
+    /// Writes the dormant clients into a CSV stream, in the same format
+    /// as the output.
+    pub fn dormant_to_csv(&self, writer: impl std::io::Write) -> Result<(), csv::Error> {
+        let mut wtr = csv::WriterBuilder::new()
+            .has_headers(true)
+            .from_writer(writer);
+        self.dormant_clients()
+            .try_for_each(|client| wtr.serialize(client))?;
+        wtr.flush()?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::Scenario;
+
+    /// The rejection code of `result`, if it was rejected.
+    fn code(result: Result<(), errors::Error>) -> Option<RejectionCode> {
+        result.err().and_then(|err| err.rejection_code())
+    }
+
+    /// The largest amount a balance holds, which is less than
+    /// `Decimal::MAX` with the `fixed-point` feature.
+    fn max() -> Decimal {
+        Balance::max_amount()
+    }
+
+    /// Applies every record of `scenario`, which must all be accepted.
+    fn run_accepted(scenario: &Scenario, state: &mut CurrentState) {
+        for (tx, result) in scenario.records().iter().zip(scenario.run(state)) {
+            assert!(result.is_ok(), "{:?} was rejected: {:?}", tx, result);
+        }
+    }
+
+    /// The clients with IDs `ids`, added in order, with only the last
+    /// locked, so it can be told apart once moved.
+    fn client_states(ids: &[u16]) -> ClientStates {
+        let mut states = ClientStates::default();
+        for &id in ids {
+            let mut client = Client::from_id(id);
+            client.locked = Some(&id) == ids.last();
+            states.insert(client);
+        }
+        states
+    }
+
+    #[test]
+    fn removes_the_last_and_a_middle_client() {
+        let mut states = client_states(&[1, 2, 3, 4]);
+        assert_eq!(states.remove(4).map(|client| client.id), Some(4));
+        assert_eq!(states.position(4), None);
+        assert_eq!(states.keys().collect::<Vec<_>>(), [1, 2, 3]);
+
+        // The last client moves into the place of the one removed.
+        assert_eq!(states.remove(2).map(|client| client.id), Some(2));
+        assert_eq!(states.keys().collect::<Vec<_>>(), [1, 3]);
+        assert_eq!(states.position(3), Some(1));
+        assert!(!states.get(1).unwrap().locked);
+        assert!(!states.get(3).unwrap().locked);
+        assert!(states.remove(2).is_none());
+        assert_eq!(states.len(), 2);
+    }
+
+    #[test]
+    fn follows_the_grouped_client_through_removals() {
+        let mut states = client_states(&[1, 2, 3]);
+        states.start_group(3);
+        // Removing another client moves the grouped one.
+        states.remove(1);
+        assert_eq!(states.position(3), Some(0));
+        let client = states.get(3).unwrap();
+        assert_eq!(client.id, 3);
+        assert!(client.locked);
+
+        states.end_group();
+        states.start_group(2);
+        states.remove(2);
+        assert_eq!(states.position(2), None);
+        assert!(states.get(2).is_none());
+        assert_eq!(states.position(3), Some(0));
+    }
+
+    #[test]
+    fn forgets_a_client_added_and_rolled_back_within_a_group() {
+        let mut states = client_states(&[1, 2]);
+        states.start_group(7);
+        assert_eq!(states.position(7), None);
+        states.insert(Client::from_id(7));
+        assert_eq!(states.position(7), Some(2));
+
+        // As `apply_atomic` undoes a record that created the client.
+        states.remove(7);
+        assert_eq!(states.position(7), None);
+        assert!(!states.contains_key(7));
+        assert_eq!(states.keys().collect::<Vec<_>>(), [1, 2]);
+
+        states.insert(Client::from_id(7));
+        assert_eq!(states.position(7), Some(2));
+        assert_eq!(states.get(7).unwrap().id, 7);
+    }
+
+    /// Checks that the state hash kept up to date matches one
+    /// computed again from scratch.
+    fn assert_hash_rebuilds(state: &mut CurrentState) {
+        let hash = state.state_hash();
+        state.rehash();
+        assert_eq!(state.state_hash(), hash);
+    }
+
+    #[test]
+    fn resolves_a_dispute_of_a_merged_client_through_the_other() {
+        let mut state = CurrentState::default();
+        let mut scenario = Scenario::new();
+        let tx = scenario.deposit(1, Decimal::TEN);
+        scenario.deposit(2, Decimal::ONE);
+        scenario.dispute(1, tx);
+        run_accepted(&scenario, &mut state);
+        state.merge_clients(1, 2, false).unwrap();
+        assert_hash_rebuilds(&mut state);
+        let client = state.client(2).unwrap();
+        assert_eq!(client.held, Decimal::TEN);
+        assert_eq!(client.available, Decimal::ONE);
+
+        let resolve = Transaction::builder().resolve(1, tx).build();
+        assert_eq!(code(state.add(&resolve)), Some(RejectionCode::Merged));
+        let resolve = Transaction::builder().resolve(2, tx).build();
+        state.add(&resolve).unwrap();
+        let client = state.client(2).unwrap();
+        assert_eq!(client.held, Decimal::ZERO);
+        assert_eq!(client.available, Decimal::from(11));
+        assert!(state.disputes.is_empty());
+        assert_hash_rebuilds(&mut state);
+    }
+
+    #[test]
+    fn rejects_records_naming_a_merged_client() {
+        let mut state = CurrentState::default();
+        let mut scenario = Scenario::new();
+        scenario.deposit(1, Decimal::TEN);
+        scenario.deposit(2, Decimal::ONE);
+        run_accepted(&scenario, &mut state);
+        state.merge_clients(1, 2, false).unwrap();
+
+        let deposit = Transaction::builder()
+            .deposit(1, Decimal::ONE)
+            .id(3)
+            .build();
+        assert_eq!(code(state.add(&deposit)), Some(RejectionCode::Merged));
+        assert_eq!(state.client(1), None);
+        assert_eq!(state.client(2).unwrap().total, Decimal::from(11));
+    }
+
+    #[test]
+    fn merges_a_chain_of_clients_into_the_last() {
+        let mut state = CurrentState::default();
+        let mut scenario = Scenario::new();
+        for client in 1..=3 {
+            scenario.deposit(client, Decimal::TEN);
+        }
+        run_accepted(&scenario, &mut state);
+        state.merge_clients(1, 2, false).unwrap();
+        state.merge_clients(2, 3, false).unwrap();
+        assert_hash_rebuilds(&mut state);
+
+        assert_eq!(state.merged, HashMap::from([(1, 3), (2, 3)]));
+        assert!(state.transactions.values().all(|stored| stored.client == 3));
+        assert_eq!(state.client(3).unwrap().total, Decimal::from(30));
+        for client in 1..=2 {
+            let deposit = Transaction::builder()
+                .deposit(client, Decimal::ONE)
+                .id(u32::from(client) + 3)
+                .build();
+            assert_eq!(code(state.add(&deposit)), Some(RejectionCode::Merged));
+        }
+        assert!(matches!(
+            state.merge_clients(1, 3, false),
+            Err(errors::Error::ClientMerge(ClientMergeError::AlreadyMerged(
+                1, 3
+            )))
+        ));
+    }
+
+    #[derive(Debug)]
+    /// Credits a new client 5 with the amount of each record, then
+    /// debits the record's client, which fails without enough funds.
+    struct Transfer;
+
+    impl TransactionHandler for Transfer {
+        fn apply(&self, tx: &Transaction, state: &mut StateView<'_>) -> Result<(), errors::Error> {
+            let amount = tx.amount().unwrap_or_default();
+            state.credit(5, amount);
+            state.debit(tx.client, amount);
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Default)]
+    /// Counts the disputes put to it. Leaves the first open and charges
+    /// back any after it, so a second call moves funds.
+    struct CountingAdjudicator {
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    impl Adjudicator for CountingAdjudicator {
+        fn adjudicate(&self, _case: &DisputeCase) -> Ruling {
+            match self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => Ruling::Pending,
+                _ => Ruling::Chargeback,
+            }
+        }
+    }
+
+    #[test]
+    fn adjudicates_duplicate_disputes_once() {
+        for policy in [
+            DuplicateDisputePolicy::Reject,
+            DuplicateDisputePolicy::Ignore,
+            DuplicateDisputePolicy::UpdateMetadata,
+        ] {
+            let adjudicator = Arc::new(CountingAdjudicator::default());
+            let mut state = CurrentState::builder()
+                .duplicate_disputes(policy)
+                .adjudicator(adjudicator.clone())
+                .build()
+                .unwrap();
+            let mut scenario = Scenario::new();
+            let tx = scenario.deposit(1, Decimal::TEN);
+            scenario.dispute(1, tx).dispute(1, tx);
+            let results = scenario.run(&mut state);
+            assert_eq!(
+                results[2].is_ok(),
+                policy != DuplicateDisputePolicy::Reject,
+                "{:?}",
+                policy
+            );
+            let calls = adjudicator.calls.load(std::sync::atomic::Ordering::SeqCst);
+            assert_eq!(calls, 1, "{:?}", policy);
+            let client = state.client(1).unwrap();
+            assert_eq!(client.held, Decimal::TEN, "{:?}", policy);
+            assert!(!client.locked, "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn rolls_back_a_chargeback_whose_fee_overflows() {
+        let allocator = Arc::new(crate::ids::Sequential::starting_at(1000));
+        let mut state = CurrentState::builder()
+            .overdraft(max())
+            .chargeback_fee(Decimal::ONE)
+            .id_allocator(allocator.clone())
+            .build()
+            .unwrap();
+        let mut scenario = Scenario::new();
+        let tx = scenario.deposit(1, Decimal::TEN);
+        scenario.dispute(1, tx);
+        // Takes the available funds to the bottom of the range, so the
+        // fee, the last operation of the chargeback, cannot be taken.
+        scenario.withdrawal(1, max());
+        run_accepted(&scenario, &mut state);
+        let client = state.client(1).unwrap();
+        let hash = state.state_hash();
+
+        let chargeback = Transaction::builder().chargeback(1, tx).build();
+        assert_eq!(
+            code(state.add(&chargeback)),
+            Some(RejectionCode::ArithmeticOverflow)
+        );
+        // The charge-off and the lock before the fee are undone.
+        assert_eq!(state.client(1), Some(client));
+        assert_eq!(client.held, Decimal::TEN);
+        assert!(!client.locked);
+        assert_eq!(state.state_hash(), hash);
+        assert!(state.disputes.contains_key(&tx));
+        // So is the ID the fee would have had.
+        assert_eq!(allocator.allocate(), Some(1000));
+    }
+
+    #[test]
+    fn rolls_back_a_custom_record_without_creating_clients() {
+        let mut state = CurrentState::builder()
+            .transaction_type("transfer", Arc::new(Transfer))
+            .build()
+            .unwrap();
+        let mut scenario = Scenario::new();
+        scenario.deposit(1, Decimal::TEN);
+        run_accepted(&scenario, &mut state);
+        let client = state.client(1).unwrap();
+        let hash = state.state_hash();
+
+        let transfer = Transaction::builder()
+            .custom(1, "transfer", Some(Decimal::ONE_HUNDRED))
+            .id(2)
+            .build();
+        assert_eq!(
+            code(state.add(&transfer)),
+            Some(RejectionCode::InsufficientFunds)
+        );
+        // The credit to client 5 came first, and created it.
+        assert_eq!(state.client(5), None);
+        assert_eq!(state.clients().count(), 1);
+        assert_eq!(state.client(1), Some(client));
+        assert_eq!(state.state_hash(), hash);
+    }
+
+    #[test]
+    fn applies_every_operation_of_an_accepted_record() {
+        let mut state = CurrentState::builder()
+            .transaction_type("transfer", Arc::new(Transfer))
+            .build()
+            .unwrap();
+        let mut scenario = Scenario::new();
+        scenario.deposit(1, Decimal::ONE_HUNDRED);
+        scenario.custom(1, "transfer", Some(Decimal::TEN));
+        run_accepted(&scenario, &mut state);
+        assert_eq!(state.client(1).unwrap().available, Decimal::from(90));
+        assert_eq!(state.client(5).unwrap().available, Decimal::TEN);
+    }
+
+    #[test]
+    fn rejects_deposits_past_the_maximum() {
+        let mut state = CurrentState::default();
+        let mut scenario = Scenario::new();
+        scenario.deposit(1, max());
+        run_accepted(&scenario, &mut state);
+        let hash = state.state_hash();
+
+        let deposit = Transaction::builder()
+            .deposit(1, Decimal::ONE)
+            .id(2)
+            .build();
+        assert_eq!(
+            code(state.add(&deposit)),
+            Some(RejectionCode::ArithmeticOverflow)
+        );
+        assert_eq!(state.client(1).unwrap().available, max());
+        assert_eq!(state.state_hash(), hash);
+        assert!(!state.transactions.contains_key(&2));
+    }
+
+    #[test]
+    fn holds_and_releases_the_maximum() {
+        let mut state = CurrentState::default();
+        let mut scenario = Scenario::new();
+        let tx = scenario.deposit(1, max());
+        scenario.dispute(1, tx);
+        run_accepted(&scenario, &mut state);
+        let client = state.client(1).unwrap();
+        assert_eq!(client.available, Decimal::ZERO);
+        assert_eq!(client.held, max());
+        assert_eq!(client.total, max());
+
+        let resolve = Transaction::builder().resolve(1, tx).build();
+        assert!(state.add(&resolve).is_ok());
+        let client = state.client(1).unwrap();
+        assert_eq!(client.available, max());
+        assert_eq!(client.held, Decimal::ZERO);
+    }
+
+    #[test]
+    fn rejects_holds_whose_total_overflows() {
+        let mut state = CurrentState::default();
+        let mut scenario = Scenario::new();
+        let tx = scenario.deposit(1, max());
+        scenario.dispute(1, tx);
+        run_accepted(&scenario, &mut state);
+
+        let client = state.client(1).unwrap();
+        let hash = state.state_hash();
+        let deposit = Transaction::builder()
+            .deposit(1, Decimal::ONE)
+            .id(2)
+            .build();
+        // Either balance has room, but available plus held would be
+        // past the maximum.
+        assert_eq!(
+            code(state.add(&deposit)),
+            Some(RejectionCode::ArithmeticOverflow)
+        );
+        assert_eq!(state.client(1), Some(client));
+        assert_eq!(state.state_hash(), hash);
+    }
+
+    #[test]
+    fn overdraws_to_the_limit_near_the_minimum() {
+        let mut state = CurrentState::builder().overdraft(max()).build().unwrap();
+        let mut scenario = Scenario::new();
+        scenario.deposit(1, Decimal::ONE);
+        scenario.withdrawal(1, max());
+        run_accepted(&scenario, &mut state);
+        let client = state.client(1).unwrap();
+        assert_eq!(client.available, -max() + Decimal::ONE);
+        let hash = state.state_hash();
+
+        let withdrawal = Transaction::builder()
+            .withdrawal(1, Decimal::TWO)
+            .id(3)
+            .build();
+        assert_eq!(
+            code(state.add(&withdrawal)),
+            Some(RejectionCode::ArithmeticOverflow)
+        );
+        assert_eq!(state.client(1), Some(client));
+        assert_eq!(state.state_hash(), hash);
+    }
+
+    #[test]
+    fn rejects_overdrafts_past_the_limit_near_the_minimum() {
+        let limit = max() - Decimal::TWO;
+        let mut state = CurrentState::builder().overdraft(limit).build().unwrap();
+        let mut scenario = Scenario::new();
+        scenario.deposit(1, Decimal::ONE);
+        run_accepted(&scenario, &mut state);
+
+        let withdrawal = Transaction::builder().withdrawal(1, max()).id(2).build();
+        // One more than the limit allows, and still in range.
+        assert_eq!(
+            code(state.add(&withdrawal)),
+            Some(RejectionCode::InsufficientFunds)
+        );
+        assert_eq!(state.client(1).unwrap().available, Decimal::ONE);
+    }
+
+    #[test]
+    // Balances with the `fixed-point` feature cannot get near the
+    // range of the summary's sums.
+    #[cfg(not(feature = "fixed-point"))]
+    fn rejects_records_that_would_overflow_the_summary() {
+        let mut state = CurrentState::default();
+        let mut scenario = Scenario::new();
+        scenario.deposit(1, Decimal::MAX);
+        run_accepted(&scenario, &mut state);
+        let summary = state.summary().clone();
+
+        // Client 2 has room, but the run-wide sum of deposits does not.
+        let deposit = Transaction::builder()
+            .deposit(2, Decimal::ONE)
+            .id(2)
+            .build();
+        assert_eq!(
+            code(state.add(&deposit)),
+            Some(RejectionCode::ArithmeticOverflow)
+        );
+        assert_eq!(state.client(2), None);
+        assert_eq!(state.summary().deposited, summary.deposited);
+        assert_eq!(state.summary().accepted, summary.accepted);
+    }
+
+    #[test]
+    fn leaves_the_state_unchanged_when_balances_overflow_a_merge() {
+        let mut first = CurrentState::default();
+        let mut scenario = Scenario::new();
+        scenario.deposit(1, max());
+        run_accepted(&scenario, &mut first);
+        let mut second = CurrentState::default();
+        let mut scenario = Scenario::new();
+        scenario.push(
+            Transaction::builder()
+                .deposit(1, Decimal::ONE)
+                .id(2)
+                .build(),
+        );
+        run_accepted(&scenario, &mut second);
+
+        let client = first.client(1);
+        let hash = first.state_hash();
+        let summary = first.summary().clone();
+        assert!(matches!(
+            first.merge(second),
+            Err(MergeError::BalanceOverflow(1))
+        ));
+        assert_eq!(first.client(1), client);
+        assert_eq!(first.state_hash(), hash);
+        assert_eq!(first.summary(), &summary);
+        assert!(!first.transactions.contains_key(&2));
+    }
+
+    #[test]
+    // Balances with the `fixed-point` feature cannot get near the
+    // range of the summary's sums.
+    #[cfg(not(feature = "fixed-point"))]
+    fn leaves_the_state_unchanged_when_the_summary_overflows_a_merge() {
+        let mut first = CurrentState::default();
+        let mut scenario = Scenario::new();
+        scenario.deposit(1, Decimal::MAX);
+        run_accepted(&scenario, &mut first);
+        let mut second = CurrentState::default();
+        let mut scenario = Scenario::new();
+        scenario.push(
+            Transaction::builder()
+                .deposit(2, Decimal::MAX)
+                .id(2)
+                .build(),
+        );
+        run_accepted(&scenario, &mut second);
+
+        let hash = first.state_hash();
+        let summary = first.summary().clone();
+        assert!(matches!(
+            first.merge(second),
+            Err(MergeError::SummaryOverflow)
+        ));
+        assert_eq!(first.client(2), None);
+        assert_eq!(first.state_hash(), hash);
+        assert_eq!(first.summary(), &summary);
+    }
+
+    #[test]
+    fn counts_the_velocity_of_both_merged_states() {
+        let builder = CurrentState::builder()
+            .timestamps(true)
+            .quarantine_velocity(VelocityLimit {
+                count: 2,
+                window: 100,
+            });
+        let deposit = |id, timestamp| {
+            let mut scenario = Scenario::new();
+            scenario.push(
+                Transaction::builder()
+                    .deposit(1, Decimal::ONE)
+                    .id(id)
+                    .timestamp(timestamp)
+                    .build(),
+            );
+            scenario
+        };
+        let mut first = builder.clone().build().unwrap();
+        run_accepted(&deposit(1, 10), &mut first);
+        let mut second = builder.build().unwrap();
+        run_accepted(&deposit(2, 20), &mut second);
+        first.merge(second).unwrap();
+
+        run_accepted(&deposit(3, 30), &mut first);
+        assert!(first.quarantined().any(|held| held.tx.id == 3));
+    }
+}
@@ -0,0 +1,251 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+
+use crate::calendar::Date;
+use crate::state::{CsvClient, CurrentState};
+use crate::transaction::TransactionType;
+
+/// The number of seconds in a day.
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// The bank formats a statement can be written in.
+pub enum StatementFormat {
+    /// Quicken Interchange Format, with dates as `MM/DD/YYYY`.
+    #[default]
+    Qif,
+    /// Open Financial Exchange 2.1.1, which is XML.
+    Ofx,
+}
+
+impl StatementFormat {
+    /// The file extension for the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            StatementFormat::Qif => "qif",
+            StatementFormat::Ofx => "ofx",
+        }
+    }
+}
+
+impl FromStr for StatementFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "qif" => Ok(StatementFormat::Qif),
+            "ofx" => Ok(StatementFormat::Ofx),
+            _ => Err(format!("expected `qif` or `ofx`, got `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// One deposit, withdrawal or adjustment on a statement.
+pub struct StatementLine {
+    pub tx: u32,
+    pub r#type: TransactionType,
+    /// The amount, negative for withdrawals.
+    pub amount: Decimal,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The operator reference or the sender's own ID, if any.
+    pub memo: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// The transaction history and closing balances of one client.
+pub struct Statement {
+    pub client: CsvClient,
+    pub lines: Vec<StatementLine>,
+    /// When the balances are as of: the latest timestamp seen.
+    pub as_of: u64,
+}
+
+impl Statement {
+    /// The statement of the client with ID `id`, if it exists, from the
+    /// deposits, withdrawals and adjustments `state` still holds, in
+    /// processing order. Chargebacks are not listed, but are reflected
+    /// in the closing balances.
+    ///
+    /// Bank formats require a date on every entry, so records without a
+    /// timestamp are dated to the latest timestamp seen, or to the Unix
+    /// epoch if there is none.
+    pub fn new(state: &CurrentState, id: u16) -> Option<Self> {
+        let client = state.client(id)?;
+        let as_of = state.latest_timestamp().unwrap_or_default();
+        let lines = state
+            .client_history(id)
+            .map(|tx| {
+                let amount = tx.amount().unwrap_or_default();
+                StatementLine {
+                    tx: tx.id,
+                    r#type: tx.r#type(),
+                    amount: match tx.r#type() {
+                        TransactionType::Withdrawal => -amount,
+                        _ => amount,
+                    },
+                    timestamp: tx.timestamp.unwrap_or(as_of),
+                    memo: tx.reference.or(tx.external_ref),
+                }
+            })
+            .collect();
+        Some(Statement {
+            client,
+            lines,
+            as_of,
+        })
+    }
+
+    /// Writes the statement in `format`, in `currency` for OFX.
+    pub fn write(
+        &self,
+        writer: impl Write,
+        format: StatementFormat,
+        currency: &str,
+    ) -> std::io::Result<()> {
+        match format {
+            StatementFormat::Qif => self.write_qif(writer),
+            StatementFormat::Ofx => self.write_ofx(writer, currency),
+        }
+    }
+
+    /// Writes the statement as QIF, which has no place for balances.
+    pub fn write_qif(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writeln!(writer, "!Type:Bank")?;
+        for line in &self.lines {
+            let (year, month, day) = Date::from_timestamp(line.timestamp).ymd();
+            writeln!(writer, "D{:02}/{:02}/{:04}", month, day, year)?;
+            writeln!(writer, "T{}", line.amount)?;
+            writeln!(writer, "N{}", line.tx)?;
+            writeln!(writer, "P{}", type_name(line.r#type))?;
+            if let Some(memo) = &line.memo {
+                // Each field is one line.
+                writeln!(writer, "M{}", memo.replace(['\r', '\n'], " "))?;
+            }
+            writeln!(writer, "^")?;
+        }
+        writer.flush()
+    }
+
+    /// Writes the statement as OFX, with the client's total as the
+    /// ledger balance and their available funds as the available
+    /// balance.
+    pub fn write_ofx(&self, mut writer: impl Write, currency: &str) -> std::io::Result<()> {
+        let as_of = ofx_time(self.as_of);
+        let start = self
+            .lines
+            .iter()
+            .map(|line| line.timestamp)
+            .min()
+            .unwrap_or(self.as_of);
+        writeln!(
+            writer,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>"
+        )?;
+        writeln!(
+            writer,
+            "<?OFX OFXHEADER=\"200\" VERSION=\"211\" SECURITY=\"NONE\" OLDFILEUID=\"NONE\" NEWFILEUID=\"NONE\"?>"
+        )?;
+        writeln!(writer, "<OFX>")?;
+        writeln!(writer, "<SIGNONMSGSRSV1><SONRS>")?;
+        writeln!(
+            writer,
+            "<STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>"
+        )?;
+        writeln!(
+            writer,
+            "<DTSERVER>{}</DTSERVER><LANGUAGE>ENG</LANGUAGE>",
+            as_of
+        )?;
+        writeln!(writer, "</SONRS></SIGNONMSGSRSV1>")?;
+        writeln!(writer, "<BANKMSGSRSV1><STMTTRNRS>")?;
+        writeln!(writer, "<TRNUID>{}</TRNUID>", self.client.client)?;
+        writeln!(
+            writer,
+            "<STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>"
+        )?;
+        writeln!(writer, "<STMTRS>")?;
+        writeln!(writer, "<CURDEF>{}</CURDEF>", escape(currency))?;
+        writeln!(
+            writer,
+            "<BANKACCTFROM><BANKID>payment-engine</BANKID><ACCTID>{}</ACCTID><ACCTTYPE>CHECKING</ACCTTYPE></BANKACCTFROM>",
+            self.client.client
+        )?;
+        writeln!(writer, "<BANKTRANLIST>")?;
+        writeln!(
+            writer,
+            "<DTSTART>{}</DTSTART><DTEND>{}</DTEND>",
+            ofx_time(start),
+            as_of
+        )?;
+        for line in &self.lines {
+            writeln!(writer, "<STMTTRN>")?;
+            writeln!(
+                writer,
+                "<TRNTYPE>{}</TRNTYPE>",
+                match line.amount.is_sign_negative() {
+                    true => "DEBIT",
+                    false => "CREDIT",
+                }
+            )?;
+            writeln!(writer, "<DTPOSTED>{}</DTPOSTED>", ofx_time(line.timestamp))?;
+            writeln!(writer, "<TRNAMT>{}</TRNAMT>", line.amount)?;
+            writeln!(writer, "<FITID>{}</FITID>", line.tx)?;
+            writeln!(writer, "<NAME>{}</NAME>", type_name(line.r#type))?;
+            if let Some(memo) = &line.memo {
+                writeln!(writer, "<MEMO>{}</MEMO>", escape(memo))?;
+            }
+            writeln!(writer, "</STMTTRN>")?;
+        }
+        writeln!(writer, "</BANKTRANLIST>")?;
+        writeln!(
+            writer,
+            "<LEDGERBAL><BALAMT>{}</BALAMT><DTASOF>{}</DTASOF></LEDGERBAL>",
+            self.client.total, as_of
+        )?;
+        writeln!(
+            writer,
+            "<AVAILBAL><BALAMT>{}</BALAMT><DTASOF>{}</DTASOF></AVAILBAL>",
+            self.client.available, as_of
+        )?;
+        writeln!(writer, "</STMTRS>")?;
+        writeln!(writer, "</STMTTRNRS></BANKMSGSRSV1>")?;
+        writeln!(writer, "</OFX>")?;
+        writer.flush()
+    }
+}
+
+/// The payee shown for a type of transaction.
+fn type_name(r#type: TransactionType) -> &'static str {
+    match r#type {
+        TransactionType::Deposit => "Deposit",
+        TransactionType::Withdrawal => "Withdrawal",
+        TransactionType::Adjustment => "Adjustment",
+        _ => "Other",
+    }
+}
+
+/// Escapes text for XML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Formats a timestamp as an OFX date and time, in UTC.
+fn ofx_time(timestamp: u64) -> String {
+    let (year, month, day) = Date::from_timestamp(timestamp).ymd();
+    let seconds = timestamp % SECONDS_PER_DAY;
+    format!(
+        "{:04}{:02}{:02}{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,453 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use rust_decimal::Decimal;
+use serde::{Serialize, Serializer};
+
+use crate::errors::RejectionCode;
+use crate::transaction::TransactionType;
+
+#[derive(Debug, Default, Serialize, PartialEq, Eq, Clone)]
+/// Aggregate statistics about processed records. Every field is a
+/// count, a sum or a histogram of counts, so summaries of separate
+/// inputs can be merged in any order into the summary of all of them,
+/// with `Default` as the empty summary.
+pub struct Summary {
+    /// Every record processed.
+    pub records: u64,
+    /// Records that were applied.
+    pub accepted: u64,
+    /// Records that were held for review.
+    pub quarantined: u64,
+    /// Records skipped as replays of transactions already applied.
+    pub replayed: u64,
+    /// Records that were rejected.
+    pub rejected: u64,
+    /// Rejected records, by rejection code.
+    pub rejections: BTreeMap<RejectionCode, u64>,
+    /// Held records that were later approved and applied.
+    pub approved: u64,
+    /// Held records that were later denied.
+    pub denied: u64,
+    /// Applied deposits.
+    pub deposits: u64,
+    /// Applied withdrawals.
+    pub withdrawals: u64,
+    /// Applied adjustments.
+    pub adjustments: u64,
+    /// Disputes opened.
+    pub disputes: u64,
+    /// Disputes resolved.
+    pub resolves: u64,
+    /// Disputes charged back.
+    pub chargebacks: u64,
+    /// The sum of applied deposits.
+    #[serde(with = "rust_decimal::serde::str")]
+    pub deposited: Decimal,
+    /// The sum of applied withdrawals.
+    #[serde(with = "rust_decimal::serde::str")]
+    pub withdrawn: Decimal,
+    /// The sum of applied adjustments, which may be negative.
+    #[serde(with = "rust_decimal::serde::str")]
+    pub adjusted: Decimal,
+    /// The sum of the amounts of opened disputes.
+    #[serde(with = "rust_decimal::serde::str")]
+    pub disputed: Decimal,
+    /// The sum of the amounts of resolved disputes.
+    #[serde(with = "rust_decimal::serde::str")]
+    pub resolved: Decimal,
+    /// The sum of the amounts of chargebacks.
+    #[serde(with = "rust_decimal::serde::str")]
+    pub charged_back: Decimal,
+    /// The sum of the fees charged for chargebacks.
+    #[serde(with = "rust_decimal::serde::str")]
+    pub fees: Decimal,
+    /// The distribution of the amounts of applied deposits.
+    pub deposit_amounts: AmountHistogram,
+    /// The distribution of the amounts of applied withdrawals.
+    pub withdrawal_amounts: AmountHistogram,
+    /// Disputes, resolves and chargebacks giving a reason code, by code.
+    pub reasons: BTreeMap<String, ReasonSummary>,
+}
+
+#[derive(Debug, Default, Serialize, PartialEq, Eq, Clone)]
+/// Statistics about the disputes opened for one reason code, and how
+/// they were settled.
+pub struct ReasonSummary {
+    pub disputes: u64,
+    pub resolves: u64,
+    pub chargebacks: u64,
+    /// The sum of the amounts of opened disputes.
+    #[serde(with = "rust_decimal::serde::str")]
+    pub disputed: Decimal,
+    /// The sum of the amounts of chargebacks.
+    #[serde(with = "rust_decimal::serde::str")]
+    pub charged_back: Decimal,
+}
+
+impl Summary {
+    /// Adds the statistics of `other` to this summary.
+    pub fn merge(&mut self, other: Summary) {
+        self.records += other.records;
+        self.accepted += other.accepted;
+        self.quarantined += other.quarantined;
+        self.replayed += other.replayed;
+        self.rejected += other.rejected;
+        for (code, count) in other.rejections {
+            *self.rejections.entry(code).or_default() += count;
+        }
+        self.approved += other.approved;
+        self.denied += other.denied;
+        self.deposits += other.deposits;
+        self.withdrawals += other.withdrawals;
+        self.adjustments += other.adjustments;
+        self.disputes += other.disputes;
+        self.resolves += other.resolves;
+        self.chargebacks += other.chargebacks;
+        self.deposited += other.deposited;
+        self.withdrawn += other.withdrawn;
+        self.adjusted += other.adjusted;
+        self.disputed += other.disputed;
+        self.resolved += other.resolved;
+        self.charged_back += other.charged_back;
+        self.fees += other.fees;
+        self.deposit_amounts.merge(other.deposit_amounts);
+        self.withdrawal_amounts.merge(other.withdrawal_amounts);
+        for (code, other) in other.reasons {
+            let reason = self.reasons.entry(code).or_default();
+            reason.disputes += other.disputes;
+            reason.resolves += other.resolves;
+            reason.chargebacks += other.chargebacks;
+            reason.disputed += other.disputed;
+            reason.charged_back += other.charged_back;
+        }
+    }
+
+    /// The sums of the summary, apart from those of reason codes.
+    fn sums(&self) -> [Decimal; 7] {
+        [
+            self.deposited,
+            self.withdrawn,
+            self.adjusted,
+            self.disputed,
+            self.resolved,
+            self.charged_back,
+            self.fees,
+        ]
+    }
+
+    /// Whether every sum stays within the range of a decimal when
+    /// `other` is merged in.
+    pub(crate) fn can_merge(&self, other: &Summary) -> bool {
+        let fits = |a: Decimal, b: Decimal| a.checked_add(b).is_some();
+        self.sums()
+            .into_iter()
+            .zip(other.sums())
+            .all(|(a, b)| fits(a, b))
+            && other.reasons.iter().all(|(code, other)| {
+                self.reasons.get(code).is_none_or(|reason| {
+                    fits(reason.disputed, other.disputed)
+                        && fits(reason.charged_back, other.charged_back)
+                })
+            })
+    }
+
+    /// Whether every sum stays within the range of a decimal when a
+    /// record of `type` and `amount` is counted, with its reason `code`
+    /// and chargeback `fee`, if any.
+    pub(crate) fn has_room(
+        &self,
+        r#type: TransactionType,
+        amount: Option<Decimal>,
+        code: Option<&str>,
+        fee: Option<Decimal>,
+    ) -> bool {
+        let mut record = Summary::default();
+        if let Some(amount) = amount {
+            record.record_applied(r#type, amount);
+            if let Some(code) = code {
+                record.record_reason(r#type, code, amount);
+            }
+        }
+        record.fees = fee.unwrap_or_default();
+        self.can_merge(&record)
+    }
+
+    /// Counts a rejected record.
+    pub(crate) fn record_rejection(&mut self, code: Option<RejectionCode>) {
+        self.rejected += 1;
+        if let Some(code) = code {
+            *self.rejections.entry(code).or_default() += 1;
+        }
+    }
+
+    /// Counts an applied record and adds its amount to the matching sum.
+    pub(crate) fn record_applied(&mut self, r#type: TransactionType, amount: Decimal) {
+        match r#type {
+            TransactionType::Deposit => self.deposit_amounts.record(amount),
+            TransactionType::Withdrawal => self.withdrawal_amounts.record(amount),
+            _ => {}
+        }
+        let (count, sum) = match r#type {
+            TransactionType::Deposit => (&mut self.deposits, &mut self.deposited),
+            TransactionType::Withdrawal => (&mut self.withdrawals, &mut self.withdrawn),
+            TransactionType::Adjustment => (&mut self.adjustments, &mut self.adjusted),
+            TransactionType::Dispute => (&mut self.disputes, &mut self.disputed),
+            TransactionType::Resolve => (&mut self.resolves, &mut self.resolved),
+            TransactionType::Chargeback => (&mut self.chargebacks, &mut self.charged_back),
+            // Custom amounts mean whatever their handler makes of them.
+            TransactionType::Custom => return,
+        };
+        *count += 1;
+        *sum += amount;
+    }
+
+    /// Counts an applied dispute, resolve or chargeback of `amount`
+    /// under its reason `code`.
+    pub(crate) fn record_reason(&mut self, r#type: TransactionType, code: &str, amount: Decimal) {
+        let reason = self.reasons.entry(code.to_owned()).or_default();
+        match r#type {
+            TransactionType::Dispute => {
+                reason.disputes += 1;
+                reason.disputed += amount;
+            }
+            TransactionType::Resolve => reason.resolves += 1,
+            TransactionType::Chargeback => {
+                reason.chargebacks += 1;
+                reason.charged_back += amount;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The significant digits each amount is rounded up to in a histogram,
+/// so percentiles are within 1% of the true amounts.
+const SIGNIFICANT_DIGITS: i64 = 3;
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+/// An approximate distribution of amounts, in the manner of an HDR
+/// histogram: each amount is counted in a bucket named by the amount
+/// rounded up to three significant digits. Histograms of separate
+/// inputs merge exactly, like the rest of a summary.
+///
+/// Serializes as the number of amounts and their 50th, 90th and 99th
+/// percentiles, and the largest amount, each rounded up.
+pub struct AmountHistogram {
+    buckets: BTreeMap<Decimal, u64>,
+    count: u64,
+}
+
+impl AmountHistogram {
+    /// Counts `amount`.
+    pub fn record(&mut self, amount: Decimal) {
+        *self.buckets.entry(bucket(amount)).or_default() += 1;
+        self.count += 1;
+    }
+
+    /// Adds the amounts counted in `other`.
+    pub fn merge(&mut self, other: AmountHistogram) {
+        for (bucket, count) in other.buckets {
+            *self.buckets.entry(bucket).or_default() += count;
+        }
+        self.count += other.count;
+    }
+
+    /// How many amounts were counted.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The smallest bucket at or below which at least `percent`% of the
+    /// amounts fall, or `None` if no amounts were counted.
+    pub fn percentile(&self, percent: u8) -> Option<Decimal> {
+        let rank = (u64::from(percent.min(100)) * self.count)
+            .div_ceil(100)
+            .max(1);
+        let mut seen = 0;
+        self.buckets.iter().find_map(|(&bucket, &count)| {
+            seen += count;
+            (seen >= rank).then_some(bucket)
+        })
+    }
+
+    /// How far apart this distribution and `other` are, as the largest
+    /// difference between the shares of their amounts at or below any
+    /// bucket: zero for the same shape, and one for distributions that
+    /// do not overlap. Empty histograms are never apart.
+    pub fn distance(&self, other: &AmountHistogram) -> f64 {
+        if self.count == 0 || other.count == 0 {
+            return 0.0;
+        }
+        let mut buckets: Vec<Decimal> = self
+            .buckets
+            .keys()
+            .chain(other.buckets.keys())
+            .copied()
+            .collect();
+        buckets.sort_unstable();
+        buckets.dedup();
+        let (mut ours, mut theirs, mut distance) = (0, 0, 0.0f64);
+        for bucket in buckets {
+            ours += self.buckets.get(&bucket).copied().unwrap_or_default();
+            theirs += other.buckets.get(&bucket).copied().unwrap_or_default();
+            let gap = ours as f64 / self.count as f64 - theirs as f64 / other.count as f64;
+            distance = distance.max(gap.abs());
+        }
+        distance
+    }
+}
+
+/// The bucket `amount` is counted in: the amount rounded up to
+/// `SIGNIFICANT_DIGITS` significant digits. Amounts of zero or less
+/// all go in the bucket of zero.
+fn bucket(amount: Decimal) -> Decimal {
+    if amount <= Decimal::ZERO {
+        return Decimal::ZERO;
+    }
+    let amount = amount.normalize();
+    let digits = amount.mantissa().to_string().len() as i64;
+    // The power of ten of the last significant digit kept.
+    let exponent = digits - amount.scale() as i64 - SIGNIFICANT_DIGITS;
+    let unit = match u32::try_from(-exponent) {
+        Ok(scale) => Decimal::new(1, scale),
+        Err(_) => Decimal::from_i128_with_scale(10i128.pow(exponent as u32), 0),
+    };
+    // Amounts too close to the largest decimal to round up keep
+    // buckets of their own.
+    match amount
+        .checked_div(unit)
+        .and_then(|units| units.ceil().checked_mul(unit))
+    {
+        Some(bucket) => bucket.normalize(),
+        None => amount,
+    }
+}
+
+impl Serialize for AmountHistogram {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Percentiles {
+            count: u64,
+            #[serde(with = "rust_decimal::serde::str_option")]
+            p50: Option<Decimal>,
+            #[serde(with = "rust_decimal::serde::str_option")]
+            p90: Option<Decimal>,
+            #[serde(with = "rust_decimal::serde::str_option")]
+            p99: Option<Decimal>,
+            #[serde(with = "rust_decimal::serde::str_option")]
+            max: Option<Decimal>,
+        }
+        Percentiles {
+            count: self.count,
+            p50: self.percentile(50),
+            p90: self.percentile(90),
+            p99: self.percentile(99),
+            max: self.buckets.keys().next_back().copied(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Statistics about the records processed so far, overall and for
+/// each client, as maintained while processing.
+pub struct Stats<'a> {
+    /// The statistics of every record.
+    pub total: &'a Summary,
+    pub(crate) clients: &'a HashMap<u16, Summary>,
+}
+
+impl<'a> Stats<'a> {
+    /// The statistics of the records for the client with ID `id`,
+    /// if any were processed.
+    pub fn client(&self, id: u16) -> Option<&'a Summary> {
+        self.clients.get(&id)
+    }
+
+    /// The statistics of each client, in no particular order.
+    pub fn clients(&self) -> impl Iterator<Item = (u16, &'a Summary)> {
+        self.clients.iter().map(|(&id, summary)| (id, summary))
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+/// The summary of one input file.
+pub struct InputSummary {
+    pub path: PathBuf,
+    pub summary: Summary,
+}
+
+#[derive(Debug, Serialize, Clone)]
+/// A change in the distribution of deposit or withdrawal amounts from
+/// one input to the next.
+pub struct DistributionShift {
+    /// The input compared against.
+    pub from: PathBuf,
+    /// The input whose amounts shifted.
+    pub to: PathBuf,
+    /// `deposit` or `withdrawal`.
+    pub r#type: TransactionType,
+    /// The distance between the distributions; see
+    /// `AmountHistogram::distance`.
+    pub distance: f64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+/// The summary of a run, and of each input it read.
+pub struct SummaryReport {
+    pub total: Summary,
+    pub inputs: Vec<InputSummary>,
+    /// The inputs whose amounts were distributed differently from the
+    /// input before them, in order.
+    pub shifts: Vec<DistributionShift>,
+}
+
+impl SummaryReport {
+    /// Builds a report from the summary of each input, in order,
+    /// flagging where the distribution of amounts moved at least
+    /// `shift_threshold` apart from one input to the next.
+    pub fn new(inputs: Vec<InputSummary>, shift_threshold: f64) -> Self {
+        let mut total = Summary::default();
+        for input in &inputs {
+            total.merge(input.summary.clone());
+        }
+        let mut shifts = Vec::new();
+        for pair in inputs.windows(2) {
+            let (from, to) = (&pair[0], &pair[1]);
+            let amounts = [
+                (
+                    TransactionType::Deposit,
+                    &from.summary.deposit_amounts,
+                    &to.summary.deposit_amounts,
+                ),
+                (
+                    TransactionType::Withdrawal,
+                    &from.summary.withdrawal_amounts,
+                    &to.summary.withdrawal_amounts,
+                ),
+            ];
+            for (r#type, before, after) in amounts {
+                let distance = before.distance(after);
+                if distance >= shift_threshold {
+                    shifts.push(DistributionShift {
+                        from: from.path.clone(),
+                        to: to.path.clone(),
+                        r#type,
+                        distance,
+                    });
+                }
+            }
+        }
+        SummaryReport {
+            total,
+            inputs,
+            shifts,
+        }
+    }
+
+    /// Writes the report as pretty-printed JSON.
+    pub fn write(&self, writer: impl std::io::Write) -> Result<(), serde_json::Error> {
+        serde_json::to_writer_pretty(writer, self)
+    }
+}
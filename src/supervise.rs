@@ -0,0 +1,251 @@
+use std::any::Any;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::errors;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// When a failed subsystem is restarted, and when its failures are
+/// escalated instead.
+pub struct RestartPolicy {
+    /// How long to wait before the first restart. The wait doubles
+    /// with every failure, until the subsystem stays up for `window`.
+    pub initial_backoff: Duration,
+    /// The longest wait between restarts.
+    pub max_backoff: Duration,
+    /// How many failures within `window` are restarted; one more is
+    /// escalated.
+    pub max_restarts: usize,
+    pub window: Duration,
+}
+
+impl Default for RestartPolicy {
+    /// Waits from 100 milliseconds up to 10 seconds, and escalates the
+    /// sixth failure within a minute.
+    fn default() -> Self {
+        RestartPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            max_restarts: 5,
+            window: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+/// What a supervised subsystem is doing.
+pub enum SubsystemState {
+    Running,
+    /// The subsystem failed, and is waiting to be restarted.
+    Restarting,
+    /// The subsystem returned without failing.
+    Stopped,
+    /// The subsystem failed too often, and was escalated.
+    Failed,
+}
+
+#[derive(Debug, Serialize, Clone)]
+/// The status of one supervised subsystem.
+pub struct SubsystemStatus {
+    pub name: String,
+    pub state: SubsystemState,
+    /// How many times the subsystem was restarted.
+    pub restarts: u64,
+    /// Why the subsystem last failed, if it has.
+    pub last_error: Option<String>,
+}
+
+/// Called with the name of a subsystem that failed too often, and its
+/// last error.
+type Escalation = dyn Fn(&str, &str) + Send + Sync;
+
+#[derive(Clone)]
+/// Runs subsystems of a server, such as the replication listener, each
+/// on a thread of its own, and restarts them with backoff when they
+/// fail or panic, so one failed subsystem cannot silently stop the
+/// server doing its job.
+///
+/// A subsystem failing more often than its `RestartPolicy` allows is
+/// escalated to the handler set with `on_escalation`, which should shut
+/// the server down. Without one, the subsystem is only left `Failed`.
+pub struct Supervisor {
+    policy: RestartPolicy,
+    subsystems: Arc<Mutex<BTreeMap<String, SubsystemStatus>>>,
+    on_escalation: Option<Arc<Escalation>>,
+}
+
+impl fmt::Debug for Supervisor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Supervisor")
+            .field("policy", &self.policy)
+            .field("subsystems", &self.subsystems)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Supervisor {
+    pub fn new(policy: RestartPolicy) -> Self {
+        Supervisor {
+            policy,
+            subsystems: Arc::default(),
+            on_escalation: None,
+        }
+    }
+
+    /// Calls `handler` with the name and last error of any subsystem
+    /// that fails too often.
+    pub fn on_escalation(mut self, handler: impl Fn(&str, &str) + Send + Sync + 'static) -> Self {
+        self.on_escalation = Some(Arc::new(handler));
+        self
+    }
+
+    /// Runs `run` as the subsystem `name` on a thread of its own,
+    /// calling it again whenever it returns an error or panics.
+    pub fn spawn(
+        &self,
+        name: &str,
+        run: impl Fn() -> Result<(), errors::Error> + Send + 'static,
+    ) -> thread::JoinHandle<()> {
+        let supervisor = self.clone();
+        let name = name.to_owned();
+        thread::spawn(move || supervisor.supervise(&name, run))
+    }
+
+    /// The status of every subsystem, by name.
+    pub fn status(&self) -> Vec<SubsystemStatus> {
+        self.subsystems.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Whether every subsystem is running, or stopped without failing.
+    pub fn is_healthy(&self) -> bool {
+        self.subsystems.lock().unwrap().values().all(|status| {
+            matches!(
+                status.state,
+                SubsystemState::Running | SubsystemState::Stopped
+            )
+        })
+    }
+
+    /// Changes the status of the subsystem `name`.
+    fn update(&self, name: &str, change: impl FnOnce(&mut SubsystemStatus)) {
+        let mut subsystems = self.subsystems.lock().unwrap();
+        let status = subsystems
+            .entry(name.to_owned())
+            .or_insert_with(|| SubsystemStatus {
+                name: name.to_owned(),
+                state: SubsystemState::Running,
+                restarts: 0,
+                last_error: None,
+            });
+        change(status);
+    }
+
+    /// Runs the subsystem `name` until it stops or is escalated.
+    fn supervise(&self, name: &str, run: impl Fn() -> Result<(), errors::Error>) {
+        let mut failures: VecDeque<Instant> = VecDeque::new();
+        let mut backoff = self.policy.initial_backoff;
+        loop {
+            self.update(name, |status| status.state = SubsystemState::Running);
+            let started = Instant::now();
+            let error = match panic::catch_unwind(AssertUnwindSafe(&run)) {
+                Ok(Ok(())) => {
+                    self.update(name, |status| status.state = SubsystemState::Stopped);
+                    return;
+                }
+                Ok(Err(err)) => err.to_string(),
+                Err(payload) => format!("panicked: {}", panic_message(&*payload)),
+            };
+            let now = Instant::now();
+            if now.duration_since(started) >= self.policy.window {
+                backoff = self.policy.initial_backoff;
+            }
+            failures.push_back(now);
+            while failures
+                .front()
+                .is_some_and(|&failed| now.duration_since(failed) > self.policy.window)
+            {
+                failures.pop_front();
+            }
+            if failures.len() > self.policy.max_restarts {
+                self.update(name, |status| {
+                    status.state = SubsystemState::Failed;
+                    status.last_error = Some(error.clone());
+                });
+                match &self.on_escalation {
+                    Some(handler) => handler(name, &error),
+                    None => eprintln!("Warning: subsystem `{}` failed for good: {}", name, error),
+                }
+                return;
+            }
+            eprintln!(
+                "Warning: subsystem `{}` failed, restarting in {:?}: {}",
+                name, backoff, error
+            );
+            self.update(name, |status| {
+                status.state = SubsystemState::Restarting;
+                status.restarts += 1;
+                status.last_error = Some(error);
+            });
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(self.policy.max_backoff);
+        }
+    }
+}
+
+/// The message a panic was raised with, if it was a string.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload
+            .downcast_ref::<String>()
+            .map_or("unknown panic", String::as_str),
+    }
+}
+
+/// Answers HTTP requests for `/healthz` on `listener` with the status of
+/// every subsystem of `supervisor` as JSON: `200 OK` if they are all
+/// healthy, and `503 Service Unavailable` otherwise. Other paths get
+/// `404 Not Found`. Returns if the listener fails.
+pub fn serve_health(listener: &TcpListener, supervisor: &Supervisor) -> Result<(), errors::Error> {
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        // A slow or broken client only loses its own answer.
+        let result = (|| -> Result<(), errors::Error> {
+            stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request)?;
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            let (status, body) = match path {
+                "/healthz" => {
+                    let status = match supervisor.is_healthy() {
+                        true => "200 OK",
+                        false => "503 Service Unavailable",
+                    };
+                    (status, serde_json::to_string(&supervisor.status())?)
+                }
+                _ => ("404 Not Found", String::new()),
+            };
+            write!(
+                stream,
+                "HTTP/1.0 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )?;
+            Ok(())
+        })();
+        if let Err(err) = result {
+            eprintln!("Warning: could not answer health check: {}", err);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{
+    ArrayRef, BooleanArray, Decimal128Array, StringArray, UInt16Array, UInt32Array, UInt64Array,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::arrow::error::ArrowError;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::datasource::MemTable;
+use datafusion::error::Result;
+use datafusion::prelude::SessionContext;
+use rust_decimal::Decimal;
+
+use crate::state::CurrentState;
+
+/// The largest precision Arrow supports for 128-bit decimals.
+const MAX_PRECISION: u8 = 38;
+
+/// Converts amounts into an Arrow decimal column, at the largest scale
+/// among them so no amount loses precision.
+fn decimal_column(amounts: &[Option<Decimal>]) -> Result<ArrayRef> {
+    let scale = amounts
+        .iter()
+        .flatten()
+        .map(Decimal::scale)
+        .max()
+        .unwrap_or_default();
+    let values = amounts
+        .iter()
+        .map(|amount| {
+            amount
+                .map(|amount| {
+                    10i128
+                        .checked_pow(scale - amount.scale())
+                        .and_then(|factor| amount.mantissa().checked_mul(factor))
+                        .ok_or_else(|| {
+                            ArrowError::InvalidArgumentError(format!(
+                                "`{}` cannot be represented with {} decimal places",
+                                amount, scale
+                            ))
+                        })
+                })
+                .transpose()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let array =
+        Decimal128Array::from(values).with_precision_and_scale(MAX_PRECISION, scale as i8)?;
+    Ok(Arc::new(array))
+}
+
+/// A table of the current state of every client, with the same
+/// columns as the output.
+pub fn clients_table(state: &CurrentState) -> Result<MemTable> {
+    let mut clients: Vec<_> = state.clients().collect();
+    clients.sort_by_key(|client| client.client);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt16Array::from_iter_values(
+            clients.iter().map(|client| client.client),
+        )),
+        decimal_column(
+            &clients
+                .iter()
+                .map(|c| Some(c.available))
+                .collect::<Vec<_>>(),
+        )?,
+        decimal_column(&clients.iter().map(|c| Some(c.held)).collect::<Vec<_>>())?,
+        decimal_column(&clients.iter().map(|c| Some(c.total)).collect::<Vec<_>>())?,
+        Arc::new(BooleanArray::from(
+            clients
+                .iter()
+                .map(|client| client.locked)
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            clients.iter().map(|client| client.flags.to_string()),
+        )),
+    ];
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("client", DataType::UInt16, false),
+        Field::new("available", columns[1].data_type().clone(), false),
+        Field::new("held", columns[2].data_type().clone(), false),
+        Field::new("total", columns[3].data_type().clone(), false),
+        Field::new("locked", DataType::Boolean, false),
+        Field::new("flags", DataType::Utf8, false),
+    ]));
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    MemTable::try_new(schema, vec![vec![batch]])
+}
+
+/// A table of the deposits, withdrawals and adjustments held in
+/// memory, with whether each is currently disputed.
+pub fn transactions_table(state: &CurrentState) -> Result<MemTable> {
+    let mut transactions: Vec<_> = state.transactions().collect();
+    transactions.sort_by_key(|tx| tx.id);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(
+            transactions.iter().map(|tx| tx.type_name()),
+        )),
+        Arc::new(UInt16Array::from_iter_values(
+            transactions.iter().map(|tx| tx.client),
+        )),
+        Arc::new(UInt32Array::from_iter_values(
+            transactions.iter().map(|tx| tx.id),
+        )),
+        decimal_column(
+            &transactions
+                .iter()
+                .map(|tx| tx.amount())
+                .collect::<Vec<_>>(),
+        )?,
+        Arc::new(UInt64Array::from(
+            transactions
+                .iter()
+                .map(|tx| tx.timestamp)
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(StringArray::from(
+            transactions
+                .iter()
+                .map(|tx| tx.reference.as_deref())
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(BooleanArray::from(
+            transactions
+                .iter()
+                .map(|tx| state.is_disputed(tx.id))
+                .collect::<Vec<_>>(),
+        )),
+    ];
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("type", DataType::Utf8, false),
+        Field::new("client", DataType::UInt16, false),
+        Field::new("tx", DataType::UInt32, false),
+        Field::new("amount", columns[3].data_type().clone(), true),
+        Field::new("timestamp", DataType::UInt64, true),
+        Field::new("reference", DataType::Utf8, true),
+        Field::new("disputed", DataType::Boolean, false),
+    ]));
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    MemTable::try_new(schema, vec![vec![batch]])
+}
+
+/// Registers the `clients` and `transactions` tables of `state` with
+/// `ctx`, so they can be queried with SQL. The tables are copies, so
+/// later changes to `state` are not reflected.
+pub fn register(ctx: &SessionContext, state: &CurrentState) -> Result<()> {
+    ctx.register_table("clients", Arc::new(clients_table(state)?))?;
+    ctx.register_table("transactions", Arc::new(transactions_table(state)?))?;
+    Ok(())
+}
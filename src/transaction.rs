@@ -1,9 +1,11 @@
 use std::convert::TryFrom;
+use std::str::FromStr;
 
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
 use crate::errors;
+use crate::fixture::{NeedsType, TransactionBuilder};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
 #[serde(rename_all = "snake_case")]
@@ -14,43 +16,386 @@ pub enum TransactionType {
     Dispute,
     Resolve,
     Chargeback,
+    Adjustment,
+    #[serde(skip)]
+    /// A type registered by an embedder, named by `Transaction::tag`.
+    Custom,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+impl TransactionType {
+    /// The name of a built-in type, as it is written in the input.
+    pub fn name(self) -> &'static str {
+        match self {
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Deposit => "deposit",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Adjustment => "adjustment",
+            TransactionType::Custom => "custom",
+        }
+    }
+}
+
+impl FromStr for TransactionType {
+    type Err = String;
+
+    /// Parses the name of a built-in type.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "withdrawal" => Ok(TransactionType::Withdrawal),
+            "deposit" => Ok(TransactionType::Deposit),
+            "dispute" => Ok(TransactionType::Dispute),
+            "resolve" => Ok(TransactionType::Resolve),
+            "chargeback" => Ok(TransactionType::Chargeback),
+            "adjustment" => Ok(TransactionType::Adjustment),
+            _ => Err(format!("unknown transaction type `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Hash)]
+#[serde(rename_all = "snake_case")]
+/// Who opened a dispute.
+pub enum DisputeInitiator {
+    Cardholder,
+    Issuer,
+    Internal,
+}
+
+impl DisputeInitiator {
+    /// Every initiator, in declaration order.
+    pub const ALL: [DisputeInitiator; 3] = [
+        DisputeInitiator::Cardholder,
+        DisputeInitiator::Issuer,
+        DisputeInitiator::Internal,
+    ];
+}
+
+impl FromStr for DisputeInitiator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cardholder" => Ok(DisputeInitiator::Cardholder),
+            "issuer" => Ok(DisputeInitiator::Issuer),
+            "internal" => Ok(DisputeInitiator::Internal),
+            _ => Err(format!(
+                "unknown dispute initiator `{}`, expected one of `cardholder`, `issuer` or `internal`",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "snake_case")]
 /// An unchecked transaction type.
 struct TransactionUnchecked {
+    /// Any name other than a built-in type names a custom type.
     #[serde(rename = "type")]
-    pub r#type: TransactionType,
-    pub client: u16,
+    pub r#type: String,
+    #[serde(default)]
+    pub client: Option<u16>,
     #[serde(alias = "tx")]
     pub id: u32,
     pub amount: Option<Decimal>,
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    #[serde(default)]
+    pub reference: Option<String>,
+    #[serde(default)]
+    pub initiator: Option<DisputeInitiator>,
+    #[serde(default)]
+    pub account: Option<String>,
+    #[serde(default)]
+    pub external_ref: Option<String>,
+    #[serde(default)]
+    pub reason_code: Option<String>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub merchant: Option<String>,
+    #[serde(default)]
+    pub seq: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// What a transaction does, along with its amount for the kinds that
+/// move funds, so those always have one and the others never do.
+pub enum TransactionKind {
+    /// Adds a positive `amount` to the available funds.
+    Deposit {
+        amount: Decimal,
+    },
+    /// Removes a positive `amount` from the available funds.
+    Withdrawal {
+        amount: Decimal,
+    },
+    Dispute,
+    Resolve,
+    Chargeback,
+    /// A manual correction by a signed, nonzero `amount`.
+    Adjustment {
+        amount: Decimal,
+    },
+    /// A type registered by an embedder under `tag`, whose handler
+    /// checks the amount, if any.
+    Custom {
+        tag: String,
+        amount: Option<Decimal>,
+    },
+}
+
+impl TransactionKind {
+    /// The type of the transaction.
+    pub fn r#type(&self) -> TransactionType {
+        match self {
+            TransactionKind::Deposit { .. } => TransactionType::Deposit,
+            TransactionKind::Withdrawal { .. } => TransactionType::Withdrawal,
+            TransactionKind::Dispute => TransactionType::Dispute,
+            TransactionKind::Resolve => TransactionType::Resolve,
+            TransactionKind::Chargeback => TransactionType::Chargeback,
+            TransactionKind::Adjustment { .. } => TransactionType::Adjustment,
+            TransactionKind::Custom { .. } => TransactionType::Custom,
+        }
+    }
+
+    /// The amount, for the kinds that have one.
+    pub fn amount(&self) -> Option<Decimal> {
+        match *self {
+            TransactionKind::Deposit { amount }
+            | TransactionKind::Withdrawal { amount }
+            | TransactionKind::Adjustment { amount } => Some(amount),
+            TransactionKind::Custom { amount, .. } => amount,
+            TransactionKind::Dispute | TransactionKind::Resolve | TransactionKind::Chargeback => {
+                None
+            }
+        }
+    }
+
+    /// The kind of type `r#type` with `amount`, or `None` if the type
+    /// must have an amount and none is given, or must not and one is.
+    /// Custom kinds are named by `tag`.
+    fn new(r#type: TransactionType, amount: Option<Decimal>, tag: Option<String>) -> Option<Self> {
+        match (r#type, amount) {
+            (TransactionType::Deposit, Some(amount)) => Some(TransactionKind::Deposit { amount }),
+            (TransactionType::Withdrawal, Some(amount)) => {
+                Some(TransactionKind::Withdrawal { amount })
+            }
+            (TransactionType::Adjustment, Some(amount)) => {
+                Some(TransactionKind::Adjustment { amount })
+            }
+            (TransactionType::Dispute, None) => Some(TransactionKind::Dispute),
+            (TransactionType::Resolve, None) => Some(TransactionKind::Resolve),
+            (TransactionType::Chargeback, None) => Some(TransactionKind::Chargeback),
+            (TransactionType::Custom, amount) => Some(TransactionKind::Custom {
+                tag: tag.unwrap_or_default(),
+                amount,
+            }),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Deserialize, Clone)]
 #[serde(try_from = "TransactionUnchecked")]
-/// A transaction type with fields internally validated.
+/// A transaction with fields internally validated.
 pub struct Transaction {
-    #[serde(rename = "type")]
-    pub r#type: TransactionType,
+    /// The type, and the amount for types that move funds.
+    pub kind: TransactionKind,
+    /// The client ID, which is zero until `account` is resolved
+    /// for records naming an account instead.
     pub client: u16,
-    #[serde(alias = "tx")]
     pub id: u32,
-    pub amount: Option<Decimal>,
+    /// Seconds since the Unix epoch, if the input provides them.
+    pub timestamp: Option<u64>,
+    /// The operator reference, required for adjustments.
+    pub reference: Option<String>,
+    /// Who opened the dispute, for disputes only.
+    pub initiator: Option<DisputeInitiator>,
+    /// The external account identifier, for records that name an
+    /// account rather than a client.
+    pub account: Option<String>,
+    /// The sender's own ID for the record, echoed in every output
+    /// that mentions it.
+    pub external_ref: Option<String>,
+    /// Why the dispute was opened, resolved or charged back, such as a
+    /// card network's reason code, for those records only.
+    pub reason_code: Option<String>,
+    /// The currency of the amount, such as `EUR`. Only echoed.
+    pub currency: Option<String>,
+    /// What the transaction was for, such as a merchant category code.
+    pub category: Option<String>,
+    /// The merchant the transaction was made with.
+    pub merchant: Option<String>,
+    /// The sender's sequence number for the record among those of its
+    /// client. A client's records that give one must give increasing
+    /// numbers, and records arriving out of order are rejected.
+    pub seq: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+/// A transaction with its fields as they were before the type and
+/// amount were combined in `TransactionKind`. Used for serialization.
+struct SerializedTransaction<'a> {
+    #[serde(rename = "type")]
+    r#type: TransactionType,
+    client: u16,
+    id: u32,
+    amount: Option<Decimal>,
+    timestamp: Option<u64>,
+    reference: Option<&'a str>,
+    initiator: Option<DisputeInitiator>,
+    account: Option<&'a str>,
+    external_ref: Option<&'a str>,
+    reason_code: Option<&'a str>,
+    currency: Option<&'a str>,
+    category: Option<&'a str>,
+    merchant: Option<&'a str>,
+    seq: Option<u64>,
+}
+
+impl Serialize for Transaction {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedTransaction {
+            r#type: self.r#type(),
+            client: self.client,
+            id: self.id,
+            amount: self.amount(),
+            timestamp: self.timestamp,
+            reference: self.reference.as_deref(),
+            initiator: self.initiator,
+            account: self.account.as_deref(),
+            external_ref: self.external_ref.as_deref(),
+            reason_code: self.reason_code.as_deref(),
+            currency: self.currency.as_deref(),
+            category: self.category.as_deref(),
+            merchant: self.merchant.as_deref(),
+            seq: self.seq,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Debug, Serialize)]
+/// A transaction in the input format, with custom types under their
+/// own names. Used for serialization.
+pub(crate) struct InputRecord<'a> {
+    #[serde(rename = "type")]
+    r#type: &'a str,
+    client: Option<u16>,
+    tx: u32,
+    amount: Option<Decimal>,
+    timestamp: Option<u64>,
+    reference: Option<&'a str>,
+    initiator: Option<DisputeInitiator>,
+    account: Option<&'a str>,
+    external_ref: Option<&'a str>,
+    reason_code: Option<&'a str>,
+    currency: Option<&'a str>,
+    category: Option<&'a str>,
+    merchant: Option<&'a str>,
+    seq: Option<u64>,
 }
 
 impl Transaction {
-    /// Creates a `Transaction` from its unchecked variant,
-    /// without running any checks.
-    fn from_unchecked(tx: TransactionUnchecked) -> Self {
+    /// The type of the transaction.
+    pub fn r#type(&self) -> TransactionType {
+        self.kind.r#type()
+    }
+
+    /// The amount, for the kinds that have one.
+    pub fn amount(&self) -> Option<Decimal> {
+        self.kind.amount()
+    }
+
+    /// The name of a custom type, for custom transactions only.
+    pub fn tag(&self) -> Option<&str> {
+        match &self.kind {
+            TransactionKind::Custom { tag, .. } => Some(tag),
+            _ => None,
+        }
+    }
+
+    /// The transaction with its amount replaced by `amount`, if it has
+    /// one.
+    pub(crate) fn with_amount(&self, amount: Decimal) -> Self {
+        let mut tx = self.clone();
+        match &mut tx.kind {
+            TransactionKind::Deposit { amount: old }
+            | TransactionKind::Withdrawal { amount: old }
+            | TransactionKind::Adjustment { amount: old }
+            | TransactionKind::Custom {
+                amount: Some(old), ..
+            } => *old = amount,
+            _ => {}
+        }
+        tx
+    }
+
+    /// The transaction as it would be written in the input, so it
+    /// reads back as the same transaction.
+    pub(crate) fn to_input(&self) -> InputRecord<'_> {
+        InputRecord {
+            r#type: self.type_name(),
+            // Records naming an account have no client until resolved.
+            client: self.account.is_none().then_some(self.client),
+            tx: self.id,
+            amount: self.amount(),
+            timestamp: self.timestamp,
+            reference: self.reference.as_deref(),
+            initiator: self.initiator,
+            account: self.account.as_deref(),
+            external_ref: self.external_ref.as_deref(),
+            reason_code: self.reason_code.as_deref(),
+            currency: self.currency.as_deref(),
+            category: self.category.as_deref(),
+            merchant: self.merchant.as_deref(),
+            seq: self.seq,
+        }
+    }
+
+    /// Creates a `Transaction` from its unchecked variant with `kind`,
+    /// without running any other checks.
+    fn from_unchecked(kind: TransactionKind, tx: TransactionUnchecked) -> Self {
         Transaction {
-            amount: tx.amount,
-            client: tx.client,
+            kind,
+            client: tx.client.unwrap_or_default(),
             id: tx.id,
-            r#type: tx.r#type,
+            timestamp: tx.timestamp,
+            reference: tx.reference,
+            initiator: tx.initiator,
+            account: tx.account,
+            external_ref: tx.external_ref,
+            reason_code: tx.reason_code,
+            currency: tx.currency,
+            category: tx.category,
+            merchant: tx.merchant,
+            seq: tx.seq,
         }
     }
+
+    /// Reads a record from JSON. Unlike deserializing a `Transaction`
+    /// directly, a record that is read but fails its checks, such as a
+    /// deposit without an amount, gives the `TransactionError`, so it
+    /// can be rejected with its code.
+    pub(crate) fn from_json(value: serde_json::Value) -> Result<Self, errors::Error> {
+        let tx: TransactionUnchecked = serde_json::from_value(value)?;
+        Ok(Transaction::try_from(tx)?)
+    }
+
+    /// Starts a builder for a well-formed record, for tests and examples.
+    pub fn builder() -> TransactionBuilder<NeedsType> {
+        TransactionBuilder::default()
+    }
+
+    /// The name of the transaction's type, as it is written in the input.
+    pub fn type_name(&self) -> &str {
+        self.tag().unwrap_or_else(|| self.r#type().name())
+    }
 }
 
 impl TryFrom<TransactionUnchecked> for Transaction {
@@ -59,23 +404,188 @@ impl TryFrom<TransactionUnchecked> for Transaction {
     /// Performs all necessary checks on an `UncheckedTransaction` and then converts
     /// it to a `Transaction`.
     fn try_from(tx: TransactionUnchecked) -> Result<Self, Self::Error> {
-        match tx.r#type {
+        let (r#type, tag) = match tx.r#type.parse() {
+            Ok(r#type) => (r#type, None),
+            Err(_) => (TransactionType::Custom, Some(tx.r#type.clone())),
+        };
+        if tx.initiator.is_some() && r#type != TransactionType::Dispute {
+            return Err(errors::TransactionError::SuperfluousInitiator(tx.id));
+        }
+        if tx.reason_code.is_some()
+            && !matches!(
+                r#type,
+                TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback
+            )
+        {
+            return Err(errors::TransactionError::SuperfluousReasonCode(tx.id));
+        }
+        match (tx.client, &tx.account) {
+            (None, None) => return Err(errors::TransactionError::MissingClient(tx.id)),
+            (Some(_), Some(_)) => return Err(errors::TransactionError::SuperfluousAccount(tx.id)),
+            _ => {}
+        }
+        match r#type {
             TransactionType::Deposit | TransactionType::Withdrawal => match tx.amount {
-                Some(amount) => {
-                    if amount <= Decimal::default() {
-                        Err(errors::TransactionError::AmountNotPositive(tx.id))
-                    } else {
-                        Ok(Self::from_unchecked(tx))
-                    }
+                Some(amount) if amount <= Decimal::default() => {
+                    return Err(errors::TransactionError::AmountNotPositive(tx.id))
                 }
-                None => Err(errors::TransactionError::MissingAmount(tx.id)),
+                Some(_) => {}
+                None => return Err(errors::TransactionError::MissingAmount(tx.id)),
             },
             TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
-                match tx.amount {
-                    Some(_) => Err(errors::TransactionError::SuperfluousAmount(tx.id)),
-                    None => Ok(Self::from_unchecked(tx)),
+                if tx.amount.is_some() {
+                    return Err(errors::TransactionError::SuperfluousAmount(tx.id));
                 }
             }
+            // Adjustments are signed, so only a zero amount is invalid.
+            TransactionType::Adjustment => match (tx.amount, &tx.reference) {
+                (None, _) => return Err(errors::TransactionError::MissingAmount(tx.id)),
+                (Some(amount), _) if amount.is_zero() => {
+                    return Err(errors::TransactionError::ZeroAmount(tx.id))
+                }
+                (Some(_), None) => return Err(errors::TransactionError::MissingReference(tx.id)),
+                (Some(_), Some(_)) => {}
+            },
+            // Custom types are checked by their handlers.
+            TransactionType::Custom => {}
+        }
+        // Every type has the amount it needs by now.
+        let kind = TransactionKind::new(r#type, tx.amount, tag)
+            .ok_or(errors::TransactionError::MissingAmount(tx.id))?;
+        Ok(Self::from_unchecked(kind, tx))
+    }
+}
+
+/// The built-in types, in the order `StoredTx` numbers them.
+const STORED_TYPES: [TransactionType; 7] = [
+    TransactionType::Withdrawal,
+    TransactionType::Deposit,
+    TransactionType::Dispute,
+    TransactionType::Resolve,
+    TransactionType::Chargeback,
+    TransactionType::Adjustment,
+    TransactionType::Custom,
+];
+
+/// Set in `StoredTx::packed` if the transaction has a timestamp.
+const HAS_TIMESTAMP: u8 = 1 << 3;
+
+/// Set in `StoredTx::packed` if the transaction has an amount.
+const HAS_AMOUNT: u8 = 1 << 4;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// The fields of a stored transaction that are rarely set.
+struct StoredExtras {
+    reference: Option<String>,
+    initiator: Option<DisputeInitiator>,
+    account: Option<String>,
+    external_ref: Option<String>,
+    currency: Option<String>,
+    category: Option<String>,
+    merchant: Option<String>,
+    tag: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// An applied transaction as kept in memory, keyed by its ID. The type
+/// and which optional fields are present are packed into one byte, and
+/// the text fields, which most records lack, are boxed together, so it
+/// takes a fraction of the space of a `Transaction`.
+pub(crate) struct StoredTx {
+    amount: Decimal,
+    timestamp: u64,
+    extras: Option<Box<StoredExtras>>,
+    pub(crate) client: u16,
+    /// The index of the type in `STORED_TYPES` in the low three bits,
+    /// and `HAS_TIMESTAMP` and `HAS_AMOUNT`.
+    packed: u8,
+}
+
+impl StoredTx {
+    pub(crate) fn new(tx: &Transaction) -> Self {
+        let extras = StoredExtras {
+            reference: tx.reference.clone(),
+            initiator: tx.initiator,
+            account: tx.account.clone(),
+            external_ref: tx.external_ref.clone(),
+            currency: tx.currency.clone(),
+            category: tx.category.clone(),
+            merchant: tx.merchant.clone(),
+            tag: tx.tag().map(str::to_owned),
+        };
+        let empty = extras.reference.is_none()
+            && extras.initiator.is_none()
+            && extras.account.is_none()
+            && extras.external_ref.is_none()
+            && extras.currency.is_none()
+            && extras.category.is_none()
+            && extras.merchant.is_none()
+            && extras.tag.is_none();
+        // Every type is in `STORED_TYPES`.
+        let mut packed = STORED_TYPES
+            .iter()
+            .position(|&r#type| r#type == tx.r#type())
+            .unwrap() as u8;
+        if tx.timestamp.is_some() {
+            packed |= HAS_TIMESTAMP;
+        }
+        let amount = tx.amount();
+        if amount.is_some() {
+            packed |= HAS_AMOUNT;
+        }
+        StoredTx {
+            amount: amount.unwrap_or_default(),
+            timestamp: tx.timestamp.unwrap_or_default(),
+            extras: (!empty).then(|| Box::new(extras)),
+            client: tx.client,
+            packed,
+        }
+    }
+
+    pub(crate) fn r#type(&self) -> TransactionType {
+        STORED_TYPES[usize::from(self.packed & 0b111)]
+    }
+
+    pub(crate) fn amount(&self) -> Option<Decimal> {
+        (self.packed & HAS_AMOUNT != 0).then_some(self.amount)
+    }
+
+    pub(crate) fn timestamp(&self) -> Option<u64> {
+        (self.packed & HAS_TIMESTAMP != 0).then_some(self.timestamp)
+    }
+
+    pub(crate) fn reference(&self) -> Option<&str> {
+        self.extras.as_ref()?.reference.as_deref()
+    }
+
+    pub(crate) fn currency(&self) -> Option<&str> {
+        self.extras.as_ref()?.currency.as_deref()
+    }
+
+    /// The transaction as it was recorded, given its ID.
+    pub(crate) fn to_transaction(&self, id: u32) -> Transaction {
+        let extras = self.extras.as_deref();
+        let tag = extras.and_then(|extras| extras.tag.clone());
+        Transaction {
+            // Only valid transactions are stored, so the type has the
+            // amount it needs.
+            kind: TransactionKind::new(self.r#type(), self.amount(), tag)
+                .expect("a stored transaction has the amount its type needs"),
+            client: self.client,
+            id,
+            timestamp: self.timestamp(),
+            reference: extras.and_then(|extras| extras.reference.clone()),
+            initiator: extras.and_then(|extras| extras.initiator),
+            account: extras.and_then(|extras| extras.account.clone()),
+            external_ref: extras.and_then(|extras| extras.external_ref.clone()),
+            // Only disputes and the records closing them give reasons,
+            // and they are never stored.
+            reason_code: None,
+            currency: extras.and_then(|extras| extras.currency.clone()),
+            category: extras.and_then(|extras| extras.category.clone()),
+            merchant: extras.and_then(|extras| extras.merchant.clone()),
+            // Sequence numbers are only checked on arrival.
+            seq: None,
         }
     }
 }
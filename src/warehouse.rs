@@ -0,0 +1,221 @@
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::time::Duration;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::errors;
+use crate::flags::ClientFlags;
+use crate::http::Endpoint;
+use crate::state::{CsvClient, CurrentState};
+
+/// How long to wait for the warehouse at each step of a request.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The most rows sent in one request, as BigQuery recommends.
+const BATCH_ROWS: usize = 500;
+
+/// Loads rows into tables of a data warehouse.
+pub trait WarehouseSink: Debug {
+    /// Appends `rows`, JSON objects, to `table`.
+    fn insert(&self, table: &str, rows: &[Value]) -> Result<(), errors::Error>;
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+/// The tables results are loaded into.
+pub struct WarehouseTables {
+    /// The table of final client states.
+    pub clients: String,
+    /// The table of changelog events.
+    pub events: String,
+}
+
+impl Default for WarehouseTables {
+    fn default() -> Self {
+        WarehouseTables {
+            clients: "clients".to_owned(),
+            events: "events".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+/// A final client state as loaded into the warehouse, with amounts as
+/// strings, so they load into `NUMERIC` columns without losing
+/// precision.
+struct ClientRow {
+    client: u16,
+    #[serde(with = "rust_decimal::serde::str")]
+    available: Decimal,
+    #[serde(with = "rust_decimal::serde::str")]
+    held: Decimal,
+    #[serde(with = "rust_decimal::serde::str")]
+    total: Decimal,
+    #[serde(with = "rust_decimal::serde::str_option")]
+    fees: Option<Decimal>,
+    #[serde(with = "rust_decimal::serde::str_option")]
+    reserve: Option<Decimal>,
+    locked: bool,
+    flags: ClientFlags,
+    frozen: Option<bool>,
+    risk_score: Option<u32>,
+}
+
+impl From<CsvClient> for ClientRow {
+    fn from(client: CsvClient) -> Self {
+        ClientRow {
+            client: client.client,
+            available: client.available,
+            held: client.held,
+            total: client.total,
+            fees: client.fees,
+            reserve: client.reserve,
+            locked: client.locked,
+            flags: client.flags,
+            frozen: client.frozen,
+            risk_score: client.risk_score,
+        }
+    }
+}
+
+/// Loads the clients in the output of `state`, in ID order, and its
+/// changelog, if events were recorded, into `tables` through `sink`.
+pub fn export(
+    sink: &dyn WarehouseSink,
+    state: &CurrentState,
+    tables: &WarehouseTables,
+) -> Result<(), errors::Error> {
+    let mut clients: Vec<CsvClient> = state.active_clients().collect();
+    clients.sort_unstable_by_key(|client| client.client);
+    let rows = clients
+        .into_iter()
+        .map(|client| serde_json::to_value(ClientRow::from(client)))
+        .collect::<Result<Vec<_>, _>>()?;
+    sink.insert(&tables.clients, &rows)?;
+    if !state.events().is_empty() {
+        let rows = state
+            .events()
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        sink.insert(&tables.events, &rows)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+/// The API a warehouse is loaded through.
+pub enum WarehouseApi {
+    /// BigQuery's streaming `insertAll`.
+    #[default]
+    BigQuery,
+    /// A generic endpoint taking
+    /// `{"dataset": ..., "table": ..., "rows": [...]}`.
+    Rest,
+}
+
+impl FromStr for WarehouseApi {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bigquery" => Ok(WarehouseApi::BigQuery),
+            "rest" => Ok(WarehouseApi::Rest),
+            _ => Err(format!("expected `bigquery` or `rest`, got `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+/// The parts of an `insertAll` reply that matter here.
+struct InsertAllReply {
+    #[serde(default, rename = "insertErrors")]
+    insert_errors: Vec<Value>,
+}
+
+#[derive(Debug, Clone)]
+/// Streams rows into BigQuery tables through `tabledata.insertAll`.
+///
+/// Requests are plain HTTP, so they should go through a local proxy
+/// that adds TLS and credentials, such as one in front of
+/// `https://bigquery.googleapis.com/bigquery/v2`.
+pub struct BigQuerySink {
+    endpoint: Endpoint,
+    project: String,
+    dataset: String,
+}
+
+impl BigQuerySink {
+    /// Loads into `dataset` of `project` through the API at `url`.
+    pub fn new(url: &str, project: &str, dataset: &str) -> Result<Self, String> {
+        Ok(BigQuerySink {
+            endpoint: Endpoint::new(url)?,
+            project: project.to_owned(),
+            dataset: dataset.to_owned(),
+        })
+    }
+}
+
+impl WarehouseSink for BigQuerySink {
+    fn insert(&self, table: &str, rows: &[Value]) -> Result<(), errors::Error> {
+        let path = format!(
+            "{}/projects/{}/datasets/{}/tables/{}/insertAll",
+            self.endpoint.path().trim_end_matches('/'),
+            self.project,
+            self.dataset,
+            table
+        );
+        for batch in rows.chunks(BATCH_ROWS) {
+            let body = json!({
+                "kind": "bigquery#tableDataInsertAllRequest",
+                "rows": batch.iter().map(|row| json!({ "json": row })).collect::<Vec<_>>(),
+            });
+            let reply = self
+                .endpoint
+                .post_json(&path, &serde_json::to_vec(&body)?, TIMEOUT)?;
+            let reply: InsertAllReply = serde_json::from_str(&reply)?;
+            if !reply.insert_errors.is_empty() {
+                return Err(std::io::Error::other(format!(
+                    "BigQuery rejected {} of {} rows for table `{}`",
+                    reply.insert_errors.len(),
+                    batch.len(),
+                    table
+                ))
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+/// POSTs rows to a generic loading endpoint as
+/// `{"dataset": ..., "table": ..., "rows": [...]}`, such as an ODBC or
+/// JDBC bridge, which replies `200` once they are loaded.
+pub struct RestSink {
+    endpoint: Endpoint,
+    dataset: String,
+}
+
+impl RestSink {
+    /// Loads into `dataset` through the endpoint at `url`.
+    pub fn new(url: &str, dataset: &str) -> Result<Self, String> {
+        Ok(RestSink {
+            endpoint: Endpoint::new(url)?,
+            dataset: dataset.to_owned(),
+        })
+    }
+}
+
+impl WarehouseSink for RestSink {
+    fn insert(&self, table: &str, rows: &[Value]) -> Result<(), errors::Error> {
+        for batch in rows.chunks(BATCH_ROWS) {
+            let body = json!({ "dataset": self.dataset, "table": table, "rows": batch });
+            self.endpoint
+                .post_json(self.endpoint.path(), &serde_json::to_vec(&body)?, TIMEOUT)?;
+        }
+        Ok(())
+    }
+}
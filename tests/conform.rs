@@ -0,0 +1,103 @@
+//! Runs the test vectors in `vectors` as `payment-engine conform` does.
+//! The vectors directly in `vectors` specify the core rules, under the
+//! default policies, and each directory below it the rules of one
+//! policy, under that policy.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use payment_engine::conform::{self, VectorResult};
+use payment_engine::errors;
+use payment_engine::holds::HoldRates;
+use payment_engine::limits::{AccountLimits, LimitLevels, LimitSet};
+use payment_engine::registry::TransactionHandler;
+use payment_engine::reserve::Reserves;
+use payment_engine::state::{CurrentState, CurrentStateBuilder, StateView};
+use payment_engine::transaction::Transaction;
+use rust_decimal::Decimal;
+
+/// The directory `name` of the vectors.
+fn vectors(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("vectors")
+        .join(name)
+}
+
+/// Runs every vector in `dir` against states built by `builder`, and
+/// fails with the report if any of them failed.
+fn check(builder: CurrentStateBuilder, dir: &Path) {
+    let results = conform::run_dir(&builder, dir).unwrap();
+    assert!(!results.is_empty(), "no vectors in `{}`", dir.display());
+    let mut report = Vec::new();
+    conform::write_report(&mut report, &results).unwrap();
+    assert!(
+        results.iter().all(VectorResult::passed),
+        "{}",
+        String::from_utf8_lossy(&report)
+    );
+}
+
+#[derive(Debug)]
+/// Handles the `panic` type: asks for a credit of the record's amount
+/// to its client, then panics before the credit can be applied.
+struct Panics;
+
+impl TransactionHandler for Panics {
+    fn apply(&self, tx: &Transaction, state: &mut StateView<'_>) -> Result<(), errors::Error> {
+        state.credit(tx.client, tx.amount().unwrap_or_default());
+        panic!("record {} panics", tx.id);
+    }
+}
+
+#[test]
+fn core_rules() {
+    check(CurrentState::builder(), &vectors(""));
+}
+
+#[test]
+fn partial_holds() {
+    let rates = HoldRates::new(Decimal::new(8, 1)).unwrap();
+    check(
+        CurrentState::builder().hold_rates(rates),
+        &vectors("partial-holds"),
+    );
+}
+
+#[test]
+fn reserves() {
+    let dir = vectors("reserves");
+    let reserves = Reserves::from_csv(File::open(dir.join("reserves.csv")).unwrap()).unwrap();
+    check(
+        CurrentState::builder().timestamps(true).reserves(reserves),
+        &dir,
+    );
+}
+
+#[test]
+fn account_limits() {
+    let limits = LimitSet {
+        transaction: LimitLevels {
+            soft: None,
+            hard: Some(Decimal::from(500)),
+        },
+        balance: LimitLevels {
+            soft: Some(Decimal::from(800)),
+            hard: Some(Decimal::from(1000)),
+        },
+        ..LimitSet::default()
+    };
+    let limits = AccountLimits::new(limits).unwrap();
+    check(
+        CurrentState::builder().account_limits(limits),
+        &vectors("account-limits"),
+    );
+}
+
+#[test]
+fn panics_are_rolled_back() {
+    check(
+        CurrentState::builder().transaction_type("panic", Arc::new(Panics)),
+        &vectors("panics"),
+    );
+}